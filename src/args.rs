@@ -2,98 +2,1066 @@
 ///
 /// Using clap as a CLI parser to manage command-line arguments
 /// Reference: Java ProducerArgs.java
-use clap::Parser;
+///
+/// The connection settings can also come from KAFKA_* environment variables
+/// (or a .env file), see resolve_args for which value wins
+/// resolve_config also reports the value each arg ended up with and where it came from
+use crate::content::PayloadPath;
+use crate::content_type::ContentTypeDetection;
+use crate::dedup::{DedupMode, VisitedMarkers, DEFAULT_DEDUP_CAPACITY};
+use crate::envelope::EnvelopeFormat;
+use crate::file::{FileOrder, KeyEncoding, KeyStrategy, NameMismatchPolicy};
+use crate::logging::{LogFormat, LogLevel};
+use crate::partition::PartitionStrategy;
+use crate::path_template::PathTemplate;
+use crate::probe::OutputFormat;
+use crate::schema_registry::redacted_url;
+use crate::sink::FanoutMode;
+use crate::stdin::SplitMode;
+use crate::template::PayloadTemplate;
+use crate::timestamp::TimestampSource;
+use crate::topics::TopicPattern;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::Path;
+use std::time::SystemTime;
+
+// The .env file loaded when --env-file is not given (if it exists)
+const DEFAULT_ENV_FILE: &str = ".env";
+
+// The args whose values are never shown, only whether they are set
+const SECRET_ARGS: &[&str] = &["sasl_jaas_config", "truststore_password"];
+const REDACTED: &str = "***";
+// A kafka-prop whose key contains one of these shows its key but not its value
+const SECRET_PROP_KEYS: &[&str] = &["password", "secret", "jaas", ".key"];
 
 #[derive(Parser, Debug)]
-#[command(name = "kafka_pub_cli")]
+#[command(name = "kafka_pub_cli", version)]
 #[command(about = "Publish files from a directory to Kafka", long_about = None)]
 pub struct ProducerArgs {
+    /// print the git commit, build timestamp and rustc version then exit
+    #[arg(
+        long = "build-info",
+        hide = true,
+        exclusive = true,
+        default_value_t = false
+    )]
+    pub build_info: bool,
+
+    /// load environment variables from this file before parsing the args
+    /// defaults to .env in the working directory when that file exists
+    #[arg(long = "env-file")]
+    pub env_file: Option<String>,
+
     /// identifies the product working w/ Kafka
-    #[arg(long = "client.id", default_value = "kafkautil.rust.producer")]
+    #[arg(
+        long = "client.id",
+        env = "KAFKA_CLIENT_ID",
+        default_value = "kafkautil.rust.producer"
+    )]
     pub client_id: String,
 
     /// the Kafka topic
-    #[arg(long = "topic", required = true)]
-    pub topic: String,
+    /// with topic-from-subdir only the files directly in messageLocation go here
+    #[arg(
+        long = "topic",
+        env = "KAFKA_TOPIC",
+        required_unless_present_any = ["topic_from_subdir", "path_template"]
+    )]
+    pub topic: Option<String>,
 
     /// comma-separated list of kafka brokers (host:port)
-    #[arg(long = "bootstrap-server", required = true)]
-    pub bootstrap: String,
+    #[arg(
+        long = "bootstrap-server",
+        env = "KAFKA_BOOTSTRAP_SERVER",
+        required_unless_present = "broker_srv"
+    )]
+    pub bootstrap: Option<String>,
+
+    /// find the brokers from this DNS SRV record instead of bootstrap-server
+    /// e.g. _kafka._tcp.kafka-headless.default.svc.cluster.local
+    #[arg(
+        long = "broker-srv",
+        alias = "discover-brokers",
+        conflicts_with = "bootstrap"
+    )]
+    pub broker_srv: Option<String>,
 
     /// how many replicas must receive message (0, 1, all)
-    #[arg(long = "acks", required = true)]
+    #[arg(long = "acks", env = "KAFKA_ACKS", required = true)]
     pub ack_mode: String,
 
     /// how many times failures will be retried
     #[arg(long = "retries", default_value_t = 0)]
     pub retries: i32,
 
+    /// flush buffered sends at least this often (in ms), 0 only flushes on shutdown
+    #[arg(long = "flush-interval-ms", default_value_t = 0)]
+    pub flush_interval_ms: u64,
+
+    /// how the partition of each file is chosen: auto or keyhash (the producer decides),
+    /// fixed:N (always partition N) or regex-hash:PATTERN (hash the first capture group
+    /// of the pattern on the file name, e.g. regex-hash:^([a-z]+)-)
+    #[arg(long = "partition-strategy", value_parser = PartitionStrategy::parse, default_value = "auto")]
+    pub partition_strategy: PartitionStrategy,
+
+    /// the timestamp of each record: publish (stamped when it is published), mtime
+    /// (the file's modified time, for event-time topics) or header:NAME (the RFC3339 time
+    /// in that header of the file, e.g. from the sidecar)
+    #[arg(long = "timestamp-from", value_parser = TimestampSource::parse, default_value = "publish")]
+    pub timestamp_from: TimestampSource,
+
+    /// before polling, check the topic exists and the partition strategy fits it
+    /// and exit with an error if not, rather than failing on the first file
+    #[arg(long = "fail-fast-on-startup", default_value_t = false)]
+    pub fail_fast_on_startup: bool,
+
+    /// also publish every file to this topic (can be given more than once)
+    /// e.g. to feed the old and the new topic during a migration
+    #[arg(long = "fanout-topic")]
+    pub fanout_topics: Vec<String>,
+
+    /// with fanout-topic, whether a file is only deleted once every topic has it
+    /// or once any of them has it
+    #[arg(long = "fanout-mode", value_enum, default_value_t = FanoutMode::All)]
+    pub fanout_mode: FanoutMode,
+
+    /// topic that receives files which could not be published (after retries)
+    /// a timeout or an unreachable broker is retried instead (see --send-retries)
+    #[arg(long = "dlq-topic")]
+    pub dlq_topic: Option<String>,
+
     /// the delay in ms between retries (retry.backoff.ms)
     #[arg(long = "retryDelays", default_value_t = 100)]
     pub retry_delay: i32,
 
+    /// how many times a file is republished when the broker cannot be reached
+    /// permanent errors (auth, unknown topic) are not retried
+    #[arg(long = "reconnect-max-retries", default_value_t = 0)]
+    pub reconnect_max_retries: u32,
+
+    /// the wait in ms before the first republish, doubled for each retry after that
+    #[arg(long = "reconnect-backoff-ms", default_value_t = 1000)]
+    pub reconnect_backoff_ms: u64,
+
+    /// how many times a file is sent again after a publish that timed out or hit a full queue
+    /// content the brokers reject (e.g. too large) fails the file at once
+    #[arg(long = "send-retries", default_value_t = 0)]
+    pub send_retries: u32,
+
+    /// the wait in ms before sending a file again, doubled for each retry after that
+    #[arg(long = "send-retry-backoff-ms", default_value_t = 1000)]
+    pub send_retry_backoff_ms: u64,
+
+    /// when the producer queue is full, stop reading files and let it drain
+    /// for up to this many ms between attempts (up to delivery-timeout-ms in all),
+    /// 0 fails the file straight away
+    #[arg(long = "queue-full-backoff-ms", default_value_t = 100)]
+    pub queue_full_backoff_ms: u64,
+
+    /// the most reconnect retries for the whole run, across all files
+    /// once they are used up failed publishes go straight to the error path
+    #[arg(long = "max-total-retries")]
+    pub max_total_retries: Option<u64>,
+
+    /// stop (with a failure exit code) once max-total-retries is used up
+    /// as that many retries points to an outage
+    #[arg(
+        long = "exit-on-retry-budget",
+        default_value_t = false,
+        requires = "max_total_retries"
+    )]
+    pub exit_on_retry_budget: bool,
+
+    /// how long in ms to wait for the broker to confirm delivery of a message
+    #[arg(long = "delivery-timeout-ms", default_value_t = 30_000)]
+    pub delivery_timeout_ms: u64,
+
     /// the number of batches on a connection that can be sent to broker without a response
-    #[arg(long = "maxInflight", default_value_t = 1)]
+    /// (max.in.flight.requests.per.connection)
+    #[arg(long = "maxInflight", default_value_t = 1, value_parser = clap::value_parser!(i32).range(0..))]
     pub max_inflight: i32,
 
     /// the maximum size in bytes of the buffer used to batch messages before sending to Kafka (batch.size)
-    #[arg(long = "batchSizeBytes", default_value_t = 16_384)]
+    #[arg(long = "batchSizeBytes", default_value_t = 16_384, value_parser = clap::value_parser!(i32).range(0..))]
     pub batch_size_bytes: i32,
 
     /// the delay in ms that producer will wait for buffer to be filled (linger.ms)
-    #[arg(long = "batchDelay", default_value_t = 0)]
+    #[arg(long = "batchDelay", default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..))]
     pub batch_delay: i32,
 
+    /// publish files strictly in order: each file waits for the broker to confirm
+    /// the previous one and only one batch is in flight (overrides maxInflight)
+    #[arg(long = "ordered", default_value_t = false)]
+    pub ordered: bool,
+
+    /// enable the idempotent producer so broker retries cannot duplicate messages
+    /// this forces acks=all and at most 5 batches in flight
+    #[arg(long = "idempotent", default_value_t = false)]
+    pub idempotent: bool,
+
+    /// publish the files of each poll cycle in one Kafka transaction with this transactional.id
+    /// the files are only deleted once it commits, a failed file aborts the whole cycle
+    #[arg(long = "transactional-id", conflicts_with_all = ["fanout_topics", "stdin"])]
+    pub transactional_id: Option<String>,
+
+    /// compression applied by the producer to each batch (compression.type)
+    /// batches are compressed as a whole, so a larger batchSizeBytes
+    /// and a non-zero batchDelay (linger.ms) give better compression ratios
+    #[arg(long = "compression", value_enum, default_value_t = Compression::None)]
+    pub compression: Compression,
+
+    /// set any other librdkafka property, e.g. --kafka-prop socket.timeout.ms=10000 (repeatable)
+    /// applied after the typed flags, so it wins over one setting the same property (with a warning)
+    #[arg(long = "kafka-prop", value_parser = parse_kafka_prop)]
+    pub kafka_props: Vec<(String, String)>,
+
+    /// fail instead of warning when a kafka-prop sets a property a typed flag already sets
+    #[arg(long = "strict-props", default_value_t = false)]
+    pub strict_props: bool,
+
     /// app will connect to the broker in a secure way
     #[arg(long = "isSecure", default_value_t = false)]
     pub is_secure: bool,
 
     /// the security protocol used to communicate w/ brokers
-    #[arg(long = "securityProtocol")]
+    #[arg(long = "securityProtocol", env = "KAFKA_SECURITY_PROTOCOL")]
     pub security_protocol: Option<String>,
 
     /// SASL mechanism
-    #[arg(long = "saslMechanism")]
+    #[arg(long = "saslMechanism", env = "KAFKA_SASL_MECHANISM")]
     pub sasl_mechanism: Option<String>,
 
     /// SASL JaaS config
-    #[arg(long = "saslJaasConfig")]
+    #[arg(
+        long = "saslJaasConfig",
+        env = "KAFKA_SASL_JAAS_CONFIG",
+        hide_env_values = true
+    )]
     pub sasl_jaas_config: Option<String>,
 
     /// the format of the trust store
-    #[arg(long = "trustStoreType")]
+    #[arg(long = "trustStoreType", env = "KAFKA_TRUSTSTORE_TYPE")]
     pub trust_store_type: Option<String>,
 
     /// the path to the trust store
-    #[arg(long = "trustStoreLocation")]
+    #[arg(long = "trustStoreLocation", env = "KAFKA_TRUSTSTORE_LOCATION")]
     pub truststore_location: Option<String>,
 
     /// the password for the trust store
-    #[arg(long = "trustStorePassword")]
+    #[arg(
+        long = "trustStorePassword",
+        env = "KAFKA_TRUSTSTORE_PASSWORD",
+        hide_env_values = true
+    )]
     pub truststore_password: Option<String>,
 
     //
     // CLI args related to where payload files are located
     //
     /// directory where files are located that will be published to topic
-    #[arg(long = "messageLocation", required = true)]
-    pub message_location: String,
+    /// repeat the parameter or use a comma-separated list to poll several directories
+    /// the directories are polled in the given (priority) order
+    /// required unless a subcommand says where the files are or stdin is set
+    #[arg(long = "messageLocation", value_delimiter = ',')]
+    pub message_location: Vec<String>,
+
+    /// publish what is piped in on stdin instead of polling messageLocation, then exit
+    #[arg(long = "stdin", default_value_t = false)]
+    pub stdin: bool,
+
+    /// with stdin, publish the whole input as one message or one message per line
+    #[arg(long = "split-mode", value_enum, default_value_t = SplitMode::Whole)]
+    pub split_mode: SplitMode,
+
+    /// also publish the files in subdirectories of messageLocation
+    #[arg(long = "recursive", default_value_t = false)]
+    pub recursive: bool,
+
+    /// publish each file to the topic named after the subdirectory holding it
+    /// e.g. messageLocation/orders/a.json goes to orders (implies recursive)
+    #[arg(long = "topic-from-subdir", default_value_t = false)]
+    pub topic_from_subdir: bool,
+
+    /// take the topic, key and headers from the path of each file under messageLocation
+    /// e.g. {topic}/{key}/* ({name} adds a header, * matches any one directory or file name)
+    /// a file whose path does not match fails (implies recursive)
+    #[arg(
+        long = "path-template",
+        value_parser = PathTemplate::parse,
+        conflicts_with = "topic_from_subdir"
+    )]
+    pub path_template: Option<PathTemplate>,
+
+    /// only publish files whose name matches one of these globs (e.g. "*.json")
+    #[arg(long = "include", value_delimiter = ',')]
+    pub include: Vec<String>,
+
+    /// skip files whose name matches one of these globs
+    #[arg(long = "exclude", value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// a JSON file giving some of the messageLocation directories their own topic,
+    /// key_strategy, delete_files, include or exclude over the global settings
+    /// e.g. {"/data/orders": {"topic": "orders", "delete_files": false}}
+    #[arg(long = "directory-overrides")]
+    pub directory_overrides: Option<String>,
+
+    /// never publish a file whose name does not match this regex, e.g. ^[a-z]+-\d+\.json$
+    /// a mismatch is a misbehaving producer, see name-mismatch
+    #[arg(long = "require-name-regex", value_parser = parse_name_regex)]
+    pub require_name_regex: Option<Regex>,
+
+    /// reject a file whose name does not match (a failed file, moved to errorLocation if set)
+    /// or skip it with a warning, leaving it in place
+    #[arg(long = "name-mismatch", value_enum, default_value_t = NameMismatchPolicy::Reject)]
+    pub name_mismatch: NameMismatchPolicy,
+
+    /// the order files are published in within each directory
+    #[arg(long = "file-order", value_enum, default_value_t = FileOrder::Unsorted)]
+    pub file_order: FileOrder,
+
+    /// skip files already published in this run, recognised by path, modified time and size
+    /// or (content-hash) by the SHA-256 of their content under any name
+    #[arg(long = "dedup-mode", value_enum, default_value_t = DedupMode::Off)]
+    pub dedup_mode: DedupMode,
+
+    /// how many published files dedup-mode remembers, the oldest is forgotten first
+    #[arg(long = "dedup-capacity", default_value_t = DEFAULT_DEDUP_CAPACITY)]
+    pub dedup_capacity: usize,
+
+    /// write a marker (file.done) next to each published file that is kept and skip files with one
+    /// run markers only count within the run, persistent markers for good
+    #[arg(long = "visited-marker", value_enum, default_value_t = VisitedMarkers::Off)]
+    pub visited_marker: VisitedMarkers,
+
+    /// print the files that would be published, in order, and exit
+    /// nothing is read, published or deleted
+    #[arg(long = "list", default_value_t = false)]
+    pub list: bool,
+
+    /// print the resolved configuration (from the args, the environment and the defaults)
+    /// as JSON and exit, secrets are shown as ***
+    #[arg(long = "dump-config", default_value_t = false)]
+    pub dump_config: bool,
+
+    /// how the message key is derived from each file
+    #[arg(long = "key-strategy", value_enum, default_value_t = KeyStrategy::None)]
+    pub key_strategy: KeyStrategy,
+
+    /// leave the final extension out of keys taken from file names (order-123.json is order-123)
+    #[arg(long = "strip-extension", default_value_t = false, action = clap::ArgAction::Set)]
+    pub strip_extension: bool,
+
+    /// how the key is encoded, i64be and i32be match a Java producer's Long/IntegerSerializer
+    /// a key that is not a number (or not hex) fails the file
+    #[arg(long = "key-encoding", value_enum, default_value_t = KeyEncoding::Utf8)]
+    pub key_encoding: KeyEncoding,
+
+    /// publish a tombstone (the key with a null value) for empty files and files ending in .delete
+    /// the file needs a key e.g. from key-strategy
+    #[arg(long = "tombstone-on-empty", default_value_t = false)]
+    pub tombstone_on_empty: bool,
+
+    /// publish the raw file content or a JSON envelope with the file name, size,
+    /// modified time and the content base64 encoded
+    #[arg(long = "envelope", value_enum, default_value_t = EnvelopeFormat::Raw)]
+    pub envelope: EnvelopeFormat,
+
+    /// add a content-type header worked out from the file extension
+    /// or the magic bytes of the content (application/octet-stream when unknown)
+    #[arg(long = "content-type-detection", value_enum, default_value_t = ContentTypeDetection::Off)]
+    pub content_type_detection: ContentTypeDetection,
+
+    /// take the whitespace out of the JSON of .json files (of every file when
+    /// content-type is JSON) before publishing, a file that is not valid JSON fails
+    #[arg(long = "compact-json", default_value_t = false)]
+    pub compact_json: bool,
+
+    /// give every message this content-type header instead of detecting it
+    #[arg(long = "content-type")]
+    pub content_type: Option<String>,
+
+    /// wrap the content of each file e.g. "BEGIN {file_name}\n{content}\nEND"
+    /// the placeholders are {content}, {file_name} and {timestamp} (seconds since the epoch)
+    /// use {{ and }} for literal braces
+    #[arg(long = "payload-template", value_parser = PayloadTemplate::parse)]
+    pub payload_template: Option<PayloadTemplate>,
+
+    /// publish only the value at this JSON path of each file, e.g. $.body
+    /// (a string as its text, anything else as JSON), files without it fail
+    #[arg(long = "payload-jsonpath", value_parser = PayloadPath::parse)]
+    pub payload_jsonpath: Option<PayloadPath>,
+
+    /// publish only the first this many bytes of longer payloads (text is cut at a character)
+    /// with a truncated: true header and the original size in truncated.original.size
+    #[arg(long = "truncate-to-bytes", alias = "payload-max-bytes")]
+    pub truncate_to_bytes: Option<usize>,
+
+    /// publish the files as one message per this many files, joined by batch-separator
+    /// a batch is also published at the end of each cycle and its files are deleted once it is delivered
+    #[arg(long = "batch-files")]
+    pub batch_files: Option<usize>,
+
+    /// publish a batch once it holds this many bytes (with or without batch-files)
+    #[arg(long = "batch-bytes")]
+    pub batch_bytes: Option<usize>,
+
+    /// what goes between the files of a batch, \n, \r, \t and \\ are escapes
+    #[arg(long = "batch-separator", default_value = "\\n", value_parser = parse_separator)]
+    pub batch_separator: String,
+
+    /// frame each payload in the schema registry wire format with this schema id
+    /// (a zero magic byte then the big-endian id) for Avro consumers
+    #[arg(long = "schema-id", conflicts_with = "schema_registry_url")]
+    pub schema_id: Option<u32>,
+
+    /// look up the id of the latest schema for the topic's value subject (<topic>-value)
+    /// in this schema registry at startup and frame each payload with it
+    #[arg(long = "schema-registry-url", requires = "topic")]
+    pub schema_registry_url: Option<String>,
+
+    /// read the message key and headers for each file from a JSON sidecar file
+    /// named after the file plus this extension (e.g. ".meta")
+    #[arg(long = "sidecar-extension")]
+    pub sidecar_extension: Option<String>,
+
+    /// check each file against the SHA-256 digest in a .sha256 file next to it
+    /// files that do not match are never published
+    #[arg(long = "verify-checksum", default_value_t = false)]
+    pub verify_checksum: bool,
+
+    /// with verify-checksum a file without a .sha256 file is an error
+    #[arg(long = "require-checksum", default_value_t = false)]
+    pub require_checksum: bool,
+
+    /// also publish files whose name starts with a dot (e.g. .order.json.swp)
+    /// which are passed over by default
+    #[arg(long = "include-hidden", default_value_t = false)]
+    pub include_hidden: bool,
+
+    /// rename each file by adding this suffix (e.g. ".inprogress") before publishing it
+    /// so several instances can poll the same directory without publishing a file twice
+    #[arg(long = "claim-suffix")]
+    pub claim_suffix: Option<String>,
+
+    /// path to a JSON schema that the content of every file must conform to
+    #[arg(long = "jsonSchema")]
+    pub json_schema: Option<String>,
+
+    /// directory where files are moved when they cannot be processed
+    /// otherwise the app stops on the first file that fails
+    #[arg(long = "errorLocation")]
+    pub error_location: Option<String>,
+
+    /// move the files in errorLocation back into messageLocation at startup to publish them again
+    /// files that fail again return to errorLocation
+    #[arg(long = "retry-errors-on-startup", requires = "error_location")]
+    pub retry_errors_on_startup: bool,
+
+    /// only retry error files modified less than this many seconds ago, 0 retries them all
+    #[arg(long = "retry-errors-max-age-secs", default_value_t = 0)]
+    pub retry_errors_max_age_secs: u64,
+
+    /// after each file write the time and path of the last file published to this file
+    #[arg(long = "checkpoint-file")]
+    pub checkpoint_file: Option<String>,
+
+    /// only publish the files listed in this file (one path per line, relative to
+    /// messageLocation) in the listed order instead of every file in messageLocation
+    #[arg(long = "manifest")]
+    pub manifest: Option<String>,
+
+    /// stop on a manifest entry that is missing or outside messageLocation
+    /// instead of logging and skipping it
+    #[arg(
+        long = "strict-manifest",
+        default_value_t = false,
+        requires = "manifest"
+    )]
+    pub strict_manifest: bool,
+
+    /// list a polled directory that failed to read (e.g. on a flaky network file system)
+    /// again up to this many times within the cycle before the rest of the cycle is skipped
+    #[arg(long = "directory-read-retries", default_value_t = 3)]
+    pub directory_read_retries: u32,
+
+    /// the wait in ms before listing the directory again, doubled for each retry after that
+    #[arg(long = "directory-read-backoff-ms", default_value_t = 100)]
+    pub directory_read_backoff_ms: u64,
+
+    /// stop with an error on a directory that still cannot be read after the retries
+    /// instead of skipping the cycle
+    #[arg(
+        long = "strict-directory-read",
+        alias = "strict",
+        default_value_t = false
+    )]
+    pub strict_directory_read: bool,
+
+    /// lock this file while polling so a second instance on the same directory fails to start
+    /// defaults to .kafka_pub_cli.lock in each polled directory
+    #[arg(long = "lock-file")]
+    pub lock_file: Option<String>,
 
     /// how long to wait between file polls looking for new messages
     #[arg(long = "delayInMillis", default_value_t = 1000)]
     pub delay_millis: u64,
 
+    /// the most files published per second, 0 means unlimited
+    #[arg(long = "max-rate", default_value_t = 0)]
+    pub max_rate: u32,
+
+    /// poll as soon as a file is added to messageLocation
+    /// delayInMillis still applies as a fallback to catch anything missed
+    #[arg(long = "watch", default_value_t = false)]
+    pub watch: bool,
+
+    /// add a random delay of up to this many ms to each wait between polls
+    /// so instances sharing a directory do not poll in sync
+    #[arg(long = "jitterInMillis", default_value_t = 0)]
+    pub jitter_millis: u64,
+
+    /// start a poll every delayInMillis rather than waiting delayInMillis after each poll
+    /// so slow polls do not make the schedule drift
+    #[arg(long = "fixed-rate", default_value_t = false)]
+    pub fixed_rate: bool,
+
+    /// double the wait between polls after each poll that finds no files
+    #[arg(long = "backoffOnEmpty", default_value_t = false)]
+    pub backoff_on_empty: bool,
+
+    /// only log every n-th poll in a row that finds no files
+    #[arg(long = "heartbeat-every", default_value_t = 1)]
+    pub heartbeat_every: u32,
+
+    /// the longest wait between polls when backoffOnEmpty is used
+    #[arg(long = "maxBackoffInMillis", default_value_t = 60_000)]
+    pub max_backoff_millis: u64,
+
     /// only poll messageLocation once when this parameter is added
     /// otherwise, the app will continue to poll the directory
     #[arg(long = "runOnce", default_value_t = false)]
     pub run_once: bool,
 
+    /// keep polling while files are found and exit after the first poll that finds none
+    /// overrides runOnce, but maxCycles still applies
+    #[arg(long = "until-empty", default_value_t = false)]
+    pub until_empty: bool,
+
     /// overrides runOnce and will only poll the specified number of times
     /// if maxCycles is < 0 then relies on runOnce or keep polling indefinitely
     #[arg(long = "maxCycles", default_value_t = -1)]
     pub max_cycles: i32,
 
+    /// keep published files until this poll cycle, then delete them (without publishing again)
+    /// to check publishing works on a new deployment before any file is removed
+    #[arg(long = "delete-after-cycles", default_value_t = 0)]
+    pub delete_after_cycles: i32,
+
+    /// process at most this many files per poll and leave the rest for the next poll
+    /// 0 means no limit
+    #[arg(long = "max-files-per-cycle", default_value_t = 0)]
+    pub max_files_per_cycle: usize,
+
+    /// hold at most this many bytes of file content in memory at a time
+    /// a file larger than this fails
+    #[arg(long = "max-inflight-bytes")]
+    pub max_inflight_bytes: Option<u64>,
+
+    /// pause polling while the file system of messageLocation has less than this many MB free
+    /// and resume once space frees up, 0 disables the check
+    #[arg(long = "min-free-disk-mb", default_value_t = 0)]
+    pub min_free_disk_mb: u64,
+
+    /// log an error (and send an alert to --control-topic) when no file has been processed
+    /// for longer than this many seconds, 0 disables the watchdog
+    #[arg(long = "expected-interval-secs", default_value_t = 0)]
+    pub expected_interval_secs: u64,
+
+    /// stop with exit code 6 when the --expected-interval-secs watchdog fires
+    #[arg(long = "exit-on-silence", requires = "expected_interval_secs")]
+    pub exit_on_silence: bool,
+
+    /// after each poll that published files, send a JSON marker with the cycle number,
+    /// file count, byte count and timestamp to this topic
+    #[arg(long = "control-topic")]
+    pub control_topic: Option<String>,
+
+    /// drop files last modified more than this many seconds ago instead of publishing them
+    /// 0 means files never expire, with file-order newest this is the lookback window
+    #[arg(
+        long = "file-ttl-secs",
+        visible_alias = "lookback-secs",
+        default_value_t = 0
+    )]
+    pub file_ttl_secs: u64,
+
+    /// stop polling after this many seconds, finishing the file being published
+    /// 0 means no limit
+    #[arg(long = "max-runtime-secs", default_value_t = 0)]
+    pub max_runtime_secs: u64,
+
     /// app will delete the files after a poll unless this parameter is added
     #[arg(long = "noDeleteFiles", default_value_t = false)]
     pub no_delete_files: bool,
+
+    //
+    // CLI args related to monitoring
+    //
+    /// serve a JSON snapshot of the poller on GET /status at this port
+    #[arg(long = "status-port")]
+    pub status_port: Option<u16>,
+
+    /// the default log level, RUST_LOG overrides it when set
+    #[arg(long = "log-level", value_enum, default_value_t = LogLevel::Info)]
+    pub log_level: LogLevel,
+
+    /// only log errors (unless RUST_LOG is set)
+    #[arg(long = "quiet", default_value_t = false)]
+    pub quiet: bool,
+
+    /// write log lines as text or as JSON objects
+    #[arg(long = "log-format", value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Runs other than polling messageLocation
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// publish every file in an archive directory once, leaving the files in place
+    /// the ordering and file selection args still apply
+    Replay(ReplayArgs),
+    /// print what would be published for one file (topic, key, headers and payload size)
+    /// without publishing it, the key, header and transform args still apply
+    Probe(ProbeArgs),
+    /// copy the messages of one topic to another, e.g. during a migration
+    /// the payload args (payload-jsonpath, payload-template, jsonSchema) still apply
+    Mirror(MirrorArgs),
+    /// publish each line appended to one growing file, e.g. an application log
+    /// carrying on from the offset stored by the last run
+    Tail(TailArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReplayArgs {
+    /// the directory holding the files to publish again
+    pub archive_directory: String,
+
+    /// only publish files modified after this time (RFC 3339 e.g. 2024-05-01T12:00:00Z)
+    #[arg(long = "since", value_parser = humantime::parse_rfc3339_weak)]
+    pub since: Option<SystemTime>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ProbeArgs {
+    /// the file to run through the publish pipeline
+    pub file: String,
+
+    /// also show the payload: raw (the bytes as they are), utf8 (as text),
+    /// hex, or json-lines (one JSON object with the key, headers and payload)
+    #[arg(long = "output-format", value_enum)]
+    pub output_format: Option<OutputFormat>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct MirrorArgs {
+    /// the topic the messages are consumed from
+    #[arg(
+        long = "source-topic",
+        required_unless_present = "source_topic_regex",
+        conflicts_with = "source_topic_regex"
+    )]
+    pub source_topic: Option<String>,
+
+    /// consume every topic whose whole name matches this regex instead, e.g. orders-.*
+    /// topics created later are picked up once the cluster is asked again
+    #[arg(long = "source-topic-regex", value_parser = TopicPattern::parse)]
+    pub source_topic_regex: Option<TopicPattern>,
+
+    /// how often the cluster is asked which topics match --source-topic-regex
+    #[arg(long = "topic-refresh-secs", default_value_t = 60)]
+    pub topic_refresh_secs: u64,
+
+    /// the topic the messages are published to
+    #[arg(long = "dest-topic")]
+    pub dest_topic: String,
+
+    /// the consumer group whose offsets record how far the mirror got
+    #[arg(long = "group-id")]
+    pub group_id: String,
+
+    /// stop after this many messages instead of running until shutdown
+    #[arg(long = "max-messages")]
+    pub max_messages: Option<u64>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TailArgs {
+    /// the file to tail
+    pub file: String,
+
+    /// where the offset of the last published line is kept (default: the file with .offset appended)
+    #[arg(long = "offset-file")]
+    pub offset_file: Option<String>,
+
+    /// keep publishing lines as they are appended, every delayInMillis, until shutdown
+    #[arg(long = "follow", default_value_t = false)]
+    pub follow: bool,
+}
+
+/// The compression codecs supported by the producer
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    // the value librdkafka expects for compression.type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Snappy => "snappy",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+// Parse the args, taking each value from the first place that sets it:
+//   1. an explicit flag
+//   2. its KAFKA_* environment variable, the .env file fills in variables
+//      that are not already set in the environment
+//   3. the default
+// (there is no config file, so nothing sits between the environment and the defaults)
+pub fn resolve_args<I, S>(args: I) -> Result<ProducerArgs, clap::Error>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<OsString> + AsRef<str> + Clone,
+{
+    resolve_config(args).map(|(args, _config)| args)
+}
+
+// resolve_args along with the configuration it resolved to
+pub fn resolve_config<I, S>(args: I) -> Result<(ProducerArgs, ResolvedConfig), clap::Error>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<OsString> + AsRef<str> + Clone,
+{
+    let args: Vec<S> = args.into_iter().collect();
+    load_env_file(env_file_arg(&args).as_deref())?;
+    let mut command = ProducerArgs::command();
+    let matches = command.try_get_matches_from_mut(args)?;
+    let config = ResolvedConfig::from_matches(&command, &matches);
+    let args = ProducerArgs::from_arg_matches(&matches).map_err(|e| e.format(&mut command))?;
+    if args.command.is_none() && !args.stdin && args.message_location.is_empty() {
+        return Err(ProducerArgs::command().error(
+            ErrorKind::MissingRequiredArgument,
+            "the following required arguments were not provided:\n  --messageLocation <MESSAGE_LOCATION>",
+        ));
+    }
+    Ok((args, config))
+}
+
+/// The value every arg resolved to and where it came from
+/// the args of a subcommand are prefixed with its name (e.g. probe.file)
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ResolvedConfig {
+    pub command: Option<String>,
+    // null for an arg that is not set
+    pub settings: BTreeMap<String, Value>,
+    // command line, environment or default, for the args that are set
+    pub sources: BTreeMap<String, String>,
+}
+
+impl ResolvedConfig {
+    fn from_matches(command: &clap::Command, matches: &ArgMatches) -> Self {
+        let mut config = ResolvedConfig::default();
+        config.add_args(command, matches, "");
+        if let Some((name, subcommand_matches)) = matches.subcommand() {
+            config.command = Some(name.to_string());
+            if let Some(subcommand) = command.find_subcommand(name) {
+                config.add_args(subcommand, subcommand_matches, &format!("{}.", name));
+            }
+        }
+        config
+    }
+
+    fn add_args(&mut self, command: &clap::Command, matches: &ArgMatches, prefix: &str) {
+        for arg in command.get_arguments() {
+            let id = arg.get_id().as_str();
+            if matches!(arg.get_action(), ArgAction::Help | ArgAction::Version) {
+                continue;
+            }
+            let key = format!("{}{}", prefix, id);
+            let values: Option<Vec<String>> =
+                matches.try_get_raw(id).ok().flatten().map(|values| {
+                    values
+                        .map(|value| value.to_string_lossy().into_owned())
+                        .collect()
+                });
+            let value = match values {
+                None => Value::Null,
+                Some(_) if SECRET_ARGS.contains(&id) => Value::String(REDACTED.to_string()),
+                Some(values) if id == "kafka_props" => values
+                    .iter()
+                    .map(|prop| Value::String(redact_kafka_prop(prop)))
+                    .collect(),
+                Some(values) if id == "schema_registry_url" => values
+                    .first()
+                    .map_or(Value::Null, |url| Value::String(redacted_url(url))),
+                Some(values) => match arg.get_action() {
+                    ArgAction::SetTrue | ArgAction::SetFalse => {
+                        Value::Bool(values.first().is_some_and(|value| value == "true"))
+                    }
+                    ArgAction::Append => values.into_iter().map(Value::String).collect(),
+                    _ => values.into_iter().next().map_or(Value::Null, Value::String),
+                },
+            };
+            if !value.is_null() {
+                let source = match matches.value_source(id) {
+                    Some(ValueSource::CommandLine) => "command line",
+                    Some(ValueSource::EnvVariable) => "environment",
+                    Some(ValueSource::DefaultValue) => "default",
+                    _ => "unknown",
+                };
+                self.sources.insert(key.clone(), source.to_string());
+            }
+            self.settings.insert(key, value);
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+// Whether a librdkafka property holds a secret, e.g. sasl.password or ssl.key.password
+pub(crate) fn is_secret_prop(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SECRET_PROP_KEYS.iter().any(|secret| key.contains(secret))
+}
+
+// The value of the property as it may be shown, *** for a secret
+pub(crate) fn shown_prop_value<'a>(key: &str, value: &'a str) -> &'a str {
+    if is_secret_prop(key) {
+        REDACTED
+    } else {
+        value
+    }
+}
+
+// key=*** for a kafka-prop that holds a secret
+fn redact_kafka_prop(prop: &str) -> String {
+    match prop.split_once('=') {
+        Some((key, value)) => format!("{}={}", key, shown_prop_value(key, value)),
+        None => prop.to_string(),
+    }
+}
+
+fn parse_name_regex(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("Invalid regex {:?}: {}", pattern, e))
+}
+
+// A librdkafka property as key=value, the value may contain = itself
+fn parse_kafka_prop(prop: &str) -> Result<(String, String), String> {
+    match prop.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("Expected key=value but got {:?}", prop)),
+    }
+}
+
+// The separator with its escapes replaced, e.g. \r\n for CRLF
+fn parse_separator(separator: &str) -> Result<String, String> {
+    let mut parsed = String::new();
+    let mut chars = separator.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            parsed.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => parsed.push('\n'),
+            Some('r') => parsed.push('\r'),
+            Some('t') => parsed.push('\t'),
+            Some('\\') => parsed.push('\\'),
+            other => {
+                return Err(format!(
+                    "Unknown escape \\{} in separator {:?}",
+                    other.map(String::from).unwrap_or_default(),
+                    separator
+                ))
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+// The --env-file value, read before clap parses the args
+// because the environment has to be loaded first
+fn env_file_arg<S: AsRef<str>>(args: &[S]) -> Option<String> {
+    let mut args = args.iter().map(AsRef::as_ref);
+    while let Some(arg) = args.next() {
+        if arg == "--env-file" {
+            return args.next().map(str::to_string);
+        }
+        if let Some(env_file) = arg.strip_prefix("--env-file=") {
+            return Some(env_file.to_string());
+        }
+    }
+    None
+}
+
+// A missing env file is only an error when it was asked for
+fn load_env_file(env_file: Option<&str>) -> Result<(), clap::Error> {
+    let path = Path::new(env_file.unwrap_or(DEFAULT_ENV_FILE));
+    if env_file.is_none() && !path.exists() {
+        return Ok(());
+    }
+    dotenvy::from_path(path).map_err(|e| {
+        ProducerArgs::command().error(
+            ErrorKind::Io,
+            format!("Failed to load env file {}: {}", path.display(), e),
+        )
+    })
+}
+
+// The hidden --build-info flag is checked before clap parses the args
+// so it works without supplying the required args (like --version)
+// only an option position counts, not the value of an option or a token after --
+pub fn build_info_requested<I, S>(args: I) -> bool
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let takes_value = value_options(&ProducerArgs::command());
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.as_ref();
+        if arg == "--" {
+            return false;
+        }
+        if arg == "--build-info" {
+            return true;
+        }
+        if takes_value.iter().any(|option| option == arg) {
+            args.next();
+        }
+    }
+    false
+}
+
+// The --long and -s names of the options (of the command and its subcommands)
+// that read the next token as their value
+fn value_options(command: &clap::Command) -> Vec<String> {
+    let mut options = Vec::new();
+    for arg in command.get_arguments() {
+        if arg.is_positional() || !arg.get_action().takes_values() {
+            continue;
+        }
+        options.extend(arg.get_long().map(|long| format!("--{}", long)));
+        options.extend(arg.get_short().map(|short| format!("-{}", short)));
+    }
+    for subcommand in command.get_subcommands() {
+        options.extend(value_options(subcommand));
+    }
+    options
+}
+
+// Describe the build of this binary
+// the values are captured at compile time by build.rs
+pub fn build_info() -> String {
+    format!(
+        "kafka_pub_cli {}\ncommit: {}\nbuilt: {}\nrustc: {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("BUILD_GIT_COMMIT"),
+        env!("BUILD_TIMESTAMP"),
+        env!("BUILD_RUSTC_VERSION"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_flag_prints_crate_version() {
+        let result = ProducerArgs::try_parse_from(["kafka_pub_cli", "--version"]);
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DisplayVersion);
+        assert!(err.to_string().contains(env!("CARGO_PKG_VERSION")));
+    }
+
+    #[test]
+    fn env_file_arg_found() {
+        assert_eq!(
+            env_file_arg(&["kafka_pub_cli", "--env-file", "dev.env"]),
+            Some("dev.env".to_string())
+        );
+        assert_eq!(
+            env_file_arg(&["kafka_pub_cli", "--env-file=dev.env"]),
+            Some("dev.env".to_string())
+        );
+        assert_eq!(env_file_arg(&["kafka_pub_cli", "--topic", "foo"]), None);
+    }
+
+    #[test]
+    fn build_info_flag_detected() {
+        assert!(build_info_requested(["kafka_pub_cli", "--build-info"]));
+        assert!(!build_info_requested(["kafka_pub_cli", "--topic", "foo"]));
+        assert!(build_info_requested([
+            "kafka_pub_cli",
+            "--topic",
+            "foo",
+            "--build-info"
+        ]));
+        assert!(!build_info_requested([
+            "kafka_pub_cli",
+            "--topic",
+            "--build-info"
+        ]));
+        assert!(!build_info_requested([
+            "kafka_pub_cli",
+            "--",
+            "--build-info"
+        ]));
+    }
+
+    #[test]
+    fn build_info_format() {
+        let info = build_info();
+        let lines: Vec<&str> = info.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            format!("kafka_pub_cli {}", env!("CARGO_PKG_VERSION"))
+        );
+        assert!(lines[1].starts_with("commit: "));
+        assert!(lines[2].starts_with("built: "));
+        assert!(lines[3].starts_with("rustc: "));
+    }
+
+    #[test]
+    fn separator_escapes_replaced() {
+        assert_eq!(parse_separator(r"\r\n").unwrap(), "\r\n");
+        assert_eq!(parse_separator(r"\t|\\").unwrap(), "\t|\\");
+        assert_eq!(parse_separator(",").unwrap(), ",");
+        assert!(parse_separator(r"\x").is_err());
+    }
 }
@@ -0,0 +1,141 @@
+/// Publishing several files as one message (--batch-files and --batch-bytes)
+///
+/// For topics that take newline-delimited batches the content of the files
+/// is joined by a separator into one message instead of a message per file
+/// the batch is published once it holds max_files files or max_bytes bytes
+/// (or at the end of the cycle) and its files are only deleted once it is delivered
+use crate::sink::FileContext;
+
+pub const DEFAULT_BATCH_SEPARATOR: &str = "\n";
+
+/// When a batch is full and how its files are joined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchLimits {
+    pub max_files: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub separator: Vec<u8>,
+}
+
+impl BatchLimits {
+    pub fn new(max_files: Option<usize>, max_bytes: Option<usize>) -> Self {
+        BatchLimits {
+            max_files,
+            max_bytes,
+            separator: DEFAULT_BATCH_SEPARATOR.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn separator(mut self, separator: &[u8]) -> Self {
+        self.separator = separator.to_vec();
+        self
+    }
+}
+
+/// The files waiting to be published as one message
+#[derive(Default)]
+pub struct Batch {
+    // the message so far, named after its first file
+    message: Option<FileContext>,
+    files: usize,
+}
+
+impl Batch {
+    pub fn is_empty(&self) -> bool {
+        self.files == 0
+    }
+
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    // A batch goes to one topic, a file routed elsewhere starts the next batch
+    pub fn accepts(&self, ctx: &FileContext) -> bool {
+        self.message
+            .as_ref()
+            .is_none_or(|message| message.topic == ctx.topic)
+    }
+
+    // Add the content of the file after the separator
+    // the message has no key or headers, those of the files are dropped
+    pub fn add(&mut self, ctx: &FileContext, separator: &[u8]) {
+        match &mut self.message {
+            Some(message) => {
+                message.content.extend_from_slice(separator);
+                message.content.extend_from_slice(&ctx.content);
+            }
+            None => {
+                let mut message = FileContext::new(&ctx.path, ctx.content.clone());
+                message.topic.clone_from(&ctx.topic);
+                message.modified = ctx.modified;
+                self.message = Some(message);
+            }
+        }
+        self.files += 1;
+    }
+
+    pub fn is_full(&self, limits: &BatchLimits) -> bool {
+        let bytes = self
+            .message
+            .as_ref()
+            .map_or(0, |message| message.content.len());
+        limits
+            .max_files
+            .is_some_and(|max_files| self.files >= max_files)
+            || limits.max_bytes.is_some_and(|max_bytes| bytes >= max_bytes)
+    }
+
+    // The message to publish, leaving the batch empty
+    pub fn take(&mut self) -> Option<FileContext> {
+        self.files = 0;
+        self.message.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn file(name: &str, content: &str, topic: Option<&str>) -> FileContext {
+        let mut ctx = FileContext::new(Path::new(name), content.as_bytes().to_vec());
+        ctx.topic = topic.map(str::to_string);
+        ctx.key = Some(name.as_bytes().to_vec());
+        ctx
+    }
+
+    #[test]
+    fn joins_files_with_separator() {
+        let limits = BatchLimits::new(Some(2), None);
+        let mut batch = Batch::default();
+        batch.add(&file("a.json", "a", None), &limits.separator);
+        assert!(!batch.is_full(&limits));
+        batch.add(&file("b.json", "b", None), &limits.separator);
+        assert!(batch.is_full(&limits));
+
+        let message = batch.take().expect("batch was empty");
+        assert_eq!(message.content, b"a\nb");
+        assert_eq!(message.file_name, "a.json");
+        assert_eq!(message.key, None);
+        assert!(batch.is_empty());
+        assert!(batch.take().is_none());
+    }
+
+    #[test]
+    fn full_at_max_bytes() {
+        let limits = BatchLimits::new(None, Some(5)).separator(b",");
+        let mut batch = Batch::default();
+        batch.add(&file("a.json", "ab", None), &limits.separator);
+        batch.add(&file("b.json", "c", None), &limits.separator);
+        assert!(!batch.is_full(&limits));
+        batch.add(&file("c.json", "d", None), &limits.separator);
+        assert!(batch.is_full(&limits));
+    }
+
+    #[test]
+    fn file_for_another_topic_is_not_accepted() {
+        let mut batch = Batch::default();
+        batch.add(&file("a.json", "a", Some("orders")), b"\n");
+        assert!(batch.accepts(&file("b.json", "b", Some("orders"))));
+        assert!(!batch.accepts(&file("c.json", "c", Some("audit"))));
+    }
+}
@@ -0,0 +1,179 @@
+/// Budgets shared by everything publishing in a run
+///
+/// ByteBudget bounds the memory taken by files being published
+/// a semaphore over bytes rather than files
+/// a file takes permits equal to its size before it is read
+/// and gives them back once it has been handed to the sink
+/// so pollers on several threads sharing a budget never hold more than it in memory
+///
+/// RetryBudget bounds the publish retries of the whole run
+/// so a broker outage fails files quickly rather than retrying each of them for hours
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+pub struct ByteBudget {
+    total: u64,
+    // the bytes taken by files still being published
+    in_use: Mutex<u64>,
+    released: Condvar,
+}
+
+/// Bytes taken from a ByteBudget, given back when dropped
+pub struct BytePermit<'a> {
+    budget: &'a ByteBudget,
+    bytes: u64,
+}
+
+impl ByteBudget {
+    pub fn new(total: u64) -> Self {
+        ByteBudget {
+            total,
+            in_use: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    // A budget for pollers on several threads to share
+    pub fn shared(total: u64) -> Arc<Self> {
+        Arc::new(ByteBudget::new(total))
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    // The bytes taken by files still being published
+    pub fn in_use(&self) -> u64 {
+        *self.in_use.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Take bytes from the budget, waiting until enough are free
+    // fails straight away for more than the whole budget, which would never be free
+    pub fn acquire(&self, bytes: u64) -> Result<BytePermit<'_>, String> {
+        if bytes > self.total {
+            return Err(format!(
+                "File of {} bytes is larger than the in-flight budget of {} bytes",
+                bytes, self.total
+            ));
+        }
+        let mut in_use = self.in_use.lock().unwrap_or_else(|e| e.into_inner());
+        while *in_use + bytes > self.total {
+            in_use = self
+                .released
+                .wait(in_use)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *in_use += bytes;
+        Ok(BytePermit {
+            budget: self,
+            bytes,
+        })
+    }
+}
+
+impl Drop for BytePermit<'_> {
+    fn drop(&mut self) {
+        let mut in_use = self.budget.in_use.lock().unwrap_or_else(|e| e.into_inner());
+        *in_use -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
+
+pub struct RetryBudget {
+    total: u64,
+    used: AtomicU64,
+    // set once a retry was refused
+    exhausted: AtomicBool,
+    // raised when the budget runs out so the poller stops
+    shutdown_flag: Option<Arc<AtomicBool>>,
+}
+
+impl RetryBudget {
+    pub fn new(total: u64) -> Self {
+        RetryBudget {
+            total,
+            used: AtomicU64::new(0),
+            exhausted: AtomicBool::new(false),
+            shutdown_flag: None,
+        }
+    }
+
+    // A budget for every producer of the run to share
+    pub fn shared(total: u64) -> Arc<Self> {
+        Arc::new(RetryBudget::new(total))
+    }
+
+    // Request a shutdown with the flag once the budget runs out
+    // a likely outage stops the run instead of failing every file that is left
+    pub fn stop_when_exhausted(mut self, shutdown_flag: Arc<AtomicBool>) -> Self {
+        self.shutdown_flag = Some(shutdown_flag);
+        self
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    // The retries taken so far
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    // Whether a retry has been refused
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::SeqCst)
+    }
+
+    // Take one retry, false once all of them have been used
+    pub fn try_retry(&self) -> bool {
+        let taken = self
+            .used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                (used < self.total).then_some(used + 1)
+            })
+            .is_ok();
+        if !taken {
+            self.exhausted.store(true, Ordering::SeqCst);
+            if let Some(shutdown_flag) = &self.shutdown_flag {
+                shutdown_flag.store(true, Ordering::SeqCst);
+            }
+        }
+        taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permits_are_given_back_on_drop() {
+        let budget = ByteBudget::new(10);
+        let first = budget.acquire(6).expect("acquire failed");
+        let second = budget.acquire(4).expect("acquire failed");
+        assert_eq!(budget.in_use(), 10);
+        drop(first);
+        drop(second);
+        assert_eq!(budget.in_use(), 0);
+    }
+
+    #[test]
+    fn larger_than_budget_fails_instead_of_waiting() {
+        let budget = ByteBudget::new(10);
+        let err = budget.acquire(11).err().expect("acquire should fail");
+        assert!(err.contains("larger than the in-flight budget of 10 bytes"));
+    }
+
+    #[test]
+    fn retries_refused_once_used_up() {
+        let shutdown_flag = Arc::new(AtomicBool::new(false));
+        let budget = RetryBudget::new(2).stop_when_exhausted(shutdown_flag.clone());
+        assert!(budget.try_retry());
+        assert!(budget.try_retry());
+        assert!(!budget.is_exhausted());
+        assert!(!budget.try_retry());
+        assert_eq!(budget.used(), 2);
+        assert!(budget.is_exhausted());
+        assert!(shutdown_flag.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,32 @@
+/// The source of time for the poller
+///
+/// The poller asks the clock for the time and to sleep
+/// so tests can swap in a fake clock and advance time without waiting
+use std::time::{Duration, Instant, SystemTime};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    // the wall-clock time, for comparing with file times (TTL, retry age)
+    // and for the timestamps the poller writes out
+    fn wall_time(&self) -> SystemTime;
+
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
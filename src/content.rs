@@ -0,0 +1,386 @@
+/// Preparing the file content for the sink
+/// Reference: KafkaContentHandler
+///
+/// The content of every file goes through the same steps before it is sent
+/// the JSON compaction, the payload path, the transforms (in the order they were added), the JSON schema check,
+/// the truncation, the payload template, the envelope and finally the schema registry framing
+use crate::content_type;
+use crate::envelope::{build_envelope, EnvelopeFormat};
+use crate::schema_registry;
+use crate::sink::FileContext;
+use crate::template::PayloadTemplate;
+use serde_json_path::JsonPath;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// Headers on a payload cut down to the truncation limit
+pub const TRUNCATED_HEADER: &str = "truncated";
+pub const ORIGINAL_SIZE_HEADER: &str = "truncated.original.size";
+
+/// The content ContentProcessor::process made of a file
+/// with the size the payload had before it was truncated, if it was
+#[derive(Debug)]
+pub struct ProcessedContent {
+    pub content: Vec<u8>,
+    pub original_size: Option<usize>,
+}
+
+// Type alias for a step that rewrites the file content before it is sent
+pub type ContentTransform = Box<dyn Fn(&[u8]) -> Result<Vec<u8>, Box<dyn Error>>>;
+
+/// A JSON path to the field of the file that is published, e.g. $.body
+/// for upstreams that wrap the message in an envelope of their own
+#[derive(Debug, Clone)]
+pub struct PayloadPath {
+    path: String,
+    json_path: JsonPath,
+}
+
+impl PayloadPath {
+    pub fn parse(path: &str) -> Result<Self, String> {
+        let json_path =
+            JsonPath::parse(path).map_err(|e| format!("Invalid JSON path {:?}: {}", path, e))?;
+        Ok(PayloadPath {
+            path: path.to_string(),
+            json_path,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    // The value at the path, a string as its text and anything else as JSON
+    // fails if the content is not JSON or the path does not match exactly one value
+    pub fn extract(&self, content: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let document: serde_json::Value = serde_json::from_slice(content)
+            .map_err(|e| format!("File content is not valid JSON: {}", e))?;
+        let value = self.json_path.query(&document).exactly_one().map_err(|e| {
+            format!(
+                "JSON path {} does not match exactly one value: {}",
+                self.path, e
+            )
+        })?;
+        match value {
+            serde_json::Value::String(text) => Ok(text.clone().into_bytes()),
+            value => Ok(serde_json::to_vec(value)?),
+        }
+    }
+}
+
+/// Which files have the whitespace taken out of their JSON (--compact-json)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompactJson {
+    #[default]
+    Off,
+    // files with a .json extension
+    JsonFiles,
+    // every file, for when the files are declared to be JSON
+    EveryFile,
+}
+
+/// The content pipeline, with no steps the content is passed through as it is
+#[derive(Default)]
+pub struct ContentProcessor {
+    compact_json: CompactJson,
+    payload_path: Option<PayloadPath>,
+    transforms: Vec<ContentTransform>,
+    json_schema: Option<jsonschema::Validator>,
+    truncate_to_bytes: Option<usize>,
+    payload_template: Option<PayloadTemplate>,
+    envelope: EnvelopeFormat,
+    schema_id: Option<u32>,
+}
+
+impl ContentProcessor {
+    pub fn new() -> Self {
+        ContentProcessor::default()
+    }
+
+    // minify the JSON of the files before any other step sees it
+    // a file that is not valid JSON fails
+    pub fn compact_json(mut self, compact_json: CompactJson) -> Self {
+        self.compact_json = compact_json;
+        self
+    }
+
+    pub fn compact_json_mode(&self) -> CompactJson {
+        self.compact_json
+    }
+
+    // publish only this field of the file, before the transforms see it
+    pub fn payload_path(mut self, payload_path: PayloadPath) -> Self {
+        self.payload_path = Some(payload_path);
+        self
+    }
+
+    // each transform gets the output of the one added before it
+    pub fn transform(mut self, transform: ContentTransform) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    // reject content that does not conform to the schema (after the transforms)
+    pub fn json_schema(mut self, json_schema: jsonschema::Validator) -> Self {
+        self.json_schema = Some(json_schema);
+        self
+    }
+
+    // cut the payload down to this many bytes, before the template and envelope are added
+    pub fn truncate_to_bytes(mut self, max_bytes: usize) -> Self {
+        self.truncate_to_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn payload_template(mut self, payload_template: PayloadTemplate) -> Self {
+        self.payload_template = Some(payload_template);
+        self
+    }
+
+    pub fn envelope(mut self, envelope: EnvelopeFormat) -> Self {
+        self.envelope = envelope;
+        self
+    }
+
+    // frame the payload in the schema registry wire format with this schema id
+    pub fn schema_id(mut self, schema_id: u32) -> Self {
+        self.schema_id = Some(schema_id);
+        self
+    }
+
+    pub fn envelope_format(&self) -> EnvelopeFormat {
+        self.envelope
+    }
+
+    pub fn has_json_schema(&self) -> bool {
+        self.json_schema.is_some()
+    }
+
+    // Run the transforms and the schema check
+    // the content as it should be before anything is wrapped around it
+    pub fn prepare(&self, raw: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut content = match &self.payload_path {
+            Some(payload_path) => payload_path.extract(raw)?,
+            None => raw.to_vec(),
+        };
+        for transform in &self.transforms {
+            content = transform(&content)?;
+        }
+        if let Some(json_schema) = &self.json_schema {
+            validate_json(json_schema, &content)?;
+        }
+        Ok(content)
+    }
+
+    // Run every step on the raw content of the file
+    // the template and the envelope take the file name (and modified time) from ctx
+    pub fn process(
+        &self,
+        ctx: &FileContext,
+        raw: &[u8],
+    ) -> Result<ProcessedContent, Box<dyn Error>> {
+        let raw = match self.compact_json {
+            CompactJson::Off => Cow::Borrowed(raw),
+            CompactJson::JsonFiles
+                if content_type::from_extension(&ctx.file_name) != Some("application/json") =>
+            {
+                Cow::Borrowed(raw)
+            }
+            CompactJson::JsonFiles | CompactJson::EveryFile => Cow::Owned(compact_json(raw)?),
+        };
+        let mut content = self.prepare(&raw)?;
+        // the template and the envelope are kept whole so they stay valid
+        let original_size = self
+            .truncate_to_bytes
+            .and_then(|max_bytes| truncate_payload(&mut content, max_bytes));
+        if let Some(payload_template) = &self.payload_template {
+            content = payload_template.render(&content, &ctx.file_name);
+        }
+        // the schema applies to the content, not to the envelope around it
+        if self.envelope == EnvelopeFormat::JsonEnvelope {
+            content = build_envelope(&ctx.file_name, &content, ctx.modified);
+        }
+        if let Some(schema_id) = self.schema_id {
+            content = schema_registry::frame(schema_id, &content);
+        }
+        Ok(ProcessedContent {
+            content,
+            original_size,
+        })
+    }
+}
+
+// Cut the payload down to at most max_bytes
+// text is cut at a character boundary so it stays valid UTF-8, anything else at the byte
+// the original size if the payload was longer
+pub fn truncate_payload(content: &mut Vec<u8>, max_bytes: usize) -> Option<usize> {
+    let original_size = content.len();
+    if original_size <= max_bytes {
+        return None;
+    }
+    let mut end = max_bytes;
+    if let Ok(text) = std::str::from_utf8(content) {
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+    }
+    content.truncate(end);
+    Some(original_size)
+}
+
+// The JSON without the whitespace between its tokens
+// the rest is kept as it is, so the key order and the numbers do not change
+// content that is not valid JSON fails
+pub fn compact_json(content: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    serde_json::from_slice::<serde::de::IgnoredAny>(content)
+        .map_err(|e| format!("File content is not valid JSON: {}", e))?;
+    let mut compacted = Vec::with_capacity(content.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in content {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+        } else if byte == b'"' {
+            in_string = true;
+        } else if matches!(byte, b' ' | b'\t' | b'\n' | b'\r') {
+            continue;
+        }
+        compacted.push(byte);
+    }
+    Ok(compacted)
+}
+
+pub fn load_json_schema(schema_path: &Path) -> Result<jsonschema::Validator, Box<dyn Error>> {
+    let schema_text = fs::read_to_string(schema_path)?;
+    let schema: serde_json::Value = serde_json::from_str(&schema_text)?;
+    Ok(jsonschema::validator_for(&schema)?)
+}
+
+// Check the content is JSON that conforms to the schema
+// the error names the JSON path of each violation
+fn validate_json(
+    json_schema: &jsonschema::Validator,
+    content: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    let document: serde_json::Value = serde_json::from_slice(content)
+        .map_err(|e| format!("File content is not valid JSON: {}", e))?;
+
+    let violations: Vec<String> = json_schema
+        .iter_errors(&document)
+        .map(|error| {
+            let json_path = error.instance_path().to_string();
+            let json_path = if json_path.is_empty() {
+                "/".to_string()
+            } else {
+                json_path
+            };
+            format!("{}: {}", json_path, error)
+        })
+        .collect();
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("JSON schema validation failed at {}", violations.join("; ")).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_encoding::BASE64;
+    use serde_json::Value;
+
+    #[test]
+    fn identity_processor_keeps_content() {
+        let ctx = FileContext::new(Path::new("in/data.bin"), Vec::new());
+        let raw = vec![0xff, 0x00, b'h', b'i'];
+        let content = ContentProcessor::new()
+            .process(&ctx, &raw)
+            .expect("processing failed");
+        assert_eq!(content.content, raw);
+        assert_eq!(content.original_size, None);
+    }
+
+    #[test]
+    fn transform_runs_before_envelope() {
+        let ctx = FileContext::new(Path::new("in/greeting.txt"), Vec::new());
+        let processor = ContentProcessor::new()
+            .transform(Box::new(|content| Ok(content.to_ascii_uppercase())))
+            .transform(Box::new(|content| Ok([content, b"!"].concat())))
+            .envelope(EnvelopeFormat::JsonEnvelope);
+
+        let content = processor
+            .process(&ctx, b"hello")
+            .expect("processing failed")
+            .content;
+        let envelope: Value = serde_json::from_slice(&content).expect("envelope is not JSON");
+        assert_eq!(envelope["file_name"], "greeting.txt");
+        assert_eq!(envelope["size"], 6);
+        let payload = envelope["payload_base64"].as_str().unwrap();
+        assert_eq!(BASE64.decode(payload.as_bytes()).unwrap(), b"HELLO!");
+    }
+
+    #[test]
+    fn compaction_keeps_strings_and_key_order() {
+        let raw = b"{\n  \"z\": \"a b\\\" c\",\n  \"a\": [1.50, 2e3]\n}\n";
+        assert_eq!(
+            compact_json(raw).unwrap(),
+            br#"{"z":"a b\" c","a":[1.50,2e3]}"#
+        );
+        let err = compact_json(b"{\"a\": ").unwrap_err().to_string();
+        assert!(err.contains("not valid JSON"), "{}", err);
+    }
+
+    #[test]
+    fn only_json_files_compacted() {
+        let processor = ContentProcessor::new().compact_json(CompactJson::JsonFiles);
+        let json = FileContext::new(Path::new("in/order.json"), Vec::new());
+        let text = FileContext::new(Path::new("in/order.txt"), Vec::new());
+        assert_eq!(processor.process(&json, b"{ }").unwrap().content, b"{}");
+        assert_eq!(processor.process(&text, b"{ }").unwrap().content, b"{ }");
+    }
+
+    #[test]
+    fn truncation_keeps_text_valid_utf8() {
+        // é is 2 bytes, cutting at 4 would split it
+        let mut text = "abcé-tail".as_bytes().to_vec();
+        assert_eq!(truncate_payload(&mut text, 4), Some(10));
+        assert_eq!(text, b"abc");
+
+        let mut binary = vec![0xff, 0xc3, 0xa9, 0x00];
+        assert_eq!(truncate_payload(&mut binary, 2), Some(4));
+        assert_eq!(binary, [0xff, 0xc3]);
+
+        let mut short = b"abc".to_vec();
+        assert_eq!(truncate_payload(&mut short, 3), None);
+        assert_eq!(short, b"abc");
+    }
+
+    #[test]
+    fn payload_path_keeps_string_text_and_other_values_as_json() {
+        let raw = br#"{"meta": {"source": "billing"}, "body": "hello", "items": [1, 2]}"#;
+        let body = PayloadPath::parse("$.body").unwrap();
+        let items = PayloadPath::parse("$.items").unwrap();
+        assert_eq!(body.extract(raw).unwrap(), b"hello");
+        assert_eq!(items.extract(raw).unwrap(), b"[1,2]");
+    }
+
+    #[test]
+    fn payload_path_fails_on_missing_field_or_non_json() {
+        let body = PayloadPath::parse("$.body").unwrap();
+        let err = body.extract(br#"{"meta": {}}"#).unwrap_err().to_string();
+        assert!(err.contains("does not match exactly one value"), "{}", err);
+        let err = body.extract(b"not json").unwrap_err().to_string();
+        assert!(err.contains("not valid JSON"), "{}", err);
+        assert!(PayloadPath::parse("body[").is_err());
+    }
+}
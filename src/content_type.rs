@@ -0,0 +1,115 @@
+/// The content-type header for published files
+///
+/// The MIME type comes from the file extension
+/// or, with magic detection, from the first bytes of the content
+/// so consumers can tell JSON from XML from binary without parsing the payload
+use std::path::Path;
+
+pub const CONTENT_TYPE_HEADER: &str = "content-type";
+
+// The type of a file nothing more is known about
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// How the content-type header is worked out
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContentTypeDetection {
+    // no content-type header
+    #[default]
+    Off,
+    // from the file extension
+    Extension,
+    // from the magic bytes of the content, falling back on the extension
+    Magic,
+}
+
+// The MIME type for a well-known file extension
+pub fn from_extension(file_name: &str) -> Option<&'static str> {
+    let extension = Path::new(file_name).extension()?.to_str()?;
+    let content_type = match extension.to_ascii_lowercase().as_str() {
+        "json" => "application/json",
+        "jsonl" | "ndjson" => "application/x-ndjson",
+        "xml" => "application/xml",
+        "avro" => "application/avro",
+        "csv" => "text/csv",
+        "txt" | "log" => "text/plain",
+        "html" | "htm" => "text/html",
+        "yaml" | "yml" => "application/yaml",
+        "pdf" => "application/pdf",
+        "gz" => "application/gzip",
+        "zip" => "application/zip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        _ => return None,
+    };
+    Some(content_type)
+}
+
+// Whether the MIME type is JSON, e.g. application/json; charset=utf-8 or application/cloudevents+json
+pub fn is_json(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+// The MIME type of the file, None when detection is off
+pub fn detect(
+    detection: ContentTypeDetection,
+    file_name: &str,
+    content: &[u8],
+) -> Option<&'static str> {
+    let content_type = match detection {
+        ContentTypeDetection::Off => return None,
+        ContentTypeDetection::Extension => from_extension(file_name),
+        ContentTypeDetection::Magic => infer::get(content)
+            .map(|kind| kind.mime_type())
+            .or_else(|| from_extension(file_name)),
+    };
+    Some(content_type.unwrap_or(DEFAULT_CONTENT_TYPE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_HEADER: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+    #[test]
+    fn detects_from_extension() {
+        let detect = |name| detect(ContentTypeDetection::Extension, name, b"");
+        assert_eq!(detect("order.json"), Some("application/json"));
+        assert_eq!(detect("order.XML"), Some("application/xml"));
+        assert_eq!(detect("order.bin"), Some(DEFAULT_CONTENT_TYPE));
+        assert_eq!(detect("order"), Some(DEFAULT_CONTENT_TYPE));
+    }
+
+    #[test]
+    fn magic_bytes_win_over_extension() {
+        assert_eq!(
+            detect(ContentTypeDetection::Magic, "image.bin", PNG_HEADER),
+            Some("image/png")
+        );
+        // text has no magic bytes so the extension decides
+        assert_eq!(
+            detect(ContentTypeDetection::Magic, "order.json", b"{}"),
+            Some("application/json")
+        );
+    }
+
+    #[test]
+    fn off_adds_no_content_type() {
+        assert_eq!(detect(ContentTypeDetection::Off, "order.json", b"{}"), None);
+    }
+
+    #[test]
+    fn recognises_json_types() {
+        assert!(is_json("application/json"));
+        assert!(is_json("Application/JSON; charset=utf-8"));
+        assert!(is_json("application/cloudevents+json"));
+        assert!(!is_json("application/x-ndjson"));
+        assert!(!is_json("text/plain"));
+    }
+}
@@ -0,0 +1,187 @@
+/// Skipping files that were already published in this run
+///
+/// A file is recognised by its path, modified time and size
+/// or by the SHA-256 of its content when upstream writes the same payload under new names
+/// the poller only remembers the most recent files it published, up to a capacity
+/// kept files can instead get a marker file next to them which costs no memory
+use crate::filesystem::FileSystem;
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// How many published files are remembered when no other capacity is given
+pub const DEFAULT_DEDUP_CAPACITY: usize = 10_000;
+
+/// How a file is recognised as one already published
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DedupMode {
+    // every file is published
+    #[default]
+    Off,
+    // the same path with the same modified time and size
+    PathMtimeSize,
+    // the same content under any name
+    ContentHash,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DedupKey {
+    File {
+        path: PathBuf,
+        modified: Option<SystemTime>,
+        size: u64,
+    },
+    Content([u8; 32]),
+}
+
+impl DedupMode {
+    // The key the file is remembered by, None when dedup is off
+    // the file is read from source (which differs from file_path while it is claimed)
+    pub fn key(
+        self,
+        file_system: &dyn FileSystem,
+        file_path: &Path,
+        source: &Path,
+    ) -> io::Result<Option<DedupKey>> {
+        match self {
+            DedupMode::Off => Ok(None),
+            DedupMode::PathMtimeSize => {
+                let metadata = file_system.metadata(source)?;
+                Ok(Some(DedupKey::File {
+                    path: file_path.to_path_buf(),
+                    modified: metadata.modified,
+                    size: metadata.len,
+                }))
+            }
+            DedupMode::ContentHash => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut file_system.open(source)?, &mut hasher)?;
+                Ok(Some(DedupKey::Content(hasher.finalize().into())))
+            }
+        }
+    }
+}
+
+// The marker next to a published file, e.g. order-1.json.done
+pub const VISITED_MARKER_EXTENSION: &str = ".done";
+
+/// Whether a kept file gets a marker once it is published, so it is skipped from then on
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VisitedMarkers {
+    #[default]
+    Off,
+    // only markers written since the run started count, the next run publishes the files again
+    Run,
+    // a file with a marker is never published again
+    Persistent,
+}
+
+impl VisitedMarkers {
+    pub fn marker_path(file_path: &Path) -> PathBuf {
+        let mut marker_path = file_path.as_os_str().to_owned();
+        marker_path.push(VISITED_MARKER_EXTENSION);
+        PathBuf::from(marker_path)
+    }
+
+    // Whether the file's marker shows it was already published
+    pub fn is_visited(
+        self,
+        file_system: &dyn FileSystem,
+        file_path: &Path,
+        run_started: SystemTime,
+    ) -> bool {
+        let Ok(metadata) = file_system.metadata(&Self::marker_path(file_path)) else {
+            return false;
+        };
+        match self {
+            VisitedMarkers::Off => false,
+            VisitedMarkers::Run => metadata
+                .modified
+                .is_some_and(|modified| modified >= run_started),
+            VisitedMarkers::Persistent => true,
+        }
+    }
+
+    // Write the marker, holding the time the file was published
+    // (the content also makes rewriting a stale marker update its modified time)
+    pub fn mark(
+        self,
+        file_system: &dyn FileSystem,
+        file_path: &Path,
+        published: SystemTime,
+    ) -> io::Result<()> {
+        let published = humantime::format_rfc3339_millis(published);
+        file_system.write(
+            &Self::marker_path(file_path),
+            format!("{}\n", published).as_bytes(),
+        )
+    }
+}
+
+/// The most recently published files, the oldest is forgotten once the capacity is reached
+pub struct PublishedFiles {
+    capacity: usize,
+    order: VecDeque<DedupKey>,
+    keys: HashSet<DedupKey>,
+}
+
+impl PublishedFiles {
+    pub fn new(capacity: usize) -> Self {
+        PublishedFiles {
+            capacity,
+            order: VecDeque::new(),
+            keys: HashSet::new(),
+        }
+    }
+
+    pub fn contains(&self, key: &DedupKey) -> bool {
+        self.keys.contains(key)
+    }
+
+    pub fn insert(&mut self, key: DedupKey) {
+        if self.capacity == 0 || !self.keys.insert(key.clone()) {
+            return;
+        }
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::RealFileSystem;
+
+    #[test]
+    fn oldest_file_forgotten_at_capacity() {
+        let mut published = PublishedFiles::new(2);
+        for digest in [1, 2, 3] {
+            published.insert(DedupKey::Content([digest; 32]));
+        }
+        assert!(!published.contains(&DedupKey::Content([1; 32])));
+        assert!(published.contains(&DedupKey::Content([2; 32])));
+        assert!(published.contains(&DedupKey::Content([3; 32])));
+    }
+
+    #[test]
+    fn run_markers_from_an_earlier_run_do_not_count() {
+        let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+        let file_path = temp_dir.path().join("order-1.json");
+        VisitedMarkers::Persistent
+            .mark(&RealFileSystem, &file_path, SystemTime::now())
+            .expect("writing marker failed");
+
+        let run_started = SystemTime::now() + std::time::Duration::from_secs(60);
+        let fs = &RealFileSystem;
+        assert!(VisitedMarkers::Persistent.is_visited(fs, &file_path, run_started));
+        assert!(!VisitedMarkers::Run.is_visited(fs, &file_path, run_started));
+        assert!(VisitedMarkers::Run.is_visited(fs, &file_path, SystemTime::UNIX_EPOCH));
+        assert!(!VisitedMarkers::Off.is_visited(fs, &file_path, SystemTime::UNIX_EPOCH));
+    }
+}
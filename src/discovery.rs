@@ -0,0 +1,123 @@
+/// Finding the brokers from a DNS SRV record (--broker-srv)
+///
+/// Behind a headless Kubernetes service the SRV record lists a host and port
+/// for each broker pod, so the bootstrap list follows the pods as they change
+/// the record is looked up each time a client configuration is built
+/// and again by the producer when it starts retrying a lost connection
+use hickory_resolver::Resolver;
+use std::error::Error;
+
+/// One target of an SRV record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    // the host name, with or without the trailing dot
+    pub target: String,
+}
+
+/// Looks up the targets of an SRV record by name
+/// in the order DNS returns them, bootstrap_servers sorts them by preference
+pub trait SrvResolver {
+    fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, Box<dyn Error>>;
+}
+
+/// SrvResolver using the system's DNS configuration (resolv.conf)
+pub struct DnsResolver;
+
+impl SrvResolver for DnsResolver {
+    fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, Box<dyn Error>> {
+        let resolver = Resolver::from_system_conf()?;
+        let records = resolver
+            .srv_lookup(name)?
+            .iter()
+            .map(|srv| SrvRecord {
+                priority: srv.priority(),
+                weight: srv.weight(),
+                port: srv.port(),
+                target: srv.target().to_utf8(),
+            })
+            .collect();
+        Ok(records)
+    }
+}
+
+// The bootstrap list (host:port,...) from the SRV record
+// the most preferred targets come first: lowest priority, then highest weight
+pub fn bootstrap_servers(resolver: &dyn SrvResolver, name: &str) -> Result<String, Box<dyn Error>> {
+    let mut records = resolver
+        .lookup_srv(name)
+        .map_err(|e| format!("Failed to look up SRV record {}: {}", name, e))?;
+    if records.is_empty() {
+        return Err(format!("SRV record {} has no targets", name).into());
+    }
+    records.sort_by(|a, b| {
+        a.priority
+            .cmp(&b.priority)
+            .then(b.weight.cmp(&a.weight))
+            .then(a.target.cmp(&b.target))
+    });
+    let mut servers: Vec<String> = Vec::new();
+    for record in records {
+        let server = format!("{}:{}", record.target.trim_end_matches('.'), record.port);
+        if !servers.contains(&server) {
+            servers.push(server);
+        }
+    }
+    Ok(servers.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers every lookup with the same records
+    struct MockResolver {
+        records: Vec<SrvRecord>,
+    }
+
+    impl SrvResolver for MockResolver {
+        fn lookup_srv(&self, _name: &str) -> Result<Vec<SrvRecord>, Box<dyn Error>> {
+            Ok(self.records.clone())
+        }
+    }
+
+    fn record(priority: u16, weight: u16, target: &str) -> SrvRecord {
+        SrvRecord {
+            priority,
+            weight,
+            port: 9092,
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn bootstrap_list_built_from_srv_records() {
+        let resolver = MockResolver {
+            records: vec![
+                record(20, 0, "kafka-2.kafka-headless."),
+                record(10, 5, "kafka-1.kafka-headless."),
+                record(10, 50, "kafka-0.kafka-headless."),
+                record(10, 50, "kafka-0.kafka-headless."),
+            ],
+        };
+        let bootstrap =
+            bootstrap_servers(&resolver, "_kafka._tcp.kafka-headless").expect("discovery failed");
+        assert_eq!(
+            bootstrap,
+            "kafka-0.kafka-headless:9092,kafka-1.kafka-headless:9092,kafka-2.kafka-headless:9092"
+        );
+    }
+
+    #[test]
+    fn srv_record_without_targets_fails() {
+        let resolver = MockResolver {
+            records: Vec::new(),
+        };
+        let err = bootstrap_servers(&resolver, "_kafka._tcp.kafka-headless")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("has no targets"), "{}", err);
+    }
+}
@@ -0,0 +1,20 @@
+/// Free space on the file system of the polled directories
+///
+/// The poller asks the provider before each cycle
+/// so tests can swap in a fake one that reports a full disk
+use std::io;
+use std::path::Path;
+
+pub trait DiskSpace: Send + Sync {
+    // The bytes available to this process on the file system holding the path
+    fn available_bytes(&self, path: &Path) -> io::Result<u64>;
+}
+
+/// The free space the OS reports
+pub struct SystemDiskSpace;
+
+impl DiskSpace for SystemDiskSpace {
+    fn available_bytes(&self, path: &Path) -> io::Result<u64> {
+        fs2::available_space(path)
+    }
+}
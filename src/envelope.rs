@@ -0,0 +1,56 @@
+/// Publishing a file as a JSON envelope
+///
+/// Instead of the raw bytes the message is a JSON object with the file metadata
+/// and the content base64 encoded so binary files survive the trip
+use data_encoding::BASE64;
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What the poller publishes for each file
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnvelopeFormat {
+    // the file content as it is
+    #[default]
+    Raw,
+    // { "file_name", "size", "modified", "payload_base64" }
+    JsonEnvelope,
+}
+
+// The envelope for a file
+// modified is in seconds since the epoch (null when the file system does not know it)
+pub fn build_envelope(file_name: &str, content: &[u8], modified: Option<SystemTime>) -> Vec<u8> {
+    let modified = modified
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs());
+
+    json!({
+        "file_name": file_name,
+        "size": content.len(),
+        "modified": modified,
+        "payload_base64": BASE64.encode(content),
+    })
+    .to_string()
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::time::Duration;
+
+    #[test]
+    fn envelope_decodes_to_content() {
+        let content = vec![0xff, 0x00, b'h', b'i'];
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let envelope: Value =
+            serde_json::from_slice(&build_envelope("data.bin", &content, Some(modified)))
+                .expect("envelope is not JSON");
+        assert_eq!(envelope["file_name"], "data.bin");
+        assert_eq!(envelope["size"], 4);
+        assert_eq!(envelope["modified"], 1_700_000_000);
+        let payload = envelope["payload_base64"].as_str().unwrap();
+        assert_eq!(BASE64.decode(payload.as_bytes()).unwrap(), content);
+    }
+}
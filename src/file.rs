@@ -1,247 +1,2751 @@
+use crate::batch::{Batch, BatchLimits};
+use crate::budget::{ByteBudget, RetryBudget};
+use crate::clock::Clock;
+use crate::content::{CompactJson, ContentProcessor};
+use crate::content_type::ContentTypeDetection;
+use crate::dedup::{DedupMode, PublishedFiles, VisitedMarkers, VISITED_MARKER_EXTENSION};
+use crate::disk::DiskSpace;
+use crate::envelope::EnvelopeFormat;
+use crate::filesystem::FileSystem;
+use crate::lock::{DirectoryLock, DEFAULT_LOCK_FILE};
+use crate::overrides::DirectoryOverrides;
+use crate::path_template::PathTemplate;
+use crate::rate::RateLimiter;
+use crate::sink::{FileContext, FileSink, PublishOutcome, SinkError};
+use crate::status::{SharedStatus, Status};
+use crate::watch::DirectoryWatcher;
+use data_encoding::HEXLOWER_PERMISSIVE;
+use rand::Rng;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
 use std::error::Error;
 /// File reading and directory polling
 /// Reference: DefaultDirectoryPollingService
 /// Reference: DefaultKafkaProducerUtil
 use std::fs;
-use std::path::Path;
+use std::io::{BufReader, Read};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-// Type alias for file content callback
-// Need to wrap closure in Box to allocate on the heap
-// and use dynamic dispatch since we don't know the closure at compile time
-// allowing us to use any closure that matches the signature
-type FileContentCallback = Box<dyn Fn(&str) -> Result<(), Box<dyn Error>>>;
+mod builder;
+mod routing;
+mod transaction;
+
+pub use builder::{BuildError, DirectoryPollerBuilder};
+#[cfg(feature = "async")]
+use routing::mark_truncated;
+use transaction::PendingFile;
+
+// Type alias for a callback that streams the file itself
+// instead of the poller loading the whole file into memory
+type FileReaderCallback = Box<dyn Fn(&mut dyn Read) -> Result<(), Box<dyn Error>>>;
+
+// Type alias for a callback that picks the topic and key for each file
+type FileRouteCallback = Box<dyn Fn(&FileContext) -> Result<PublishOutcome, Box<dyn Error>>>;
+
+// Type alias for a callback run once at the end of every poll cycle
+type CycleCallback = Box<dyn Fn(&CycleStats) -> Result<(), Box<dyn Error>>>;
+
+// Type alias for a callback handed the stats after every cycle and once more at shutdown
+type StatsCallback = Box<dyn Fn(&PollStats, Option<&CycleStats>)>;
+
+/// The order files are processed in within a directory
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileOrder {
+    // whatever order the file system lists them in
+    #[default]
+    Unsorted,
+    // by file name
+    Name,
+    // oldest modification time first
+    Oldest,
+    // newest modification time first, with file_ttl to drop the backlog older than a window
+    Newest,
+}
+
+/// How the message key is derived from the file
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyStrategy {
+    // messages have no key
+    #[default]
+    None,
+    // the file name e.g. order-123.json
+    FileName,
+    // the file name without its extension e.g. order-123
+    FileStem,
+}
+
+/// How the key string (from the key strategy or a sidecar) is turned into bytes
+/// the numeric encodings match a Java producer using LongSerializer or IntegerSerializer
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyEncoding {
+    // the string as it is
+    #[default]
+    Utf8,
+    // a 64-bit integer, 8 bytes big-endian
+    I64be,
+    // a 32-bit integer, 4 bytes big-endian
+    I32be,
+    // hex digits decoded to the bytes they spell
+    Hex,
+}
+
+impl KeyEncoding {
+    pub fn encode(self, key: &str) -> Result<Vec<u8>, String> {
+        match self {
+            KeyEncoding::Utf8 => Ok(key.as_bytes().to_vec()),
+            KeyEncoding::I64be => key
+                .parse::<i64>()
+                .map(|key| key.to_be_bytes().to_vec())
+                .map_err(|e| format!("Key {:?} is not a 64-bit integer: {}", key, e)),
+            KeyEncoding::I32be => key
+                .parse::<i32>()
+                .map(|key| key.to_be_bytes().to_vec())
+                .map_err(|e| format!("Key {:?} is not a 32-bit integer: {}", key, e)),
+            KeyEncoding::Hex => HEXLOWER_PERMISSIVE
+                .decode(key.as_bytes())
+                .map_err(|e| format!("Key {:?} is not hex: {}", key, e)),
+        }
+    }
+}
+
+// The checksum file next to a payload e.g. order-123.json.sha256
+const CHECKSUM_EXTENSION: &str = ".sha256";
+
+// The record next to a file in the error directory of the directory it failed in
+// e.g. order-123.json.source-dir, so a retry puts the file back there
+const SOURCE_DIR_EXTENSION: &str = ".source-dir";
+
+// Files with this extension publish a tombstone for their key
+// (when tombstones are enabled)
+const TOMBSTONE_EXTENSION: &str = ".delete";
+
+// How often a paused poller checks whether it can resume
+const PAUSE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+// and how often it logs that it is still paused
+const PAUSE_HEARTBEAT: Duration = Duration::from_secs(60);
+
+// How often a poller that is low on disk space checks again
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const MB: u64 = 1024 * 1024;
+
+// How often a directory that fails to read is listed again before the cycle is skipped
+const DEFAULT_DIRECTORY_READ_RETRIES: u32 = 3;
+const DEFAULT_DIRECTORY_READ_BACKOFF: Duration = Duration::from_millis(100);
+
+// The longest wait between two attempts, however often the backoff doubled
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// What the poller does with a zero-byte file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyFilePolicy {
+    // send it like any other file
+    #[default]
+    Process,
+    // leave it out of the sink (it is still deleted if deletion is enabled)
+    Skip,
+    // treat it as a failed file
+    Error,
+}
+
+/// What the poller does with a file whose name does not match the required name regex
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameMismatchPolicy {
+    // treat it as a failed file (moved to the error directory if there is one)
+    #[default]
+    Reject,
+    // leave it where it is with a warning
+    Skip,
+}
+
+/// What the poller does with a file that is not valid UTF-8
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Utf8Policy {
+    // send the bytes as they are, a text sink (on_file_content) fails the file
+    #[default]
+    Strict,
+    // replace the invalid bytes with U+FFFD and send it with a warning
+    Lossy,
+    // leave it out of the sink like a skipped empty file
+    Skip,
+}
+
+impl Utf8Policy {
+    // The content to send under the policy, None to skip the file
+    fn apply(self, name: &str, content: Vec<u8>) -> Option<Vec<u8>> {
+        if self == Utf8Policy::Strict {
+            return Some(content);
+        }
+        match String::from_utf8(content) {
+            Ok(text) => Some(text.into_bytes()),
+            Err(e) if self == Utf8Policy::Lossy => {
+                log::warn!(
+                    "File {:?} is not valid UTF-8, replacing the invalid bytes: {}",
+                    name,
+                    e.utf8_error()
+                );
+                Some(
+                    String::from_utf8_lossy(e.as_bytes())
+                        .into_owned()
+                        .into_bytes(),
+                )
+            }
+            Err(e) => {
+                log::warn!(
+                    "Skipping file {:?}, it is not valid UTF-8: {}",
+                    name,
+                    e.utf8_error()
+                );
+                None
+            }
+        }
+    }
+}
+
+// The exit codes of the executable, one per class of failure
+//   0  polling finished
+//   1  anything else that stopped the utility (e.g. the status port was taken)
+//   2  invalid arguments or configuration (clap's own usage errors also exit with 2)
+//   3  a directory or file could not be read, locked or moved
+//   4  the brokers could not be reached (or the producer could not be set up)
+//   5  a file could not be processed (a sink, callback or check failed)
+//   6  no file arrived within the expected interval (with exit_on_silence)
+pub const EXIT_USAGE: u8 = 2;
+pub const EXIT_DIRECTORY: u8 = 3;
+pub const EXIT_CONNECTION: u8 = 4;
+pub const EXIT_PROCESSING: u8 = 5;
+pub const EXIT_SILENCE: u8 = 6;
+
+/// Why polling stopped
+#[derive(Debug, thiserror::Error)]
+pub enum PollError {
+    #[error("Directory does not exist: {}", .0.display())]
+    DirectoryNotFound(PathBuf),
+    #[error("Path is not a directory: {}", .0.display())]
+    NotADirectory(PathBuf),
+    #[error("Failed to read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    // the sink or a callback failed, or the file did not pass a check
+    #[error("{0}")]
+    Callback(Box<dyn Error>),
+    // the sink could not reach its destination (after any retries)
+    #[error("{0}")]
+    Connection(Box<dyn Error>),
+    // a failed file could not be moved out of the polled directory
+    #[error("Failed to move file {} to the error directory: {source}", path.display())]
+    Delete {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    // another instance is polling the directory
+    #[error("Lock file {} is held by {holder}, is another instance polling the directory?", path.display())]
+    Locked { path: PathBuf, holder: String },
+    #[error("Manifest entry {entry:?} {reason}")]
+    ManifestEntry { entry: String, reason: &'static str },
+    // the watchdog found no file processed for longer than the expected interval
+    #[error("No files processed for {} s, is the upstream writer running?", .0.as_secs())]
+    Silent(Duration),
+    #[error(transparent)]
+    Build(#[from] BuildError),
+}
+
+impl PollError {
+    // The exit code for the class of failure
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            PollError::DirectoryNotFound(_)
+            | PollError::NotADirectory(_)
+            | PollError::Read { .. }
+            | PollError::Delete { .. }
+            | PollError::Locked { .. } => EXIT_DIRECTORY,
+            PollError::Connection(_) => EXIT_CONNECTION,
+            PollError::Callback(_) | PollError::ManifestEntry { .. } => EXIT_PROCESSING,
+            PollError::Silent(_) => EXIT_SILENCE,
+            PollError::Build(_) => EXIT_USAGE,
+        }
+    }
+}
+
+// Errors from callbacks (and the poller's own checks) are Box<dyn Error>
+// the PollError is recovered when that is what was boxed (inside a SinkError too)
+impl From<Box<dyn Error>> for PollError {
+    fn from(error: Box<dyn Error>) -> Self {
+        let error = match error.downcast::<SinkError>() {
+            Ok(sink_error) => sink_error.into_inner(),
+            Err(error) => error,
+        };
+        match error.downcast::<PollError>() {
+            Ok(error) => *error,
+            Err(error) => PollError::Callback(error),
+        }
+    }
+}
+
+// A file published during the warmup, deleted once the warmup is over
+struct WarmupFile {
+    file_path: PathBuf,
+    name: String,
+    // published again instead if it is rewritten in the meantime
+    modified: Option<SystemTime>,
+}
+
+// The stages of processing a file that are timed (and traced)
+#[derive(Debug, Clone, Copy)]
+enum Stage {
+    Read,
+    Transform,
+    Publish,
+    Delivery,
+    Delete,
+}
+
+impl Stage {
+    // span names have to be known at compile time
+    #[cfg(feature = "tracing")]
+    fn span(self) -> tracing::Span {
+        match self {
+            Stage::Read => tracing::info_span!("read"),
+            Stage::Transform => tracing::info_span!("transform"),
+            Stage::Publish => tracing::info_span!("publish"),
+            Stage::Delivery => tracing::info_span!("delivery"),
+            Stage::Delete => tracing::info_span!("delete"),
+        }
+    }
+}
+
+// What happened to a file the poller picked up
+enum FileOutcome {
+    // the number of bytes handed to the sink
+    Processed(u64),
+    Skipped,
+    // another poller claimed the file first
+    NotClaimed,
+    // the file was gone by the time it was read, another process likely took it
+    Vanished,
+}
 
 pub struct DirectoryPoller {
     keep_running: bool,
+    until_empty: bool,
     delete_files: bool,
     poll_interval_millis: u64,
+    poll_jitter_millis: u64,
+    fixed_rate: bool,
+    backoff_on_empty: bool,
+    max_backoff_millis: u64,
     max_poll_cycles: i32,
-    on_file_content: FileContentCallback,
+    delete_after_cycles: i32,
+    max_files_per_cycle: usize,
+    max_runtime: Duration,
+    flush_interval_millis: u64,
+    ordered: bool,
+    control_topic: Option<String>,
+    watch: bool,
+    heartbeat_every: u32,
+    // None leaves the message out
+    empty_cycle_log_level: Option<log::Level>,
+    file_log_level: Option<log::Level>,
+    cycle_log_level: Option<log::Level>,
+    file_order: FileOrder,
+    recursive: bool,
+    topic_from_subdir: bool,
+    path_template: Option<PathTemplate>,
+    modified_since: Option<SystemTime>,
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+    // the settings of the directories that override the global ones
+    directory_settings: Vec<DirectorySettings>,
+    empty_file_policy: EmptyFilePolicy,
+    // every file name has to match this, misbehaving producers are flagged rather than published
+    require_name_regex: Option<Regex>,
+    name_mismatch_policy: NameMismatchPolicy,
+    utf8_policy: Utf8Policy,
+    dedup_mode: DedupMode,
+    visited_markers: VisitedMarkers,
+    file_ttl: Duration,
+    key_strategy: KeyStrategy,
+    strip_extension: bool,
+    key_encoding: KeyEncoding,
+    tombstone_on_empty: bool,
+    content_type_detection: ContentTypeDetection,
+    content_type: Option<String>,
+    sidecar_extension: Option<String>,
+    verify_checksum: bool,
+    require_checksum: bool,
+    // pass over files whose name starts with a dot (editor swap files, partial writes)
+    skip_hidden: bool,
+    claim_suffix: Option<String>,
+    error_directory: Option<PathBuf>,
+    // move the files in the error directory back to be published again when polling starts
+    retry_errors_on_startup: bool,
+    // only those modified this recently, zero retries every file
+    retry_errors_max_age: Duration,
+    checkpoint_file: Option<PathBuf>,
+    manifest: Option<PathBuf>,
+    strict_manifest: bool,
+    // how often listing a directory that failed to read is tried again within the cycle
+    directory_read_retries: u32,
+    directory_read_backoff: Duration,
+    // fail the poll once the retries are used up instead of skipping the cycle
+    strict_directory_read: bool,
+    lock_file: Option<PathBuf>,
+    // also lock the default lock file in each polled directory
+    lock_directories: bool,
+    sink: Box<dyn FileSink>,
+    on_file_reader: Option<FileReaderCallback>,
+    on_file_route: Option<FileRouteCallback>,
+    on_cycle_complete: Option<CycleCallback>,
+    on_stats: Option<StatsCallback>,
+    content: ContentProcessor,
+    // publish this many files (or bytes) as one message
+    batch_limits: Option<BatchLimits>,
+    // the files published so far as the next batch
+    batch: RefCell<Batch>,
+    rate_limiter: RateLimiter,
+    shutdown_flag: Arc<AtomicBool>,
+    pause_flag: Arc<AtomicBool>,
+    inflight_budget: Option<Arc<ByteBudget>>,
+    // how often a send that failed with SinkError::Retriable is tried again
+    send_retries: u32,
+    send_retry_backoff: Duration,
+    // retries shared with the rest of the run, unlimited when None
+    retry_budget: Option<Arc<RetryBudget>>,
+    min_free_disk_mb: u64,
+    disk_space: Arc<dyn DiskSpace>,
+    expected_interval: Duration,
+    exit_on_silence: bool,
+    // files sent to the sink that are deleted once a flush confirms them
+    pending_files: RefCell<Vec<PendingFile>>,
+    // the cycles started over the life of the poller (across poll_directory calls)
+    cycles_started: Cell<i32>,
+    warmup_files: RefCell<Vec<WarmupFile>>,
+    // the stage times of the poll in progress
+    stage_times: Cell<StageTimes>,
+    // a transactional sink has a transaction open for the cycle
+    transaction_open: Cell<bool>,
+    // when the watchdog last saw a file processed (or polling started)
+    last_file_at: Cell<Option<Instant>>,
+    // the silence has been reported, until the next file
+    silence_reported: Cell<bool>,
+    // the files published so far, for dedup_mode
+    published_files: RefCell<PublishedFiles>,
+    // when polling started, older run markers do not count
+    run_started: Cell<SystemTime>,
+    clock: Arc<dyn Clock>,
+    file_system: Arc<dyn FileSystem>,
+    // the options poll_directory_async refuses to run with
+    #[cfg(feature = "async")]
+    blocking_only_options: Vec<&'static str>,
+    status: Option<SharedStatus>,
+}
+
+impl DirectoryPoller {
+    pub fn builder() -> DirectoryPollerBuilder {
+        DirectoryPollerBuilder::new()
+    }
+
+    // Poll directory for files
+    pub fn poll_directory<P: AsRef<Path>>(&self, directory: P) -> Result<PollStats, PollError> {
+        self.poll_directories(&[directory])
+    }
+
+    // Poll several directories for files
+    // each poll cycle visits the directories in the given (priority) order
+    pub fn poll_directories<P: AsRef<Path>>(
+        &self,
+        directories: &[P],
+    ) -> Result<PollStats, PollError> {
+        let directory_paths: Vec<&Path> = directories.iter().map(AsRef::as_ref).collect();
+
+        // Validate every directory exists and is a directory
+        // and fail fast if one is not valid
+        // to avoid repeated attempts in next poll cycle
+        for directory_path in &directory_paths {
+            self.verify_directory(directory_path)?;
+        }
+        // released when polling stops
+        let _locks = self.acquire_locks(&directory_paths)?;
+
+        self.retry_error_files(&directory_paths);
+
+        let mut stats = PollStats::new(&directory_paths);
+        self.stage_times.take();
+        self.run_started.set(self.clock.wall_time());
+        let mut keep_running = true;
+        let mut empty_cycles = 0;
+        let mut cycle_failed = false;
+        let mut last_flush = self.clock.now();
+        self.last_file_at.set(Some(self.clock.now()));
+        let deadline = self.deadline();
+        let watcher = self.watch_directories(&directory_paths);
+
+        'polling: while keep_running {
+            if !self.wait_while_paused(deadline)
+                || !self.wait_for_disk_space(&directory_paths, deadline)
+            {
+                break 'polling;
+            }
+            self.cycles_started.set(self.cycles_started.get() + 1);
+            self.end_warmup();
+            self.begin_transaction()?;
+            cycle_failed = false;
+            self.update_status(|status| {
+                status.running = true;
+                status.current_cycle = stats.cycles + 1;
+            });
+            let mut file_count = 0;
+            let cycle = CycleStart::new(&stats, self.clock.now());
+            'cycle: for (index, directory_path) in directory_paths.iter().enumerate() {
+                log::info!("Polling directory: {}", directory_path.display());
+                self.update_status(|status| {
+                    status.current_directory = Some(directory_path.to_path_buf())
+                });
+
+                let files = match self.enumerate_files_with_retries(directory_path) {
+                    Ok(files) => files,
+                    // the next cycle may well read the directory again
+                    Err(e @ PollError::Read { .. }) if !self.strict_directory_read => {
+                        log::error!("Skipping the rest of the poll cycle: {}", e);
+                        cycle_failed = true;
+                        break 'cycle;
+                    }
+                    Err(e) => {
+                        self.settle_transaction(false);
+                        return Err(e);
+                    }
+                };
+                for file_path in files {
+                    // still waiting for the sink to confirm an earlier send
+                    // or already published during the warmup
+                    if self.is_pending(&file_path) || self.is_held_for_warmup(&file_path) {
+                        continue;
+                    }
+                    if self.is_cycle_full(file_count) {
+                        break 'cycle;
+                    }
+                    // stop between files when shutting down
+                    // waiting on the rate limit can also be cut short
+                    if !self.wait_for_permit() {
+                        log::info!("Shutdown requested, stopping poll loop");
+                        break 'polling;
+                    }
+                    if self.is_past(deadline) {
+                        log::info!("Maximum runtime reached, stopping poll loop");
+                        break 'polling;
+                    }
+
+                    file_count += 1;
+                    match self.claim_and_process_file(directory_path, &file_path) {
+                        Ok(FileOutcome::Processed(bytes)) => {
+                            stats.record_file(index, bytes);
+                            self.write_checkpoint(&file_path);
+                            self.reset_watchdog();
+                        }
+                        Ok(FileOutcome::Skipped) => stats.files_skipped += 1,
+                        Ok(FileOutcome::Vanished) => stats.files_vanished += 1,
+                        Ok(FileOutcome::NotClaimed) => {}
+                        Err(e) => {
+                            cycle_failed = true;
+                            if let Err(e) = self.handle_failed_file(directory_path, &file_path, e) {
+                                self.settle_transaction(false);
+                                return Err(e);
+                            }
+                            stats.files_failed += 1;
+                        }
+                    }
+                }
+            }
+
+            // end of poll cycle
+            // a batch is never held over to the next cycle
+            self.flush_batch();
+            stats.cycles += 1;
+            stats.stage_times = self.stage_times.get();
+            self.log_cycle(&stats, &cycle);
+            // the marker (and any alert) is part of the cycle's transaction
+            self.publish_cycle_marker(&stats, &cycle);
+            let silence = self.check_watchdog();
+            self.settle_transaction(!cycle_failed);
+            silence?;
+            self.update_status(|status| status.last_cycle = Some(stats.clone()));
+            let cycle_stats = cycle.stats(&stats, self.clock.now());
+            self.report_stats(&stats, Some(&cycle_stats));
+            self.complete_cycle(&cycle_stats)?;
+            if self.flush_interval_millis > 0
+                && self.clock.now().saturating_duration_since(last_flush)
+                    >= Duration::from_millis(self.flush_interval_millis)
+            {
+                if let Err(e) = self.flush_sink() {
+                    log::warn!("Failed to flush sink: {}", e);
+                }
+                last_flush = self.clock.now();
+            }
+            if file_count == 0 {
+                empty_cycles += 1;
+                self.log_empty_cycle(empty_cycles);
+            } else {
+                empty_cycles = 0;
+            }
+
+            keep_running = self.should_continue_polling(
+                stats.cycles,
+                empty_cycles,
+                cycle.started,
+                deadline,
+                watcher.as_ref(),
+            );
+        }
+
+        // a shutdown in the middle of a cycle commits the files published so far
+        self.flush_batch();
+        self.settle_transaction(!cycle_failed);
+        self.update_status(|status| {
+            status.running = false;
+            status.current_directory = None;
+        });
+
+        // always flush when the poller stops
+        // so files still waiting on the sink are deleted, or kept if it cannot confirm them
+        if let Err(e) = self.flush_sink() {
+            log::error!("Failed to flush sink: {}", e);
+        }
+        stats.stage_times = self.stage_times.get();
+        self.report_stats(&stats, None);
+
+        Ok(stats)
+    }
+
+    // The settings the poller was built with
+    pub fn config(&self) -> PollerConfig {
+        let patterns = |patterns: &[glob::Pattern]| {
+            patterns
+                .iter()
+                .map(|pattern| pattern.as_str().to_string())
+                .collect()
+        };
+        PollerConfig {
+            keep_running: self.keep_running,
+            until_empty: self.until_empty,
+            poll_interval_millis: self.poll_interval_millis,
+            poll_jitter_millis: self.poll_jitter_millis,
+            fixed_rate: self.fixed_rate,
+            watch: self.watch,
+            backoff_on_empty: self.backoff_on_empty,
+            max_poll_cycles: self.max_poll_cycles,
+            delete_after_cycles: self.delete_after_cycles,
+            max_files_per_cycle: self.max_files_per_cycle,
+            max_inflight_bytes: self.inflight_budget.as_ref().map(|budget| budget.total()),
+            min_free_disk_mb: self.min_free_disk_mb,
+            expected_interval: self.expected_interval,
+            max_runtime: self.max_runtime,
+            flush_interval_millis: self.flush_interval_millis,
+            ordered: self.ordered,
+            delete_files: self.delete_files,
+            error_directory: self.error_directory.clone(),
+            retry_errors_on_startup: self.retry_errors_on_startup,
+            skip_hidden: self.skip_hidden,
+            claim_suffix: self.claim_suffix.clone(),
+            recursive: self.recursive,
+            topic_from_subdir: self.topic_from_subdir,
+            path_template: self
+                .path_template
+                .as_ref()
+                .map(|path_template| path_template.as_str().to_string()),
+            file_order: self.file_order,
+            include: patterns(&self.include),
+            exclude: patterns(&self.exclude),
+            manifest: self.manifest.clone(),
+            lock_file: self.lock_file.clone(),
+            lock_directories: self.lock_directories,
+            modified_since: self.modified_since,
+            file_ttl: self.file_ttl,
+            empty_file_policy: self.empty_file_policy,
+            require_name_regex: self
+                .require_name_regex
+                .as_ref()
+                .map(|regex| regex.as_str().to_string()),
+            name_mismatch_policy: self.name_mismatch_policy,
+            utf8_policy: self.utf8_policy,
+            dedup_mode: self.dedup_mode,
+            visited_markers: self.visited_markers,
+            key_strategy: self.key_strategy,
+            strip_extension: self.strip_extension,
+            key_encoding: self.key_encoding,
+            envelope: self.content.envelope_format(),
+            content_type_detection: self.content_type_detection,
+            json_schema: self.content.has_json_schema(),
+            compact_json: self.content.compact_json_mode(),
+            verify_checksum: self.verify_checksum,
+            control_topic: self.control_topic.clone(),
+            content_type: self.content_type.clone(),
+            batch: self.batch_limits.clone(),
+        }
+    }
+
+    // The settings on one line for the startup log
+    pub fn config_summary(&self) -> String {
+        format!("{:?}", self.config())
+    }
+
+    // The files a poll cycle would process in the directory
+    // in the order they would be processed
+    // only regular files that pass the include/exclude globs are returned
+    pub fn enumerate_files(&self, directory: &Path) -> Result<Vec<PathBuf>, PollError> {
+        if let Some(manifest) = &self.manifest {
+            return self.manifest_files(directory, manifest);
+        }
+        let mut files = Vec::new();
+        self.collect_files(directory, &mut files)?;
+
+        match self.file_order {
+            FileOrder::Unsorted => {}
+            FileOrder::Name => files.sort(),
+            FileOrder::Oldest => {
+                // a file we cannot stat sorts first and fails when it is processed
+                files.sort_by_cached_key(|file_path| self.modified_time(file_path));
+            }
+            FileOrder::Newest => {
+                // a file we cannot stat sorts last
+                files.sort_by_cached_key(|file_path| Reverse(self.modified_time(file_path)));
+            }
+        }
+        Ok(files)
+    }
+
+    // List the directory, listing it again while reading it fails
+    // (e.g. a network file system that drops out for a moment) and there are retries left
+    fn enumerate_files_with_retries(&self, directory: &Path) -> Result<Vec<PathBuf>, PollError> {
+        let mut backoff = self.directory_read_backoff;
+        let mut retries = 0;
+        loop {
+            let error = match self.enumerate_files(directory) {
+                Ok(files) => return Ok(files),
+                Err(e) => e,
+            };
+            if retries >= self.directory_read_retries || !matches!(error, PollError::Read { .. }) {
+                return Err(error);
+            }
+
+            retries += 1;
+            log::warn!(
+                "{}, retrying in {} ms ({}/{})",
+                error,
+                backoff.as_millis(),
+                retries,
+                self.directory_read_retries
+            );
+            self.sleep(backoff);
+            if self.is_shutdown() {
+                return Err(error);
+            }
+            backoff = backoff.saturating_mul(2).min(MAX_RETRY_BACKOFF);
+        }
+    }
+
+    // What would be sent for the file, without sending it or touching the file
+    // root is the polled directory the file is under, the file is routed by its path below it
+    // the topic is only set when something other than the sink chose it
+    // None when the UTF-8 policy would skip the file
+    pub fn probe(
+        &self,
+        root: &Path,
+        file_path: &Path,
+    ) -> Result<Option<FileContext>, Box<dyn Error>> {
+        let tombstone = self.is_tombstone(file_path, file_path)?;
+        self.file_context(root, file_path, file_path, tombstone)
+    }
+
+    // The files listed in the manifest, in the listed order
+    // an entry that does not exist, is not a file or is not under the directory is skipped
+    // (or fails with strict_manifest), as is a repeated entry
+    fn manifest_files(&self, directory: &Path, manifest: &Path) -> Result<Vec<PathBuf>, PollError> {
+        let manifest_text = self
+            .file_system
+            .read_to_string(manifest)
+            .map_err(|source| PollError::Read {
+                path: manifest.to_path_buf(),
+                source,
+            })?;
+        let root = self
+            .file_system
+            .canonicalize(directory)
+            .map_err(|source| PollError::Read {
+                path: directory.to_path_buf(),
+                source,
+            })?;
+
+        let mut files = Vec::new();
+        let mut seen = Vec::new();
+        let entries = manifest_text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for entry in entries {
+            // an absolute entry replaces the directory
+            let file_path = directory.join(entry);
+            let problem = match self.file_system.canonicalize(&file_path) {
+                Err(_) => Some("does not exist"),
+                Ok(resolved) if !resolved.starts_with(&root) => {
+                    Some("is not under the polled directory")
+                }
+                Ok(resolved) if !self.file_system.is_file(&resolved) => Some("is not a file"),
+                Ok(resolved) if seen.contains(&resolved) => Some("is listed twice"),
+                Ok(resolved) => {
+                    seen.push(resolved);
+                    None
+                }
+            };
+            match problem {
+                None => files.push(file_path),
+                Some(reason) if self.strict_manifest => {
+                    return Err(PollError::ManifestEntry {
+                        entry: entry.to_string(),
+                        reason,
+                    })
+                }
+                Some(reason) => log::warn!("Skipping manifest entry {:?}, it {}", entry, reason),
+            }
+        }
+        Ok(files)
+    }
+
+    // Only process regular files
+    // subdirectories are descended into when polling recursively
+    // symlinks to directories are never followed so a link back up cannot loop
+    fn collect_files(&self, directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), PollError> {
+        let read_error = |source| PollError::Read {
+            path: directory.to_path_buf(),
+            source,
+        };
+        for file_path in self.file_system.read_dir(directory).map_err(read_error)? {
+            if self.file_system.is_file(&file_path) {
+                if self.is_selected(&file_path) && self.is_modified_since(&file_path) {
+                    files.push(file_path);
+                }
+            } else if self.is_recursive()
+                && self.file_system.is_dir(&file_path)
+                && !self.file_system.is_symlink(&file_path)
+            {
+                self.collect_files(&file_path, files)?;
+            }
+        }
+        Ok(())
+    }
+
+    // a file we cannot stat is kept and fails when it is processed
+    fn is_modified_since(&self, file_path: &Path) -> bool {
+        let Some(since) = self.modified_since else {
+            return true;
+        };
+        self.modified_time(file_path)
+            .is_none_or(|modified| modified > since)
+    }
+
+    // Whether the file was last modified longer ago than the TTL
+    // a file we cannot stat (or modified in the future) has not expired
+    fn is_expired(&self, file_path: &Path) -> bool {
+        if self.file_ttl.is_zero() {
+            return false;
+        }
+        self.modified_time(file_path)
+            .and_then(|modified| self.clock.wall_time().duration_since(modified).ok())
+            .is_some_and(|age| age > self.file_ttl)
+    }
+
+    // Whether the file name matches the required name regex, if there is one
+    fn has_required_name(&self, file_path: &Path) -> bool {
+        let Some(require_name_regex) = &self.require_name_regex else {
+            return true;
+        };
+        let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+        require_name_regex.is_match(&file_name)
+    }
+
+    fn is_recursive(&self) -> bool {
+        self.recursive || self.topic_from_subdir || self.path_template.is_some()
+    }
+
+    // Check the file name against the include and exclude globs
+    // sidecar and checksum files are never selected, they travel with their payload file
+    // and neither are claimed files
+    fn is_selected(&self, file_path: &Path) -> bool {
+        let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(sidecar_extension) = &self.sidecar_extension {
+            if file_name.ends_with(sidecar_extension.as_str()) {
+                return false;
+            }
+        }
+        if self.verify_checksum && file_name.ends_with(CHECKSUM_EXTENSION) {
+            return false;
+        }
+        if self.visited_markers != VisitedMarkers::Off
+            && file_name.ends_with(VISITED_MARKER_EXTENSION)
+        {
+            return false;
+        }
+        if self.is_lock_file(file_path) {
+            return false;
+        }
+        if self.skip_hidden && file_name.starts_with('.') {
+            return false;
+        }
+        // files being processed by another poller
+        if let Some(claim_suffix) = &self.claim_suffix {
+            if file_name.ends_with(claim_suffix.as_str()) {
+                return false;
+            }
+        }
+        let settings = self.settings_for(file_path);
+        let include = settings
+            .and_then(|settings| settings.include.as_ref())
+            .unwrap_or(&self.include);
+        let exclude = settings
+            .and_then(|settings| settings.exclude.as_ref())
+            .unwrap_or(&self.exclude);
+        let included = include.is_empty() || include.iter().any(|p| p.matches(&file_name));
+        included && !exclude.iter().any(|p| p.matches(&file_name))
+    }
+
+    // The overrides of the directory the file is in
+    // the most specific one when the directories are nested
+    fn settings_for(&self, file_path: &Path) -> Option<&DirectorySettings> {
+        self.directory_settings
+            .iter()
+            .filter(|settings| file_path.starts_with(&settings.directory))
+            .max_by_key(|settings| settings.directory.components().count())
+    }
+
+    fn deletes_files_in(&self, file_path: &Path) -> bool {
+        self.settings_for(file_path)
+            .and_then(|settings| settings.delete_files)
+            .unwrap_or(self.delete_files)
+    }
+
+    // Whether the file is a lock file (compared by name, the paths may be spelled differently)
+    fn is_lock_file(&self, file_path: &Path) -> bool {
+        let Some(file_name) = file_path.file_name() else {
+            return false;
+        };
+        (self.lock_directories && file_name == DEFAULT_LOCK_FILE)
+            || self
+                .lock_file
+                .as_deref()
+                .and_then(Path::file_name)
+                .is_some_and(|lock_name| file_name == lock_name)
+    }
+
+    // Lock the lock file and (with lock_directories) each polled directory
+    // a path is only locked once, the same file locked twice would clash with itself
+    fn acquire_locks(&self, directory_paths: &[&Path]) -> Result<Vec<DirectoryLock>, PollError> {
+        let mut lock_paths: Vec<PathBuf> = self.lock_file.iter().cloned().collect();
+        if self.lock_directories {
+            lock_paths.extend(
+                directory_paths
+                    .iter()
+                    .map(|directory_path| directory_path.join(DEFAULT_LOCK_FILE)),
+            );
+        }
+        let mut unique_paths: Vec<PathBuf> = Vec::new();
+        for lock_path in lock_paths {
+            let resolved = lock_path
+                .parent()
+                .and_then(|parent| fs::canonicalize(parent).ok())
+                .zip(lock_path.file_name())
+                .map_or(lock_path.clone(), |(parent, name)| parent.join(name));
+            if !unique_paths.contains(&resolved) {
+                unique_paths.push(resolved);
+            }
+        }
+        unique_paths
+            .iter()
+            .map(|lock_path| DirectoryLock::acquire(lock_path))
+            .collect()
+    }
+
+    fn verify_directory(&self, directory_path: &Path) -> Result<(), PollError> {
+        if !self.file_system.exists(directory_path) {
+            return Err(PollError::DirectoryNotFound(directory_path.to_path_buf()));
+        }
+        if !self.file_system.is_dir(directory_path) {
+            return Err(PollError::NotADirectory(directory_path.to_path_buf()));
+        }
+        Ok(())
+    }
+
+    // This will read the file content
+    // then pass that to the sink that will send the content to its destination
+    // the file is only deleted once the sink reports success
+    // so a failed publish leaves the file on disk for the next poll cycle
+    // the content is read from source
+    // which is the file itself unless the file was claimed (renamed) first
+    // a file that is gone by the time it is read is skipped rather than failed
+    // in a shared directory another process most likely picked it up
+    fn process_file(
+        &self,
+        root: &Path,
+        file_path: &Path,
+        source: &Path,
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        match self.process_present_file(root, file_path, source) {
+            Err(e) if has_vanished(e.as_ref(), self.file_system.as_ref(), source) => {
+                log::debug!(
+                    "File {:?} vanished before it was read, another process likely took it",
+                    display_path(root, file_path)
+                );
+                Ok(FileOutcome::Vanished)
+            }
+            outcome => outcome,
+        }
+    }
+
+    fn process_present_file(
+        &self,
+        root: &Path,
+        file_path: &Path,
+        source: &Path,
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        let name = display_path(root, file_path);
+        if let Some(level) = self.file_log_level {
+            log::log!(level, "Processing file: {:?}", name);
+        }
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("process_file", file = %name).entered();
+
+        if !self.has_required_name(file_path) {
+            let problem = format!(
+                "File name {:?} does not match the required pattern {}",
+                name,
+                self.require_name_regex
+                    .as_ref()
+                    .map(Regex::as_str)
+                    .unwrap_or_default()
+            );
+            if self.name_mismatch_policy == NameMismatchPolicy::Reject {
+                return Err(problem.into());
+            }
+            log::warn!("{}, skipping it", problem);
+            return Ok(FileOutcome::Skipped);
+        }
+
+        if self.is_expired(source) {
+            log::warn!(
+                "Dropping file {:?}, it is older than the {} s TTL",
+                name,
+                self.file_ttl.as_secs()
+            );
+            self.delete_file(file_path, source, &name);
+            return Ok(FileOutcome::Skipped);
+        }
+
+        if self.visited_markers.is_visited(
+            self.file_system.as_ref(),
+            file_path,
+            self.run_started.get(),
+        ) {
+            log::info!("Skipping file {:?}, it has a visited marker", name);
+            return Ok(FileOutcome::Skipped);
+        }
+
+        let dedup_key = self
+            .dedup_mode
+            .key(self.file_system.as_ref(), file_path, source)
+            .map_err(|e| read_error(source, e))?;
+        if let Some(dedup_key) = &dedup_key {
+            if self.published_files.borrow().contains(dedup_key) {
+                log::info!("Skipping file {:?}, it was already published", name);
+                self.delete_file(file_path, source, &name);
+                return Ok(FileOutcome::Skipped);
+            }
+        }
+
+        // never publish a file that does not match its checksum
+        if self.verify_checksum {
+            self.verify_file_checksum(file_path, source, &name)?;
+        }
+
+        // with tombstones enabled empty files delete their key
+        // instead of following the empty file policy
+        let tombstone = self.is_tombstone(file_path, source)?;
+
+        // check for an empty file before reading it
+        if !tombstone
+            && self.empty_file_policy != EmptyFilePolicy::Process
+            && self.is_empty_file(source)?
+        {
+            if self.empty_file_policy == EmptyFilePolicy::Error {
+                return Err(format!("File {:?} is empty", name).into());
+            }
+            log::warn!("Skipping empty file: {:?}", name);
+            self.delete_file(file_path, source, &name);
+            return Ok(FileOutcome::Skipped);
+        }
+
+        let result = match &self.on_file_reader {
+            Some(on_file_reader) => self
+                .timed(Stage::Publish, || self.stream_file(source, on_file_reader))
+                .map(Some),
+            None => self.send_file(root, file_path, source, tombstone),
+        };
+        // left out under Utf8Policy::Skip
+        let Some(bytes) = result.map_err(|e| file_error(&name, e))? else {
+            self.delete_file(file_path, source, &name);
+            return Ok(FileOutcome::Skipped);
+        };
+        if self.ordered {
+            self.timed(Stage::Delivery, || self.sink.flush())
+                .map_err(|e| format!("Failed to confirm delivery of file {:?}: {}", name, e))?;
+        }
+
+        if self.defers_deletion() {
+            // the marker is written once delivery is confirmed
+            self.pending_files.borrow_mut().push(PendingFile {
+                file_path: file_path.to_path_buf(),
+                source: source.to_path_buf(),
+                name,
+                dedup_key,
+            });
+            if self.is_batch_full() {
+                self.flush_batch();
+            }
+        } else {
+            self.delete_file(file_path, source, &name);
+            self.mark_visited(file_path, source, &name);
+            if let Some(dedup_key) = dedup_key {
+                self.published_files.borrow_mut().insert(dedup_key);
+            }
+        }
+        Ok(FileOutcome::Processed(bytes))
+    }
+
+    // Claim the file by renaming it before processing it
+    // so only one of several pollers sharing the directory gets it
+    // the file is renamed back unless it was deleted
+    fn claim_and_process_file(
+        &self,
+        root: &Path,
+        file_path: &Path,
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        let Some(claim_suffix) = &self.claim_suffix else {
+            return self.process_file(root, file_path, file_path);
+        };
+
+        let mut claimed_path = file_path.as_os_str().to_owned();
+        claimed_path.push(claim_suffix);
+        let claimed_path = PathBuf::from(claimed_path);
+
+        // rename is atomic so the loser finds the file gone
+        if let Err(e) = self.file_system.rename(file_path, &claimed_path) {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                log::debug!(
+                    "File {} was claimed by another poller",
+                    display_path(root, file_path)
+                );
+                return Ok(FileOutcome::NotClaimed);
+            }
+            return Err(format!(
+                "Failed to claim file {}: {}",
+                display_path(root, file_path),
+                e
+            )
+            .into());
+        }
+
+        let result = self.process_file(root, file_path, &claimed_path);
+        // a file waiting on the sink keeps its claim until the flush
+        if self.file_system.exists(&claimed_path) && !self.is_pending(file_path) {
+            if let Err(e) = self.file_system.rename(&claimed_path, file_path) {
+                log::error!(
+                    "Failed to release claimed file {}: {}",
+                    claimed_path.display(),
+                    e
+                );
+            }
+        }
+        result
+    }
+
+    // Read the whole file and hand it to the sink
+    // None when the UTF-8 policy skips the file
+    fn send_file(
+        &self,
+        root: &Path,
+        file_path: &Path,
+        source: &Path,
+        tombstone: bool,
+    ) -> Result<Option<u64>, Box<dyn Error>> {
+        // held until the sink has the content
+        let _permit = match &self.inflight_budget {
+            Some(inflight_budget) if !tombstone => {
+                let size = self
+                    .file_system
+                    .metadata(source)
+                    .map_err(|e| read_error(source, e))?
+                    .len;
+                Some(inflight_budget.acquire(size)?)
+            }
+            _ => None,
+        };
+        let Some(ctx) = self.file_context(root, file_path, source, tombstone)? else {
+            return Ok(None);
+        };
+        if self.batch_limits.is_some() && !ctx.tombstone {
+            self.add_to_batch(&ctx);
+        } else {
+            self.send_to_sink(&ctx)?;
+        }
+        Ok(Some(ctx.content.len() as u64))
+    }
+
+    // Hand the file to the sink, sending it again while the sink fails with a retriable error
+    // and there are retries left (in the retry budget too)
+    fn send_to_sink(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        let mut backoff = self.send_retry_backoff;
+        let mut retries = 0;
+        loop {
+            let error = match self.timed(Stage::Publish, || self.sink.send(ctx)) {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            if retries >= self.send_retries || !SinkError::is_retriable(error.as_ref()) {
+                return Err(error);
+            }
+            if let Some(retry_budget) = &self.retry_budget {
+                if !retry_budget.try_retry() {
+                    log::warn!(
+                        "Sending file {} failed ({}), the {} retries of the run are used up",
+                        ctx.file_name,
+                        error,
+                        retry_budget.total()
+                    );
+                    return Err(error);
+                }
+            }
+
+            retries += 1;
+            log::warn!(
+                "Sending file {} failed ({}), retrying in {} ms ({}/{})",
+                ctx.file_name,
+                error,
+                backoff.as_millis(),
+                retries,
+                self.send_retries
+            );
+            self.sleep(backoff);
+            if self.is_shutdown() {
+                log::warn!(
+                    "Shutdown requested, not sending file {} again",
+                    ctx.file_name
+                );
+                return Err(error);
+            }
+            backoff = backoff.saturating_mul(2).min(MAX_RETRY_BACKOFF);
+        }
+    }
+
+    // A tombstone is an empty file or a file with the tombstone extension
+    fn is_tombstone(&self, file_path: &Path, source: &Path) -> Result<bool, Box<dyn Error>> {
+        if !self.tombstone_on_empty {
+            return Ok(false);
+        }
+        let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+        Ok(file_name.ends_with(TOMBSTONE_EXTENSION) || self.is_empty_file(source)?)
+    }
+
+    // The key from the key strategy
+    // for a tombstone file the tombstone extension is not part of the key
+    fn derive_key(&self, file_path: &Path, tombstone: bool) -> Result<Option<Vec<u8>>, String> {
+        let Some(file_name) = file_path.file_name() else {
+            return Ok(None);
+        };
+        let file_name = file_name.to_string_lossy();
+        let file_name = if tombstone {
+            file_name
+                .strip_suffix(TOMBSTONE_EXTENSION)
+                .unwrap_or(&file_name)
+        } else {
+            &file_name
+        };
+        let key_strategy = self
+            .settings_for(file_path)
+            .and_then(|settings| settings.key_strategy)
+            .unwrap_or(self.key_strategy);
+        let key = match key_strategy {
+            KeyStrategy::None => return Ok(None),
+            KeyStrategy::FileName => file_identifier(file_name, self.strip_extension),
+            KeyStrategy::FileStem => file_identifier(file_name, true),
+        };
+        self.key_encoding.encode(key).map(Some)
+    }
+
+    // Hand a buffered reader to the callback
+    // so it decides how much of the file is held in memory
+    fn stream_file(
+        &self,
+        source: &Path,
+        on_file_reader: &FileReaderCallback,
+    ) -> Result<u64, Box<dyn Error>> {
+        let file = self
+            .file_system
+            .open(source)
+            .map_err(|e| read_error(source, e))?;
+
+        // count what the callback actually reads
+        let mut reader = CountingReader {
+            inner: BufReader::new(file),
+            count: 0,
+        };
+        on_file_reader(&mut reader)?;
+        Ok(reader.count)
+    }
+
+    // The error path for a file that could not be processed
+    // with an error directory the file is moved there and polling continues
+    // otherwise the error stops the poller and the file is left in place
+    fn handle_failed_file(
+        &self,
+        root: &Path,
+        file_path: &Path,
+        error: Box<dyn Error>,
+    ) -> Result<(), PollError> {
+        let Some(error_directory) = &self.error_directory else {
+            return Err(error.into());
+        };
+
+        let name = display_path(root, file_path);
+        log::error!("{}", error);
+        log::warn!(
+            "Moving file {} to error directory {}",
+            name,
+            error_directory.display()
+        );
+        // the sidecar and checksum files move with their file
+        let companion_files = self.companion_files(file_path);
+        let file_system = self.file_system.as_ref();
+        let source_dir = file_path.parent().unwrap_or(root);
+        std::iter::once(file_path)
+            .chain(companion_files.iter().map(PathBuf::as_path))
+            .try_for_each(|moved_file| {
+                move_file(file_system, moved_file, error_directory)?;
+                self.record_source_dir(error_directory, moved_file, source_dir);
+                Ok(())
+            })
+            .map_err(|source| PollError::Delete {
+                path: file_path.to_path_buf(),
+                source,
+            })
+    }
+
+    // Note the directory a file failed in next to it in the error directory
+    // without the record a retry puts the file in the first polled directory
+    fn record_source_dir(&self, error_directory: &Path, file_path: &Path, source_dir: &Path) {
+        let record = source_dir_record(error_directory, file_path);
+        let content = source_dir.to_string_lossy();
+        if let Err(e) = self.file_system.write(&record, content.as_bytes()) {
+            log::warn!(
+                "Failed to record the directory of {} in {}: {}",
+                file_path.display(),
+                record.display(),
+                e
+            );
+        }
+    }
+
+    // The directory a retried file goes back to, the one it failed in
+    // unless that is not (under) a polled directory any more
+    fn retry_target(&self, file_path: &Path, directory_paths: &[&Path]) -> Option<PathBuf> {
+        let error_directory = file_path.parent()?;
+        let record = source_dir_record(error_directory, file_path);
+        let recorded = self
+            .file_system
+            .read_to_string(&record)
+            .ok()
+            .map(PathBuf::from)
+            .filter(|source_dir| {
+                directory_paths
+                    .iter()
+                    .any(|directory_path| source_dir.starts_with(directory_path))
+            });
+        recorded.or_else(|| directory_paths.first().map(|path| path.to_path_buf()))
+    }
+
+    // Move the files in the error directory back into the directory they failed in to publish them again
+    // a file whose name is already taken in the directory stays where it is
+    // the problems are logged, the files that cannot be moved are tried on the next start
+    fn retry_error_files(&self, directory_paths: &[&Path]) {
+        let Some(error_directory) = &self.error_directory else {
+            return;
+        };
+        if !self.retry_errors_on_startup {
+            return;
+        }
+        let entries = match self.file_system.read_dir(error_directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                log::error!(
+                    "Failed to list error directory {}: {}",
+                    error_directory.display(),
+                    e
+                );
+                return;
+            }
+        };
+        let mut retried = 0;
+        for file_path in entries {
+            if !self.file_system.is_file(&file_path)
+                || is_source_dir_record(&file_path)
+                || self.is_too_old_to_retry(&file_path)
+            {
+                continue;
+            }
+            let Some(directory) = self.retry_target(&file_path, directory_paths) else {
+                continue;
+            };
+            let target = directory.join(file_path.file_name().unwrap_or_default());
+            if self.file_system.exists(&target) {
+                log::warn!(
+                    "Not retrying {}, {} already exists",
+                    file_path.display(),
+                    target.display()
+                );
+                continue;
+            }
+            match move_file(self.file_system.as_ref(), &file_path, &directory) {
+                Ok(()) => {
+                    retried += 1;
+                    let record = source_dir_record(error_directory, &file_path);
+                    if self.file_system.exists(&record) {
+                        if let Err(e) = self.file_system.remove(&record) {
+                            log::warn!("Failed to delete {}: {}", record.display(), e);
+                        }
+                    }
+                }
+                Err(e) => log::error!("Failed to retry {}: {}", file_path.display(), e),
+            }
+        }
+        if retried > 0 {
+            log::info!(
+                "Moved {} files from error directory {} back to their directories to retry them",
+                retried,
+                error_directory.display()
+            );
+        }
+    }
+
+    fn is_too_old_to_retry(&self, file_path: &Path) -> bool {
+        if self.retry_errors_max_age.is_zero() {
+            return false;
+        }
+        self.modified_time(file_path)
+            .and_then(|modified| self.clock.wall_time().duration_since(modified).ok())
+            .is_some_and(|age| age > self.retry_errors_max_age)
+    }
+
+    // Record the file as the last one processed
+    // the line is written to a temp file and renamed over the checkpoint
+    // so a reader never sees a partial line
+    // failing to write it only logs a warning
+    fn write_checkpoint(&self, file_path: &Path) {
+        let Some(checkpoint_file) = &self.checkpoint_file else {
+            return;
+        };
+        let timestamp = self
+            .clock
+            .wall_time()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let line = format!("{} {}\n", timestamp, file_path.display());
+
+        let mut temp_file = checkpoint_file.as_os_str().to_owned();
+        temp_file.push(".tmp");
+        let temp_file = PathBuf::from(temp_file);
+        let result = self
+            .file_system
+            .write(&temp_file, line.as_bytes())
+            .and_then(|()| self.file_system.rename(&temp_file, checkpoint_file));
+        if let Err(e) = result {
+            log::warn!(
+                "Failed to write checkpoint file {}: {}",
+                checkpoint_file.display(),
+                e
+            );
+        }
+    }
+
+    // Run a stage of processing a file and add the time it took to the stage times
+    // with the tracing feature the stage is a span (inside the span of the file)
+    fn timed<T>(&self, stage: Stage, step: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "tracing")]
+        let _span = stage.span().entered();
+        let started = self.clock.now();
+        let result = step();
+        let mut stage_times = self.stage_times.get();
+        stage_times.add(stage, self.clock.now().saturating_duration_since(started));
+        self.stage_times.set(stage_times);
+        result
+    }
+
+    // delete file if the delete_files flag is enabled
+    // if deletion fails, log the error
+    // but do not return an error from this function
+    fn delete_file(&self, file_path: &Path, source: &Path, name: &str) {
+        let delete_files = self.deletes_files_in(file_path);
+        if delete_files && self.in_warmup() {
+            self.hold_for_warmup(file_path, source, name);
+        } else if delete_files {
+            // delete file logic
+            if let Err(e) = self.timed(Stage::Delete, || self.file_system.remove(source)) {
+                // TODO: this can result in processing the file
+                // multiple times across poll cycles
+                log::error!("Failed to delete file {}: {}", name, e);
+                return;
+            }
+            // the sidecar and checksum files go second so a failure in between
+            // leaves an orphaned sidecar rather than a payload without its metadata
+            for companion_file in self.companion_files(file_path) {
+                if let Err(e) = self.file_system.remove(&companion_file) {
+                    log::error!(
+                        "Failed to delete {} of file {}: {}",
+                        companion_file.display(),
+                        name,
+                        e
+                    );
+                }
+            }
+        } else {
+            log::info!(
+                "File deletion is disabled, skipping deletion for file: {}",
+                name
+            );
+        }
+    }
+
+    // Whether deletion is still held back until delete_after_cycles
+    fn in_warmup(&self) -> bool {
+        self.cycles_started.get() < self.delete_after_cycles
+    }
+
+    // Keep a published file on disk until the warmup is over
+    // a claimed file is released so it keeps its own name
+    fn hold_for_warmup(&self, file_path: &Path, source: &Path, name: &str) {
+        if source != file_path {
+            if let Err(e) = self.file_system.rename(source, file_path) {
+                log::error!("Failed to release claimed file {}: {}", source.display(), e);
+            }
+        }
+        log::info!(
+            "Keeping file {} until cycle {} (delete after cycles)",
+            name,
+            self.delete_after_cycles
+        );
+        self.warmup_files.borrow_mut().push(WarmupFile {
+            file_path: file_path.to_path_buf(),
+            name: name.to_string(),
+            modified: self.modified_time(file_path),
+        });
+    }
+
+    // Whether the file was published during the warmup and is unchanged since
+    fn is_held_for_warmup(&self, file_path: &Path) -> bool {
+        let mut warmup_files = self.warmup_files.borrow_mut();
+        let Some(index) = warmup_files
+            .iter()
+            .position(|warmup_file| warmup_file.file_path == file_path)
+        else {
+            return false;
+        };
+        if warmup_files[index].modified == self.modified_time(file_path) {
+            return true;
+        }
+        // rewritten since, so the new content is published
+        warmup_files.remove(index);
+        false
+    }
+
+    // Delete the files published during the warmup once it is over
+    fn end_warmup(&self) {
+        if self.in_warmup() {
+            return;
+        }
+        let warmup_files = std::mem::take(&mut *self.warmup_files.borrow_mut());
+        if !warmup_files.is_empty() {
+            log::info!(
+                "Warmup over, deleting the {} files published during it",
+                warmup_files.len()
+            );
+        }
+        for warmup_file in warmup_files {
+            let file_path = &warmup_file.file_path;
+            if self.file_system.exists(file_path)
+                && warmup_file.modified == self.modified_time(file_path)
+            {
+                self.delete_file(file_path, file_path, &warmup_file.name);
+            }
+        }
+    }
+
+    fn should_continue_polling(
+        &self,
+        poll_cycles: i32,
+        empty_cycles: u32,
+        cycle_started: Instant,
+        deadline: Option<Instant>,
+        watcher: Option<&DirectoryWatcher>,
+    ) -> bool {
+        let continue_polling =
+            self.continue_polling(poll_cycles, empty_cycles) && !self.is_past(deadline);
+
+        // if we are going to keep running, sleep for the configured delay
+        // but never past the deadline
+        // a watcher wakes up early when a file arrives
+        if continue_polling {
+            let wait = self
+                .next_wait(empty_cycles, cycle_started)
+                .min(self.time_until(deadline));
+            match watcher {
+                Some(watcher) => {
+                    if watcher.wait(wait, || self.is_shutdown()) {
+                        log::debug!("New file detected, polling now");
+                    }
+                }
+                None => self.sleep(wait),
+            }
+        }
+
+        continue_polling && !self.is_shutdown() && !self.is_past(deadline)
+    }
+
+    // Watch the directories when watching is enabled
+    // polling carries on without the watcher if it cannot be set up
+    fn watch_directories(&self, directories: &[&Path]) -> Option<DirectoryWatcher> {
+        if !self.watch {
+            return None;
+        }
+        match DirectoryWatcher::new(directories, self.is_recursive()) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                log::warn!("Failed to watch directories, polling only: {}", e);
+                None
+            }
+        }
+    }
+
+    // When the poll loop has to stop (started now)
+    // None when there is no maximum runtime
+    fn deadline(&self) -> Option<Instant> {
+        (!self.max_runtime.is_zero()).then(|| self.clock.now() + self.max_runtime)
+    }
+
+    fn is_past(&self, deadline: Option<Instant>) -> bool {
+        deadline.is_some_and(|deadline| self.clock.now() >= deadline)
+    }
+
+    fn time_until(&self, deadline: Option<Instant>) -> Duration {
+        deadline.map_or(Duration::MAX, |deadline| {
+            deadline.saturating_duration_since(self.clock.now())
+        })
+    }
+
+    // Log how much a cycle published and how fast
+    // cycles that published nothing are left to log_empty_cycle
+    fn log_cycle(&self, stats: &PollStats, cycle: &CycleStart) {
+        let Some(level) = self.cycle_log_level else {
+            return;
+        };
+        let files = stats.files_processed - cycle.files_processed;
+        if files == 0 {
+            return;
+        }
+        let bytes = stats.bytes_processed - cycle.bytes_processed;
+        // a cycle that took no measurable time counts as 1 ms
+        let secs = self
+            .clock
+            .now()
+            .saturating_duration_since(cycle.started)
+            .as_secs_f64()
+            .max(0.001);
+        log::log!(
+            level,
+            "cycle {}: {} files, {}, {}/s",
+            stats.cycles,
+            files,
+            format_bytes(bytes as f64),
+            format_bytes(bytes as f64 / secs)
+        );
+    }
+
+    // Hand what the cycle did to the on_cycle_complete callback
+    // with an error directory an error is only logged, like a failed file
+    fn complete_cycle(&self, cycle_stats: &CycleStats) -> Result<(), PollError> {
+        let Some(on_cycle_complete) = &self.on_cycle_complete else {
+            return Ok(());
+        };
+        match on_cycle_complete(cycle_stats) {
+            Ok(()) => Ok(()),
+            Err(e) if self.error_directory.is_some() => {
+                log::error!("Cycle {} callback failed: {}", cycle_stats.cycle, e);
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Hand the stats to the on_stats callback
+    // a panic in the callback is logged rather than stopping the poller
+    fn report_stats(&self, stats: &PollStats, cycle_stats: Option<&CycleStats>) {
+        let Some(on_stats) = &self.on_stats else {
+            return;
+        };
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| on_stats(stats, cycle_stats)));
+        if let Err(panic) = result {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            log::error!("Stats callback panicked: {}", message);
+        }
+    }
+
+    // A processed file shows the upstream writer is alive
+    fn reset_watchdog(&self) {
+        self.last_file_at.set(Some(self.clock.now()));
+        self.silence_reported.set(false);
+    }
+
+    // Raise the alarm once no file has been processed for longer than expected_interval
+    // the alarm is raised once per silence, the next file starts the watch again
+    fn check_watchdog(&self) -> Result<(), PollError> {
+        if self.expected_interval.is_zero() || self.silence_reported.get() {
+            return Ok(());
+        }
+        let Some(last_file_at) = self.last_file_at.get() else {
+            return Ok(());
+        };
+        let silent_for = self.clock.now().saturating_duration_since(last_file_at);
+        if silent_for <= self.expected_interval {
+            return Ok(());
+        }
+
+        self.silence_reported.set(true);
+        log::error!(
+            "No files processed for {} s, expected at least one every {} s, is the upstream writer running?",
+            silent_for.as_secs(),
+            self.expected_interval.as_secs()
+        );
+        self.publish_silence_alert(silent_for);
+        if self.exit_on_silence {
+            return Err(PollError::Silent(silent_for));
+        }
+        Ok(())
+    }
+
+    // Tell the control topic the watchdog fired
+    fn publish_silence_alert(&self, silent_for: Duration) {
+        let Some(control_topic) = &self.control_topic else {
+            return;
+        };
+        let timestamp = self
+            .clock
+            .wall_time()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let alert = json!({
+            "alert": "no_files",
+            "silent_secs": silent_for.as_secs(),
+            "expected_interval_secs": self.expected_interval.as_secs(),
+            "timestamp": timestamp,
+        });
+        let mut ctx = FileContext::new(Path::new(""), alert.to_string().into_bytes());
+        ctx.file_name = "silence alert".to_string();
+        ctx.topic = Some(control_topic.clone());
+        if let Err(e) = self.sink.send(&ctx) {
+            log::warn!(
+                "Failed to send the silence alert to control topic {}: {}",
+                control_topic,
+                e
+            );
+        }
+    }
+
+    // Tell the control topic the cycle's files have all been delivered
+    fn publish_cycle_marker(&self, stats: &PollStats, cycle: &CycleStart) {
+        let Some(control_topic) = &self.control_topic else {
+            return;
+        };
+        let files = stats.files_processed - cycle.files_processed;
+        if files == 0 {
+            return;
+        }
+
+        // only once the sink has delivered everything sent in the cycle
+        if let Err(e) = self.flush_sink() {
+            log::warn!(
+                "Not sending the marker for cycle {}, flushing the sink failed: {}",
+                stats.cycles,
+                e
+            );
+            return;
+        }
+
+        let timestamp = self
+            .clock
+            .wall_time()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let marker = json!({
+            "cycle": stats.cycles,
+            "files": files,
+            "bytes": stats.bytes_processed - cycle.bytes_processed,
+            "timestamp": timestamp,
+        });
+        let mut ctx = FileContext::new(Path::new(""), marker.to_string().into_bytes());
+        ctx.file_name = format!("cycle {} marker", stats.cycles);
+        ctx.topic = Some(control_topic.clone());
+        if let Err(e) = self.sink.send(&ctx) {
+            log::warn!(
+                "Failed to send the marker for cycle {} to control topic {}: {}",
+                stats.cycles,
+                control_topic,
+                e
+            );
+        }
+    }
+
+    // Log the empty cycle, or every heartbeat_every-th one while idle
+    fn log_empty_cycle(&self, empty_cycles: u32) {
+        let Some(level) = self.empty_cycle_log_level else {
+            return;
+        };
+        if self.heartbeat_every <= 1 {
+            log::log!(level, "No files found on this poll cycle");
+        } else if empty_cycles.is_multiple_of(self.heartbeat_every) {
+            log::log!(
+                level,
+                "No files found in the last {} poll cycles ({} empty cycles in a row)",
+                self.heartbeat_every,
+                empty_cycles
+            );
+        }
+    }
+
+    // Whether the cycle has taken max_files_per_cycle files
+    // the rest are left for the next cycle
+    fn is_cycle_full(&self, file_count: usize) -> bool {
+        self.max_files_per_cycle > 0 && file_count >= self.max_files_per_cycle
+    }
+
+    // Whether another poll cycle should run (without waiting for it)
+    fn continue_polling(&self, poll_cycles: i32, empty_cycles: u32) -> bool {
+        // max poll cycles takes precedence over the keep_running flag
+        // until_empty stops on the first empty cycle or at max poll cycles
+        // whichever comes first
+        if self.until_empty && empty_cycles > 0 {
+            false
+        } else if self.until_empty && self.max_poll_cycles <= 0 {
+            true
+        } else if self.max_poll_cycles <= 0 {
+            // max poll cycles is not enabled
+            // use the keep_running flag
+            self.keep_running
+        } else {
+            // max poll cycles is enabled
+            // only continue if we have not reached the max cycles
+            poll_cycles < self.max_poll_cycles
+        }
+    }
+
+    // The sidecar next to the file if sidecars are enabled and it exists
+    fn sidecar_path(&self, file_path: &Path) -> Option<PathBuf> {
+        companion_path(
+            self.file_system.as_ref(),
+            file_path,
+            self.sidecar_extension.as_ref()?,
+        )
+    }
+
+    // The checksum file next to the file if checksums are verified and it exists
+    fn checksum_path(&self, file_path: &Path) -> Option<PathBuf> {
+        if !self.verify_checksum {
+            return None;
+        }
+        companion_path(self.file_system.as_ref(), file_path, CHECKSUM_EXTENSION)
+    }
+
+    // The visited marker next to the file if markers are written and it exists
+    fn marker_path(&self, file_path: &Path) -> Option<PathBuf> {
+        if self.visited_markers == VisitedMarkers::Off {
+            return None;
+        }
+        companion_path(
+            self.file_system.as_ref(),
+            file_path,
+            VISITED_MARKER_EXTENSION,
+        )
+    }
+
+    // When the file was last modified, if the file system knows
+    fn modified_time(&self, file_path: &Path) -> Option<SystemTime> {
+        self.file_system
+            .metadata(file_path)
+            .ok()
+            .and_then(|metadata| metadata.modified)
+    }
+
+    // Check for a zero-byte file without reading it
+    fn is_empty_file(&self, file_path: &Path) -> Result<bool, Box<dyn Error>> {
+        let metadata = self
+            .file_system
+            .metadata(file_path)
+            .map_err(|e| read_error(file_path, e))?;
+        Ok(metadata.len == 0)
+    }
+
+    // The files that are deleted or moved along with the file
+    fn companion_files(&self, file_path: &Path) -> Vec<PathBuf> {
+        self.sidecar_path(file_path)
+            .into_iter()
+            .chain(self.checksum_path(file_path))
+            .chain(self.marker_path(file_path))
+            .collect()
+    }
+
+    // Write the visited marker of a published file that was kept
+    // a file that could not be marked is published again by a later cycle
+    pub(super) fn mark_visited(&self, file_path: &Path, source: &Path, name: &str) {
+        if self.visited_markers == VisitedMarkers::Off || !self.file_system.exists(source) {
+            return;
+        }
+        if let Err(e) =
+            self.visited_markers
+                .mark(self.file_system.as_ref(), file_path, self.clock.wall_time())
+        {
+            log::warn!(
+                "Failed to write the visited marker of file {:?}: {}",
+                name,
+                e
+            );
+        }
+    }
+
+    // Compare the SHA-256 of the file against its checksum file
+    // a file without a checksum file only fails when checksums are required
+    fn verify_file_checksum(
+        &self,
+        file_path: &Path,
+        source: &Path,
+        name: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(checksum_path) = self.checksum_path(file_path) else {
+            if self.require_checksum {
+                return Err(format!("File {:?} has no checksum file", name).into());
+            }
+            return Ok(());
+        };
+
+        let expected = read_checksum(self.file_system.as_ref(), &checksum_path)?;
+        let actual =
+            sha256_file(self.file_system.as_ref(), source).map_err(|e| read_error(source, e))?;
+        if actual != expected {
+            return Err(format!(
+                "Checksum mismatch for file {:?}: expected {} but was {}",
+                name, expected, actual
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    // Apply a change to the shared status (if there is one)
+    fn update_status<F>(&self, update: F)
+    where
+        F: FnOnce(&mut Status),
+    {
+        if let Some(status) = &self.status {
+            if let Ok(mut status) = status.lock() {
+                update(&mut status);
+            }
+        }
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shutdown_flag.load(Ordering::SeqCst)
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause_flag.load(Ordering::SeqCst)
+    }
+
+    // Hold the poller between cycles while the pause flag is set
+    // returns false if shutdown was requested or the runtime ran out instead
+    fn wait_while_paused(&self, deadline: Option<Instant>) -> bool {
+        if !self.is_paused() {
+            return true;
+        }
+        log::info!("Polling paused");
+        let mut last_heartbeat = self.clock.now();
+        while self.is_paused() {
+            if self.is_shutdown() || self.is_past(deadline) {
+                return false;
+            }
+            if self.clock.now().saturating_duration_since(last_heartbeat) >= PAUSE_HEARTBEAT {
+                log::info!("Polling still paused");
+                last_heartbeat = self.clock.now();
+            }
+            self.sleep(PAUSE_CHECK_INTERVAL);
+        }
+        log::info!("Polling resumed");
+        true
+    }
+
+    // The first polled directory on a file system with less than min_free_disk_mb free
+    // and the MB it has, a directory that cannot be checked is not held against polling
+    fn low_disk_space(&self, directories: &[&Path]) -> Option<(PathBuf, u64)> {
+        if self.min_free_disk_mb == 0 {
+            return None;
+        }
+        for directory in directories {
+            match self.disk_space.available_bytes(directory) {
+                Ok(bytes) if bytes / MB < self.min_free_disk_mb => {
+                    return Some((directory.to_path_buf(), bytes / MB));
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!(
+                    "Failed to check the free disk space of {}: {}",
+                    directory.display(),
+                    e
+                ),
+            }
+        }
+        None
+    }
+
+    // Hold the poller between cycles while a polled directory is low on disk space
+    // returns false if shutdown was requested or the runtime ran out instead
+    fn wait_for_disk_space(&self, directories: &[&Path], deadline: Option<Instant>) -> bool {
+        let Some((directory, free_mb)) = self.low_disk_space(directories) else {
+            return true;
+        };
+        log::warn!(
+            "Only {} MB free for {}, below the {} MB minimum, pausing polling",
+            free_mb,
+            directory.display(),
+            self.min_free_disk_mb
+        );
+        loop {
+            if self.is_shutdown() || self.is_past(deadline) {
+                return false;
+            }
+            self.sleep(DISK_CHECK_INTERVAL);
+            if self.low_disk_space(directories).is_none() {
+                break;
+            }
+        }
+        log::info!("Disk space freed up, resuming polling");
+        true
+    }
+
+    // Wait until the rate limiter allows the next file
+    // returns false if shutdown was requested instead
+    fn wait_for_permit(&self) -> bool {
+        if self.is_shutdown() {
+            return false;
+        }
+        let wait = self.rate_limiter.reserve(self.clock.now());
+        self.sleep(wait);
+        !self.is_shutdown()
+    }
+
+    // Sleep in short slices so a shutdown request is noticed quickly
+    fn sleep(&self, duration: Duration) {
+        const SLICE: Duration = Duration::from_millis(50);
+        let deadline = self.clock.now() + duration;
+        while !self.is_shutdown() {
+            let remaining = deadline.saturating_duration_since(self.clock.now());
+            if remaining.is_zero() {
+                break;
+            }
+            self.clock.sleep(remaining.min(SLICE));
+        }
+    }
+
+    // How long to wait before the next cycle
+    // at a fixed rate the time the cycle took comes off the interval
+    fn next_wait(&self, empty_cycles: u32, cycle_started: Instant) -> Duration {
+        let interval = Duration::from_millis(self.next_poll_interval_millis(empty_cycles));
+        if !self.fixed_rate {
+            return interval;
+        }
+        let took = self.clock.now().saturating_duration_since(cycle_started);
+        if took > interval {
+            log::warn!(
+                "Polling is falling behind, the cycle took {} ms of a {} ms interval",
+                took.as_millis(),
+                interval.as_millis()
+            );
+        }
+        interval.saturating_sub(took)
+    }
+
+    fn next_poll_interval_millis(&self, empty_cycles: u32) -> u64 {
+        let mut interval = self.poll_interval_millis;
+
+        // double the interval for each consecutive empty cycle
+        // but never go past the cap (or below the configured interval)
+        if self.backoff_on_empty && empty_cycles > 0 {
+            let cap = self.max_backoff_millis.max(self.poll_interval_millis);
+            interval = 2u64
+                .checked_pow(empty_cycles)
+                .and_then(|factor| interval.checked_mul(factor))
+                .map_or(cap, |backoff| backoff.min(cap));
+        }
+
+        // spread out instances polling on the same cadence
+        if self.poll_jitter_millis > 0 {
+            interval += rand::rng().random_range(0..=self.poll_jitter_millis);
+        }
+
+        interval
+    }
+}
+
+// The async API
+// the same poll loop as poll_directories
+// with tokio::fs and tokio::time in place of the blocking calls
+#[cfg(feature = "async")]
+impl DirectoryPoller {
+    // Poll directory for files, handing each file to the async callback
+    // the callback replaces the sink (and on_file_reader)
+    // the content is processed and routed as usual
+    // but an option only the blocking poller supports (e.g. sidecars or claims) fails the poll
+    pub async fn poll_directory_async<P, F, Fut>(
+        &self,
+        directory: P,
+        callback: F,
+    ) -> Result<PollStats, PollError>
+    where
+        P: AsRef<Path>,
+        F: Fn(FileContext) -> Fut,
+        Fut: std::future::Future<Output = Result<(), Box<dyn Error>>>,
+    {
+        let directory_path = directory.as_ref();
+        if !self.blocking_only_options.is_empty() {
+            let options = self.blocking_only_options.clone();
+            return Err(BuildError::NotSupportedByAsync(options).into());
+        }
+        self.verify_directory(directory_path)?;
+
+        let mut stats = PollStats::new(&[directory_path]);
+        let mut keep_running = true;
+        let mut empty_cycles = 0;
+        let deadline = self.deadline();
+
+        'polling: while keep_running {
+            self.update_status(|status| {
+                status.running = true;
+                status.current_cycle = stats.cycles + 1;
+                status.current_directory = Some(directory_path.to_path_buf());
+            });
+            log::info!("Polling directory: {}", directory_path.display());
+
+            let mut file_count = 0;
+            let cycle = CycleStart::new(&stats, self.clock.now());
+            for file_path in self.enumerate_files_async(directory_path).await? {
+                if self.is_cycle_full(file_count) {
+                    break;
+                }
+                if !self.wait_for_permit_async().await {
+                    log::info!("Shutdown requested, stopping poll loop");
+                    break 'polling;
+                }
+                if self.is_past(deadline) {
+                    log::info!("Maximum runtime reached, stopping poll loop");
+                    break 'polling;
+                }
+
+                file_count += 1;
+                match self
+                    .process_file_async(directory_path, &file_path, &callback)
+                    .await
+                {
+                    Ok(FileOutcome::Processed(bytes)) => {
+                        stats.record_file(0, bytes);
+                        self.write_checkpoint(&file_path);
+                    }
+                    Ok(FileOutcome::Skipped) => stats.files_skipped += 1,
+                    Ok(FileOutcome::Vanished) => stats.files_vanished += 1,
+                    Ok(FileOutcome::NotClaimed) => {}
+                    Err(e) => {
+                        self.handle_failed_file(directory_path, &file_path, e)?;
+                        stats.files_failed += 1;
+                    }
+                }
+            }
+
+            // end of poll cycle
+            stats.cycles += 1;
+            self.log_cycle(&stats, &cycle);
+            self.update_status(|status| status.last_cycle = Some(stats.clone()));
+            if file_count == 0 {
+                empty_cycles += 1;
+                self.log_empty_cycle(empty_cycles);
+            } else {
+                empty_cycles = 0;
+            }
+
+            let continue_polling =
+                self.continue_polling(stats.cycles, empty_cycles) && !self.is_past(deadline);
+            if continue_polling {
+                let wait = self.next_wait(empty_cycles, cycle.started);
+                self.sleep_async(wait.min(self.time_until(deadline))).await;
+            }
+            keep_running = continue_polling && !self.is_shutdown() && !self.is_past(deadline);
+        }
+
+        self.update_status(|status| {
+            status.running = false;
+            status.current_directory = None;
+        });
+        Ok(stats)
+    }
+
+    // enumerate_files using tokio::fs
+    async fn enumerate_files_async(&self, directory: &Path) -> Result<Vec<PathBuf>, PollError> {
+        let read_error = |source| PollError::Read {
+            path: directory.to_path_buf(),
+            source,
+        };
+        let mut files = Vec::new();
+        let mut entries = tokio::fs::read_dir(directory).await.map_err(read_error)?;
+        while let Some(directory_entry) = entries.next_entry().await.map_err(read_error)? {
+            let file_path = directory_entry.path();
+            // follow symlinks like Path::is_file
+            let is_file = tokio::fs::metadata(&file_path)
+                .await
+                .is_ok_and(|m| m.is_file());
+            if is_file && self.is_selected(&file_path) {
+                files.push(file_path);
+            }
+        }
+
+        match self.file_order {
+            FileOrder::Unsorted => {}
+            FileOrder::Name => files.sort(),
+            FileOrder::Oldest | FileOrder::Newest => {
+                let mut modified = Vec::with_capacity(files.len());
+                for file_path in files {
+                    let time = tokio::fs::metadata(&file_path)
+                        .await
+                        .and_then(|m| m.modified())
+                        .ok();
+                    modified.push((time, file_path));
+                }
+                modified.sort();
+                if self.file_order == FileOrder::Newest {
+                    modified.reverse();
+                }
+                files = modified
+                    .into_iter()
+                    .map(|(_, file_path)| file_path)
+                    .collect();
+            }
+        }
+        Ok(files)
+    }
+
+    async fn process_file_async<F, Fut>(
+        &self,
+        root: &Path,
+        file_path: &Path,
+        callback: &F,
+    ) -> Result<FileOutcome, Box<dyn Error>>
+    where
+        F: Fn(FileContext) -> Fut,
+        Fut: std::future::Future<Output = Result<(), Box<dyn Error>>>,
+    {
+        let name = display_path(root, file_path);
+        if let Some(level) = self.file_log_level {
+            log::log!(level, "Processing file: {:?}", name);
+        }
+
+        let result = async {
+            let content = tokio::fs::read(file_path)
+                .await
+                .map_err(|e| read_error(file_path, e))?;
+            if content.is_empty() {
+                match self.empty_file_policy {
+                    EmptyFilePolicy::Process => {}
+                    EmptyFilePolicy::Skip => {
+                        log::warn!("Skipping empty file: {:?}", name);
+                        return Ok(FileOutcome::Skipped);
+                    }
+                    EmptyFilePolicy::Error => {
+                        return Err(format!("File {:?} is empty", name).into());
+                    }
+                }
+            }
+            let Some(content) = self.utf8_policy.apply(&name, content) else {
+                return Ok(FileOutcome::Skipped);
+            };
+
+            let mut ctx = FileContext::new(file_path, Vec::new());
+            ctx.modified = tokio::fs::metadata(file_path)
+                .await
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            let processed = self.content.process(&ctx, &content)?;
+            ctx.content = processed.content;
+            if let Some(original_size) = processed.original_size {
+                mark_truncated(&mut ctx, original_size);
+            }
+            ctx.key = self.derive_key(file_path, false)?;
+            if let Some(on_file_route) = &self.on_file_route {
+                let outcome = on_file_route(&ctx)?;
+                ctx.route(outcome);
+            }
+            let bytes = ctx.content.len() as u64;
+            callback(ctx).await?;
+            Ok(FileOutcome::Processed(bytes))
+        };
+        let result: Result<FileOutcome, Box<dyn Error>> = result.await;
+        let outcome = match result {
+            Err(e) if has_vanished(e.as_ref(), &crate::filesystem::RealFileSystem, file_path) => {
+                log::debug!(
+                    "File {:?} vanished before it was read, another process likely took it",
+                    name
+                );
+                return Ok(FileOutcome::Vanished);
+            }
+            outcome => outcome.map_err(|e| file_error(&name, e))?,
+        };
+
+        if self.deletes_files_in(file_path) {
+            if let Err(e) = tokio::fs::remove_file(file_path).await {
+                log::error!("Failed to delete file {}: {}", name, e);
+            }
+        }
+        Ok(outcome)
+    }
+
+    // wait_for_permit without blocking the thread
+    async fn wait_for_permit_async(&self) -> bool {
+        if self.is_shutdown() {
+            return false;
+        }
+        let wait = self.rate_limiter.reserve(self.clock.now());
+        self.sleep_async(wait).await;
+        !self.is_shutdown()
+    }
+
+    // sleep without blocking the thread, cut short by a shutdown request
+    // this waits on tokio::time in slices that count down the duration rather than reading a clock
+    async fn sleep_async(&self, duration: Duration) {
+        const SLICE: Duration = Duration::from_millis(50);
+        let mut remaining = duration;
+        while !self.is_shutdown() && !remaining.is_zero() {
+            let slice = remaining.min(SLICE);
+            tokio::time::sleep(slice).await;
+            remaining -= slice;
+        }
+    }
+}
+
+// How a file is named in logs and errors
+// the path relative to the polled directory so files in subdirectories are unambiguous
+// (the full path if it is not under the directory)
+// names that are not valid UTF-8 are marked since the conversion is lossy
+fn display_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    match relative.to_str() {
+        Some(name) => name.to_string(),
+        None => format!("{} (non-UTF-8)", relative.to_string_lossy()),
+    }
+}
+
+// The identifier (key or topic) taken from a file name
+// stripping the extension only removes the last one, a.b.json is a.b
+// and a name that starts with its only dot (.env) has no extension
+pub fn file_identifier(file_name: &str, strip_extension: bool) -> &str {
+    if !strip_extension {
+        return file_name;
+    }
+    match file_name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => file_name,
+    }
+}
+
+// Kafka topic names are up to 249 ASCII letters, digits, '.', '_' and '-'
+// and cannot be "." or ".."
+pub fn is_valid_topic(topic: &str) -> bool {
+    !topic.is_empty()
+        && topic.len() <= 249
+        && topic != "."
+        && topic != ".."
+        && topic
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
 }
 
-impl DirectoryPoller {
-    pub fn builder() -> DirectoryPollerBuilder {
-        DirectoryPollerBuilder::new()
-    }
+// The file next to file_path with the extension appended if it exists
+fn companion_path(
+    file_system: &dyn FileSystem,
+    file_path: &Path,
+    extension: &str,
+) -> Option<PathBuf> {
+    let mut companion_path = file_path.as_os_str().to_owned();
+    companion_path.push(extension);
+    let companion_path = PathBuf::from(companion_path);
+    file_system
+        .is_file(&companion_path)
+        .then_some(companion_path)
+}
 
-    // Poll directory for files
-    pub fn poll_directory(&self, directory: &str) -> Result<(), Box<dyn Error>> {
-        let directory_path = Path::new(directory);
+// Read the hex digest from a checksum file
+// the sha256sum format ("<digest>  <file name>") is accepted
+fn read_checksum(
+    file_system: &dyn FileSystem,
+    checksum_path: &Path,
+) -> Result<String, Box<dyn Error>> {
+    let checksum_text = file_system.read_to_string(checksum_path).map_err(|e| {
+        format!(
+            "Failed to read checksum file {}: {}",
+            checksum_path.display(),
+            e
+        )
+    })?;
+    let digest = checksum_text.split_whitespace().next().unwrap_or_default();
+    if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "Checksum file {} does not contain a SHA-256 digest",
+            checksum_path.display()
+        )
+        .into());
+    }
+    Ok(digest.to_ascii_lowercase())
+}
 
-        // Validate directory exists and is a directory
-        // and fail fast if it is not valid
-        // to avoid repeated attempts in next poll cycle
-        match self.verify_directory(directory_path) {
-            Ok(_) => (),
-            Err(e) => return Err(e),
-        }
+// Hash the file without loading it into memory
+fn sha256_file(file_system: &dyn FileSystem, file_path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file_system.open(file_path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
-        // Poll the directory
-        log::info!("Polling directory: {}", self.file_name(directory_path));
+// Compile the include/exclude globs
+// a glob that is not valid is a BuildError::InvalidGlob naming the pattern
+fn compile_globs(patterns: &[String]) -> Result<Vec<glob::Pattern>, BuildError> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern).map_err(|e| BuildError::InvalidGlob {
+                pattern: pattern.clone(),
+                message: e.to_string(),
+            })
+        })
+        .collect()
+}
 
-        let mut poll_cycles = 0;
-        let mut keep_running = true;
+// Whether the error is the file not being found because it is no longer there
+// other IO errors (permissions, a missing sidecar ...) still fail the file
+fn has_vanished(
+    error: &(dyn Error + 'static),
+    file_system: &dyn FileSystem,
+    file_path: &Path,
+) -> bool {
+    let not_found = matches!(
+        error.downcast_ref::<PollError>(),
+        Some(PollError::Read { source, .. }) if source.kind() == std::io::ErrorKind::NotFound
+    );
+    not_found && !file_system.exists(file_path)
+}
 
-        while keep_running {
-            let mut file_count = 0;
-            for directory_iter in fs::read_dir(directory_path)? {
-                let directory_entry = directory_iter?;
-                let file_path = directory_entry.path();
-                // Only process regular files
-                // we will skip subdirectories, symlinks etc
-                if file_path.is_file() {
-                    file_count += 1;
-                    self.process_file(&file_path);
-                }
-            }
+// The source directory record of a file moved into the error directory
+fn source_dir_record(error_directory: &Path, file_path: &Path) -> PathBuf {
+    let mut record = file_path.file_name().unwrap_or_default().to_owned();
+    record.push(SOURCE_DIR_EXTENSION);
+    error_directory.join(record)
+}
 
-            // end of poll cycle
-            poll_cycles += 1;
-            if file_count == 0 {
-                log::info!("No files found on this poll cycle");
-            }
+fn is_source_dir_record(file_path: &Path) -> bool {
+    file_path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with(SOURCE_DIR_EXTENSION))
+}
 
-            keep_running = self.should_continue_polling(poll_cycles);
-        }
+// A failure to read the file keeps its own error type
+fn read_error(path: &Path, source: std::io::Error) -> Box<dyn Error> {
+    Box::new(PollError::Read {
+        path: path.to_path_buf(),
+        source,
+    })
+}
 
-        Ok(())
+// Name the file in the error unless the error already does
+// the retries are over by now, so a SinkError is unwrapped
+fn file_error(name: &str, error: Box<dyn Error>) -> Box<dyn Error> {
+    let error = match error.downcast::<SinkError>() {
+        Ok(sink_error) => sink_error.into_inner(),
+        Err(error) => error,
+    };
+    if error.is::<PollError>() {
+        return error;
     }
+    format!("Error processing content of file {:?}: {}", name, error).into()
+}
 
-    fn verify_directory(&self, directory_path: &Path) -> Result<(), Box<dyn Error>> {
-        if !directory_path.exists() {
-            return Err(format!(
-                "Directory does not exist: {}",
-                self.file_name(directory_path)
-            )
-            .into());
-        }
-        if !directory_path.is_dir() {
-            return Err(format!(
-                "Path is not a directory: {}",
-                self.file_name(directory_path)
-            )
-            .into());
-        }
-        Ok(())
+// Move a file into a directory (creating the directory if needed)
+// rename does not work across file systems
+// so fall back to copying and removing the original
+fn move_file(
+    file_system: &dyn FileSystem,
+    file_path: &Path,
+    directory: &Path,
+) -> std::io::Result<()> {
+    file_system.create_dir_all(directory)?;
+    let target = directory.join(file_path.file_name().unwrap_or_default());
+    if file_system.rename(file_path, &target).is_err() {
+        file_system.copy(file_path, &target)?;
+        file_system.remove(file_path)?;
     }
+    Ok(())
+}
 
-    // This will read the file content
-    // then pass that to a closure that can be used to process the content
-    // TODO: should we pass along Result using chaining instead of panicking in this function
-    fn process_file(&self, file_path: &Path) {
-        //-> Result<(), Box<dyn Error>> {
-        log::info!("Processing file: {:?}", self.file_name(file_path));
-
-        // options to handle Result
-        // 1) match or if let (can deal w/ error now)
-        // 2) unwrap/expect (panic on error)
-        // 3) try operator (?) (propagate error up)
-
-        // panic version of reading a file
-        let content = std::fs::read_to_string(file_path)
-            .unwrap_or_else(|_| panic!("Failed to read file {:?}", self.file_name(file_path)));
-
-        // panic version of processing file content
-        (self.on_file_content)(content.as_str()).unwrap_or_else(|_| {
-            panic!(
-                "Error processing content of file {:?}",
-                self.file_name(file_path)
-            )
-        });
+/// The effective settings of a DirectoryPoller for logging and troubleshooting
+/// the sink, callbacks and clock are left out
+/// (the poller holds no credentials, those stay with the sink)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PollerConfig {
+    pub keep_running: bool,
+    pub until_empty: bool,
+    pub poll_interval_millis: u64,
+    pub poll_jitter_millis: u64,
+    pub fixed_rate: bool,
+    pub watch: bool,
+    pub backoff_on_empty: bool,
+    pub max_poll_cycles: i32,
+    pub delete_after_cycles: i32,
+    pub max_files_per_cycle: usize,
+    pub max_inflight_bytes: Option<u64>,
+    pub min_free_disk_mb: u64,
+    pub expected_interval: Duration,
+    pub max_runtime: Duration,
+    pub flush_interval_millis: u64,
+    pub ordered: bool,
+    pub delete_files: bool,
+    pub error_directory: Option<PathBuf>,
+    pub retry_errors_on_startup: bool,
+    pub skip_hidden: bool,
+    pub claim_suffix: Option<String>,
+    pub recursive: bool,
+    pub topic_from_subdir: bool,
+    pub path_template: Option<String>,
+    pub file_order: FileOrder,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub manifest: Option<PathBuf>,
+    pub lock_file: Option<PathBuf>,
+    pub lock_directories: bool,
+    pub modified_since: Option<SystemTime>,
+    pub file_ttl: Duration,
+    pub empty_file_policy: EmptyFilePolicy,
+    pub require_name_regex: Option<String>,
+    pub name_mismatch_policy: NameMismatchPolicy,
+    pub utf8_policy: Utf8Policy,
+    pub dedup_mode: DedupMode,
+    pub visited_markers: VisitedMarkers,
+    pub key_strategy: KeyStrategy,
+    pub strip_extension: bool,
+    pub key_encoding: KeyEncoding,
+    pub envelope: EnvelopeFormat,
+    pub content_type_detection: ContentTypeDetection,
+    pub content_type: Option<String>,
+    pub batch: Option<BatchLimits>,
+    pub json_schema: bool,
+    pub compact_json: CompactJson,
+    pub verify_checksum: bool,
+    pub control_topic: Option<String>,
+}
 
-        self.delete_file(file_path);
+/// The overrides of a directory, with the globs compiled
+struct DirectorySettings {
+    directory: PathBuf,
+    topic: Option<String>,
+    key_strategy: Option<KeyStrategy>,
+    delete_files: Option<bool>,
+    include: Option<Vec<glob::Pattern>>,
+    exclude: Option<Vec<glob::Pattern>>,
+}
 
-        // match std::fs::read_to_string(file_path) {
-        //     Ok(content) => {
-        //         // process file content
-        //         // TODO: accept a lambda/function to process content
-        //         log::info!("File content: {}", content);
+impl DirectorySettings {
+    fn new(directory: &Path, overrides: &DirectoryOverrides) -> Result<Self, BuildError> {
+        let globs =
+            |patterns: &Option<Vec<String>>| patterns.as_deref().map(compile_globs).transpose();
+        Ok(DirectorySettings {
+            directory: directory.to_path_buf(),
+            topic: overrides.topic.clone(),
+            key_strategy: overrides.key_strategy,
+            delete_files: overrides.delete_files,
+            include: globs(&overrides.include)?,
+            exclude: globs(&overrides.exclude)?,
+        })
+    }
+}
 
-        //         // the file content is a String
-        //         // and it will go out of scope at the end of this block
-        //         // TODO: we probably should "move" ownership to our content handler
-        //         // but maybe NOT since we will not need the content to outlive this block
-        //         // TODO: our content handler will need to return Result
-        //         // and then we need to decide how to handle failures in content processing
+/// Counts of what the poller has done
+/// the totals aggregate across all directories
+/// and per_directory breaks them down in the order the directories were given
+#[derive(Debug, Clone, Default)]
+pub struct PollStats {
+    pub cycles: i32,
+    pub files_processed: u64,
+    // the bytes handed to the sink (after any transforms)
+    pub bytes_processed: u64,
+    pub files_failed: u64,
+    // empty files left out under EmptyFilePolicy::Skip
+    pub files_skipped: u64,
+    // files that were gone by the time they were read
+    pub files_vanished: u64,
+    pub per_directory: Vec<DirectoryStats>,
+    pub stage_times: StageTimes,
+}
 
-        //         // manage deletion of file
-        //         self.delete_file(file_path);
-        //     }
-        //     Err(e) => {
-        //         log::error!("Failed to read file {}: {}", self.file_name(file_path), e);
-        //     }
-        //}
-        //Ok(())
-    }
+/// The time spent in each stage of processing files
+/// delivery is the wait for the sink to confirm what it was sent (on flush)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StageTimes {
+    pub read: Duration,
+    pub transform: Duration,
+    pub publish: Duration,
+    pub delivery: Duration,
+    pub delete: Duration,
+}
 
-    // delete file if the delete_files flag is enabled
-    // if deletion fails, log the error
-    // but do not return an error from this function
-    fn delete_file(&self, file_path: &Path) {
-        if self.delete_files {
-            // delete file logic
-            if let Err(e) = std::fs::remove_file(file_path) {
-                // TODO: this can result in processing the file
-                // multiple times across poll cycles
-                log::error!("Failed to delete file {}: {}", self.file_name(file_path), e);
-            }
-        } else {
-            log::info!(
-                "File deletion is disabled, skipping deletion for file: {}",
-                self.file_name(file_path)
-            );
-        }
+impl StageTimes {
+    fn add(&mut self, stage: Stage, elapsed: Duration) {
+        let total = match stage {
+            Stage::Read => &mut self.read,
+            Stage::Transform => &mut self.transform,
+            Stage::Publish => &mut self.publish,
+            Stage::Delivery => &mut self.delivery,
+            Stage::Delete => &mut self.delete,
+        };
+        *total += elapsed;
     }
+}
 
-    fn should_continue_polling(&self, poll_cycles: i32) -> bool {
-        // max poll cycles takes precedence over the keep_running flag
-        let continue_polling = if self.max_poll_cycles <= 0 {
-            // max poll cycles is not enabled
-            // use the keep_running flag
-            self.keep_running
-        } else {
-            // max poll cycles is enabled
-            // only continue if we have not reached the max cycles
-            poll_cycles < self.max_poll_cycles
-        };
+/// What a single poll cycle did, handed to the on_cycle_complete callback
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CycleStats {
+    // counts from 1
+    pub cycle: i32,
+    pub files_processed: u64,
+    pub bytes_processed: u64,
+    pub files_failed: u64,
+    pub files_skipped: u64,
+    pub elapsed: Duration,
+}
 
-        // if we are going to keep running, sleep for the configured delay
-        if continue_polling {
-            std::thread::sleep(std::time::Duration::from_millis(self.poll_interval_millis));
-        }
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryStats {
+    pub directory: PathBuf,
+    pub files_processed: u64,
+}
 
-        continue_polling
+impl PollStats {
+    fn new(directories: &[&Path]) -> Self {
+        PollStats {
+            per_directory: directories
+                .iter()
+                .map(|directory| DirectoryStats {
+                    directory: directory.to_path_buf(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
     }
 
-    fn file_name(&self, path: &Path) -> String {
-        let file_name = path.file_name().unwrap_or_default();
-        file_name.to_string_lossy().to_string()
+    fn record_file(&mut self, directory_index: usize, bytes: u64) {
+        self.files_processed += 1;
+        self.bytes_processed += bytes;
+        self.per_directory[directory_index].files_processed += 1;
     }
 }
 
-/// Builder for DirectoryPoller
-pub struct DirectoryPollerBuilder {
-    keep_running: bool,
-    delete_files: bool,
-    poll_interval_millis: u64,
-    max_poll_cycles: i32,
-    // default no-op closure
-    on_file_content: FileContentCallback,
+// The totals when a poll cycle started
+// so the cycle's own share can be logged at its end
+struct CycleStart {
+    files_processed: u64,
+    bytes_processed: u64,
+    files_failed: u64,
+    files_skipped: u64,
+    started: Instant,
 }
 
-impl DirectoryPollerBuilder {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
-        DirectoryPollerBuilder {
-            keep_running: false,
-            delete_files: false,
-            poll_interval_millis: 1000,
-            max_poll_cycles: -1,
-            on_file_content: Box::new(|_content| Ok(())),
+impl CycleStart {
+    fn new(stats: &PollStats, started: Instant) -> Self {
+        CycleStart {
+            files_processed: stats.files_processed,
+            bytes_processed: stats.bytes_processed,
+            files_failed: stats.files_failed,
+            files_skipped: stats.files_skipped,
+            started,
         }
     }
 
-    pub fn keep_running(mut self, keep_running: bool) -> Self {
-        self.keep_running = keep_running;
-        self
-    }
-
-    pub fn delete_files(mut self, delete_files: bool) -> Self {
-        self.delete_files = delete_files;
-        self
+    // The cycle's own share of the totals
+    fn stats(&self, stats: &PollStats, now: Instant) -> CycleStats {
+        CycleStats {
+            cycle: stats.cycles,
+            files_processed: stats.files_processed - self.files_processed,
+            bytes_processed: stats.bytes_processed - self.bytes_processed,
+            files_failed: stats.files_failed - self.files_failed,
+            files_skipped: stats.files_skipped - self.files_skipped,
+            elapsed: now.saturating_duration_since(self.started),
+        }
     }
+}
 
-    pub fn poll_interval_millis(mut self, poll_interval_millis: u64) -> Self {
-        self.poll_interval_millis = poll_interval_millis;
-        self
-    }
+// A reader that counts the bytes read through it
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
 
-    pub fn max_poll_cycles(mut self, max_poll_cycles: i32) -> Self {
-        self.max_poll_cycles = max_poll_cycles;
-        self
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
     }
+}
 
-    // wrap the callback function in a Box to allow for dynamic dispatch
-    // and use static lifetime since we want the closure
-    // to be valid for the life of the DirectoryPoller
-    #[allow(dead_code)]
-    pub fn on_file_content<F>(mut self, callback: F) -> Self
-    where
-        F: Fn(&str) -> Result<(), Box<dyn Error>> + 'static,
-    {
-        self.on_file_content = Box::new(callback);
-        self
+// A byte count for the logs e.g. 3.1 MB
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000.0 {
+        return format!("{:.0} B", bytes);
     }
-
-    pub fn build(self) -> DirectoryPoller {
-        DirectoryPoller {
-            keep_running: self.keep_running,
-            delete_files: self.delete_files,
-            poll_interval_millis: self.poll_interval_millis,
-            max_poll_cycles: self.max_poll_cycles,
-            on_file_content: self.on_file_content,
-        }
+    let mut value = bytes / 1000.0;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
     }
+    format!("{:.1} {}", value, UNITS[unit])
 }
 
 /// unit tests for DirectoryPoller
@@ -250,6 +2754,68 @@ impl DirectoryPollerBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::filesystem::{FileMetadata, RealFileSystem};
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    #[test]
+    fn format_bytes_units() {
+        assert_eq!(format_bytes(42.0), "42 B");
+        assert_eq!(format_bytes(3_100_000.0), "3.1 MB");
+        assert_eq!(format_bytes(1_200.0), "1.2 KB");
+    }
+
+    #[test]
+    fn valid_topic_names() {
+        assert!(is_valid_topic("orders.v1_eu-west"));
+        assert!(!is_valid_topic(""));
+        assert!(!is_valid_topic(".."));
+        assert!(!is_valid_topic("not a topic"));
+        assert!(!is_valid_topic(&"a".repeat(250)));
+    }
+
+    #[test]
+    fn file_identifier_strips_single_extension() {
+        assert_eq!(file_identifier("order-123.json", true), "order-123");
+        assert_eq!(file_identifier("order-123.json", false), "order-123.json");
+    }
+
+    #[test]
+    fn file_identifier_strips_only_last_extension() {
+        assert_eq!(file_identifier("a.b.json", true), "a.b");
+        assert_eq!(file_identifier("orders.tar.gz", true), "orders.tar");
+    }
+
+    #[test]
+    fn file_identifier_without_extension() {
+        assert_eq!(file_identifier("README", true), "README");
+        assert_eq!(file_identifier(".env", true), ".env");
+    }
+
+    #[test]
+    fn key_encoding_of_numeric_key() {
+        assert_eq!(KeyEncoding::Utf8.encode("123").unwrap(), b"123");
+        assert_eq!(
+            KeyEncoding::I64be.encode("123").unwrap(),
+            [0, 0, 0, 0, 0, 0, 0, 123]
+        );
+        assert_eq!(
+            KeyEncoding::I32be.encode("-2").unwrap(),
+            [0xff, 0xff, 0xff, 0xfe]
+        );
+        assert_eq!(
+            KeyEncoding::Hex.encode("00Ab7f").unwrap(),
+            [0x00, 0xab, 0x7f]
+        );
+    }
+
+    #[test]
+    fn key_encoding_rejects_unparseable_keys() {
+        assert!(KeyEncoding::I64be.encode("order-123").is_err());
+        assert!(KeyEncoding::I32be.encode("4294967296").is_err());
+        assert!(KeyEncoding::Hex.encode("abc").is_err());
+    }
 
     #[test]
     fn verify_directory_fails() {
@@ -266,6 +2832,448 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn poll_errors_map_to_exit_codes() {
+        let io_error = || std::io::Error::other("failed");
+        let path = PathBuf::from("/data/incoming");
+        let cases = [
+            (PollError::DirectoryNotFound(path.clone()), EXIT_DIRECTORY),
+            (PollError::NotADirectory(path.clone()), EXIT_DIRECTORY),
+            (
+                PollError::Read {
+                    path: path.clone(),
+                    source: io_error(),
+                },
+                EXIT_DIRECTORY,
+            ),
+            (
+                PollError::Delete {
+                    path: path.clone(),
+                    source: io_error(),
+                },
+                EXIT_DIRECTORY,
+            ),
+            (
+                PollError::Locked {
+                    path: path.clone(),
+                    holder: "process 42".to_string(),
+                },
+                EXIT_DIRECTORY,
+            ),
+            (
+                PollError::Connection("all brokers down".into()),
+                EXIT_CONNECTION,
+            ),
+            (PollError::Callback("sink failed".into()), EXIT_PROCESSING),
+            (PollError::Silent(Duration::from_secs(3)), EXIT_SILENCE),
+            (
+                PollError::ManifestEntry {
+                    entry: "a.json".to_string(),
+                    reason: "does not exist",
+                },
+                EXIT_PROCESSING,
+            ),
+            (
+                PollError::Build(BuildError::StrictManifestWithoutManifest),
+                EXIT_USAGE,
+            ),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.exit_code(), code, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn display_path_relative_to_root() {
+        let root = Path::new("/data/incoming");
+        assert_eq!(
+            display_path(root, Path::new("/data/incoming/sample.txt")),
+            "sample.txt"
+        );
+        assert_eq!(
+            display_path(root, &root.join("orders").join("order-1.json")),
+            Path::new("orders").join("order-1.json").to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn display_path_outside_root_is_full_path() {
+        let root = Path::new("/data/incoming");
+        assert_eq!(
+            display_path(root, Path::new("/elsewhere/sample.txt")),
+            "/elsewhere/sample.txt"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn display_path_marks_non_utf8_name() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = Path::new("/data");
+        let path = root.join(OsStr::from_bytes(b"bad\xffname.txt"));
+        let name = display_path(root, &path);
+        assert_eq!(name, "bad\u{FFFD}name.txt (non-UTF-8)");
+        // a file really named with the replacement character is not marked
+        assert_ne!(name, display_path(root, &root.join("bad\u{FFFD}name.txt")));
+    }
+
+    #[test]
+    fn claim_lost_to_other_poller() {
+        let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+        let file_path = temp_dir.path().join("sample.txt");
+        fs::write(&file_path, "test content").expect("writing temp file failed");
+
+        let poller = DirectoryPoller::builder()
+            .claim_suffix(".inprogress".to_string())
+            .build();
+        // another poller renamed the file after it was listed
+        fs::rename(&file_path, temp_dir.path().join("sample.txt.inprogress"))
+            .expect("rename failed");
+
+        let outcome = poller
+            .claim_and_process_file(temp_dir.path(), &file_path)
+            .expect("claim failed");
+        assert!(matches!(outcome, FileOutcome::NotClaimed));
+    }
+
+    #[test]
+    fn only_a_missing_file_has_vanished() {
+        let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+        let present = temp_dir.path().join("present.txt");
+        fs::write(&present, "test content").expect("writing temp file failed");
+        let missing = temp_dir.path().join("missing.txt");
+        let io_error = |kind| std::io::Error::from(kind);
+
+        let not_found = read_error(&missing, io_error(std::io::ErrorKind::NotFound));
+        assert!(has_vanished(not_found.as_ref(), &RealFileSystem, &missing));
+        // e.g. a companion file that is missing while the file itself is there
+        let not_found = read_error(&present, io_error(std::io::ErrorKind::NotFound));
+        assert!(!has_vanished(not_found.as_ref(), &RealFileSystem, &present));
+        let denied = read_error(&missing, io_error(std::io::ErrorKind::PermissionDenied));
+        assert!(!has_vanished(denied.as_ref(), &RealFileSystem, &missing));
+    }
+
+    // file system holding the files in memory
+    #[derive(Default)]
+    struct MockFileSystem {
+        files: Mutex<HashMap<PathBuf, Vec<u8>>>,
+    }
+
+    impl FileSystem for MockFileSystem {
+        fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+            let files = self.files.lock().unwrap();
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+        }
+
+        fn write(&self, path: &Path, content: &[u8]) -> std::io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            files.insert(path.to_path_buf(), content.to_vec());
+            Ok(())
+        }
+
+        fn metadata(&self, path: &Path) -> std::io::Result<FileMetadata> {
+            if self.is_dir(path) {
+                return Ok(FileMetadata {
+                    len: 0,
+                    modified: None,
+                });
+            }
+            let content = self.read(path)?;
+            Ok(FileMetadata {
+                len: content.len() as u64,
+                modified: None,
+            })
+        }
+
+        fn remove(&self, path: &Path) -> std::io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            files
+                .remove(path)
+                .map(|_| ())
+                .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            let content = files.remove(from).ok_or(std::io::ErrorKind::NotFound)?;
+            files.insert(to.to_path_buf(), content);
+            Ok(())
+        }
+
+        // the directories are the parents of the files
+        fn create_dir_all(&self, _path: &Path) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn read_dir(&self, path: &Path) -> std::io::Result<Vec<PathBuf>> {
+            let files = self.files.lock().unwrap();
+            Ok(files
+                .keys()
+                .filter(|file_path| file_path.parent() == Some(path))
+                .cloned()
+                .collect())
+        }
+
+        fn is_file(&self, path: &Path) -> bool {
+            self.files.lock().unwrap().contains_key(path)
+        }
+
+        fn is_dir(&self, path: &Path) -> bool {
+            let files = self.files.lock().unwrap();
+            files
+                .keys()
+                .any(|file_path| file_path.starts_with(path) && file_path != path)
+        }
+    }
+
+    #[test]
+    fn process_file_uses_injected_file_system() {
+        let root = Path::new("/virtual/in");
+        let file_path = root.join("order-1.json");
+        let empty_path = root.join("empty.json");
+        let file_system = Arc::new(MockFileSystem::default());
+        {
+            let mut files = file_system.files.lock().unwrap();
+            files.insert(file_path.clone(), br#"{"id":1}"#.to_vec());
+            files.insert(empty_path.clone(), Vec::new());
+        }
+        let received = Rc::new(RefCell::new(Vec::new()));
+
+        let sink_received = Rc::clone(&received);
+        let poller = DirectoryPoller::builder()
+            .delete_files(true)
+            .empty_file_policy(EmptyFilePolicy::Skip)
+            .file_system(file_system.clone())
+            .on_file_content(move |content| {
+                sink_received.borrow_mut().push(content.to_string());
+                Ok(())
+            })
+            .build();
+
+        let outcome = poller
+            .process_file(root, &file_path, &file_path)
+            .expect("processing failed");
+        assert!(matches!(outcome, FileOutcome::Processed(8)));
+        assert_eq!(*received.borrow(), [r#"{"id":1}"#]);
+        let outcome = poller
+            .process_file(root, &empty_path, &empty_path)
+            .expect("processing failed");
+        assert!(matches!(outcome, FileOutcome::Skipped));
+        assert!(file_system.files.lock().unwrap().is_empty());
+
+        // gone from the file system between listing and reading
+        let outcome = poller
+            .process_file(root, &file_path, &file_path)
+            .expect("processing failed");
+        assert!(matches!(outcome, FileOutcome::Vanished));
+    }
+
+    #[test]
+    fn poll_directory_stays_on_injected_file_system() {
+        let root = Path::new("/virtual/in");
+        let file_system = Arc::new(MockFileSystem::default());
+        {
+            let mut files = file_system.files.lock().unwrap();
+            files.insert(root.join("order-1.json"), br#"{"id":1}"#.to_vec());
+            files.insert(root.join("order-1.json.meta"), br#"{"key":"1"}"#.to_vec());
+            files.insert(root.join("order-2.json"), b"not json".to_vec());
+        }
+
+        let poller = DirectoryPoller::builder()
+            .delete_files(true)
+            .poll_interval_millis(0)
+            .max_poll_cycles(1)
+            .file_order(FileOrder::Name)
+            .sidecar_extension(".meta".to_string())
+            .checkpoint_file(PathBuf::from("/virtual/checkpoint"))
+            .error_directory(PathBuf::from("/virtual/errors"))
+            .file_system(file_system.clone())
+            .on_file_content(|content| {
+                serde_json::from_str::<serde_json::Value>(content)?;
+                Ok(())
+            })
+            .build();
+        let stats = poller.poll_directory(root).expect("polling failed");
+        assert_eq!(stats.files_processed, 1);
+        assert_eq!(stats.files_failed, 1);
+
+        let files = file_system.files.lock().unwrap();
+        let mut paths: Vec<_> = files.keys().cloned().collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("/virtual/checkpoint"),
+                PathBuf::from("/virtual/errors/order-2.json"),
+                PathBuf::from("/virtual/errors/order-2.json.source-dir"),
+            ]
+        );
+        let checkpoint = String::from_utf8_lossy(&files[Path::new("/virtual/checkpoint")]);
+        assert!(
+            checkpoint.ends_with(" /virtual/in/order-1.json\n"),
+            "{}",
+            checkpoint
+        );
+    }
+
+    // the real file system, except that listing a directory fails the first few times
+    struct FlakyFileSystem {
+        failures_left: Mutex<u32>,
+        reads: Mutex<u32>,
+    }
+
+    impl FlakyFileSystem {
+        fn failing(failures: u32) -> Self {
+            FlakyFileSystem {
+                failures_left: Mutex::new(failures),
+                reads: Mutex::new(0),
+            }
+        }
+    }
+
+    impl FileSystem for FlakyFileSystem {
+        fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+            RealFileSystem.read(path)
+        }
+
+        fn write(&self, path: &Path, content: &[u8]) -> std::io::Result<()> {
+            RealFileSystem.write(path, content)
+        }
+
+        fn metadata(&self, path: &Path) -> std::io::Result<FileMetadata> {
+            RealFileSystem.metadata(path)
+        }
+
+        fn remove(&self, path: &Path) -> std::io::Result<()> {
+            RealFileSystem.remove(path)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+            RealFileSystem.rename(from, to)
+        }
+
+        fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+            RealFileSystem.create_dir_all(path)
+        }
+
+        fn is_file(&self, path: &Path) -> bool {
+            RealFileSystem.is_file(path)
+        }
+
+        fn is_dir(&self, path: &Path) -> bool {
+            RealFileSystem.is_dir(path)
+        }
+
+        fn is_symlink(&self, path: &Path) -> bool {
+            RealFileSystem.is_symlink(path)
+        }
+
+        fn read_dir(&self, path: &Path) -> std::io::Result<Vec<PathBuf>> {
+            *self.reads.lock().unwrap() += 1;
+            let mut failures_left = self.failures_left.lock().unwrap();
+            if *failures_left > 0 {
+                *failures_left -= 1;
+                return Err(std::io::Error::other("stale NFS file handle"));
+            }
+            RealFileSystem.read_dir(path)
+        }
+    }
+
+    #[test]
+    fn directory_read_retried_until_it_succeeds() {
+        let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+        fs::write(temp_dir.path().join("a.json"), "a").expect("writing temp file failed");
+        let file_system = Arc::new(FlakyFileSystem::failing(2));
+        let received = Rc::new(RefCell::new(Vec::new()));
+
+        let sink_received = Rc::clone(&received);
+        let poller = DirectoryPoller::builder()
+            .max_poll_cycles(1)
+            .directory_read_retries(3, Duration::from_millis(1))
+            .file_system(file_system.clone())
+            .on_file_content(move |content| {
+                sink_received.borrow_mut().push(content.to_string());
+                Ok(())
+            })
+            .build();
+
+        let stats = poller
+            .poll_directory(temp_dir.path())
+            .expect("polling failed");
+        assert_eq!(stats.files_processed, 1);
+        assert_eq!(*received.borrow(), ["a"]);
+        assert_eq!(*file_system.reads.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn unreadable_directory_skips_cycle_unless_strict() {
+        let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+        fs::write(temp_dir.path().join("a.json"), "a").expect("writing temp file failed");
+
+        let file_system = Arc::new(FlakyFileSystem::failing(u32::MAX));
+        let poller = DirectoryPoller::builder()
+            .max_poll_cycles(2)
+            .poll_interval_millis(1)
+            .directory_read_retries(1, Duration::from_millis(1))
+            .file_system(file_system.clone())
+            .build();
+        let stats = poller
+            .poll_directory(temp_dir.path())
+            .expect("polling failed");
+        assert_eq!(stats.cycles, 2);
+        assert_eq!(stats.files_processed, 0);
+        assert_eq!(*file_system.reads.lock().unwrap(), 4);
+
+        let poller = DirectoryPoller::builder()
+            .max_poll_cycles(2)
+            .directory_read_retries(1, Duration::from_millis(1))
+            .strict_directory_read(true)
+            .file_system(Arc::new(FlakyFileSystem::failing(u32::MAX)))
+            .build();
+        let result = poller.poll_directory(temp_dir.path());
+        assert!(matches!(result, Err(PollError::Read { .. })));
+    }
+
+    #[test]
+    fn poll_interval_fixed_by_default() {
+        let poller = DirectoryPoller::builder().poll_interval_millis(100).build();
+        assert_eq!(poller.next_poll_interval_millis(0), 100);
+        assert_eq!(poller.next_poll_interval_millis(5), 100);
+    }
+
+    #[test]
+    fn poll_interval_grows_after_empty_cycles() {
+        let poller = DirectoryPoller::builder()
+            .poll_interval_millis(100)
+            .backoff_on_empty(true)
+            .max_backoff_millis(1_000)
+            .build();
+        assert_eq!(poller.next_poll_interval_millis(0), 100);
+        assert_eq!(poller.next_poll_interval_millis(1), 200);
+        assert_eq!(poller.next_poll_interval_millis(2), 400);
+        assert_eq!(poller.next_poll_interval_millis(3), 800);
+        // capped
+        assert_eq!(poller.next_poll_interval_millis(4), 1_000);
+        assert_eq!(poller.next_poll_interval_millis(100), 1_000);
+    }
+
+    #[test]
+    fn poll_interval_jitter_in_range() {
+        let poller = DirectoryPoller::builder()
+            .poll_interval_millis(100)
+            .poll_jitter_millis(50)
+            .build();
+        for _ in 0..20 {
+            let interval = poller.next_poll_interval_millis(0);
+            assert!((100..=150).contains(&interval));
+        }
+    }
+
     #[test]
     fn call_on_file_content() {
         let temp_dir = tempfile::tempdir().expect("create temp dir failed");
@@ -280,7 +3288,7 @@ mod tests {
                 Ok(())
             })
             .build();
-        let result = poller.poll_directory(temp_dir.path().to_str().unwrap());
+        let result = poller.poll_directory(temp_dir.path());
         assert!(result.is_ok());
         assert!(file_path.exists());
     }
@@ -310,15 +3318,13 @@ mod tests {
             })
             .build();
 
-        let result = poller.poll_directory(temp_dir.path().to_str().unwrap());
+        let result = poller.poll_directory(temp_dir.path());
         assert!(result.is_ok());
         assert!(file_path.exists());
         assert_eq!(call_count.get(), poll_cycles);
     }
 
     #[test]
-    //#[should_panic(expected = "Simulated error in callback")]
-    #[should_panic(expected = "Error processing content of file \"test_unit.txt\"")]
     fn call_on_file_content_error() {
         let temp_dir = tempfile::tempdir().expect("create temp dir failed");
         let file_path = temp_dir.path().join("test_unit.txt");
@@ -332,9 +3338,12 @@ mod tests {
                 Err("Simulated error in callback".into())
             })
             .build();
-        let _ = poller.poll_directory(temp_dir.path().to_str().unwrap());
-        // TODO: we should be able to propagate the error instead of panicking in the callback
-        // assert!(result.is_err());
-        // assert!(file_path.exists());
+        let result = poller.poll_directory(temp_dir.path());
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Error processing content of file \"test_unit.txt\""));
+        assert!(file_path.exists());
     }
 }
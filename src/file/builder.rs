@@ -0,0 +1,1250 @@
+/// Building a DirectoryPoller
+///
+/// Every option has a setter on DirectoryPollerBuilder
+/// try_build checks the combination of options before the poller is put together
+use super::{
+    compile_globs, is_valid_topic, CycleCallback, CycleStats, DirectoryPoller, DirectorySettings,
+    EmptyFilePolicy, FileOrder, FileReaderCallback, FileRouteCallback, KeyEncoding, KeyStrategy,
+    NameMismatchPolicy, PollStats, StageTimes, StatsCallback, Utf8Policy,
+    DEFAULT_DIRECTORY_READ_BACKOFF, DEFAULT_DIRECTORY_READ_RETRIES,
+};
+use crate::batch::{Batch, BatchLimits};
+use crate::budget::{ByteBudget, RetryBudget};
+use crate::clock::{Clock, SystemClock};
+use crate::content::{
+    load_json_schema, CompactJson, ContentProcessor, ContentTransform, PayloadPath,
+};
+use crate::content_type::{self, ContentTypeDetection};
+use crate::dedup::{DedupMode, PublishedFiles, VisitedMarkers, DEFAULT_DEDUP_CAPACITY};
+use crate::disk::{DiskSpace, SystemDiskSpace};
+use crate::envelope::EnvelopeFormat;
+use crate::filesystem::{FileSystem, RealFileSystem};
+use crate::overrides::DirectoryOverrides;
+use crate::path_template::PathTemplate;
+use crate::rate::RateLimiter;
+use crate::sink::{
+    ClosureSink, FanoutMode, FanoutSink, FileContext, FileSink, PublishOutcome, TimeoutSink,
+};
+use crate::status::SharedStatus;
+use crate::template::PayloadTemplate;
+use regex::Regex;
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// A combination of builder options that cannot work
+#[derive(Debug, thiserror::Error)]
+pub enum BuildError {
+    #[error("Invalid file glob {pattern:?}: {message}")]
+    InvalidGlob { pattern: String, message: String },
+    #[error("Failed to load JSON schema {}: {message}", path.display())]
+    InvalidJsonSchema { path: PathBuf, message: String },
+    #[error("require_checksum has no effect without verify_checksum")]
+    RequireChecksumWithoutVerify,
+    #[error("retry_errors_on_startup needs an error directory")]
+    RetryErrorsWithoutErrorDirectory,
+    #[error("callback_timeout needs a callback set with on_file_content_send")]
+    CallbackTimeoutWithoutCallback,
+    #[error("The claim suffix cannot be empty")]
+    EmptyClaimSuffix,
+    #[error("The claim suffix and the sidecar extension are both {0:?}")]
+    ClaimSuffixIsSidecarExtension(String),
+    #[error("The JSON envelope cannot be used with on_file_reader")]
+    EnvelopeWithFileReader,
+    #[error("The payload template cannot be used with on_file_reader")]
+    PayloadTemplateWithFileReader,
+    #[error("The payload JSON path cannot be used with on_file_reader")]
+    PayloadPathWithFileReader,
+    #[error("Truncating payloads cannot be used with on_file_reader")]
+    TruncateWithFileReader,
+    #[error("Batching files cannot be used with on_file_reader")]
+    BatchWithFileReader,
+    #[error("topic_from_subdir and a path template both set the topic")]
+    PathTemplateWithTopicFromSubdir,
+    #[error("Invalid control topic {0:?}")]
+    InvalidControlTopic(String),
+    #[error("strict_manifest has no effect without a manifest")]
+    StrictManifestWithoutManifest,
+    #[error("poll_directory_async does not support {}", .0.join(", "))]
+    NotSupportedByAsync(Vec<&'static str>),
+}
+
+/// Builder for DirectoryPoller
+pub struct DirectoryPollerBuilder {
+    keep_running: bool,
+    until_empty: bool,
+    delete_files: bool,
+    poll_interval_millis: u64,
+    poll_jitter_millis: u64,
+    fixed_rate: bool,
+    backoff_on_empty: bool,
+    max_backoff_millis: u64,
+    max_poll_cycles: i32,
+    delete_after_cycles: i32,
+    max_files_per_cycle: usize,
+    max_runtime: Duration,
+    flush_interval_millis: u64,
+    ordered: bool,
+    control_topic: Option<String>,
+    watch: bool,
+    heartbeat_every: u32,
+    // None leaves the message out
+    empty_cycle_log_level: Option<log::Level>,
+    file_log_level: Option<log::Level>,
+    cycle_log_level: Option<log::Level>,
+    file_order: FileOrder,
+    recursive: bool,
+    topic_from_subdir: bool,
+    path_template: Option<PathTemplate>,
+    modified_since: Option<SystemTime>,
+    replay: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    directory_overrides: Vec<(PathBuf, DirectoryOverrides)>,
+    empty_file_policy: EmptyFilePolicy,
+    // every file name has to match this, misbehaving producers are flagged rather than published
+    require_name_regex: Option<Regex>,
+    name_mismatch_policy: NameMismatchPolicy,
+    utf8_policy: Utf8Policy,
+    dedup_mode: DedupMode,
+    dedup_capacity: usize,
+    visited_markers: VisitedMarkers,
+    file_ttl: Duration,
+    key_strategy: KeyStrategy,
+    strip_extension: bool,
+    key_encoding: KeyEncoding,
+    tombstone_on_empty: bool,
+    envelope: EnvelopeFormat,
+    content_type_detection: ContentTypeDetection,
+    content_type: Option<String>,
+    payload_template: Option<PayloadTemplate>,
+    payload_path: Option<PayloadPath>,
+    compact_json: bool,
+    truncate_to_bytes: Option<usize>,
+    batch_limits: Option<BatchLimits>,
+    schema_id: Option<u32>,
+    sidecar_extension: Option<String>,
+    verify_checksum: bool,
+    require_checksum: bool,
+    // pass over files whose name starts with a dot (editor swap files, partial writes)
+    skip_hidden: bool,
+    claim_suffix: Option<String>,
+    error_directory: Option<PathBuf>,
+    // move the files in the error directory back to be published again when polling starts
+    retry_errors_on_startup: bool,
+    // only those modified this recently, zero retries every file
+    retry_errors_max_age: Duration,
+    checkpoint_file: Option<PathBuf>,
+    manifest: Option<PathBuf>,
+    strict_manifest: bool,
+    // how often listing a directory that failed to read is tried again within the cycle
+    directory_read_retries: u32,
+    directory_read_backoff: Duration,
+    // fail the poll once the retries are used up instead of skipping the cycle
+    strict_directory_read: bool,
+    lock_file: Option<PathBuf>,
+    // also lock the default lock file in each polled directory
+    lock_directories: bool,
+    // default no-op sink
+    sink: Box<dyn FileSink>,
+    // replaces the sink, built with the callback timeout
+    callback_sink: Option<TimeoutSink>,
+    // every file is also sent to these, after the sink
+    fanout_sinks: Vec<Box<dyn FileSink>>,
+    fanout_mode: FanoutMode,
+    callback_timeout: Option<Duration>,
+    on_file_reader: Option<FileReaderCallback>,
+    on_file_route: Option<FileRouteCallback>,
+    on_cycle_complete: Option<CycleCallback>,
+    on_stats: Option<StatsCallback>,
+    transforms: Vec<ContentTransform>,
+    json_schema: Option<PathBuf>,
+    max_files_per_second: u32,
+    shutdown_flag: Arc<AtomicBool>,
+    // None is a flag that is never set
+    pause_flag: Option<Arc<AtomicBool>>,
+    inflight_budget: Option<Arc<ByteBudget>>,
+    // how often a send that failed with SinkError::Retriable is tried again
+    send_retries: u32,
+    send_retry_backoff: Duration,
+    // retries shared with the rest of the run, unlimited when None
+    retry_budget: Option<Arc<RetryBudget>>,
+    min_free_disk_mb: u64,
+    disk_space: Arc<dyn DiskSpace>,
+    expected_interval: Duration,
+    exit_on_silence: bool,
+    clock: Arc<dyn Clock>,
+    // None is the real file system
+    file_system: Option<Arc<dyn FileSystem>>,
+    status: Option<SharedStatus>,
+}
+
+impl DirectoryPollerBuilder {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        DirectoryPollerBuilder {
+            keep_running: false,
+            until_empty: false,
+            delete_files: false,
+            poll_interval_millis: 1000,
+            poll_jitter_millis: 0,
+            fixed_rate: false,
+            backoff_on_empty: false,
+            max_backoff_millis: 60_000,
+            max_poll_cycles: -1,
+            delete_after_cycles: 0,
+            max_files_per_cycle: 0,
+            max_runtime: Duration::ZERO,
+            flush_interval_millis: 0,
+            ordered: false,
+            control_topic: None,
+            watch: false,
+            heartbeat_every: 1,
+            empty_cycle_log_level: Some(log::Level::Info),
+            file_log_level: Some(log::Level::Info),
+            cycle_log_level: Some(log::Level::Info),
+            file_order: FileOrder::Unsorted,
+            recursive: false,
+            topic_from_subdir: false,
+            path_template: None,
+            modified_since: None,
+            replay: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            directory_overrides: Vec::new(),
+            empty_file_policy: EmptyFilePolicy::Process,
+            require_name_regex: None,
+            name_mismatch_policy: NameMismatchPolicy::Reject,
+            utf8_policy: Utf8Policy::Strict,
+            dedup_mode: DedupMode::Off,
+            dedup_capacity: DEFAULT_DEDUP_CAPACITY,
+            visited_markers: VisitedMarkers::Off,
+            file_ttl: Duration::ZERO,
+            key_strategy: KeyStrategy::None,
+            strip_extension: false,
+            key_encoding: KeyEncoding::Utf8,
+            tombstone_on_empty: false,
+            envelope: EnvelopeFormat::Raw,
+            content_type_detection: ContentTypeDetection::Off,
+            content_type: None,
+            payload_template: None,
+            payload_path: None,
+            compact_json: false,
+            truncate_to_bytes: None,
+            batch_limits: None,
+            schema_id: None,
+            sidecar_extension: None,
+            verify_checksum: false,
+            require_checksum: false,
+            skip_hidden: true,
+            claim_suffix: None,
+            error_directory: None,
+            retry_errors_on_startup: false,
+            retry_errors_max_age: Duration::ZERO,
+            checkpoint_file: None,
+            manifest: None,
+            strict_manifest: false,
+            directory_read_retries: DEFAULT_DIRECTORY_READ_RETRIES,
+            directory_read_backoff: DEFAULT_DIRECTORY_READ_BACKOFF,
+            strict_directory_read: false,
+            lock_file: None,
+            lock_directories: false,
+            sink: Box::new(ClosureSink::new(|_content| Ok(()))),
+            callback_sink: None,
+            fanout_sinks: Vec::new(),
+            fanout_mode: FanoutMode::default(),
+            callback_timeout: None,
+            on_file_reader: None,
+            on_file_route: None,
+            on_cycle_complete: None,
+            on_stats: None,
+            transforms: Vec::new(),
+            json_schema: None,
+            max_files_per_second: 0,
+            shutdown_flag: Arc::new(AtomicBool::new(false)),
+            pause_flag: None,
+            inflight_budget: None,
+            send_retries: 0,
+            send_retry_backoff: Duration::ZERO,
+            retry_budget: None,
+            min_free_disk_mb: 0,
+            disk_space: Arc::new(SystemDiskSpace),
+            expected_interval: Duration::ZERO,
+            exit_on_silence: false,
+            clock: Arc::new(SystemClock),
+            file_system: None,
+            status: None,
+        }
+    }
+
+    pub fn keep_running(mut self, keep_running: bool) -> Self {
+        self.keep_running = keep_running;
+        self
+    }
+
+    // keep polling while files are found
+    // and stop after the first poll cycle that finds no files
+    pub fn until_empty(mut self, until_empty: bool) -> Self {
+        self.until_empty = until_empty;
+        self
+    }
+
+    pub fn delete_files(mut self, delete_files: bool) -> Self {
+        self.delete_files = delete_files;
+        self
+    }
+
+    pub fn poll_interval_millis(mut self, poll_interval_millis: u64) -> Self {
+        self.poll_interval_millis = poll_interval_millis;
+        self
+    }
+
+    // add a random delay in [0, jitter] to each sleep between poll cycles
+    pub fn poll_jitter_millis(mut self, poll_jitter_millis: u64) -> Self {
+        self.poll_jitter_millis = poll_jitter_millis;
+        self
+    }
+
+    // start a cycle every poll interval rather than waiting the interval after each cycle
+    // so the time a cycle takes does not push the schedule back
+    // a cycle that takes longer than the interval is followed straight away by the next one
+    pub fn fixed_rate(mut self, fixed_rate: bool) -> Self {
+        self.fixed_rate = fixed_rate;
+        self
+    }
+
+    // double the poll interval after each consecutive empty cycle
+    // the interval resets once files appear again
+    pub fn backoff_on_empty(mut self, backoff_on_empty: bool) -> Self {
+        self.backoff_on_empty = backoff_on_empty;
+        self
+    }
+
+    // the longest the interval can grow to when backing off
+    pub fn max_backoff_millis(mut self, max_backoff_millis: u64) -> Self {
+        self.max_backoff_millis = max_backoff_millis;
+        self
+    }
+
+    pub fn max_poll_cycles(mut self, max_poll_cycles: i32) -> Self {
+        self.max_poll_cycles = max_poll_cycles;
+        self
+    }
+
+    // keep published files on disk until this cycle of the poller's life
+    // so operators can check publishing works before any data is removed
+    // the files published before then are deleted (not published again) when it starts
+    // 0 deletes from the first cycle (not supported by poll_directory_async)
+    pub fn delete_after_cycles(mut self, delete_after_cycles: i32) -> Self {
+        self.delete_after_cycles = delete_after_cycles;
+        self
+    }
+
+    // process at most this many files per poll cycle (across all the directories)
+    // the files are taken in order and the rest wait for the next cycle
+    // so a large backlog cannot hold off the shutdown and runtime checks between cycles
+    // zero means no limit
+    pub fn max_files_per_cycle(mut self, max_files_per_cycle: usize) -> Self {
+        self.max_files_per_cycle = max_files_per_cycle;
+        self
+    }
+
+    // stop polling once this much time has passed since polling started
+    // checked between files (the file being processed is finished) and between cycles
+    // the earliest of this, max_poll_cycles and until_empty stops the poller
+    // zero means no limit
+    pub fn max_runtime(mut self, max_runtime: Duration) -> Self {
+        self.max_runtime = max_runtime;
+        self
+    }
+
+    // only log every n-th consecutive empty poll cycle
+    // so an idle poller does not flood the logs
+    pub fn heartbeat_every(mut self, heartbeat_every: u32) -> Self {
+        self.heartbeat_every = heartbeat_every;
+        self
+    }
+
+    // the level of the "No files found" message, None leaves it out
+    pub fn empty_cycle_log_level(mut self, level: Option<log::Level>) -> Self {
+        self.empty_cycle_log_level = level;
+        self
+    }
+
+    // the level of the "Processing file" message logged for each file, None leaves it out
+    pub fn file_log_level(mut self, level: Option<log::Level>) -> Self {
+        self.file_log_level = level;
+        self
+    }
+
+    // the level of the summary logged after each cycle that processed files, None leaves it out
+    pub fn cycle_log_level(mut self, level: Option<log::Level>) -> Self {
+        self.cycle_log_level = level;
+        self
+    }
+
+    // flush the sink at the end of a poll cycle
+    // once at least this long has passed since the last flush
+    // files sent in between are only deleted once the flush succeeds
+    // and stay on disk when it fails (including the flush when polling stops)
+    // 0 disables periodic flushing (the sink is still flushed when polling stops)
+    pub fn flush_interval_millis(mut self, flush_interval_millis: u64) -> Self {
+        self.flush_interval_millis = flush_interval_millis;
+        self
+    }
+
+    // flush the sink after every file so a file is only done (and deleted)
+    // once everything sent for it has been delivered
+    // and the next file is not sent before that
+    // this keeps strict ordering for sinks that buffer sends at the cost of throughput
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+
+    // after each cycle that processed files send a JSON marker to this topic
+    // e.g. {"cycle": 3, "files": 10, "bytes": 2048, "timestamp": 1700000000}
+    // the sink is flushed first so the marker follows the delivery of every file in the cycle
+    // a marker that cannot be sent is logged and does not fail the cycle
+    // (not supported by poll_directory_async)
+    pub fn control_topic(mut self, control_topic: String) -> Self {
+        self.control_topic = Some(control_topic);
+        self
+    }
+
+    // start the next poll cycle as soon as a file is added to a polled directory
+    // rather than waiting out the poll interval, which remains as a fallback
+    // the wait uses the real time, not the clock (not supported by poll_directory_async)
+    pub fn watch(mut self, watch: bool) -> Self {
+        self.watch = watch;
+        self
+    }
+
+    // the order files are processed in within each directory
+    pub fn file_order(mut self, file_order: FileOrder) -> Self {
+        self.file_order = file_order;
+        self
+    }
+
+    // only process files whose name matches one of the include globs
+    // e.g. "*.json" (every file is included when none are given)
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    // skip files whose name matches an exclude glob
+    // excludes win over includes
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    // give the files in the directory (and below it) their own topic, key strategy,
+    // delete policy or globs, the rest of the settings stay global
+    pub fn directory_overrides(
+        mut self,
+        directory: PathBuf,
+        overrides: DirectoryOverrides,
+    ) -> Self {
+        self.directory_overrides.push((directory, overrides));
+        self
+    }
+
+    // how the message key is derived from each file
+    // a sidecar or the route callback can still override it
+    pub fn key_strategy(mut self, key_strategy: KeyStrategy) -> Self {
+        self.key_strategy = key_strategy;
+        self
+    }
+
+    // leave the final extension out of identifiers taken from file names
+    // so with KeyStrategy::FileName order-123.json has the key order-123
+    pub fn strip_extension(mut self, strip_extension: bool) -> Self {
+        self.strip_extension = strip_extension;
+        self
+    }
+
+    // encode the key string as bytes this way, e.g. as a big-endian integer
+    // a key that does not parse fails the file
+    pub fn key_encoding(mut self, key_encoding: KeyEncoding) -> Self {
+        self.key_encoding = key_encoding;
+        self
+    }
+
+    // publish a tombstone (the key with a null value) for an empty file
+    // or a file ending in .delete e.g. order-123.json.delete
+    // the file must have a key (from the key strategy, a sidecar or the route callback)
+    pub fn tombstone_on_empty(mut self, tombstone_on_empty: bool) -> Self {
+        self.tombstone_on_empty = tombstone_on_empty;
+        self
+    }
+
+    // wrap the content of each file in a template e.g. "BEGIN {file_name}\n{content}\nEND"
+    // applied after the transforms and the JSON schema check (and inside the envelope)
+    pub fn payload_template(mut self, payload_template: PayloadTemplate) -> Self {
+        self.payload_template = Some(payload_template);
+        self
+    }
+
+    // publish only the value at this JSON path of each file
+    // files that are not JSON or lack the value fail
+    pub fn payload_path(mut self, payload_path: PayloadPath) -> Self {
+        self.payload_path = Some(payload_path);
+        self
+    }
+
+    // minify the JSON of files with a .json extension (or of every file
+    // when the content_type is JSON) before the payload path and the transforms see it
+    // a file that is not valid JSON fails
+    pub fn compact_json(mut self, compact_json: bool) -> Self {
+        self.compact_json = compact_json;
+        self
+    }
+
+    // publish the head of payloads longer than this with truncated and original size headers
+    pub fn truncate_to_bytes(mut self, truncate_to_bytes: usize) -> Self {
+        self.truncate_to_bytes = Some(truncate_to_bytes);
+        self
+    }
+
+    // join the files into one message per batch instead of a message per file
+    // the files of a batch are deleted once the batch is delivered
+    // (not supported by poll_directory_async)
+    pub fn batch(mut self, batch_limits: BatchLimits) -> Self {
+        self.batch_limits = Some(batch_limits);
+        self
+    }
+
+    // frame every payload in the schema registry wire format (magic byte and schema id)
+    // last of all, so around the envelope when there is one
+    pub fn schema_id(mut self, schema_id: u32) -> Self {
+        self.schema_id = Some(schema_id);
+        self
+    }
+
+    // publish each file as a JSON envelope with its name, size and modified time
+    // and the content base64 encoded (not for streamed files or poll_directory_async)
+    pub fn envelope(mut self, envelope: EnvelopeFormat) -> Self {
+        self.envelope = envelope;
+        self
+    }
+
+    // add a content-type header to each message
+    // worked out from the file extension or magic bytes (application/octet-stream if unknown)
+    // a content-type header from a sidecar wins, and a JSON envelope is application/json
+    // (not for streamed files or poll_directory_async)
+    pub fn content_type_detection(mut self, content_type_detection: ContentTypeDetection) -> Self {
+        self.content_type_detection = content_type_detection;
+        self
+    }
+
+    // give every message this content-type header instead of detecting it
+    pub fn content_type(mut self, content_type: String) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    // how zero-byte files are handled
+    // EmptyFilePolicy::Error sends them down the error path
+    pub fn empty_file_policy(mut self, empty_file_policy: EmptyFilePolicy) -> Self {
+        self.empty_file_policy = empty_file_policy;
+        self
+    }
+
+    // never publish a file whose name does not match the regex (e.g. ^[a-z]+-\d+\.json$)
+    // unlike the include globs a mismatch is a problem, see name_mismatch_policy
+    // (not supported by poll_directory_async)
+    pub fn require_name_regex(mut self, require_name_regex: Regex) -> Self {
+        self.require_name_regex = Some(require_name_regex);
+        self
+    }
+
+    // whether a file with a name that does not match fails or is skipped
+    pub fn name_mismatch_policy(mut self, name_mismatch_policy: NameMismatchPolicy) -> Self {
+        self.name_mismatch_policy = name_mismatch_policy;
+        self
+    }
+
+    // how files that are not valid UTF-8 are handled, checked before the transforms
+    // Utf8Policy::Skip leaves them out like skipped empty files (still deleted if deletion is enabled)
+    pub fn utf8_policy(mut self, utf8_policy: Utf8Policy) -> Self {
+        self.utf8_policy = utf8_policy;
+        self
+    }
+
+    // skip files that were already published in this run
+    // they are deleted (or kept with delete_files off) without being sent again
+    // (not supported by poll_directory_async)
+    pub fn dedup_mode(mut self, dedup_mode: DedupMode) -> Self {
+        self.dedup_mode = dedup_mode;
+        self
+    }
+
+    // how many published files dedup_mode remembers, the oldest is forgotten first
+    pub fn dedup_capacity(mut self, dedup_capacity: usize) -> Self {
+        self.dedup_capacity = dedup_capacity;
+        self
+    }
+
+    // write a marker (file.done) next to each published file that is kept
+    // and skip files that have one, without remembering anything in memory
+    // (not supported by poll_directory_async)
+    pub fn visited_markers(mut self, visited_markers: VisitedMarkers) -> Self {
+        self.visited_markers = visited_markers;
+        self
+    }
+
+    // drop files last modified longer ago than this instead of publishing stale data
+    // they are skipped and deleted like any processed file (kept with delete_files off)
+    // zero means files never expire (not supported by poll_directory_async)
+    pub fn file_ttl(mut self, file_ttl: Duration) -> Self {
+        self.file_ttl = file_ttl;
+        self
+    }
+
+    // also poll the subdirectories of each directory (and theirs)
+    // the subdirectories are left in place when their files are deleted
+    // (not supported by poll_directory_async)
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    // only pick up files modified after this time
+    pub fn modified_since(mut self, modified_since: SystemTime) -> Self {
+        self.modified_since = Some(modified_since);
+        self
+    }
+
+    // publish every file once and leave the directory as it is
+    // this overrides the polling, deletion, claim and error directory options
+    // the first file that fails stops the replay
+    pub fn replay(mut self, replay: bool) -> Self {
+        self.replay = replay;
+        self
+    }
+
+    // publish each file to the topic named after the directory holding it
+    // e.g. root/orders/a.json goes to orders, this polls recursively
+    // files directly in the polled directory go to the sink's topic
+    // a directory that is not a valid topic name fails its files
+    pub fn topic_from_subdir(mut self, topic_from_subdir: bool) -> Self {
+        self.topic_from_subdir = topic_from_subdir;
+        self
+    }
+
+    // take the topic, key and headers of each file from its path under the polled directory
+    // e.g. with {topic}/{key}/* root/orders/cust-42/a.json goes to orders with key cust-42
+    // this polls recursively and a file whose path does not match the template fails
+    pub fn path_template(mut self, path_template: PathTemplate) -> Self {
+        self.path_template = Some(path_template);
+        self
+    }
+
+    // read the message key and headers for a file from a sidecar next to it
+    // e.g. with ".meta" order-123.json takes its metadata from order-123.json.meta
+    // the sidecar is deleted or moved to the error directory along with the file
+    // (the key and headers are not applied to on_file_reader)
+    pub fn sidecar_extension(mut self, sidecar_extension: String) -> Self {
+        self.sidecar_extension = Some(sidecar_extension);
+        self
+    }
+
+    // check each file against the SHA-256 digest in a .sha256 file next to it
+    // (e.g. order-123.json.sha256) and send mismatches down the error path
+    // the checksum file is deleted or moved along with the file
+    pub fn verify_checksum(mut self, verify_checksum: bool) -> Self {
+        self.verify_checksum = verify_checksum;
+        self
+    }
+
+    // with verify_checksum a file without a checksum file is an error
+    pub fn require_checksum(mut self, require_checksum: bool) -> Self {
+        self.require_checksum = require_checksum;
+        self
+    }
+
+    // pass over files whose name starts with a dot, e.g. .order.json.swp (the default)
+    // false publishes them like any other file
+    pub fn skip_hidden(mut self, skip_hidden: bool) -> Self {
+        self.skip_hidden = skip_hidden;
+        self
+    }
+
+    // rename each file to <file><claim suffix> (e.g. order-123.json.inprogress) before processing it
+    // so pollers sharing a directory never publish the same file
+    // a file another poller has claimed is passed over
+    // the claimed file is renamed back if it fails (or is not deleted)
+    pub fn claim_suffix(mut self, claim_suffix: String) -> Self {
+        self.claim_suffix = Some(claim_suffix);
+        self
+    }
+
+    // after each file write "<unix seconds> <file path>" to this file
+    // for auditing (it is not read back when the poller starts)
+    pub fn checkpoint_file(mut self, checkpoint_file: PathBuf) -> Self {
+        self.checkpoint_file = Some(checkpoint_file);
+        self
+    }
+
+    // only process the files listed in this file (one path per line) in the listed order
+    // instead of scanning the directory, relative paths are under the polled directory
+    // blank lines and lines starting with # are ignored
+    // the manifest is read again every cycle (not supported by poll_directory_async)
+    pub fn manifest(mut self, manifest: PathBuf) -> Self {
+        self.manifest = Some(manifest);
+        self
+    }
+
+    // fail on a manifest entry that is missing or outside the polled directory
+    // rather than logging and skipping it
+    pub fn strict_manifest(mut self, strict_manifest: bool) -> Self {
+        self.strict_manifest = strict_manifest;
+        self
+    }
+
+    // list a directory that failed to read again, waiting backoff before the first retry
+    // and doubling the wait after that, 0 gives up straight away
+    // (not supported by poll_directory_async)
+    pub fn directory_read_retries(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.directory_read_retries = max_retries;
+        self.directory_read_backoff = backoff;
+        self
+    }
+
+    // fail the poll on a directory that still cannot be read after the retries
+    // rather than logging and skipping the rest of the cycle
+    // (not supported by poll_directory_async)
+    pub fn strict_directory_read(mut self, strict_directory_read: bool) -> Self {
+        self.strict_directory_read = strict_directory_read;
+        self
+    }
+
+    // hold a lock on this file while polling so a second instance fails to start
+    // instead of publishing the same files, the lock file itself is never published
+    // (not supported by poll_directory_async)
+    pub fn lock_file(mut self, lock_file: PathBuf) -> Self {
+        self.lock_file = Some(lock_file);
+        self
+    }
+
+    // hold a lock on .kafka_pub_cli.lock in every polled directory while polling
+    // so a second instance on any one of them fails to start
+    // (not supported by poll_directory_async)
+    pub fn lock_directories(mut self, lock_directories: bool) -> Self {
+        self.lock_directories = lock_directories;
+        self
+    }
+
+    // files that fail are moved here so polling can continue
+    pub fn error_directory(mut self, error_directory: PathBuf) -> Self {
+        self.error_directory = Some(error_directory);
+        self
+    }
+
+    // before the first poll move the files in the error directory back into the
+    // directory recorded for each (else the first polled one) so they are published again
+    // those that fail return
+    // (not supported by poll_directory_async)
+    pub fn retry_errors_on_startup(mut self, retry_errors_on_startup: bool) -> Self {
+        self.retry_errors_on_startup = retry_errors_on_startup;
+        self
+    }
+
+    // only retry error files modified less than this long ago
+    // zero (the default) retries them all
+    pub fn retry_errors_max_age(mut self, retry_errors_max_age: Duration) -> Self {
+        self.retry_errors_max_age = retry_errors_max_age;
+        self
+    }
+
+    // the sink receives every file found by the poller
+    pub fn sink(mut self, sink: Box<dyn FileSink>) -> Self {
+        self.sink = sink;
+        self.callback_sink = None;
+        self
+    }
+
+    // send every file to this sink as well as the sink (or callback)
+    // (not supported by poll_directory_async)
+    // can be called more than once, the sinks are sent the file in the order they were added
+    pub fn add_sink(mut self, sink: Box<dyn FileSink>) -> Self {
+        self.fanout_sinks.push(sink);
+        self
+    }
+
+    // with added sinks, whether a file needs all of them or any of them to succeed
+    // a file that is not done is kept for the next cycle (or moved to the error directory)
+    pub fn fanout_mode(mut self, fanout_mode: FanoutMode) -> Self {
+        self.fanout_mode = fanout_mode;
+        self
+    }
+
+    // convenience for a sink that only needs the file content
+    // use static lifetime since we want the closure
+    // to be valid for the life of the DirectoryPoller
+    pub fn on_file_content<F>(self, callback: F) -> Self
+    where
+        F: Fn(&str) -> Result<(), Box<dyn Error>> + 'static,
+    {
+        self.sink(Box::new(ClosureSink::new(callback)))
+    }
+
+    // like on_file_content but the callback runs on a worker thread
+    // so that with callback_timeout a stuck callback cannot hang the poller
+    pub fn on_file_content_send<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&str) -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        self.callback_sink = Some(TimeoutSink::new(callback));
+        self
+    }
+
+    // give up on an on_file_content_send callback after this long
+    // a timeout fails the file (and it is not deleted)
+    pub fn callback_timeout(mut self, callback_timeout: Duration) -> Self {
+        self.callback_timeout = Some(callback_timeout);
+        self
+    }
+
+    // stream each file to the callback rather than reading it into memory
+    // when set this is used instead of the sink
+    pub fn on_file_reader<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&mut dyn Read) -> Result<(), Box<dyn Error>> + 'static,
+    {
+        self.on_file_reader = Some(Box::new(callback));
+        self
+    }
+
+    // choose the topic and key for each file from its content
+    // the sink falls back to its own destination for anything left as None
+    pub fn on_file_route<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&FileContext) -> Result<PublishOutcome, Box<dyn Error>> + 'static,
+    {
+        self.on_file_route = Some(Box::new(callback));
+        self
+    }
+
+    // run at the end of every poll cycle with what the cycle did
+    // e.g. to commit downstream once per cycle rather than once per file
+    // an error is handled like a failed file: logged with an error directory, else it stops the poller
+    // (not supported by poll_directory_async)
+    pub fn on_cycle_complete<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&CycleStats) -> Result<(), Box<dyn Error>> + 'static,
+    {
+        self.on_cycle_complete = Some(Box::new(callback));
+        self
+    }
+
+    // run with the stats of the run so far and the cycle's own stats after every cycle
+    // and once more when polling stops (without a cycle) to forward them to a metrics system
+    // a panic in the callback is logged and polling carries on
+    // (not supported by poll_directory_async)
+    pub fn on_stats<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&PollStats, Option<&CycleStats>) + 'static,
+    {
+        self.on_stats = Some(Box::new(callback));
+        self
+    }
+
+    // rewrite the content of each file before it is validated and sent
+    // transforms run in the order they are registered (not applied to on_file_reader)
+    pub fn transform(mut self, transform: ContentTransform) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    // validate the content of each file against this JSON schema
+    // before it is sent to the sink (not applied to on_file_reader)
+    pub fn json_schema(mut self, schema_path: PathBuf) -> Self {
+        self.json_schema = Some(schema_path);
+        self
+    }
+
+    // throttle how many files are processed per second
+    // 0 means unlimited
+    pub fn max_files_per_second(mut self, max_files_per_second: u32) -> Self {
+        self.max_files_per_second = max_files_per_second;
+        self
+    }
+
+    // set the flag to stop the poller
+    // it is checked between files, at the end of each cycle
+    // and while waiting (between cycles or on the rate limit)
+    pub fn shutdown_flag(mut self, shutdown_flag: Arc<AtomicBool>) -> Self {
+        self.shutdown_flag = shutdown_flag;
+        self
+    }
+
+    // set the flag to pause the poller and clear it to resume
+    // it is checked before each cycle so the cycle in progress is finished
+    // while paused no files are read or deleted (not supported by poll_directory_async)
+    pub fn pause_flag(mut self, pause_flag: Arc<AtomicBool>) -> Self {
+        self.pause_flag = Some(pause_flag);
+        self
+    }
+
+    // take permits for the size of each file from the budget before reading it
+    // and give them back once the sink has it, waiting while the budget is used up
+    // share the budget between pollers on several threads to bound their memory together
+    // a file larger than the whole budget fails (not supported by poll_directory_async)
+    pub fn inflight_budget(mut self, inflight_budget: Arc<ByteBudget>) -> Self {
+        self.inflight_budget = Some(inflight_budget);
+        self
+    }
+
+    // send a file again when the sink fails with SinkError::Retriable
+    // waiting backoff before the first retry and doubling the wait after that
+    // a SinkError::Fatal (or any other error) fails the file without a retry
+    // (not supported by poll_directory_async)
+    pub fn send_retries(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.send_retries = max_retries;
+        self.send_retry_backoff = backoff;
+        self
+    }
+
+    // take every send retry from a budget shared with the rest of the run
+    pub fn retry_budget(mut self, retry_budget: Arc<RetryBudget>) -> Self {
+        self.retry_budget = Some(retry_budget);
+        self
+    }
+
+    // pause before a cycle while a polled directory's file system has less than this free
+    // and resume once space frees up, 0 disables the check
+    // (not supported by poll_directory_async)
+    pub fn min_free_disk_mb(mut self, min_free_disk_mb: u64) -> Self {
+        self.min_free_disk_mb = min_free_disk_mb;
+        self
+    }
+
+    // log an error when no file has been processed for longer than this
+    // (and send an alert to the control topic if there is one), zero disables the watchdog
+    // (not supported by poll_directory_async)
+    pub fn expected_interval(mut self, expected_interval: Duration) -> Self {
+        self.expected_interval = expected_interval;
+        self
+    }
+
+    // stop polling with PollError::Silent when the watchdog fires
+    pub fn exit_on_silence(mut self, exit_on_silence: bool) -> Self {
+        self.exit_on_silence = exit_on_silence;
+        self
+    }
+
+    // where the poller gets the free disk space
+    // the OS unless a test swaps in a fake one
+    pub fn disk_space(mut self, disk_space: Arc<dyn DiskSpace>) -> Self {
+        self.disk_space = disk_space;
+        self
+    }
+
+    // where the poller gets the time and sleeps
+    // the real clock unless a test swaps in a fake one
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // where the files are read, deleted and renamed
+    // the real file system unless a test swaps in one held in memory
+    // (not supported by poll_directory_async)
+    pub fn file_system(mut self, file_system: Arc<dyn FileSystem>) -> Self {
+        self.file_system = Some(file_system);
+        self
+    }
+
+    // publish the cycle, directory and stats to this status as the poller runs
+    // e.g. for the StatusServer
+    pub fn status(mut self, status: SharedStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    // Build the poller, loading the JSON schema and compiling the file globs
+    // panics on options that cannot work (or a schema or glob that is not valid), see try_build
+    pub fn build(self) -> DirectoryPoller {
+        self.try_build().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Build the poller once the combination of options has been checked
+    pub fn try_build(mut self) -> Result<DirectoryPoller, BuildError> {
+        self.validate()?;
+        #[cfg(feature = "async")]
+        let blocking_only_options = self.blocking_only_options();
+
+        // a replay reads the directory once and never changes it
+        if self.replay {
+            self.keep_running = false;
+            self.until_empty = false;
+            self.delete_files = false;
+            self.max_poll_cycles = 1;
+            self.claim_suffix = None;
+            self.error_directory = None;
+            self.retry_errors_on_startup = false;
+            for (_, overrides) in &mut self.directory_overrides {
+                overrides.delete_files = None;
+            }
+        }
+
+        let json_schema =
+            match self.json_schema {
+                Some(schema_path) => Some(load_json_schema(&schema_path).map_err(|e| {
+                    BuildError::InvalidJsonSchema {
+                        path: schema_path,
+                        message: e.to_string(),
+                    }
+                })?),
+                None => None,
+            };
+        let mut content = ContentProcessor::new().envelope(self.envelope);
+        if self.compact_json {
+            let declared_json = self
+                .content_type
+                .as_deref()
+                .is_some_and(content_type::is_json);
+            content = content.compact_json(if declared_json {
+                CompactJson::EveryFile
+            } else {
+                CompactJson::JsonFiles
+            });
+        }
+        if let Some(payload_path) = self.payload_path {
+            content = content.payload_path(payload_path);
+        }
+        for transform in self.transforms {
+            content = content.transform(transform);
+        }
+        if let Some(json_schema) = json_schema {
+            content = content.json_schema(json_schema);
+        }
+        if let Some(payload_template) = self.payload_template {
+            content = content.payload_template(payload_template);
+        }
+        if let Some(schema_id) = self.schema_id {
+            content = content.schema_id(schema_id);
+        }
+        if let Some(truncate_to_bytes) = self.truncate_to_bytes {
+            content = content.truncate_to_bytes(truncate_to_bytes);
+        }
+        let include = compile_globs(&self.include)?;
+        let exclude = compile_globs(&self.exclude)?;
+        let directory_settings = self
+            .directory_overrides
+            .iter()
+            .map(|(directory, overrides)| DirectorySettings::new(directory, overrides))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let sink: Box<dyn FileSink> = match self.callback_sink {
+            Some(callback_sink) => match self.callback_timeout {
+                Some(callback_timeout) => Box::new(callback_sink.timeout(callback_timeout)),
+                None => Box::new(callback_sink),
+            },
+            None => self.sink,
+        };
+        let sink: Box<dyn FileSink> = if self.fanout_sinks.is_empty() {
+            sink
+        } else {
+            let fanout = FanoutSink::new(self.fanout_mode).sink(sink);
+            Box::new(self.fanout_sinks.into_iter().fold(fanout, FanoutSink::sink))
+        };
+
+        Ok(DirectoryPoller {
+            keep_running: self.keep_running,
+            until_empty: self.until_empty,
+            delete_files: self.delete_files,
+            poll_interval_millis: self.poll_interval_millis,
+            poll_jitter_millis: self.poll_jitter_millis,
+            fixed_rate: self.fixed_rate,
+            backoff_on_empty: self.backoff_on_empty,
+            max_backoff_millis: self.max_backoff_millis,
+            max_poll_cycles: self.max_poll_cycles,
+            delete_after_cycles: self.delete_after_cycles,
+            max_files_per_cycle: self.max_files_per_cycle,
+            max_runtime: self.max_runtime,
+            flush_interval_millis: self.flush_interval_millis,
+            ordered: self.ordered,
+            control_topic: self.control_topic,
+            content_type: self.content_type,
+            watch: self.watch,
+            heartbeat_every: self.heartbeat_every,
+            empty_cycle_log_level: self.empty_cycle_log_level,
+            file_log_level: self.file_log_level,
+            cycle_log_level: self.cycle_log_level,
+            file_order: self.file_order,
+            recursive: self.recursive,
+            topic_from_subdir: self.topic_from_subdir,
+            path_template: self.path_template,
+            modified_since: self.modified_since,
+            include,
+            exclude,
+            directory_settings,
+            empty_file_policy: self.empty_file_policy,
+            require_name_regex: self.require_name_regex,
+            name_mismatch_policy: self.name_mismatch_policy,
+            utf8_policy: self.utf8_policy,
+            dedup_mode: self.dedup_mode,
+            visited_markers: self.visited_markers,
+            file_ttl: self.file_ttl,
+            key_strategy: self.key_strategy,
+            strip_extension: self.strip_extension,
+            key_encoding: self.key_encoding,
+            tombstone_on_empty: self.tombstone_on_empty,
+            content_type_detection: self.content_type_detection,
+            sidecar_extension: self.sidecar_extension,
+            verify_checksum: self.verify_checksum,
+            require_checksum: self.require_checksum,
+            skip_hidden: self.skip_hidden,
+            claim_suffix: self.claim_suffix,
+            error_directory: self.error_directory,
+            retry_errors_on_startup: self.retry_errors_on_startup,
+            retry_errors_max_age: self.retry_errors_max_age,
+            checkpoint_file: self.checkpoint_file,
+            manifest: self.manifest,
+            strict_manifest: self.strict_manifest,
+            directory_read_retries: self.directory_read_retries,
+            directory_read_backoff: self.directory_read_backoff,
+            strict_directory_read: self.strict_directory_read,
+            lock_file: self.lock_file,
+            lock_directories: self.lock_directories,
+            sink,
+            on_file_reader: self.on_file_reader,
+            on_file_route: self.on_file_route,
+            on_cycle_complete: self.on_cycle_complete,
+            on_stats: self.on_stats,
+            content,
+            batch_limits: self.batch_limits,
+            batch: RefCell::new(Batch::default()),
+            rate_limiter: RateLimiter::new(self.max_files_per_second),
+            shutdown_flag: self.shutdown_flag,
+            pause_flag: self
+                .pause_flag
+                .unwrap_or_else(|| Arc::new(AtomicBool::new(false))),
+            inflight_budget: self.inflight_budget,
+            send_retries: self.send_retries,
+            send_retry_backoff: self.send_retry_backoff,
+            retry_budget: self.retry_budget,
+            min_free_disk_mb: self.min_free_disk_mb,
+            disk_space: self.disk_space,
+            expected_interval: self.expected_interval,
+            exit_on_silence: self.exit_on_silence,
+            last_file_at: Cell::new(None),
+            silence_reported: Cell::new(false),
+            pending_files: RefCell::new(Vec::new()),
+            cycles_started: Cell::new(0),
+            stage_times: Cell::new(StageTimes::default()),
+            transaction_open: Cell::new(false),
+            published_files: RefCell::new(PublishedFiles::new(self.dedup_capacity)),
+            run_started: Cell::new(self.clock.wall_time()),
+            warmup_files: RefCell::new(Vec::new()),
+            clock: self.clock,
+            file_system: self.file_system.unwrap_or_else(|| Arc::new(RealFileSystem)),
+            #[cfg(feature = "async")]
+            blocking_only_options,
+            status: self.status,
+        })
+    }
+
+    fn validate(&self) -> Result<(), BuildError> {
+        if self.require_checksum && !self.verify_checksum {
+            return Err(BuildError::RequireChecksumWithoutVerify);
+        }
+        if self.strict_manifest && self.manifest.is_none() {
+            return Err(BuildError::StrictManifestWithoutManifest);
+        }
+        if self.retry_errors_on_startup && self.error_directory.is_none() {
+            return Err(BuildError::RetryErrorsWithoutErrorDirectory);
+        }
+        if self.callback_timeout.is_some() && self.callback_sink.is_none() {
+            return Err(BuildError::CallbackTimeoutWithoutCallback);
+        }
+        if let Some(claim_suffix) = &self.claim_suffix {
+            if claim_suffix.is_empty() {
+                return Err(BuildError::EmptyClaimSuffix);
+            }
+            if self.sidecar_extension.as_ref() == Some(claim_suffix) {
+                return Err(BuildError::ClaimSuffixIsSidecarExtension(
+                    claim_suffix.clone(),
+                ));
+            }
+        }
+        if self.envelope == EnvelopeFormat::JsonEnvelope && self.on_file_reader.is_some() {
+            return Err(BuildError::EnvelopeWithFileReader);
+        }
+        if self.payload_template.is_some() && self.on_file_reader.is_some() {
+            return Err(BuildError::PayloadTemplateWithFileReader);
+        }
+        if self.payload_path.is_some() && self.on_file_reader.is_some() {
+            return Err(BuildError::PayloadPathWithFileReader);
+        }
+        if self.truncate_to_bytes.is_some() && self.on_file_reader.is_some() {
+            return Err(BuildError::TruncateWithFileReader);
+        }
+        if self.topic_from_subdir && self.path_template.is_some() {
+            return Err(BuildError::PathTemplateWithTopicFromSubdir);
+        }
+        if self.batch_limits.is_some() && self.on_file_reader.is_some() {
+            return Err(BuildError::BatchWithFileReader);
+        }
+        if let Some(control_topic) = &self.control_topic {
+            if !is_valid_topic(control_topic) {
+                return Err(BuildError::InvalidControlTopic(control_topic.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    // The options that are set but only the blocking poller supports
+    // poll_directory_async fails rather than quietly leaving them out
+    #[cfg(feature = "async")]
+    fn blocking_only_options(&self) -> Vec<&'static str> {
+        [
+            ("delete_after_cycles", self.delete_after_cycles != 0),
+            ("control_topic", self.control_topic.is_some()),
+            ("watch", self.watch),
+            ("batch", self.batch_limits.is_some()),
+            ("require_name_regex", self.require_name_regex.is_some()),
+            ("dedup_mode", self.dedup_mode != DedupMode::Off),
+            (
+                "visited_markers",
+                self.visited_markers != VisitedMarkers::Off,
+            ),
+            ("file_ttl", !self.file_ttl.is_zero()),
+            ("recursive", self.recursive),
+            ("tombstone_on_empty", self.tombstone_on_empty),
+            ("sidecar_extension", self.sidecar_extension.is_some()),
+            ("verify_checksum", self.verify_checksum),
+            ("claim_suffix", self.claim_suffix.is_some()),
+            ("manifest", self.manifest.is_some()),
+            (
+                "directory_read_retries",
+                self.directory_read_retries != DEFAULT_DIRECTORY_READ_RETRIES
+                    || self.directory_read_backoff != DEFAULT_DIRECTORY_READ_BACKOFF,
+            ),
+            ("strict_directory_read", self.strict_directory_read),
+            ("lock_file", self.lock_file.is_some()),
+            ("lock_directories", self.lock_directories),
+            ("retry_errors_on_startup", self.retry_errors_on_startup),
+            ("add_sink", !self.fanout_sinks.is_empty()),
+            ("on_cycle_complete", self.on_cycle_complete.is_some()),
+            ("on_stats", self.on_stats.is_some()),
+            ("pause_flag", self.pause_flag.is_some()),
+            ("inflight_budget", self.inflight_budget.is_some()),
+            ("send_retries", self.send_retries > 0),
+            ("min_free_disk_mb", self.min_free_disk_mb > 0),
+            ("expected_interval", !self.expected_interval.is_zero()),
+            ("file_system", self.file_system.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(option, set)| set.then_some(option))
+        .collect()
+    }
+}
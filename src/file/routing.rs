@@ -0,0 +1,230 @@
+/// What the sink is sent for a file and where it goes
+///
+/// The content, key, topic and headers of each file
+/// (from its path, its sidecar and the path template) and the batches files are published in
+use super::{display_path, is_valid_topic, read_error, DirectoryPoller, Stage};
+use crate::content::{ORIGINAL_SIZE_HEADER, TRUNCATED_HEADER};
+use crate::content_type::{self, ContentTypeDetection, CONTENT_TYPE_HEADER};
+use crate::envelope::EnvelopeFormat;
+use crate::filesystem::FileSystem;
+use crate::path_template::{PathRouting, PathTemplate};
+use crate::sink::FileContext;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+impl DirectoryPoller {
+    // Everything the sink is sent for the file: the processed content, key, topic and headers
+    // a tombstone has no content so nothing is read, transformed or validated
+    // None when the UTF-8 policy skips the file
+    pub(super) fn file_context(
+        &self,
+        root: &Path,
+        file_path: &Path,
+        source: &Path,
+        tombstone: bool,
+    ) -> Result<Option<FileContext>, Box<dyn Error>> {
+        let mut ctx = FileContext::new(file_path, Vec::new());
+        ctx.tombstone = tombstone;
+        let mut original_size = None;
+        if !tombstone {
+            let raw = self
+                .timed(Stage::Read, || self.file_system.read(source))
+                .map_err(|e| read_error(source, e))?;
+            let Some(raw) = self.utf8_policy.apply(&display_path(root, file_path), raw) else {
+                return Ok(None);
+            };
+            ctx.modified = self.modified_time(source);
+            // rejects non-conforming content before it reaches the sink
+            let processed = self.timed(Stage::Transform, || self.content.process(&ctx, &raw))?;
+            ctx.content = processed.content;
+            original_size = processed.original_size;
+        }
+        ctx.key = self.derive_key(file_path, tombstone)?;
+        if self.topic_from_subdir {
+            ctx.topic = topic_from_subdir(root, file_path)?;
+        }
+        let path_routing = self
+            .path_template
+            .as_ref()
+            .map(|path_template| path_routing(path_template, root, file_path))
+            .transpose()?
+            .unwrap_or_default();
+        if let Some(topic) = path_routing.topic {
+            ctx.topic = Some(topic);
+        }
+        if let Some(key) = &path_routing.key {
+            ctx.key = Some(self.key_encoding.encode(key)?);
+        }
+        if ctx.topic.is_none() {
+            ctx.topic = self
+                .settings_for(file_path)
+                .and_then(|settings| settings.topic.clone());
+        }
+        if let Some(sidecar_path) = self.sidecar_path(file_path) {
+            let sidecar = read_sidecar(self.file_system.as_ref(), &sidecar_path)?;
+            if let Some(key) = sidecar.key {
+                ctx.key = Some(self.key_encoding.encode(&key)?);
+            }
+            ctx.headers = sidecar
+                .headers
+                .into_iter()
+                .map(|(name, value)| (name, value.into_bytes()))
+                .collect();
+        }
+        ctx.headers.extend(
+            path_routing
+                .headers
+                .into_iter()
+                .map(|(name, value)| (name, value.into_bytes())),
+        );
+        if let Some(content_type) = self.content_type_for(&ctx) {
+            let has_content_type = ctx
+                .headers
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case(CONTENT_TYPE_HEADER));
+            if !has_content_type {
+                ctx.headers
+                    .push((CONTENT_TYPE_HEADER.to_string(), content_type.into_bytes()));
+            }
+        }
+        if let Some(original_size) = original_size {
+            mark_truncated(&mut ctx, original_size);
+        }
+        if let Some(on_file_route) = &self.on_file_route {
+            let outcome = on_file_route(&ctx)?;
+            ctx.route(outcome);
+        }
+        if ctx.tombstone && ctx.key.is_none() {
+            return Err("A tombstone needs a key but the file has none".into());
+        }
+        Ok(Some(ctx))
+    }
+
+    // The content-type header for the message, a tombstone has none
+    fn content_type_for(&self, ctx: &FileContext) -> Option<String> {
+        if ctx.tombstone {
+            return None;
+        }
+        if let Some(content_type) = &self.content_type {
+            return Some(content_type.clone());
+        }
+        if self.content_type_detection != ContentTypeDetection::Off
+            && self.content.envelope_format() == EnvelopeFormat::JsonEnvelope
+        {
+            return Some("application/json".to_string());
+        }
+        content_type::detect(self.content_type_detection, &ctx.file_name, &ctx.content)
+            .map(str::to_string)
+    }
+
+    // Add the file to the batch
+    // a file routed to another topic than the batch publishes the batch first
+    pub(super) fn add_to_batch(&self, ctx: &FileContext) {
+        let Some(batch_limits) = &self.batch_limits else {
+            return;
+        };
+        if !self.batch.borrow().accepts(ctx) {
+            self.flush_batch();
+        }
+        self.batch.borrow_mut().add(ctx, &batch_limits.separator);
+    }
+
+    pub(super) fn is_batch_full(&self) -> bool {
+        self.batch_limits
+            .as_ref()
+            .is_some_and(|batch_limits| self.batch.borrow().is_full(batch_limits))
+    }
+
+    // Publish the batch as one message and flush the sink to settle its files
+    // the files of a batch that cannot be published (or confirmed) are left for the next poll
+    pub(super) fn flush_batch(&self) {
+        let files = self.batch.borrow().files();
+        let Some(message) = self.batch.borrow_mut().take() else {
+            return;
+        };
+        log::debug!(
+            "Publishing a batch of {} files ({} bytes)",
+            files,
+            message.content.len()
+        );
+        if let Err(e) = self.send_to_sink(&message) {
+            log::warn!("Failed to publish a batch of {} files: {}", files, e);
+            self.settle_pending(false);
+            return;
+        }
+        if let Err(e) = self.flush_sink() {
+            log::warn!("Failed to confirm a batch of {} files: {}", files, e);
+        }
+    }
+}
+
+// Add the headers that say the payload was truncated and from what size
+pub(super) fn mark_truncated(ctx: &mut FileContext, original_size: usize) {
+    log::warn!(
+        "Truncated the payload of file {} from {} bytes",
+        ctx.file_name,
+        original_size
+    );
+    ctx.headers
+        .push((TRUNCATED_HEADER.to_string(), b"true".to_vec()));
+    ctx.headers.push((
+        ORIGINAL_SIZE_HEADER.to_string(),
+        original_size.to_string().into_bytes(),
+    ));
+}
+
+// The topic named by the directory holding the file
+// files directly in the polled directory have none (the sink's topic is used)
+fn topic_from_subdir(root: &Path, file_path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let Some(parent) = file_path.strip_prefix(root).ok().and_then(Path::parent) else {
+        return Ok(None);
+    };
+    let Some(directory) = parent.file_name() else {
+        return Ok(None);
+    };
+    let topic = directory.to_string_lossy();
+    if !is_valid_topic(&topic) {
+        return Err(format!("Directory {:?} is not a valid topic name", topic).into());
+    }
+    Ok(Some(topic.to_string()))
+}
+
+// The routing the path template takes from the file's path under the polled directory
+// the topic has to be a valid topic name
+fn path_routing(
+    path_template: &PathTemplate,
+    root: &Path,
+    file_path: &Path,
+) -> Result<PathRouting, Box<dyn Error>> {
+    let relative_path = file_path.strip_prefix(root).unwrap_or(file_path);
+    let routing = path_template.extract(relative_path)?;
+    if let Some(topic) = &routing.topic {
+        if !is_valid_topic(topic) {
+            return Err(format!("Directory {:?} is not a valid topic name", topic).into());
+        }
+    }
+    Ok(routing)
+}
+
+/// The message key and headers for a payload file
+/// e.g. {"key": "order-123", "headers": {"source": "billing"}}
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Sidecar {
+    key: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+fn read_sidecar(
+    file_system: &dyn FileSystem,
+    sidecar_path: &Path,
+) -> Result<Sidecar, Box<dyn Error>> {
+    let sidecar_text = file_system
+        .read_to_string(sidecar_path)
+        .map_err(|e| format!("Failed to read sidecar {}: {}", sidecar_path.display(), e))?;
+    serde_json::from_str(&sidecar_text)
+        .map_err(|e| format!("Sidecar {} is malformed: {}", sidecar_path.display(), e).into())
+}
@@ -0,0 +1,116 @@
+/// Settling the files the sink accepted
+///
+/// A file is only deleted once the sink confirms it: straight away when it sends synchronously,
+/// otherwise when a flush (or the commit of the cycle's transaction) succeeds
+/// the files that are not confirmed are left where they are for the next poll
+use super::{DirectoryPoller, Stage};
+use crate::dedup::DedupKey;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// A file the sink accepted but has not confirmed yet
+pub(super) struct PendingFile {
+    pub(super) file_path: PathBuf,
+    // the claimed file when claim_suffix is set
+    pub(super) source: PathBuf,
+    pub(super) name: String,
+    // remembered as published once the file is confirmed
+    pub(super) dedup_key: Option<DedupKey>,
+}
+
+impl DirectoryPoller {
+    // With periodic flushing the sink may buffer what it is sent
+    // so files are only deleted once a flush confirms them
+    // and with a transactional sink they are only deleted once the cycle commits
+    // and batched files are only deleted once their batch is delivered
+    pub(super) fn defers_deletion(&self) -> bool {
+        self.sink.is_transactional()
+            || self.batch_limits.is_some()
+            || (self.flush_interval_millis > 0 && !self.ordered)
+    }
+
+    pub(super) fn is_pending(&self, file_path: &Path) -> bool {
+        self.pending_files
+            .borrow()
+            .iter()
+            .any(|pending| pending.file_path == file_path)
+    }
+
+    // Flush the sink and settle the files sent since the last flush
+    // they are deleted when the flush succeeds
+    // and left on disk for the next poll (or run) when it fails
+    pub(super) fn flush_sink(&self) -> Result<(), Box<dyn Error>> {
+        let result = self.timed(Stage::Delivery, || self.sink.flush());
+        // the files of an open transaction wait for it to commit
+        if !self.transaction_open.get() {
+            self.settle_pending(result.is_ok());
+        }
+        result
+    }
+
+    // Open the transaction the files of the cycle are published in
+    pub(super) fn begin_transaction(&self) -> Result<(), Box<dyn Error>> {
+        if !self.sink.is_transactional() {
+            return Ok(());
+        }
+        self.sink
+            .begin_cycle()
+            .map_err(|e| format!("Failed to begin a transaction: {}", e))?;
+        self.transaction_open.set(true);
+        Ok(())
+    }
+
+    // Commit the transaction of the cycle and delete its files
+    // or abort it when a file failed (or the commit did) and leave them all on disk
+    pub(super) fn settle_transaction(&self, commit: bool) {
+        if !self.transaction_open.replace(false) {
+            return;
+        }
+        let result = if commit {
+            self.timed(Stage::Delivery, || self.sink.commit_cycle())
+        } else {
+            Err("a file in the cycle failed".into())
+        };
+        if let Err(e) = result {
+            log::warn!(
+                "Aborting the transaction, keeping its {} files for the next poll: {}",
+                self.pending_files.borrow().len(),
+                e
+            );
+            if let Err(e) = self.sink.abort_cycle() {
+                log::error!("Failed to abort the transaction: {}", e);
+            }
+            self.settle_pending(false);
+            return;
+        }
+        self.settle_pending(true);
+    }
+
+    // Delete the files sent since they were last settled once they are confirmed
+    // or release them for the next poll (or run)
+    pub(super) fn settle_pending(&self, confirmed: bool) {
+        for pending in self.pending_files.take() {
+            if confirmed {
+                self.delete_file(&pending.file_path, &pending.source, &pending.name);
+                self.mark_visited(&pending.file_path, &pending.source, &pending.name);
+                if let Some(dedup_key) = pending.dedup_key {
+                    self.published_files.borrow_mut().insert(dedup_key);
+                }
+                continue;
+            }
+            log::warn!(
+                "Delivery of file {} was not confirmed, leaving it for the next poll",
+                pending.name
+            );
+            if pending.source != pending.file_path {
+                if let Err(e) = self.file_system.rename(&pending.source, &pending.file_path) {
+                    log::error!(
+                        "Failed to release claimed file {}: {}",
+                        pending.source.display(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
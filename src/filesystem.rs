@@ -0,0 +1,139 @@
+/// The file system the poller lists, reads, deletes and renames the files it publishes on
+///
+/// The real file system unless a test swaps in one held in memory
+/// (or a backend keeps the files somewhere else, e.g. an object store)
+/// sidecar, checksum and manifest files, the checkpoint, visited markers and the error directory are on it too
+/// only the lock files (and poll_directory_async) still use std::fs
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// What the poller needs to know about a file without reading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub len: u64,
+    // None if the file system does not know
+    pub modified: Option<SystemTime>,
+}
+
+pub trait FileSystem: Send + Sync {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    // Create the file or replace its content
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()>;
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    // The paths of the entries in the directory, in no particular order
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    // Whether the path is a file, or a symlink to one
+    fn is_file(&self, path: &Path) -> bool;
+
+    // Whether the path is a directory, or a symlink to one
+    fn is_dir(&self, path: &Path) -> bool;
+
+    // Symlinks are never followed into subdirectories, so a link back up cannot loop
+    fn is_symlink(&self, _path: &Path) -> bool {
+        false
+    }
+
+    // The path with every symlink resolved
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.metadata(path)?;
+        Ok(path.to_path_buf())
+    }
+
+    // Read the file bit by bit rather than all at once
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        Ok(Box::new(io::Cursor::new(self.read(path)?)))
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Copy the file, when a rename cannot move it
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.write(to, &self.read(from)?)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+}
+
+/// The real file system
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        fs::write(path, content)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileMetadata {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        path.is_symlink()
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::copy(from, to).map(|_| ())
+    }
+}
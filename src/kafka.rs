@@ -0,0 +1,1325 @@
+/// Kafka publishing utility
+/// Reference: KafkaProducerUtil
+use crate::args::{shown_prop_value, ProducerArgs};
+use crate::budget::RetryBudget;
+use crate::discovery::{self, DnsResolver, SrvResolver};
+use crate::file::PollError;
+use crate::partition::PartitionStrategy;
+use crate::sink::{FileContext, FileSink, SinkError};
+use crate::timestamp::TimestampSource;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// A delivery report that has not arrived yet
+// The future resolves once the broker has acknowledged (or rejected) the message
+// it is boxed so the real producer and a mock producer can return different futures
+pub type DeliveryFuture =
+    Pin<Box<dyn Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send>>;
+
+/// The broker did not confirm the record within the delivery timeout
+#[derive(Debug, thiserror::Error)]
+#[error("Delivery to topic {topic} was not confirmed within {} ms", .timeout.as_millis())]
+pub struct DeliveryTimeout {
+    pub topic: String,
+    pub timeout: Duration,
+}
+
+// Header names used when a file is routed to the dead-letter topic
+pub const DLQ_ERROR_HEADER: &str = "dlq.error.reason";
+pub const DLQ_FILE_NAME_HEADER: &str = "dlq.original.file.name";
+
+// The longest wait between reconnect attempts
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+// How often a reconnect backoff checks whether shutdown was requested
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A message to be sent to a topic
+/// Reference: Kafka ProducerRecord
+pub struct ProducerRecord<'a> {
+    pub topic: &'a str,
+    pub key: Option<&'a [u8]>,
+    // None publishes a tombstone (a null value)
+    pub payload: Option<&'a [u8]>,
+    pub headers: Vec<(String, Vec<u8>)>,
+    // None leaves the partition to the producer
+    pub partition: Option<i32>,
+    // milliseconds since the epoch, None stamps the record when it is published
+    pub timestamp: Option<i64>,
+}
+
+impl<'a> ProducerRecord<'a> {
+    pub fn new(topic: &'a str, payload: Option<&'a [u8]>) -> Self {
+        ProducerRecord {
+            topic,
+            key: None,
+            payload,
+            headers: Vec::new(),
+            partition: None,
+            timestamp: None,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &[u8]) -> Self {
+        self.headers.push((name.to_string(), value.to_vec()));
+        self
+    }
+}
+
+/// Sends records to the brokers and reports how each delivery went
+/// along with flushing, the transaction calls and following a changed broker list
+pub trait MessageProducer {
+    // Hand the message to the producer
+    // and return a future for the delivery report
+    fn send(&self, record: &ProducerRecord) -> Result<DeliveryFuture, Box<dyn Error>>;
+
+    // Push out anything still buffered (e.g. waiting on linger.ms)
+    fn flush(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    // Kafka transactions, for a producer configured with a transactional.id
+    fn init_transactions(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Err("The producer does not support transactions".into())
+    }
+
+    fn begin_transaction(&self) -> Result<(), Box<dyn Error>> {
+        Err("The producer does not support transactions".into())
+    }
+
+    fn commit_transaction(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Err("The producer does not support transactions".into())
+    }
+
+    fn abort_transaction(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Err("The producer does not support transactions".into())
+    }
+
+    // How many partitions the topic has, from the broker metadata
+    fn partition_count(&self, topic: &str, _timeout: Duration) -> Result<i32, Box<dyn Error>> {
+        Err(format!("The partition count of topic {} is not available", topic).into())
+    }
+
+    // Look the brokers up again before reconnect retries start
+    // a producer with a fixed bootstrap list has nothing to refresh
+    fn refresh_brokers(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// MessageProducer backed by the rdkafka FutureProducer
+pub struct RdKafkaProducer {
+    producer: RefCell<FutureProducer>,
+    config: RefCell<ClientConfig>,
+    // the SRV record the bootstrap list came from, looked up again by refresh_brokers
+    broker_srv: Option<(String, Box<dyn SrvResolver>)>,
+}
+
+impl RdKafkaProducer {
+    pub fn new(config: &ClientConfig) -> Result<Self, Box<dyn Error>> {
+        let producer: FutureProducer = config.create()?;
+        Ok(RdKafkaProducer {
+            producer: RefCell::new(producer),
+            config: RefCell::new(config.clone()),
+            broker_srv: None,
+        })
+    }
+
+    // find the brokers from the SRV record again when reconnecting
+    // and recreate the producer when the record lists other brokers than it was created with
+    pub fn broker_srv(mut self, broker_srv: &str, resolver: Box<dyn SrvResolver>) -> Self {
+        self.broker_srv = Some((broker_srv.to_string(), resolver));
+        self
+    }
+
+    pub fn bootstrap_servers(&self) -> Option<String> {
+        self.config
+            .borrow()
+            .get("bootstrap.servers")
+            .map(str::to_string)
+    }
+}
+
+impl MessageProducer for RdKafkaProducer {
+    fn send(&self, record: &ProducerRecord) -> Result<DeliveryFuture, Box<dyn Error>> {
+        let mut future_record = FutureRecord::<[u8], [u8]>::to(record.topic);
+        if let Some(payload) = record.payload {
+            future_record = future_record.payload(payload);
+        }
+        if let Some(key) = record.key {
+            future_record = future_record.key(key);
+        }
+        if let Some(partition) = record.partition {
+            future_record = future_record.partition(partition);
+        }
+        if let Some(timestamp) = record.timestamp {
+            future_record = future_record.timestamp(timestamp);
+        }
+        if !record.headers.is_empty() {
+            let headers =
+                record
+                    .headers
+                    .iter()
+                    .fold(OwnedHeaders::new(), |headers, (name, value)| {
+                        headers.insert(Header {
+                            key: name,
+                            value: Some(value),
+                        })
+                    });
+            future_record = future_record.headers(headers);
+        }
+
+        // send_result only enqueues the message
+        // the broker ack arrives later through the delivery future
+        let delivery = self
+            .producer
+            .borrow()
+            .send_result(future_record)
+            .map_err(|(e, _)| e)?;
+
+        Ok(Box::pin(async move {
+            match delivery.await {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err((e, _))) => Err(e.into()),
+                Err(_) => Err("Delivery report was canceled".into()),
+            }
+        }))
+    }
+
+    fn flush(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Ok(self.producer.borrow().flush(timeout)?)
+    }
+
+    fn init_transactions(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Ok(self.producer.borrow().init_transactions(timeout)?)
+    }
+
+    fn begin_transaction(&self) -> Result<(), Box<dyn Error>> {
+        Ok(self.producer.borrow().begin_transaction()?)
+    }
+
+    fn commit_transaction(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Ok(self.producer.borrow().commit_transaction(timeout)?)
+    }
+
+    fn abort_transaction(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Ok(self.producer.borrow().abort_transaction(timeout)?)
+    }
+
+    fn partition_count(&self, topic: &str, timeout: Duration) -> Result<i32, Box<dyn Error>> {
+        let metadata = self
+            .producer
+            .borrow()
+            .client()
+            .fetch_metadata(Some(topic), timeout)?;
+        let partitions = metadata
+            .topics()
+            .iter()
+            .find(|metadata_topic| metadata_topic.name() == topic)
+            .map_or(0, |metadata_topic| metadata_topic.partitions().len());
+        if partitions == 0 {
+            return Err(format!("Topic {} has no partitions (does it exist?)", topic).into());
+        }
+        Ok(partitions as i32)
+    }
+
+    // Recreate the producer when the SRV record now lists other brokers
+    // a transactional producer is kept, recreating it would drop its open transaction
+    fn refresh_brokers(&self) -> Result<(), Box<dyn Error>> {
+        let Some((broker_srv, resolver)) = &self.broker_srv else {
+            return Ok(());
+        };
+        let bootstrap = discovery::bootstrap_servers(resolver.as_ref(), broker_srv)?;
+        let mut config = self.config.borrow().clone();
+        if config.get("bootstrap.servers") == Some(bootstrap.as_str())
+            || config.get("transactional.id").is_some()
+        {
+            return Ok(());
+        }
+        log::info!(
+            "Brokers from SRV record {} changed to {}, recreating the producer",
+            broker_srv,
+            bootstrap
+        );
+        config.set("bootstrap.servers", bootstrap);
+        *self.producer.borrow_mut() = config.create()?;
+        *self.config.borrow_mut() = config;
+        Ok(())
+    }
+}
+
+pub struct KafkaProducer {
+    producer: Box<dyn MessageProducer>,
+    topic: String,
+    // files that cannot be published (and are not worth retrying) are sent here instead
+    dlq_topic: Option<String>,
+    delivery_timeout: Duration,
+    // how often a publish that failed on a transient broker error is retried
+    reconnect_max_retries: u32,
+    reconnect_backoff: Duration,
+    // how long to let the producer drain its queue when it is full, zero fails straight away
+    queue_full_backoff: Duration,
+    // retries shared with the rest of the run, unlimited when None
+    retry_budget: Option<Arc<RetryBudget>>,
+    // set on Ctrl-C or SIGTERM, cuts a reconnect backoff short
+    shutdown_flag: Option<Arc<AtomicBool>>,
+    partition_strategy: PartitionStrategy,
+    // the partitions of each topic published to, looked up once
+    partition_counts: RefCell<HashMap<String, i32>>,
+    // where the timestamp of each file's record comes from
+    timestamp_source: TimestampSource,
+    // each poll cycle is published in a transaction
+    transactional: bool,
+    // what is missing from the security settings, reported by validate_setup
+    security_problem: Option<String>,
+    // used to wait on the delivery future from synchronous code
+    runtime: tokio::runtime::Runtime,
+}
+
+impl KafkaProducer {
+    pub fn new(
+        producer: Box<dyn MessageProducer>,
+        topic: &str,
+        delivery_timeout: Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+
+        Ok(KafkaProducer {
+            producer,
+            topic: topic.to_string(),
+            dlq_topic: None,
+            delivery_timeout,
+            reconnect_max_retries: 0,
+            reconnect_backoff: Duration::ZERO,
+            queue_full_backoff: Duration::ZERO,
+            retry_budget: None,
+            shutdown_flag: None,
+            partition_strategy: PartitionStrategy::Auto,
+            partition_counts: RefCell::new(HashMap::new()),
+            timestamp_source: TimestampSource::Publish,
+            transactional: false,
+            security_problem: None,
+            runtime,
+        })
+    }
+
+    // Create a producer connected to the brokers from the CLI args
+    pub fn from_args(args: &ProducerArgs) -> Result<Self, Box<dyn Error>> {
+        Self::for_topic(args, args.topic.as_deref())
+    }
+
+    // A producer configured from the args that publishes to another topic
+    // (files routed to a topic of their own still go there)
+    pub fn for_topic(args: &ProducerArgs, topic: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        if let Some(warning) = ordering_warning(args) {
+            log::warn!("{}", warning);
+        }
+
+        let mut producer = RdKafkaProducer::new(&client_config(args)?)?;
+        if let Some(broker_srv) = &args.broker_srv {
+            producer = producer.broker_srv(broker_srv, Box::new(DnsResolver));
+        }
+        let producer = KafkaProducer::new(
+            Box::new(producer),
+            topic.unwrap_or_default(),
+            Duration::from_millis(args.delivery_timeout_ms),
+        )?;
+        let mut producer = producer
+            .dlq_topic(args.dlq_topic.clone())
+            .reconnect(
+                args.reconnect_max_retries,
+                Duration::from_millis(args.reconnect_backoff_ms),
+            )
+            .queue_full_backoff(Duration::from_millis(args.queue_full_backoff_ms))
+            .partition_strategy(args.partition_strategy.clone())
+            .timestamp_source(args.timestamp_from.clone());
+        producer.security_problem = check_security_settings(args).err().map(|e| e.to_string());
+        // fail at startup rather than on the first file
+        if let Some(topic) = topic {
+            producer.check_partition_strategy(topic)?;
+        }
+        if args.transactional_id.is_some() {
+            producer = producer.transactional()?;
+        }
+        Ok(producer)
+    }
+
+    // Publish files that fail (after the producer exhausts its retries)
+    // to a dead-letter topic rather than failing the file
+    // a retriable failure such as a delivery timeout is left to the poller's send retries
+    pub fn dlq_topic(mut self, dlq_topic: Option<String>) -> Self {
+        self.dlq_topic = dlq_topic;
+        self
+    }
+
+    // Retry a publish that failed because the broker could not be reached
+    // waiting initial_backoff before the first retry and doubling the wait after that
+    // permanent errors (auth, unknown topic ...) are never retried
+    pub fn reconnect(mut self, max_retries: u32, initial_backoff: Duration) -> Self {
+        self.reconnect_max_retries = max_retries;
+        self.reconnect_backoff = initial_backoff;
+        self
+    }
+
+    // Wait for the producer to drain its queue when it is full rather than failing the file
+    // flushing for up to this long between attempts, for at most the delivery timeout in all
+    // the poller reads no new file in the meantime so the backlog stays on disk
+    pub fn queue_full_backoff(mut self, queue_full_backoff: Duration) -> Self {
+        self.queue_full_backoff = queue_full_backoff;
+        self
+    }
+
+    // Take every reconnect retry from a budget shared with the rest of the run
+    // once it is used up failures are not retried (they go to the DLQ or fail the file)
+    pub fn retry_budget(mut self, retry_budget: Option<Arc<RetryBudget>>) -> Self {
+        self.retry_budget = retry_budget;
+        self
+    }
+
+    // Stop waiting to retry once the flag is set, the file then fails and is kept
+    // (the same flag the poller stops on)
+    pub fn shutdown_flag(mut self, shutdown_flag: Arc<AtomicBool>) -> Self {
+        self.shutdown_flag = Some(shutdown_flag);
+        self
+    }
+
+    // Publish the files of each poll cycle in one transaction
+    // the producer has to be configured with a transactional.id
+    // this fences off any earlier producer with the same id
+    pub fn transactional(mut self) -> Result<Self, Box<dyn Error>> {
+        self.producer
+            .init_transactions(self.delivery_timeout)
+            .map_err(|e| format!("Failed to initialize transactions: {}", e))?;
+        self.transactional = true;
+        Ok(self)
+    }
+
+    // Pick the partition of each file from its name
+    pub fn partition_strategy(mut self, partition_strategy: PartitionStrategy) -> Self {
+        self.partition_strategy = partition_strategy;
+        self
+    }
+
+    // Stamp each file's record with the publish time, its modified time or a header it has
+    pub fn timestamp_source(mut self, timestamp_source: TimestampSource) -> Self {
+        self.timestamp_source = timestamp_source;
+        self
+    }
+
+    // Check the producer can publish to the topic before any file is read
+    // the security settings have to be complete, the topic has to exist
+    // (the brokers are asked for its partitions) and the partition strategy has to fit it
+    pub fn validate_setup(&self, topic: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(security_problem) = &self.security_problem {
+            return Err(security_problem.clone().into());
+        }
+        self.partition_count(topic).map_err(|e| {
+            format!(
+                "Cannot publish to topic {}: {} (create the topic or check bootstrap-server)",
+                topic, e
+            )
+        })?;
+        self.check_partition_strategy(topic)
+    }
+
+    // Check the partition strategy against the partitions the topic has
+    // e.g. fixed:N needs the topic to have partition N
+    pub fn check_partition_strategy(&self, topic: &str) -> Result<(), Box<dyn Error>> {
+        if !self.partition_strategy.needs_partition_count() {
+            return Ok(());
+        }
+        let partition_count = self.partition_count(topic)?;
+        Ok(self.partition_strategy.validate(partition_count)?)
+    }
+
+    // Publish the payload to the topic
+    // This blocks until the broker confirms delivery (respecting the acks mode)
+    // so the caller can safely delete the file once this returns Ok
+    // a None payload publishes a tombstone
+    // a None partition leaves it to the producer
+    pub fn publish(
+        &self,
+        payload: Option<&[u8]>,
+        partition: Option<i32>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut record = ProducerRecord::new(&self.topic, payload);
+        record.partition = partition;
+        self.deliver(&record)
+    }
+
+    // Wait for buffered messages to be sent
+    pub fn flush(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.producer.flush(timeout)
+    }
+
+    // Publish the file to the topic chosen for it
+    // falling back to the configured topic
+    fn publish_file(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        let topic = ctx.topic.as_deref().unwrap_or(&self.topic);
+        if topic.is_empty() {
+            return Err(format!("No topic for file {}", ctx.file_name).into());
+        }
+        let payload = (!ctx.tombstone).then_some(ctx.content.as_slice());
+        let mut record = ProducerRecord::new(topic, payload);
+        record.key = ctx.key.as_deref();
+        record.headers = ctx.headers.clone();
+        record.partition = self.partition_for(topic, &ctx.file_name)?;
+        record.timestamp = self.timestamp_source.timestamp(ctx)?;
+        self.deliver(&record)
+    }
+
+    // The partition the strategy picks for the file
+    fn partition_for(&self, topic: &str, file_name: &str) -> Result<Option<i32>, Box<dyn Error>> {
+        let partition_count = if self.partition_strategy.needs_partition_count() {
+            self.partition_count(topic)?
+        } else {
+            0
+        };
+        Ok(self
+            .partition_strategy
+            .partition(file_name, partition_count))
+    }
+
+    // The partitions of the topic, asking the broker the first time
+    fn partition_count(&self, topic: &str) -> Result<i32, Box<dyn Error>> {
+        if let Some(partition_count) = self.partition_counts.borrow().get(topic) {
+            return Ok(*partition_count);
+        }
+        let partition_count = self
+            .producer
+            .partition_count(topic, self.delivery_timeout)?;
+        self.partition_counts
+            .borrow_mut()
+            .insert(topic.to_string(), partition_count);
+        Ok(partition_count)
+    }
+
+    // Publish the raw file to the dead-letter topic
+    // with headers describing why it failed
+    fn publish_to_dlq(
+        &self,
+        dlq_topic: &str,
+        ctx: &FileContext,
+        reason: &dyn Error,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut record = ProducerRecord::new(dlq_topic, Some(&ctx.content))
+            .header(DLQ_ERROR_HEADER, reason.to_string().as_bytes())
+            .header(DLQ_FILE_NAME_HEADER, ctx.file_name.as_bytes());
+        record.key = ctx.key.as_deref();
+        self.deliver(&record)
+    }
+
+    // Send the record and wait for the delivery report
+    // retrying while the broker is unreachable
+    fn deliver(&self, record: &ProducerRecord) -> Result<(), Box<dyn Error>> {
+        let mut backoff = self.reconnect_backoff;
+        let mut retries = 0;
+        let mut queue_full_since = None;
+        loop {
+            let error = match self.try_deliver(record) {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            // back-pressure rather than an outage, it does not take a retry
+            if self.wait_for_queue(error.as_ref(), &mut queue_full_since) {
+                continue;
+            }
+            if retries >= self.reconnect_max_retries || !is_transient(error.as_ref()) {
+                return Err(error);
+            }
+            if let Some(retry_budget) = &self.retry_budget {
+                if !retry_budget.try_retry() {
+                    log::warn!(
+                        "Publishing to topic {} failed ({}), the {} retries of the run are used up",
+                        record.topic,
+                        error,
+                        retry_budget.total()
+                    );
+                    return Err(error);
+                }
+            }
+
+            // the brokers may have moved while they were unreachable
+            if retries == 0 {
+                if let Err(e) = self.producer.refresh_brokers() {
+                    log::warn!("Failed to look the brokers up again: {}", e);
+                }
+            }
+            retries += 1;
+            log::warn!(
+                "Publishing to topic {} failed ({}), retrying in {} ms ({}/{})",
+                record.topic,
+                error,
+                backoff.as_millis(),
+                retries,
+                self.reconnect_max_retries
+            );
+            if !self.sleep(backoff) {
+                log::warn!(
+                    "Shutdown requested, not retrying the publish to topic {}",
+                    record.topic
+                );
+                return Err(error);
+            }
+            backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+        }
+    }
+
+    // Sleep in short slices so a shutdown request is noticed quickly
+    // false if shutdown was requested instead
+    fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while !self.is_shutdown() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return true;
+            }
+            std::thread::sleep(remaining.min(SHUTDOWN_CHECK_INTERVAL));
+        }
+        false
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shutdown_flag
+            .as_ref()
+            .is_some_and(|shutdown_flag| shutdown_flag.load(Ordering::SeqCst))
+    }
+
+    // Give the producer time to push out what it has queued after a QueueFull error
+    // false for any other error or once it has been full for the delivery timeout
+    fn wait_for_queue(
+        &self,
+        error: &(dyn Error + 'static),
+        queue_full_since: &mut Option<Instant>,
+    ) -> bool {
+        if self.queue_full_backoff.is_zero() || !is_queue_full(error) {
+            return false;
+        }
+        let queue_full_since = *queue_full_since.get_or_insert_with(Instant::now);
+        if queue_full_since.elapsed() >= self.delivery_timeout {
+            return false;
+        }
+        log::debug!(
+            "The producer queue is full, draining it for up to {} ms",
+            self.queue_full_backoff.as_millis()
+        );
+        // a flush that times out has still waited for the queue to make room
+        if let Err(e) = self.producer.flush(self.queue_full_backoff) {
+            log::debug!("The producer queue is still full: {}", e);
+        }
+        true
+    }
+
+    // A single attempt at sending the record
+    fn try_deliver(&self, record: &ProducerRecord) -> Result<(), Box<dyn Error>> {
+        let delivery = self.producer.send(record)?;
+
+        // the timeout is created inside the async block
+        // so it is set up within the runtime
+        let result = self
+            .runtime
+            .block_on(async { tokio::time::timeout(self.delivery_timeout, delivery).await });
+
+        match result {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(Box::new(DeliveryTimeout {
+                topic: record.topic.to_string(),
+                timeout: self.delivery_timeout,
+            })),
+        }
+    }
+}
+
+// The Kafka producer is the default destination for files
+impl FileSink for KafkaProducer {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        let error = match self.publish_file(ctx) {
+            Ok(()) => return Ok(()),
+            Err(e) => sink_error(e),
+        };
+
+        // without a dead-letter topic the file follows the normal error path
+        // as does a file that may still be published, a short outage must not dead-letter it
+        let Some(dlq_topic) = &self.dlq_topic else {
+            return Err(error);
+        };
+        if SinkError::is_retriable(error.as_ref()) {
+            return Err(error);
+        }
+
+        log::warn!(
+            "Publishing file {} to dead-letter topic {}: {}",
+            ctx.file_name,
+            dlq_topic,
+            error
+        );
+        // the dead-letter failure is what decides whether the file is retried
+        self.publish_to_dlq(dlq_topic, ctx, error.as_ref())
+            .map_err(|dlq_error| {
+                log::warn!(
+                    "Publishing file {} to dead-letter topic {} also failed: {}",
+                    ctx.file_name,
+                    dlq_topic,
+                    dlq_error
+                );
+                sink_error(dlq_error)
+            })
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        KafkaProducer::flush(self, self.delivery_timeout)
+    }
+
+    fn is_transactional(&self) -> bool {
+        self.transactional
+    }
+
+    fn begin_cycle(&self) -> Result<(), Box<dyn Error>> {
+        self.producer.begin_transaction()
+    }
+
+    fn commit_cycle(&self) -> Result<(), Box<dyn Error>> {
+        self.producer.commit_transaction(self.delivery_timeout)
+    }
+
+    fn abort_cycle(&self) -> Result<(), Box<dyn Error>> {
+        self.producer.abort_transaction(self.delivery_timeout)
+    }
+}
+
+// How the poller should take a publish that failed (after the producer's own retries)
+// an outage, a full queue or a delivery timeout may clear up and is SinkError::Retriable
+// (an outage is a PollError::Connection once the poller gives up on it)
+// content the brokers reject fails the same way every time and is SinkError::Fatal
+fn sink_error(error: Box<dyn Error>) -> Box<dyn Error> {
+    if is_transient(error.as_ref()) {
+        Box::new(SinkError::Retriable(Box::new(PollError::Connection(error))))
+    } else if error.is::<DeliveryTimeout>() {
+        Box::new(SinkError::Retriable(error))
+    } else if is_rejected(error.as_ref()) {
+        Box::new(SinkError::Fatal(error))
+    } else {
+        error
+    }
+}
+
+// Whether the message itself was refused, e.g. it is larger than the brokers accept
+pub fn is_rejected(error: &(dyn Error + 'static)) -> bool {
+    let Some(code) = error
+        .downcast_ref::<KafkaError>()
+        .and_then(KafkaError::rdkafka_error_code)
+    else {
+        return false;
+    };
+    matches!(
+        code,
+        RDKafkaErrorCode::MessageSizeTooLarge
+            | RDKafkaErrorCode::MessageBatchTooLarge
+            | RDKafkaErrorCode::InvalidMessageSize
+            | RDKafkaErrorCode::InvalidMessage
+            | RDKafkaErrorCode::InvalidRecord
+            | RDKafkaErrorCode::BadMessage
+    )
+}
+
+// Whether the producer refused the message because its queue is full
+pub fn is_queue_full(error: &(dyn Error + 'static)) -> bool {
+    error
+        .downcast_ref::<KafkaError>()
+        .and_then(KafkaError::rdkafka_error_code)
+        == Some(RDKafkaErrorCode::QueueFull)
+}
+
+// Whether the error is worth retrying once the broker is reachable again
+// only broker and transport errors are, anything else would fail the same way
+pub fn is_transient(error: &(dyn Error + 'static)) -> bool {
+    let Some(code) = error
+        .downcast_ref::<KafkaError>()
+        .and_then(KafkaError::rdkafka_error_code)
+    else {
+        return false;
+    };
+    matches!(
+        code,
+        RDKafkaErrorCode::BrokerTransportFailure
+            | RDKafkaErrorCode::AllBrokersDown
+            | RDKafkaErrorCode::Resolve
+            | RDKafkaErrorCode::MessageTimedOut
+            | RDKafkaErrorCode::QueueFull
+            | RDKafkaErrorCode::RequestTimedOut
+            | RDKafkaErrorCode::NetworkException
+            | RDKafkaErrorCode::LeaderNotAvailable
+            | RDKafkaErrorCode::NotLeaderForPartition
+            | RDKafkaErrorCode::NotEnoughReplicas
+            | RDKafkaErrorCode::NotEnoughReplicasAfterAppend
+    )
+}
+
+// Translate the CLI args into the rdkafka client configuration
+pub fn client_config(args: &ProducerArgs) -> Result<ClientConfig, Box<dyn Error>> {
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", bootstrap_servers(args)?)
+        .set("client.id", &args.client_id)
+        .set("acks", &args.ack_mode)
+        .set("retries", args.retries.to_string())
+        .set("retry.backoff.ms", args.retry_delay.to_string())
+        .set("compression.type", args.compression.as_str())
+        .set("batch.size", args.batch_size_bytes.to_string())
+        .set("linger.ms", args.batch_delay.to_string())
+        .set(
+            "max.in.flight.requests.per.connection",
+            args.max_inflight.to_string(),
+        )
+        // give up inside librdkafka at the same time we stop waiting for the ack
+        .set("delivery.timeout.ms", args.delivery_timeout_ms.to_string());
+
+    // a transactional producer is always idempotent
+    if args.idempotent || args.transactional_id.is_some() {
+        apply_idempotence(args, &mut config)?;
+    }
+    if let Some(transactional_id) = &args.transactional_id {
+        config.set("transactional.id", transactional_id);
+    }
+    apply_security(args, &mut config)?;
+    // one batch at a time so a retried batch cannot overtake the next one
+    if args.ordered {
+        config.set("max.in.flight.requests.per.connection", "1");
+    }
+    for warning in apply_kafka_props(args, &mut config)? {
+        log::warn!("{}", warning);
+    }
+
+    Ok(config)
+}
+
+// The rdkafka configuration of the consumer of the mirror subcommand
+// offsets are committed by the mirror once a message is delivered, never automatically
+// and a new group starts from the beginning of the source topic
+pub fn consumer_config(
+    args: &ProducerArgs,
+    group_id: &str,
+) -> Result<ClientConfig, Box<dyn Error>> {
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", bootstrap_servers(args)?)
+        .set("client.id", &args.client_id)
+        .set("group.id", group_id)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest");
+    apply_security(args, &mut config)?;
+    for warning in apply_kafka_props(args, &mut config)? {
+        log::warn!("{}", warning);
+    }
+    Ok(config)
+}
+
+// Set the --kafka-prop properties over the ones from the typed flags
+// returns a warning for each property a typed flag had already set
+// which is an error instead with --strict-props
+fn apply_kafka_props(
+    args: &ProducerArgs,
+    config: &mut ClientConfig,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut warnings = Vec::new();
+    for (key, value) in &args.kafka_props {
+        if let Some(typed_value) = config.get(key) {
+            let conflict = format!(
+                "--kafka-prop {}={} overrides the {} set by the typed flags",
+                key,
+                shown_prop_value(key, value),
+                shown_prop_value(key, typed_value)
+            );
+            if args.strict_props {
+                return Err(format!("{} (--strict-props)", conflict).into());
+            }
+            warnings.push(conflict);
+        }
+        config.set(key, value);
+    }
+    Ok(warnings)
+}
+
+// The brokers from bootstrap-server, or looked up from the broker-srv record
+// so each producer (or consumer) created connects to the brokers there are now
+fn bootstrap_servers(args: &ProducerArgs) -> Result<String, Box<dyn Error>> {
+    let Some(broker_srv) = &args.broker_srv else {
+        return Ok(args.bootstrap.clone().unwrap_or_default());
+    };
+    let bootstrap = discovery::bootstrap_servers(&DnsResolver, broker_srv)?;
+    log::info!("Brokers from SRV record {}: {}", broker_srv, bootstrap);
+    Ok(bootstrap)
+}
+
+// Set the librdkafka security properties from the isSecure settings
+// librdkafka has no JAAS config, so the username and password are taken out of it
+// and it reads the CA certificates from a PEM file rather than a JKS or PKCS12 trust store
+fn apply_security(args: &ProducerArgs, config: &mut ClientConfig) -> Result<(), Box<dyn Error>> {
+    if !args.is_secure {
+        return Ok(());
+    }
+    if let Some(security_protocol) = &args.security_protocol {
+        config.set("security.protocol", security_protocol);
+    }
+    if let Some(sasl_mechanism) = &args.sasl_mechanism {
+        config.set("sasl.mechanism", sasl_mechanism);
+    }
+    if let Some((username, password)) = args.sasl_jaas_config.as_deref().and_then(jaas_credentials)
+    {
+        config
+            .set("sasl.username", username)
+            .set("sasl.password", password);
+    }
+    if let Some(truststore_location) = &args.truststore_location {
+        match args.trust_store_type.as_deref() {
+            None => {}
+            Some(trust_store_type) if trust_store_type.eq_ignore_ascii_case("PEM") => {}
+            Some(trust_store_type) => {
+                return Err(format!(
+                    "--trustStoreType {} is not supported, librdkafka needs the CA certificates in a PEM file",
+                    trust_store_type
+                )
+                .into())
+            }
+        }
+        config.set("ssl.ca.location", truststore_location);
+    }
+    Ok(())
+}
+
+// The username and password of a JAAS config, e.g.
+// org.apache.kafka.common.security.plain.PlainLoginModule required username="app" password="secret";
+fn jaas_credentials(jaas_config: &str) -> Option<(String, String)> {
+    let option = |name: &str| {
+        let pattern = Regex::new(&format!(r#"\b{}\s*=\s*"([^"]*)""#, name)).ok()?;
+        let captures = pattern.captures(jaas_config)?;
+        Some(captures[1].to_string())
+    };
+    Some((option("username")?, option("password")?))
+}
+
+// A secure connection needs a protocol
+// and SASL protocols need the mechanism and the credentials
+// checked by validate_setup (--fail-fast-on-startup)
+pub fn check_security_settings(args: &ProducerArgs) -> Result<(), Box<dyn Error>> {
+    if !args.is_secure {
+        return Ok(());
+    }
+    let Some(security_protocol) = &args.security_protocol else {
+        return Err("--isSecure needs --securityProtocol (e.g. SASL_SSL)".into());
+    };
+    if security_protocol.to_ascii_uppercase().starts_with("SASL") {
+        if args.sasl_mechanism.is_none() {
+            return Err(format!(
+                "--securityProtocol {} needs --saslMechanism (e.g. PLAIN or SCRAM-SHA-512)",
+                security_protocol
+            )
+            .into());
+        }
+        let has_credentials = args
+            .sasl_jaas_config
+            .as_deref()
+            .and_then(jaas_credentials)
+            .is_some();
+        if !has_credentials {
+            return Err(format!(
+                "--securityProtocol {} needs credentials in --saslJaasConfig (or KAFKA_SASL_JAAS_CONFIG)",
+                security_protocol
+            )
+            .into());
+        }
+    }
+    if args.truststore_password.is_some() && args.truststore_location.is_none() {
+        return Err("--trustStorePassword needs --trustStoreLocation".into());
+    }
+    Ok(())
+}
+
+// The broker only de-duplicates retried batches when
+// every replica acks (acks=all) and there are at most 5 batches in flight
+const IDEMPOTENT_MAX_INFLIGHT: i32 = 5;
+
+fn apply_idempotence(args: &ProducerArgs, config: &mut ClientConfig) -> Result<(), Box<dyn Error>> {
+    if args.ack_mode == "0" {
+        return Err("--idempotent cannot be used with --acks=0".into());
+    }
+
+    if args.ack_mode != "all" && args.ack_mode != "-1" {
+        log::warn!(
+            "--idempotent requires acks=all, overriding --acks={}",
+            args.ack_mode
+        );
+    }
+
+    let max_inflight = if args.max_inflight > IDEMPOTENT_MAX_INFLIGHT {
+        log::warn!(
+            "--idempotent allows at most {} batches in flight, overriding --maxInflight={}",
+            IDEMPOTENT_MAX_INFLIGHT,
+            args.max_inflight
+        );
+        IDEMPOTENT_MAX_INFLIGHT
+    } else {
+        args.max_inflight.max(1)
+    };
+
+    config
+        .set("enable.idempotence", "true")
+        .set("acks", "all")
+        .set(
+            "max.in.flight.requests.per.connection",
+            max_inflight.to_string(),
+        );
+    Ok(())
+}
+
+// Retrying with more than one batch in flight can reorder messages
+// since a retried batch may land after a later batch
+// (unless the producer is idempotent)
+pub fn ordering_warning(args: &ProducerArgs) -> Option<String> {
+    if args.max_inflight > 1 && args.retries > 0 && !args.idempotent && !args.ordered {
+        Some(format!(
+            "maxInflight {} with retries {} can publish messages out of order",
+            args.max_inflight, args.retries
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::SrvRecord;
+    use clap::Parser;
+
+    // the required args plus any extra args for the test
+    fn parse_args(extra_args: &[&str]) -> Result<ProducerArgs, clap::Error> {
+        let mut argv = vec![
+            "kafka_pub_cli",
+            "--topic",
+            "test-topic",
+            "--bootstrap-server",
+            "localhost:9092",
+            "--acks",
+            "1",
+            "--messageLocation",
+            "/tmp",
+        ];
+        argv.extend_from_slice(extra_args);
+        ProducerArgs::try_parse_from(argv)
+    }
+
+    // Answers every lookup with a single broker
+    struct MovedBrokerResolver;
+
+    impl SrvResolver for MovedBrokerResolver {
+        fn lookup_srv(&self, _name: &str) -> Result<Vec<SrvRecord>, Box<dyn Error>> {
+            Ok(vec![SrvRecord {
+                priority: 10,
+                weight: 0,
+                port: 9092,
+                target: "kafka-1.kafka-headless.".to_string(),
+            }])
+        }
+    }
+
+    #[test]
+    fn refresh_brokers_follows_srv_record() {
+        let mut config = ClientConfig::new();
+        config.set("bootstrap.servers", "kafka-0.kafka-headless:9092");
+        let producer = RdKafkaProducer::new(&config)
+            .expect("create producer failed")
+            .broker_srv("_kafka._tcp.kafka-headless", Box::new(MovedBrokerResolver));
+
+        producer.refresh_brokers().expect("refresh failed");
+        assert_eq!(
+            producer.bootstrap_servers().as_deref(),
+            Some("kafka-1.kafka-headless:9092")
+        );
+    }
+
+    #[test]
+    fn secure_sasl_without_credentials_fails() {
+        let args = parse_args(&[
+            "--isSecure",
+            "--securityProtocol",
+            "SASL_SSL",
+            "--saslMechanism",
+            "PLAIN",
+        ])
+        .expect("parsing args failed");
+        let err = check_security_settings(&args).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "--securityProtocol SASL_SSL needs credentials in --saslJaasConfig (or KAFKA_SASL_JAAS_CONFIG)"
+        );
+    }
+
+    #[test]
+    fn secure_without_protocol_fails() {
+        let args = parse_args(&["--isSecure"]).expect("parsing args failed");
+        let err = check_security_settings(&args).unwrap_err().to_string();
+        assert!(err.contains("needs --securityProtocol"));
+        // only reported by validate_setup, the producer is still created
+        let producer = KafkaProducer::from_args(&args).expect("creating producer failed");
+        let err = producer
+            .validate_setup("test-topic")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("needs --securityProtocol"));
+    }
+
+    #[test]
+    fn secure_settings_in_config() {
+        let args = parse_args(&[
+            "--isSecure",
+            "--securityProtocol",
+            "SASL_SSL",
+            "--saslMechanism",
+            "SCRAM-SHA-512",
+            "--saslJaasConfig",
+            r#"org.apache.kafka.common.security.scram.ScramLoginModule required username="app" password="s3cret";"#,
+            "--trustStoreLocation",
+            "/etc/kafka/ca.pem",
+        ])
+        .expect("parsing args failed");
+        assert!(check_security_settings(&args).is_ok());
+        let config = client_config(&args).expect("building config failed");
+        assert_eq!(config.get("security.protocol"), Some("SASL_SSL"));
+        assert_eq!(config.get("sasl.mechanism"), Some("SCRAM-SHA-512"));
+        assert_eq!(config.get("sasl.username"), Some("app"));
+        assert_eq!(config.get("sasl.password"), Some("s3cret"));
+        assert_eq!(config.get("ssl.ca.location"), Some("/etc/kafka/ca.pem"));
+        assert_eq!(config.get("sasl.jaas.config"), None);
+    }
+
+    #[test]
+    fn jks_trust_store_rejected() {
+        let args = parse_args(&[
+            "--isSecure",
+            "--securityProtocol",
+            "SSL",
+            "--trustStoreType",
+            "JKS",
+            "--trustStoreLocation",
+            "/etc/kafka/truststore.jks",
+        ])
+        .expect("parsing args failed");
+        let err = client_config(&args).unwrap_err().to_string();
+        assert!(
+            err.contains("needs the CA certificates in a PEM file"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn publish_errors_classified_for_the_poller() {
+        let timed_out: Box<dyn Error> = Box::new(DeliveryTimeout {
+            topic: "test-topic".to_string(),
+            timeout: Duration::from_millis(50),
+        });
+        let queue_full: Box<dyn Error> =
+            Box::new(KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull));
+        let too_large: Box<dyn Error> = Box::new(KafkaError::MessageProduction(
+            RDKafkaErrorCode::MessageSizeTooLarge,
+        ));
+        let unknown_topic: Box<dyn Error> = Box::new(KafkaError::MessageProduction(
+            RDKafkaErrorCode::UnknownTopicOrPartition,
+        ));
+
+        assert!(SinkError::is_retriable(sink_error(timed_out).as_ref()));
+        let queue_full = sink_error(queue_full);
+        assert!(SinkError::is_retriable(queue_full.as_ref()));
+        // once the retries are used up the poller still sees a connection error
+        assert!(matches!(
+            PollError::from(queue_full),
+            PollError::Connection(_)
+        ));
+        assert!(matches!(
+            sink_error(too_large).downcast_ref(),
+            Some(SinkError::Fatal(_))
+        ));
+        assert!(sink_error(unknown_topic)
+            .downcast_ref::<SinkError>()
+            .is_none());
+    }
+
+    #[test]
+    fn kafka_props_passed_through() {
+        let args = parse_args(&[
+            "--kafka-prop",
+            "socket.timeout.ms=10000",
+            "--kafka-prop",
+            "message.max.bytes=2000000",
+        ])
+        .expect("parsing args failed");
+        let config = client_config(&args).expect("client config failed");
+        assert_eq!(config.get("socket.timeout.ms"), Some("10000"));
+        assert_eq!(config.get("message.max.bytes"), Some("2000000"));
+    }
+
+    #[test]
+    fn conflicting_kafka_prop_warns() {
+        let args = parse_args(&["--kafka-prop", "linger.ms=50"]).expect("parsing args failed");
+        let mut config = ClientConfig::new();
+        config.set("linger.ms", "0");
+        let warnings = apply_kafka_props(&args, &mut config).expect("applying props failed");
+        assert_eq!(
+            warnings,
+            ["--kafka-prop linger.ms=50 overrides the 0 set by the typed flags"]
+        );
+        assert_eq!(config.get("linger.ms"), Some("50"));
+    }
+
+    #[test]
+    fn conflicting_kafka_prop_fails_when_strict() {
+        let args =
+            parse_args(&["--kafka-prop", "acks=0", "--strict-props"]).expect("parsing args failed");
+        let err = client_config(&args).unwrap_err().to_string();
+        assert!(err.contains("--strict-props"), "{}", err);
+    }
+
+    #[test]
+    fn kafka_prop_needs_key_and_value() {
+        assert!(parse_args(&["--kafka-prop", "socket.timeout.ms"]).is_err());
+        assert!(parse_args(&["--kafka-prop", "=10"]).is_err());
+    }
+
+    #[test]
+    fn compression_defaults_to_none() {
+        let args = parse_args(&[]).expect("parsing args failed");
+        let config = client_config(&args).expect("client config failed");
+        assert_eq!(config.get("compression.type"), Some("none"));
+    }
+
+    #[test]
+    fn compression_type_set_for_each_value() {
+        for value in ["none", "gzip", "snappy", "lz4", "zstd"] {
+            let args = parse_args(&["--compression", value]).expect("parsing args failed");
+            let config = client_config(&args).expect("client config failed");
+            assert_eq!(config.get("compression.type"), Some(value));
+        }
+    }
+
+    #[test]
+    fn batching_settings_in_config() {
+        let args = parse_args(&[
+            "--batchSizeBytes",
+            "32768",
+            "--batchDelay",
+            "5",
+            "--maxInflight",
+            "3",
+        ])
+        .expect("parsing args failed");
+        let config = client_config(&args).expect("client config failed");
+        assert_eq!(config.get("batch.size"), Some("32768"));
+        assert_eq!(config.get("linger.ms"), Some("5"));
+        assert_eq!(
+            config.get("max.in.flight.requests.per.connection"),
+            Some("3")
+        );
+    }
+
+    #[test]
+    fn batching_settings_reject_negative_values() {
+        assert!(parse_args(&["--batchSizeBytes", "-1"]).is_err());
+        assert!(parse_args(&["--batchDelay", "-1"]).is_err());
+        assert!(parse_args(&["--maxInflight", "-1"]).is_err());
+    }
+
+    #[test]
+    fn ordering_warning_with_retries_and_inflight() {
+        let args = parse_args(&["--maxInflight", "5", "--retries", "3"]).expect("parsing failed");
+        assert!(ordering_warning(&args).is_some());
+
+        let args = parse_args(&["--maxInflight", "1", "--retries", "3"]).expect("parsing failed");
+        assert!(ordering_warning(&args).is_none());
+    }
+
+    #[test]
+    fn idempotent_adjusts_acks_and_inflight() {
+        // the required args already set --acks 1
+        let args =
+            parse_args(&["--idempotent", "--maxInflight", "10"]).expect("parsing args failed");
+        let config = client_config(&args).expect("client config failed");
+        assert_eq!(config.get("enable.idempotence"), Some("true"));
+        assert_eq!(config.get("acks"), Some("all"));
+        assert_eq!(
+            config.get("max.in.flight.requests.per.connection"),
+            Some("5")
+        );
+    }
+
+    #[test]
+    fn ordered_allows_one_batch_in_flight() {
+        let args = parse_args(&["--ordered", "--maxInflight", "5", "--retries", "3"])
+            .expect("parsing args failed");
+        let config = client_config(&args).expect("client config failed");
+        assert_eq!(
+            config.get("max.in.flight.requests.per.connection"),
+            Some("1")
+        );
+        assert_eq!(ordering_warning(&args), None);
+    }
+
+    #[test]
+    fn idempotent_keeps_safe_inflight() {
+        let mut args =
+            parse_args(&["--idempotent", "--maxInflight", "3"]).expect("parsing args failed");
+        args.ack_mode = "all".to_string();
+        let config = client_config(&args).expect("client config failed");
+        assert_eq!(config.get("acks"), Some("all"));
+        assert_eq!(
+            config.get("max.in.flight.requests.per.connection"),
+            Some("3")
+        );
+    }
+
+    #[test]
+    fn idempotent_rejects_acks_zero() {
+        let mut args = parse_args(&["--idempotent"]).expect("parsing args failed");
+        args.ack_mode = "0".to_string();
+        let result = client_config(&args);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("--idempotent cannot be used with --acks=0"));
+    }
+
+    #[test]
+    fn broker_errors_are_transient() {
+        let error: Box<dyn Error> = Box::new(KafkaError::MessageProduction(
+            RDKafkaErrorCode::BrokerTransportFailure,
+        ));
+        assert!(is_transient(error.as_ref()));
+
+        let error: Box<dyn Error> = Box::new(KafkaError::MessageProduction(
+            RDKafkaErrorCode::UnknownTopicOrPartition,
+        ));
+        assert!(!is_transient(error.as_ref()));
+
+        let error: Box<dyn Error> = "Delivery report was canceled".into();
+        assert!(!is_transient(error.as_ref()));
+    }
+
+    #[test]
+    fn compression_rejects_unknown_value() {
+        let result = parse_args(&["--compression", "brotli"]);
+        assert!(result.is_err());
+    }
+}
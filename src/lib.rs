@@ -1 +1,30 @@
+pub mod args;
+pub mod batch;
+pub mod budget;
+pub mod clock;
+pub mod content;
+pub mod content_type;
+pub mod dedup;
+pub mod discovery;
+pub mod disk;
+pub mod envelope;
 pub mod file;
+pub mod filesystem;
+pub mod kafka;
+pub mod lock;
+pub mod logging;
+pub mod mirror;
+pub mod overrides;
+pub mod partition;
+pub mod path_template;
+pub mod probe;
+pub mod rate;
+pub mod schema_registry;
+pub mod sink;
+pub mod status;
+pub mod stdin;
+pub mod tail;
+pub mod template;
+pub mod timestamp;
+pub mod topics;
+pub mod watch;
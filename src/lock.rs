@@ -0,0 +1,98 @@
+/// Keeping two instances from polling the same directory
+///
+/// The poller holds an advisory lock on the lock file while it polls
+/// the OS drops the lock when the process exits, even when it crashes
+/// so a lock file a crashed instance left behind is stale and is taken over
+use crate::file::PollError;
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// The lock file in the polled directory when no other path is given
+pub const DEFAULT_LOCK_FILE: &str = ".kafka_pub_cli.lock";
+
+/// The lock held on a lock file, released (and the file removed) when dropped
+pub struct DirectoryLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl DirectoryLock {
+    // Create the lock file and lock it
+    // fails straight away if another instance holds the lock
+    pub fn acquire(path: &Path) -> Result<Self, PollError> {
+        let io_error = |source| PollError::Read {
+            path: path.to_path_buf(),
+            source,
+        };
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(io_error)?;
+
+        // the lock file holds the process id of the instance that has it
+        let holder = fs::read_to_string(path)
+            .ok()
+            .and_then(|pid| pid.trim().parse::<u32>().ok());
+        if let Err(e) = file.try_lock_exclusive() {
+            if e.kind() != fs2::lock_contended_error().kind() {
+                return Err(io_error(e));
+            }
+            return Err(PollError::Locked {
+                path: path.to_path_buf(),
+                holder: holder.map_or("another process".to_string(), |pid| {
+                    format!("process {}", pid)
+                }),
+            });
+        }
+        if let Some(pid) = holder {
+            log::warn!(
+                "Taking over the stale lock file {} left by process {}",
+                path.display(),
+                pid
+            );
+        }
+
+        file.set_len(0).map_err(io_error)?;
+        write!(&file, "{}", std::process::id()).map_err(io_error)?;
+        Ok(DirectoryLock {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for DirectoryLock {
+    fn drop(&mut self) {
+        // removed while still locked so no other instance locks a file that is going away
+        if let Err(e) = fs::remove_file(&self.path) {
+            log::warn!("Failed to remove lock file {}: {}", self.path.display(), e);
+        }
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_lock_file_is_taken_over() {
+        let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+        let path = temp_dir.path().join(DEFAULT_LOCK_FILE);
+        // left behind by an instance that crashed
+        fs::write(&path, "999999").expect("writing lock file failed");
+
+        let lock = DirectoryLock::acquire(&path).expect("taking over the lock failed");
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+        drop(lock);
+        assert!(!path.exists());
+    }
+}
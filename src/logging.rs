@@ -0,0 +1,102 @@
+/// Logging setup
+///
+/// The level comes from the CLI so logs show up without setting RUST_LOG
+/// RUST_LOG still wins when it is set (e.g. RUST_LOG=kafka_rust_cli::kafka=debug)
+use clap::ValueEnum;
+use log::LevelFilter;
+use serde_json::json;
+use std::io::Write;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Trace => LevelFilter::Trace,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Error => LevelFilter::Error,
+        }
+    }
+}
+
+/// How each log line is written
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    // the env_logger format
+    #[default]
+    Text,
+    // one JSON object per line for log collectors
+    Json,
+}
+
+// Install the logger for the app
+pub fn init(level: LevelFilter, format: LogFormat) {
+    let rust_log = std::env::var("RUST_LOG").ok();
+    builder(level, format, rust_log.as_deref()).init();
+}
+
+// Report the per-file spans on stderr with their timings as they close
+// (an OpenTelemetry subscriber can be installed instead to export them)
+#[cfg(feature = "tracing")]
+pub fn init_tracing() {
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .finish();
+    if let Err(e) = tracing::subscriber::set_global_default(subscriber) {
+        log::warn!("Failed to install the tracing subscriber: {}", e);
+    }
+}
+
+// The logger configuration
+// the rust_log filters are applied on top of the default level
+pub fn builder(
+    level: LevelFilter,
+    format: LogFormat,
+    rust_log: Option<&str>,
+) -> env_logger::Builder {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level);
+    if let Some(rust_log) = rust_log {
+        builder.parse_filters(rust_log);
+    }
+
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = json!({
+                "timestamp": buf.timestamp().to_string(),
+                "level": record.level().to_string(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_from_level() {
+        let logger = builder(LogLevel::Warn.into(), LogFormat::Text, None).build();
+        assert_eq!(logger.filter(), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn rust_log_overrides_level() {
+        let logger = builder(LevelFilter::Error, LogFormat::Json, Some("debug")).build();
+        assert_eq!(logger.filter(), LevelFilter::Debug);
+    }
+}
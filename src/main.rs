@@ -1,39 +1,585 @@
-mod args;
-//mod content;
-mod file;
-//mod kafka;
+use kafka_rust_cli::args::{self, Command, MirrorArgs, ProducerArgs, TailArgs};
+use kafka_rust_cli::batch::BatchLimits;
+use kafka_rust_cli::budget::{ByteBudget, RetryBudget};
+use kafka_rust_cli::content::{self, ContentProcessor};
+use kafka_rust_cli::file;
+use kafka_rust_cli::kafka::{self, KafkaProducer};
+use kafka_rust_cli::logging;
+use kafka_rust_cli::mirror::{Mirror, RdKafkaConsumer};
+use kafka_rust_cli::overrides;
+use kafka_rust_cli::probe;
+use kafka_rust_cli::schema_registry;
+use kafka_rust_cli::status::{Status, StatusServer};
+use kafka_rust_cli::stdin;
+use kafka_rust_cli::tail::Tail;
+use kafka_rust_cli::topics::{RdKafkaMetadata, TopicSubscription};
+use log::{info, LevelFilter};
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use args::ProducerArgs;
-use clap::Parser;
-use log::info;
+fn main() -> ExitCode {
+    if args::build_info_requested(std::env::args()) {
+        println!("{}", args::build_info());
+        return ExitCode::SUCCESS;
+    }
 
-fn main() {
-    // Initialize logging
-    env_logger::init();
+    // Parse command-line arguments (and the environment)
+    let (args, config) = args::resolve_config(std::env::args()).unwrap_or_else(|e| e.exit());
+    if args.dump_config {
+        println!("{}", config.to_json());
+        return ExitCode::SUCCESS;
+    }
 
-    // Parse command-line arguments
-    let args = ProducerArgs::parse();
+    // Initialize logging
+    let log_level = if args.quiet {
+        LevelFilter::Error
+    } else {
+        args.log_level.into()
+    };
+    logging::init(log_level, args.log_format);
+    #[cfg(feature = "tracing")]
+    logging::init_tracing();
 
     // Log a few key parameters
-    info!("topic: {}", args.topic);
-    info!("bootstrap: {}", args.bootstrap);
-    info!("messageLocation: {}", args.message_location);
+    info!(
+        "topic: {}",
+        args.topic.as_deref().unwrap_or("(from subdirectory)")
+    );
+    match &args.broker_srv {
+        Some(broker_srv) => info!("bootstrap: SRV record {}", broker_srv),
+        None => info!(
+            "bootstrap: {}",
+            args.bootstrap.as_deref().unwrap_or_default()
+        ),
+    }
+    info!("messageLocation: {:?}", args.message_location);
     info!("runOnce: {}", args.run_once);
     info!("maxCycles: {}", args.max_cycles);
     info!("delayInMillis: {}", args.delay_millis);
     info!("noDeleteFiles: {}", args.no_delete_files);
 
+    // publish the piped input instead of polling
+    if args.stdin {
+        let producer = match KafkaProducer::from_args(&args) {
+            Ok(producer) => producer,
+            Err(e) => {
+                eprintln!("Error creating Kafka producer: {}", e);
+                return file::EXIT_CONNECTION.into();
+            }
+        };
+        return match stdin::publish_reader(std::io::stdin().lock(), args.split_mode, &producer) {
+            Ok(sent) => {
+                info!("Published {} messages from stdin", sent);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Error publishing stdin: {}", e);
+                file::EXIT_PROCESSING.into()
+            }
+        };
+    }
+
+    // copy a topic instead of polling
+    if let Some(Command::Mirror(mirror_args)) = &args.command {
+        return run_mirror(&args, mirror_args);
+    }
+
+    // publish the lines of a growing file instead of polling
+    if let Some(Command::Tail(tail_args)) = &args.command {
+        return run_tail(&args, tail_args);
+    }
+
     // Build the directory poller
-    let poller = file::DirectoryPoller::builder()
+    // each file is published and only deleted once the broker confirms delivery
+    let mut builder = file::DirectoryPoller::builder()
         .keep_running(!args.run_once)
+        .until_empty(args.until_empty)
         .delete_files(!args.no_delete_files)
         .poll_interval_millis(args.delay_millis)
+        .poll_jitter_millis(args.jitter_millis)
+        .fixed_rate(args.fixed_rate)
+        .min_free_disk_mb(args.min_free_disk_mb)
+        .expected_interval(Duration::from_secs(args.expected_interval_secs))
+        .exit_on_silence(args.exit_on_silence)
+        .watch(args.watch)
+        .backoff_on_empty(args.backoff_on_empty)
+        .max_backoff_millis(args.max_backoff_millis)
+        .heartbeat_every(args.heartbeat_every)
         .max_poll_cycles(args.max_cycles)
-        .build();
+        .delete_after_cycles(args.delete_after_cycles)
+        .max_files_per_cycle(args.max_files_per_cycle)
+        .max_runtime(Duration::from_secs(args.max_runtime_secs))
+        .file_ttl(Duration::from_secs(args.file_ttl_secs))
+        .max_files_per_second(args.max_rate)
+        .flush_interval_millis(args.flush_interval_ms)
+        .ordered(args.ordered)
+        .file_order(args.file_order)
+        .dedup_mode(args.dedup_mode)
+        .dedup_capacity(args.dedup_capacity)
+        .visited_markers(args.visited_marker)
+        .name_mismatch_policy(args.name_mismatch)
+        .recursive(args.recursive)
+        .topic_from_subdir(args.topic_from_subdir)
+        .key_strategy(args.key_strategy)
+        .strip_extension(args.strip_extension)
+        .key_encoding(args.key_encoding)
+        .tombstone_on_empty(args.tombstone_on_empty)
+        .envelope(args.envelope)
+        .content_type_detection(args.content_type_detection)
+        .verify_checksum(args.verify_checksum)
+        .require_checksum(args.require_checksum);
+    for pattern in &args.include {
+        builder = builder.include(pattern);
+    }
+    for pattern in &args.exclude {
+        builder = builder.exclude(pattern);
+    }
+    if let Some(require_name_regex) = &args.require_name_regex {
+        builder = builder.require_name_regex(require_name_regex.clone());
+    }
+    if let Some(directory_overrides) = &args.directory_overrides {
+        let overrides = match overrides::load_overrides(Path::new(directory_overrides)) {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                eprintln!("Invalid configuration: {}", e);
+                return file::EXIT_USAGE.into();
+            }
+        };
+        for (directory, overrides) in overrides {
+            if !args
+                .message_location
+                .iter()
+                .any(|location| directory.starts_with(location))
+            {
+                log::warn!(
+                    "Directory overrides for {} have no effect, it is not in messageLocation",
+                    directory.display()
+                );
+            }
+            builder = builder.directory_overrides(directory, overrides);
+        }
+    }
+    builder = builder.skip_hidden(!args.include_hidden);
+    if let Some(claim_suffix) = &args.claim_suffix {
+        builder = builder.claim_suffix(claim_suffix.clone());
+    }
+    if let Some(payload_template) = &args.payload_template {
+        builder = builder.payload_template(payload_template.clone());
+    }
+    if let Some(payload_jsonpath) = &args.payload_jsonpath {
+        builder = builder.payload_path(payload_jsonpath.clone());
+    }
+    if let Some(path_template) = &args.path_template {
+        builder = builder.path_template(path_template.clone());
+    }
+    builder = builder.compact_json(args.compact_json);
+    if let Some(truncate_to_bytes) = args.truncate_to_bytes {
+        builder = builder.truncate_to_bytes(truncate_to_bytes);
+    }
+    if args.batch_files.is_some() || args.batch_bytes.is_some() {
+        let batch_limits = BatchLimits::new(args.batch_files, args.batch_bytes)
+            .separator(args.batch_separator.as_bytes());
+        builder = builder.batch(batch_limits);
+    }
+    if let Some(content_type) = &args.content_type {
+        builder = builder.content_type(content_type.clone());
+    }
+    if let Some(schema_id) = args.schema_id {
+        builder = builder.schema_id(schema_id);
+    }
+    if let (Some(registry_url), Some(topic)) = (&args.schema_registry_url, &args.topic) {
+        let subject = schema_registry::value_subject(topic);
+        match schema_registry::latest_schema_id(registry_url, &subject) {
+            Ok(schema_id) => {
+                info!(
+                    "Framing payloads with schema {} of subject {}",
+                    schema_id, subject
+                );
+                builder = builder.schema_id(schema_id);
+            }
+            Err(e) => {
+                eprintln!("Error looking up the schema id: {}", e);
+                return file::EXIT_CONNECTION.into();
+            }
+        }
+    }
+    if let Some(max_inflight_bytes) = args.max_inflight_bytes {
+        builder = builder.inflight_budget(ByteBudget::shared(max_inflight_bytes));
+    }
+    if let Some(control_topic) = &args.control_topic {
+        builder = builder.control_topic(control_topic.clone());
+    }
+    builder = builder
+        .directory_read_retries(
+            args.directory_read_retries,
+            Duration::from_millis(args.directory_read_backoff_ms),
+        )
+        .strict_directory_read(args.strict_directory_read);
+    if let Some(manifest) = &args.manifest {
+        builder = builder
+            .manifest(manifest.into())
+            .strict_manifest(args.strict_manifest);
+    }
+    if let Some(sidecar_extension) = &args.sidecar_extension {
+        builder = builder.sidecar_extension(sidecar_extension.clone());
+    }
+    if let Some(json_schema) = &args.json_schema {
+        builder = builder.json_schema(json_schema.into());
+    }
+    let mut directories: Vec<&str> = args.message_location.iter().map(String::as_str).collect();
+
+    // a replay publishes the archive directory once and leaves it unchanged
+    if let Some(Command::Replay(replay)) = &args.command {
+        info!("Replaying archive directory: {}", replay.archive_directory);
+        builder = builder.replay(true);
+        if let Some(since) = replay.since {
+            builder = builder.modified_since(since);
+        }
+        directories = vec![replay.archive_directory.as_str()];
+    }
+
+    // one instance per directory
+    // a replay, probe or list only reads, so it takes no lock in the directory
+    let read_only =
+        args.list || matches!(&args.command, Some(Command::Replay(_) | Command::Probe(_)));
+    if let Some(lock_file) = &args.lock_file {
+        builder = builder.lock_file(lock_file.into());
+    } else if !read_only {
+        builder = builder.lock_directories(true);
+    }
+
+    // only show what would be published for the file
+    if let Some(Command::Probe(probe_args)) = &args.command {
+        let poller = match builder.try_build() {
+            Ok(poller) => poller,
+            Err(e) => {
+                eprintln!("Invalid configuration: {}", e);
+                return file::EXIT_USAGE.into();
+            }
+        };
+        let file_path = Path::new(&probe_args.file);
+        // routed by its path under the messageLocation it is in, as the poller would
+        let root = probe::root_directory(&directories, file_path);
+        let default_topic = args.topic.as_deref();
+        let plan = match probe_args.output_format {
+            Some(output_format) => {
+                probe::plan_with_payload(&poller, root, file_path, default_topic, output_format)
+            }
+            None => probe::plan(&poller, root, file_path, default_topic).map(String::into_bytes),
+        };
+        match plan {
+            Ok(plan) => {
+                let mut stdout = std::io::stdout().lock();
+                if let Err(e) = stdout.write_all(&plan).and_then(|()| stdout.flush()) {
+                    eprintln!("Error writing the plan for file {}: {}", probe_args.file, e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            Err(e) => {
+                eprintln!("Error probing file {}: {}", probe_args.file, e);
+                return file::EXIT_PROCESSING.into();
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
 
-    // poll directory
-    match poller.poll_directory(&args.message_location) {
-        Ok(_) => info!("Directory polling completed successfully"),
-        Err(e) => eprintln!("Error polling directory: {}", e),
+    // only show which files would be published
+    if args.list {
+        let poller = match builder.try_build() {
+            Ok(poller) => poller,
+            Err(e) => {
+                eprintln!("Invalid configuration: {}", e);
+                return file::EXIT_USAGE.into();
+            }
+        };
+        for directory in &directories {
+            match poller.enumerate_files(Path::new(directory)) {
+                Ok(files) => files.iter().for_each(|file| println!("{}", file.display())),
+                Err(e) => {
+                    eprintln!("Error listing directory {}: {}", directory, e);
+                    return file::EXIT_DIRECTORY.into();
+                }
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // stopped by Ctrl-C and SIGTERM, or by running out of retries
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let retry_budget = args.max_total_retries.map(|max_total_retries| {
+        let retry_budget = RetryBudget::new(max_total_retries);
+        let retry_budget = if args.exit_on_retry_budget {
+            retry_budget.stop_when_exhausted(Arc::clone(&shutdown_flag))
+        } else {
+            retry_budget
+        };
+        Arc::new(retry_budget)
+    });
+
+    // Connect the producer to the brokers
+    let producer = match KafkaProducer::from_args(&args) {
+        Ok(producer) => producer
+            .retry_budget(retry_budget.clone())
+            .shutdown_flag(Arc::clone(&shutdown_flag)),
+        Err(e) => {
+            eprintln!("Error creating Kafka producer: {}", e);
+            return file::EXIT_CONNECTION.into();
+        }
+    };
+    if args.fail_fast_on_startup {
+        // with topic-from-subdir the topics are only known once files are found
+        if let Some(topic) = &args.topic {
+            if let Err(e) = producer.validate_setup(topic) {
+                eprintln!("Invalid Kafka setup: {}", e);
+                return file::EXIT_CONNECTION.into();
+            }
+        }
+    }
+    builder = builder.sink(Box::new(producer)).send_retries(
+        args.send_retries,
+        Duration::from_millis(args.send_retry_backoff_ms),
+    );
+    // the poller's send retries come out of the same budget as the producer's
+    if let Some(retry_budget) = &retry_budget {
+        builder = builder.retry_budget(Arc::clone(retry_budget));
+    }
+    for fanout_topic in &args.fanout_topics {
+        match KafkaProducer::for_topic(&args, Some(fanout_topic)) {
+            Ok(producer) => {
+                let producer = producer
+                    .retry_budget(retry_budget.clone())
+                    .shutdown_flag(Arc::clone(&shutdown_flag));
+                builder = builder.add_sink(Box::new(producer))
+            }
+            Err(e) => {
+                eprintln!("Error creating Kafka producer for {}: {}", fanout_topic, e);
+                return file::EXIT_CONNECTION.into();
+            }
+        }
+    }
+    builder = builder.fanout_mode(args.fanout_mode);
+    if let Some(error_location) = &args.error_location {
+        builder = builder
+            .error_directory(error_location.into())
+            .retry_errors_on_startup(args.retry_errors_on_startup)
+            .retry_errors_max_age(Duration::from_secs(args.retry_errors_max_age_secs));
+    }
+    if let Some(checkpoint_file) = &args.checkpoint_file {
+        builder = builder.checkpoint_file(checkpoint_file.into());
+    }
+
+    // the status server runs until polling stops
+    let mut status_server = None;
+    if let Some(status_port) = args.status_port {
+        let status = Status::shared();
+        match StatusServer::start(status_port, status.clone()) {
+            Ok(server) => status_server = Some(server),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        builder = builder.status(status);
+    }
+
+    // Ctrl-C and SIGTERM stop the poller between files
+    // and the files still in flight are flushed before it returns
+    let handler_flag = Arc::clone(&shutdown_flag);
+    if let Err(e) = ctrlc::set_handler(move || {
+        info!("Shutdown requested, finishing the current file");
+        handler_flag.store(true, Ordering::SeqCst);
+    }) {
+        eprintln!("Failed to install the shutdown handler: {}", e);
+        return ExitCode::FAILURE;
+    }
+    builder = builder.shutdown_flag(shutdown_flag);
+
+    let poller = match builder.try_build() {
+        Ok(poller) => poller,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            return file::EXIT_USAGE.into();
+        }
+    };
+    info!("poller: {}", poller.config_summary());
+
+    // poll directories
+    let mut code = ExitCode::SUCCESS;
+    match poller.poll_directories(&directories) {
+        Ok(stats) => info!(
+            "Directory polling completed successfully: {} files in {} cycles",
+            stats.files_processed, stats.cycles
+        ),
+        Err(e) => {
+            eprintln!("Error polling directory: {}", e);
+            code = e.exit_code().into();
+        }
+    }
+    if let Some(status_server) = status_server {
+        status_server.stop();
+    }
+    if let Some(retry_budget) = retry_budget.filter(|_| args.exit_on_retry_budget) {
+        if retry_budget.is_exhausted() {
+            eprintln!(
+                "Stopped after using up all {} retries, the brokers are likely unreachable",
+                retry_budget.total()
+            );
+            return file::EXIT_CONNECTION.into();
+        }
+    }
+    code
+}
+
+// Consume the source topic and publish each message to the destination topic
+// until max-messages is reached or shutdown is requested
+fn run_mirror(args: &ProducerArgs, mirror_args: &MirrorArgs) -> ExitCode {
+    let mut processor = ContentProcessor::new();
+    if let Some(payload_jsonpath) = &args.payload_jsonpath {
+        processor = processor.payload_path(payload_jsonpath.clone());
+    }
+    if let Some(json_schema) = &args.json_schema {
+        match content::load_json_schema(Path::new(json_schema)) {
+            Ok(json_schema) => processor = processor.json_schema(json_schema),
+            Err(e) => {
+                eprintln!("Invalid configuration: JSON schema {}: {}", json_schema, e);
+                return file::EXIT_USAGE.into();
+            }
+        }
+    }
+    if let Some(payload_template) = &args.payload_template {
+        processor = processor.payload_template(payload_template.clone());
+    }
+
+    let consumer_config = match kafka::consumer_config(args, &mirror_args.group_id) {
+        Ok(consumer_config) => consumer_config,
+        Err(e) => {
+            eprintln!("Error creating Kafka consumer: {}", e);
+            return file::EXIT_CONNECTION.into();
+        }
+    };
+    let consumer = match RdKafkaConsumer::new(&consumer_config) {
+        Ok(consumer) => consumer,
+        Err(e) => {
+            eprintln!("Error creating Kafka consumer: {}", e);
+            return file::EXIT_CONNECTION.into();
+        }
+    };
+    // the topics matching the regex are read from the cluster metadata
+    let subscription = match &mirror_args.source_topic_regex {
+        Some(pattern) => match RdKafkaMetadata::new(&consumer_config) {
+            Ok(metadata) => Some(TopicSubscription::new(
+                pattern.clone(),
+                Box::new(metadata),
+                Duration::from_secs(mirror_args.topic_refresh_secs),
+            )),
+            Err(e) => {
+                eprintln!("Error creating Kafka metadata client: {}", e);
+                return file::EXIT_CONNECTION.into();
+            }
+        },
+        None => None,
+    };
+    // clap requires one of the two
+    let source = mirror_args
+        .source_topic
+        .clone()
+        .or_else(|| {
+            Some(
+                mirror_args
+                    .source_topic_regex
+                    .as_ref()?
+                    .as_str()
+                    .to_string(),
+            )
+        })
+        .unwrap_or_default();
+    let producer = match KafkaProducer::for_topic(args, Some(&mirror_args.dest_topic)) {
+        Ok(producer) => producer,
+        Err(e) => {
+            eprintln!("Error creating Kafka producer: {}", e);
+            return file::EXIT_CONNECTION.into();
+        }
+    };
+
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&shutdown_flag);
+    if let Err(e) = ctrlc::set_handler(move || {
+        info!("Shutdown requested, finishing the current message");
+        handler_flag.store(true, Ordering::SeqCst);
+    }) {
+        eprintln!("Failed to install the shutdown handler: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let mut mirror = Mirror::new(
+        Box::new(consumer),
+        Box::new(producer.shutdown_flag(Arc::clone(&shutdown_flag))),
+        &source,
+        &mirror_args.dest_topic,
+    )
+    .content(processor)
+    .shutdown_flag(shutdown_flag);
+    if let Some(subscription) = subscription {
+        mirror = mirror.topic_subscription(subscription);
+    }
+    if let Some(max_messages) = mirror_args.max_messages {
+        mirror = mirror.max_messages(max_messages);
+    }
+    match mirror.run() {
+        Ok(stats) => {
+            info!("Mirror completed: {} messages", stats.messages_mirrored);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error mirroring {}: {}", source, e);
+            file::EXIT_PROCESSING.into()
+        }
+    }
+}
+
+// Publish the lines appended to one file since the last run
+// with --follow until Ctrl-C, checking for new lines every delayInMillis
+fn run_tail(args: &ProducerArgs, tail_args: &TailArgs) -> ExitCode {
+    let producer = match KafkaProducer::from_args(args) {
+        Ok(producer) => producer,
+        Err(e) => {
+            eprintln!("Error creating Kafka producer: {}", e);
+            return file::EXIT_CONNECTION.into();
+        }
+    };
+
+    // only set with --follow, when Ctrl-C stops the tail
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let producer = producer.shutdown_flag(Arc::clone(&shutdown_flag));
+    let mut tail = Tail::new(Path::new(&tail_args.file), Box::new(producer));
+    if let Some(offset_file) = &tail_args.offset_file {
+        tail = tail.offset_file(Path::new(offset_file));
+    }
+    if tail_args.follow {
+        let handler_flag = Arc::clone(&shutdown_flag);
+        if let Err(e) = ctrlc::set_handler(move || {
+            info!("Shutdown requested, stopping the tail");
+            handler_flag.store(true, Ordering::SeqCst);
+        }) {
+            eprintln!("Failed to install the shutdown handler: {}", e);
+            return ExitCode::FAILURE;
+        }
+        tail = tail
+            .follow(Duration::from_millis(args.delay_millis))
+            .shutdown_flag(shutdown_flag);
+    }
+    match tail.run() {
+        Ok(stats) => {
+            info!("Tail completed: {} lines", stats.lines_published);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error tailing {}: {}", tail_args.file, e);
+            file::EXIT_PROCESSING.into()
+        }
     }
 }
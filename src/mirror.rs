@@ -0,0 +1,295 @@
+/// Copying the messages of one topic to another (the mirror subcommand)
+///
+/// Each message consumed from the source topic goes through the same content steps
+/// and sink as a file, and its offset is only committed once the sink confirms delivery
+/// so a mirror that stops (or fails) carries on from the first message not yet copied
+use crate::content::ContentProcessor;
+use crate::sink::{FileContext, FileSink};
+use crate::topics::TopicSubscription;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::message::{Headers, Message};
+use rdkafka::{Offset, TopicPartitionList};
+use std::cell::RefCell;
+use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+// How long a poll waits for a message before checking for shutdown
+const DEFAULT_POLL_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A message read from the source topic
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsumedMessage {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<Vec<u8>>,
+    // None for a tombstone
+    pub payload: Option<Vec<u8>>,
+    pub headers: Vec<(String, Vec<u8>)>,
+    // milliseconds since the epoch, if the message has a timestamp
+    pub timestamp: Option<i64>,
+}
+
+/// The consumer side of a mirror: subscribing to the source topics,
+/// polling one message at a time and committing its offset once it was produced
+pub trait MessageConsumer {
+    // Consume these topics from now on, in place of any subscribed before
+    fn subscribe(&self, topics: &[String]) -> Result<(), Box<dyn Error>>;
+
+    // The next message, None if none arrived within the timeout
+    fn poll(&self, timeout: Duration) -> Result<Option<ConsumedMessage>, Box<dyn Error>>;
+
+    // Commit the offset past the message for the consumer group
+    fn commit(&self, message: &ConsumedMessage) -> Result<(), Box<dyn Error>>;
+}
+
+/// MessageConsumer backed by the rdkafka BaseConsumer
+pub struct RdKafkaConsumer {
+    consumer: BaseConsumer,
+}
+
+impl RdKafkaConsumer {
+    pub fn new(config: &ClientConfig) -> Result<Self, Box<dyn Error>> {
+        let consumer: BaseConsumer = config.create()?;
+        Ok(RdKafkaConsumer { consumer })
+    }
+}
+
+impl MessageConsumer for RdKafkaConsumer {
+    fn subscribe(&self, topics: &[String]) -> Result<(), Box<dyn Error>> {
+        if topics.is_empty() {
+            self.consumer.unsubscribe();
+            return Ok(());
+        }
+        let topics: Vec<&str> = topics.iter().map(String::as_str).collect();
+        Ok(self.consumer.subscribe(&topics)?)
+    }
+
+    fn poll(&self, timeout: Duration) -> Result<Option<ConsumedMessage>, Box<dyn Error>> {
+        let message = match self.consumer.poll(timeout) {
+            None => return Ok(None),
+            Some(message) => message?,
+        };
+        let headers = message
+            .headers()
+            .map(|headers| {
+                headers
+                    .iter()
+                    .map(|header| {
+                        let value = header.value.unwrap_or_default();
+                        (header.key.to_string(), value.to_vec())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Some(ConsumedMessage {
+            topic: message.topic().to_string(),
+            partition: message.partition(),
+            offset: message.offset(),
+            key: message.key().map(<[u8]>::to_vec),
+            payload: message.payload().map(<[u8]>::to_vec),
+            headers,
+            timestamp: message.timestamp().to_millis(),
+        }))
+    }
+
+    fn commit(&self, message: &ConsumedMessage) -> Result<(), Box<dyn Error>> {
+        let mut offsets = TopicPartitionList::new();
+        offsets.add_partition_offset(
+            &message.topic,
+            message.partition,
+            Offset::Offset(message.offset + 1),
+        )?;
+        Ok(self.consumer.commit(&offsets, CommitMode::Sync)?)
+    }
+}
+
+/// What a mirror run copied
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MirrorStats {
+    pub messages_mirrored: u64,
+}
+
+/// Consumes the source topic and sends every message to the sink for the destination topic
+pub struct Mirror {
+    consumer: Box<dyn MessageConsumer>,
+    sink: Box<dyn FileSink>,
+    source_topic: String,
+    // follows the topics matching a pattern instead of the source topic
+    subscription: Option<RefCell<TopicSubscription>>,
+    dest_topic: String,
+    content: ContentProcessor,
+    // stop after this many messages, None runs until shutdown
+    max_messages: Option<u64>,
+    poll_timeout: Duration,
+    shutdown_flag: Option<Arc<AtomicBool>>,
+}
+
+impl Mirror {
+    pub fn new(
+        consumer: Box<dyn MessageConsumer>,
+        sink: Box<dyn FileSink>,
+        source_topic: &str,
+        dest_topic: &str,
+    ) -> Self {
+        Mirror {
+            consumer,
+            sink,
+            source_topic: source_topic.to_string(),
+            subscription: None,
+            dest_topic: dest_topic.to_string(),
+            content: ContentProcessor::new(),
+            max_messages: None,
+            poll_timeout: DEFAULT_POLL_TIMEOUT,
+            shutdown_flag: None,
+        }
+    }
+
+    // consume the topics the subscription covers (but never the destination topic)
+    // instead of the source topic, subscribing again as matching topics come and go
+    pub fn topic_subscription(mut self, subscription: TopicSubscription) -> Self {
+        self.subscription = Some(RefCell::new(subscription));
+        self
+    }
+
+    // run the payload of each message through these content steps
+    pub fn content(mut self, content: ContentProcessor) -> Self {
+        self.content = content;
+        self
+    }
+
+    pub fn max_messages(mut self, max_messages: u64) -> Self {
+        self.max_messages = Some(max_messages);
+        self
+    }
+
+    pub fn poll_timeout(mut self, poll_timeout: Duration) -> Self {
+        self.poll_timeout = poll_timeout;
+        self
+    }
+
+    // stop between messages once the flag is set
+    pub fn shutdown_flag(mut self, shutdown_flag: Arc<AtomicBool>) -> Self {
+        self.shutdown_flag = Some(shutdown_flag);
+        self
+    }
+
+    // Copy messages until max_messages is reached or shutdown is requested
+    // a message that cannot be mirrored stops the run without committing its offset
+    pub fn run(&self) -> Result<MirrorStats, Box<dyn Error>> {
+        match &self.subscription {
+            Some(subscription) => self.follow_subscription(&mut subscription.borrow_mut())?,
+            None => self
+                .consumer
+                .subscribe(std::slice::from_ref(&self.source_topic))?,
+        }
+        log::info!(
+            "Mirroring {} to topic {}",
+            self.source_description(),
+            self.dest_topic
+        );
+
+        let mut stats = MirrorStats::default();
+        while !self.is_done(&stats) {
+            // a failed metadata lookup keeps the topics subscribed so far
+            if let Some(subscription) = &self.subscription {
+                if let Err(e) = self.follow_subscription(&mut subscription.borrow_mut()) {
+                    log::warn!("Failed to look up the topics to mirror: {}", e);
+                }
+            }
+            let Some(message) = self.consumer.poll(self.poll_timeout)? else {
+                continue;
+            };
+            self.mirror_message(&message).map_err(|e| {
+                format!(
+                    "Failed to mirror message {}/{}@{}: {}",
+                    message.topic, message.partition, message.offset, e
+                )
+            })?;
+            stats.messages_mirrored += 1;
+        }
+        self.sink.flush()?;
+        log::info!(
+            "Mirrored {} messages from {} to topic {}",
+            stats.messages_mirrored,
+            self.source_description(),
+            self.dest_topic
+        );
+        Ok(stats)
+    }
+
+    fn source_description(&self) -> String {
+        match &self.subscription {
+            Some(subscription) => format!(
+                "the topics matching {:?}",
+                subscription.borrow().pattern().as_str()
+            ),
+            None => format!("topic {}", self.source_topic),
+        }
+    }
+
+    // Subscribe to the topics the pattern covers when they changed (or on the first call)
+    // the destination topic is left out so the mirror never consumes what it publishes
+    fn follow_subscription(
+        &self,
+        subscription: &mut TopicSubscription,
+    ) -> Result<(), Box<dyn Error>> {
+        if !subscription.refresh(Instant::now())? {
+            return Ok(());
+        }
+        let topics: Vec<String> = subscription
+            .topics()
+            .iter()
+            .filter(|topic| **topic != self.dest_topic)
+            .cloned()
+            .collect();
+        self.consumer.subscribe(&topics)
+    }
+
+    fn is_done(&self, stats: &MirrorStats) -> bool {
+        if self
+            .max_messages
+            .is_some_and(|max_messages| stats.messages_mirrored >= max_messages)
+        {
+            return true;
+        }
+        let shutdown = self
+            .shutdown_flag
+            .as_ref()
+            .is_some_and(|shutdown_flag| shutdown_flag.load(Ordering::SeqCst));
+        if shutdown {
+            log::info!("Shutdown requested, stopping the mirror");
+        }
+        shutdown
+    }
+
+    // Send the message on and commit its offset once the sink has it
+    fn mirror_message(&self, message: &ConsumedMessage) -> Result<(), Box<dyn Error>> {
+        let ctx = self.message_context(message)?;
+        self.sink.send(&ctx)?;
+        self.consumer.commit(message)
+    }
+
+    // The message as the sink sees a file
+    // named topic-partition-offset, with the message timestamp as its modified time
+    fn message_context(&self, message: &ConsumedMessage) -> Result<FileContext, Box<dyn Error>> {
+        let name = format!("{}-{}-{}", message.topic, message.partition, message.offset);
+        let mut ctx = FileContext::new(Path::new(&name), Vec::new());
+        ctx.modified = message
+            .timestamp
+            .and_then(|millis| u64::try_from(millis).ok())
+            .map(|millis| SystemTime::UNIX_EPOCH + Duration::from_millis(millis));
+        match &message.payload {
+            Some(payload) => ctx.content = self.content.process(&ctx, payload)?.content,
+            None => ctx.tombstone = true,
+        }
+        ctx.topic = Some(self.dest_topic.clone());
+        ctx.key = message.key.clone();
+        ctx.headers = message.headers.clone();
+        Ok(ctx)
+    }
+}
@@ -0,0 +1,99 @@
+/// Settings that differ for one of the polled directories
+///
+/// With several directories one process can serve drop folders that need
+/// their own topic, key strategy, delete policy or file filters
+/// the overrides of a directory are merged over the global settings
+/// a directory without overrides uses the global settings
+use crate::file::KeyStrategy;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The settings of one directory, None keeps the global setting
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirectoryOverrides {
+    // where the directory's files go unless something else routes them
+    pub topic: Option<String>,
+    pub key_strategy: Option<KeyStrategy>,
+    pub delete_files: Option<bool>,
+    // these replace the global include and exclude globs
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+}
+
+// Read the overrides of each directory from a JSON file that maps directories to them
+// e.g. {"/data/orders": {"topic": "orders", "key_strategy": "file-stem"},
+//       "/data/audit": {"delete_files": false, "include": ["*.csv"]}}
+// the directories are spelled as they are given to messageLocation
+pub fn load_overrides(path: &Path) -> Result<Vec<(PathBuf, DirectoryOverrides)>, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        format!(
+            "Failed to read directory overrides {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    let overrides: BTreeMap<PathBuf, DirectoryOverrides> =
+        serde_json::from_str(&text).map_err(|e| {
+            format!(
+                "Directory overrides {} are malformed: {}",
+                path.display(),
+                e
+            )
+        })?;
+    Ok(overrides.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_overrides_per_directory() {
+        let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+        let path = temp_dir.path().join("directories.json");
+        fs::write(
+            &path,
+            r#"{"/data/orders": {"topic": "orders", "key_strategy": "file-stem"},
+                "/data/audit": {"delete_files": false, "include": ["*.csv"]}}"#,
+        )
+        .expect("writing overrides failed");
+
+        let overrides = load_overrides(&path).expect("loading overrides failed");
+        assert_eq!(
+            overrides,
+            vec![
+                (
+                    PathBuf::from("/data/audit"),
+                    DirectoryOverrides {
+                        delete_files: Some(false),
+                        include: Some(vec!["*.csv".to_string()]),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    PathBuf::from("/data/orders"),
+                    DirectoryOverrides {
+                        topic: Some("orders".to_string()),
+                        key_strategy: Some(KeyStrategy::FileStem),
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_setting_is_rejected() {
+        let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+        let path = temp_dir.path().join("directories.json");
+        fs::write(&path, r#"{"/data/orders": {"topics": "orders"}}"#)
+            .expect("writing overrides failed");
+
+        let err = load_overrides(&path).unwrap_err().to_string();
+        assert!(err.contains("malformed"), "{}", err);
+    }
+}
@@ -0,0 +1,141 @@
+/// Choosing the partition a file is published to
+///
+/// By default the producer leaves it to the key (or the broker) to pick the partition
+/// these strategies pin files to a partition based on their name instead
+/// e.g. regex-hash:^([a-z]+)- keeps every file of a customer prefix on one partition
+use regex::Regex;
+
+#[derive(Debug, Clone, Default)]
+pub enum PartitionStrategy {
+    // the producer's partitioner decides (by key hash when there is a key)
+    #[default]
+    Auto,
+    // the same as auto, spelled out for keyed topics
+    KeyHash,
+    // every file goes to this partition
+    Fixed(i32),
+    // the first capture group of the regex on the file name is hashed
+    // a file name that does not match falls back to auto
+    RegexHash(Regex),
+}
+
+impl PartitionStrategy {
+    // Parse auto, keyhash, fixed:N or regex-hash:PATTERN
+    pub fn parse(strategy: &str) -> Result<Self, String> {
+        match strategy {
+            "auto" => return Ok(PartitionStrategy::Auto),
+            "keyhash" => return Ok(PartitionStrategy::KeyHash),
+            _ => {}
+        }
+        if let Some(partition) = strategy.strip_prefix("fixed:") {
+            return match partition.parse::<i32>() {
+                Ok(partition) if partition >= 0 => Ok(PartitionStrategy::Fixed(partition)),
+                _ => Err(format!("Invalid partition {:?} in fixed:N", partition)),
+            };
+        }
+        if let Some(pattern) = strategy.strip_prefix("regex-hash:") {
+            let regex =
+                Regex::new(pattern).map_err(|e| format!("Invalid regex {:?}: {}", pattern, e))?;
+            if regex.captures_len() < 2 {
+                return Err(format!("The regex {:?} has no capture group", pattern));
+            }
+            return Ok(PartitionStrategy::RegexHash(regex));
+        }
+        Err(format!(
+            "Unknown partition strategy {:?} (use auto, keyhash, fixed:N or regex-hash:PATTERN)",
+            strategy
+        ))
+    }
+
+    // Whether the strategy needs the number of partitions of the topic
+    pub fn needs_partition_count(&self) -> bool {
+        matches!(
+            self,
+            PartitionStrategy::Fixed(_) | PartitionStrategy::RegexHash(_)
+        )
+    }
+
+    // Check the strategy against a topic with this many partitions
+    pub fn validate(&self, partition_count: i32) -> Result<(), String> {
+        match self {
+            PartitionStrategy::Fixed(partition) if *partition >= partition_count => Err(format!(
+                "Partition {} does not exist, the topic has {} partitions",
+                partition, partition_count
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // The partition for the file, None leaves it to the producer
+    pub fn partition(&self, file_name: &str, partition_count: i32) -> Option<i32> {
+        match self {
+            PartitionStrategy::Auto | PartitionStrategy::KeyHash => None,
+            PartitionStrategy::Fixed(partition) => Some(*partition),
+            PartitionStrategy::RegexHash(regex) => {
+                if partition_count <= 0 {
+                    return None;
+                }
+                let group = regex.captures(file_name)?.get(1)?;
+                Some((fnv1a(group.as_str().as_bytes()) % partition_count as u32) as i32)
+            }
+        }
+    }
+}
+
+// 32-bit FNV-1a
+// stable across runs and Rust versions, unlike the std hasher
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(strategy: &str) -> PartitionStrategy {
+        PartitionStrategy::parse(strategy).expect("parsing strategy failed")
+    }
+
+    #[test]
+    fn auto_and_keyhash_leave_partition_to_producer() {
+        assert_eq!(parse("auto").partition("acme-1.json", 6), None);
+        assert_eq!(parse("keyhash").partition("acme-1.json", 6), None);
+    }
+
+    #[test]
+    fn fixed_sends_every_file_to_partition() {
+        let strategy = parse("fixed:2");
+        assert_eq!(strategy.partition("acme-1.json", 6), Some(2));
+        assert_eq!(strategy.partition("globex-9.json", 6), Some(2));
+    }
+
+    #[test]
+    fn regex_hash_pins_prefix_to_partition() {
+        let strategy = parse("regex-hash:^([a-z]+)-");
+        let acme = strategy.partition("acme-1.json", 6);
+        assert_eq!(acme, Some((fnv1a(b"acme") % 6) as i32));
+        assert_eq!(strategy.partition("acme-2.json", 6), acme);
+        assert_eq!(
+            strategy.partition("globex-1.json", 6),
+            Some((fnv1a(b"globex") % 6) as i32)
+        );
+        // no match falls back to the producer
+        assert_eq!(strategy.partition("README", 6), None);
+    }
+
+    #[test]
+    fn fixed_partition_must_exist() {
+        assert!(parse("fixed:2").validate(3).is_ok());
+        let err = parse("fixed:3").validate(3).unwrap_err();
+        assert!(err.contains("has 3 partitions"));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_strategies() {
+        assert!(PartitionStrategy::parse("fixed:-1").is_err());
+        assert!(PartitionStrategy::parse("regex-hash:^[a-z]+").is_err());
+        assert!(PartitionStrategy::parse("roundrobin").is_err());
+    }
+}
@@ -0,0 +1,149 @@
+/// Routing taken from where a file sits under the polled directory (--path-template)
+///
+/// A template like "{topic}/{key}/*" names the segments of the file's path
+/// relative to the polled directory, so /drop/orders/cust-42/a.json goes to orders with key cust-42
+/// {topic} and {key} set the topic and key and any other {name} adds a header of that name
+/// * matches any one segment and other text has to match the segment exactly
+use std::path::{Component, Path};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Topic,
+    Key,
+    Header(String),
+    Any,
+    Literal(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+/// What a file's path says about where it goes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathRouting {
+    pub topic: Option<String>,
+    pub key: Option<String>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl PathTemplate {
+    // Parse the template
+    // an empty segment, a placeholder that is not a whole segment
+    // or a placeholder used twice is an error
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments: Vec<Segment> = Vec::new();
+        for part in template.split('/') {
+            let segment = match part {
+                "" => return Err(format!("Empty segment in path template {:?}", template)),
+                "*" => Segment::Any,
+                "{topic}" => Segment::Topic,
+                "{key}" => Segment::Key,
+                _ => match part.strip_prefix('{').and_then(|part| part.strip_suffix('}')) {
+                    Some(name) if !name.is_empty() && !name.contains(['{', '}']) => {
+                        Segment::Header(name.to_string())
+                    }
+                    _ if part.contains(['{', '}', '*']) => {
+                        return Err(format!(
+                            "Segment {:?} of path template {:?} has to be a whole {{name}}, * or plain text",
+                            part, template
+                        ))
+                    }
+                    _ => Segment::Literal(part.to_string()),
+                },
+            };
+            if matches!(segment, Segment::Topic | Segment::Key | Segment::Header(_))
+                && segments.contains(&segment)
+            {
+                return Err(format!(
+                    "Segment {} is used twice in path template {:?}",
+                    part, template
+                ));
+            }
+            segments.push(segment);
+        }
+        Ok(PathTemplate {
+            template: template.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    // The topic, key and headers named by the path of the file relative to the polled directory
+    // a path with more or fewer segments than the template, or a plain text segment that differs, fails
+    pub fn extract(&self, relative_path: &Path) -> Result<PathRouting, String> {
+        let parts: Vec<String> = relative_path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        let mismatch = || {
+            format!(
+                "Path {} does not match the path template {:?}",
+                relative_path.display(),
+                self.template
+            )
+        };
+        if parts.len() != self.segments.len() {
+            return Err(mismatch());
+        }
+
+        let mut routing = PathRouting::default();
+        for (segment, part) in self.segments.iter().zip(parts) {
+            match segment {
+                Segment::Topic => routing.topic = Some(part),
+                Segment::Key => routing.key = Some(part),
+                Segment::Header(name) => routing.headers.push((name.clone(), part)),
+                Segment::Any => {}
+                Segment::Literal(text) if *text == part => {}
+                Segment::Literal(_) => return Err(mismatch()),
+            }
+        }
+        Ok(routing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_named_segments() {
+        let template =
+            PathTemplate::parse("{topic}/{region}/in/{key}/*").expect("parsing template failed");
+        let routing = template
+            .extract(Path::new("orders/eu/in/cust-42/a.json"))
+            .expect("extracting failed");
+        assert_eq!(
+            routing,
+            PathRouting {
+                topic: Some("orders".to_string()),
+                key: Some("cust-42".to_string()),
+                headers: vec![("region".to_string(), "eu".to_string())],
+            }
+        );
+    }
+
+    #[test]
+    fn mismatched_path_is_an_error() {
+        let template = PathTemplate::parse("{topic}/in/*").expect("parsing template failed");
+        assert!(template.extract(Path::new("a.json")).is_err());
+        assert!(template.extract(Path::new("orders/out/a.json")).is_err());
+        assert!(template.extract(Path::new("orders/in/x/a.json")).is_err());
+    }
+
+    #[test]
+    fn invalid_template_is_an_error() {
+        assert!(PathTemplate::parse("{topic}//*").is_err());
+        assert!(PathTemplate::parse("{topic}-x/*").is_err());
+        assert!(PathTemplate::parse("{key}/{key}/*").is_err());
+        assert!(PathTemplate::parse("{}/*").is_err());
+    }
+}
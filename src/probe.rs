@@ -0,0 +1,197 @@
+/// Showing what would be published for a single file, to debug routing, keys and transforms
+///
+/// The file goes through the whole pipeline a polled file would
+/// (read, transforms, key, sidecar, headers and routing)
+/// and the result is described instead of being sent, the file is left as it is
+/// with an output format the payload itself is shown too
+use crate::file::DirectoryPoller;
+use crate::sink::FileContext;
+use data_encoding::HEXLOWER;
+use serde_json::{json, Map, Value};
+use std::error::Error;
+use std::path::Path;
+
+/// How a message (and its payload) is shown
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    // the payload bytes as they are
+    Raw,
+    // the payload as text, invalid UTF-8 replaced
+    Utf8,
+    // the payload bytes as hex
+    Hex,
+    // one JSON object per message with the key, headers and payload as text
+    JsonLines,
+}
+
+// The polled directory the file is under
+// a file outside all of them is taken as being directly in its own directory
+pub fn root_directory<'a, S: AsRef<str>>(directories: &'a [S], file_path: &'a Path) -> &'a Path {
+    directories
+        .iter()
+        .map(|directory| Path::new(directory.as_ref()))
+        .find(|directory| file_path.starts_with(directory))
+        .or_else(|| file_path.parent())
+        .unwrap_or(Path::new(""))
+}
+
+// The publish plan for the file as text, one field per line
+// root is the polled directory the file is under (see root_directory)
+// default_topic is where the sink sends files nothing else routed
+pub fn plan(
+    poller: &DirectoryPoller,
+    root: &Path,
+    file_path: &Path,
+    default_topic: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    match poller.probe(root, file_path)? {
+        Some(ctx) => Ok(describe(&ctx, default_topic)),
+        None => Ok(format!(
+            "file: {}\nskipped: the content is not valid UTF-8\n",
+            file_path.display()
+        )),
+    }
+}
+
+// The publish plan for the file with its payload in the output format
+pub fn plan_with_payload(
+    poller: &DirectoryPoller,
+    root: &Path,
+    file_path: &Path,
+    default_topic: Option<&str>,
+    output_format: OutputFormat,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    match poller.probe(root, file_path)? {
+        Some(ctx) => Ok(format_message(&ctx, default_topic, output_format)),
+        None => plan(poller, root, file_path, default_topic).map(String::into_bytes),
+    }
+}
+
+// Show the message in the output format
+// json-lines is a single line, the others are the plan followed by the payload
+pub fn format_message(
+    ctx: &FileContext,
+    default_topic: Option<&str>,
+    output_format: OutputFormat,
+) -> Vec<u8> {
+    if output_format == OutputFormat::JsonLines {
+        let mut line = message_json(ctx, default_topic).to_string();
+        line.push('\n');
+        return line.into_bytes();
+    }
+    let mut message = describe(ctx, default_topic).into_bytes();
+    if ctx.tombstone {
+        return message;
+    }
+    match output_format {
+        OutputFormat::Raw => message.extend_from_slice(&ctx.content),
+        OutputFormat::Utf8 => {
+            message.extend_from_slice(String::from_utf8_lossy(&ctx.content).as_bytes())
+        }
+        OutputFormat::Hex => message.extend_from_slice(HEXLOWER.encode(&ctx.content).as_bytes()),
+        OutputFormat::JsonLines => unreachable!("json-lines is formatted above"),
+    }
+    message.push(b'\n');
+    message
+}
+
+// The message as a JSON object, a tombstone has a null payload
+fn message_json(ctx: &FileContext, default_topic: Option<&str>) -> Value {
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+    let headers: Map<String, Value> = ctx
+        .headers
+        .iter()
+        .map(|(name, value)| (name.clone(), Value::String(text(value))))
+        .collect();
+    json!({
+        "file": ctx.file_name,
+        "topic": ctx.topic.as_deref().or(default_topic),
+        "key": ctx.key.as_deref().map(text),
+        "headers": headers,
+        "payload": (!ctx.tombstone).then(|| text(&ctx.content)),
+    })
+}
+
+// Describe what the sink would be sent, the key as hex since it may not be text
+pub fn describe(ctx: &FileContext, default_topic: Option<&str>) -> String {
+    let mut plan = format!("file: {}\n", ctx.file_name);
+    let topic = ctx.topic.as_deref().or(default_topic).unwrap_or("(none)");
+    plan.push_str(&format!("topic: {}\n", topic));
+    match &ctx.key {
+        Some(key) => plan.push_str(&format!("key: {} (hex)\n", HEXLOWER.encode(key))),
+        None => plan.push_str("key: (none)\n"),
+    }
+    if ctx.headers.is_empty() {
+        plan.push_str("headers: (none)\n");
+    } else {
+        plan.push_str("headers:\n");
+        for (name, value) in &ctx.headers {
+            plan.push_str(&format!("  {}: {}\n", name, String::from_utf8_lossy(value)));
+        }
+    }
+    if ctx.tombstone {
+        plan.push_str("payload: tombstone (null value)\n");
+    } else {
+        plan.push_str(&format!("payload: {} bytes\n", ctx.content.len()));
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_message() -> FileContext {
+        let mut ctx = FileContext::new(Path::new("in/order-1.json"), b"{\"id\":1}\xff".to_vec());
+        ctx.key = Some(b"order-1".to_vec());
+        ctx.headers
+            .push(("source".to_string(), b"billing".to_vec()));
+        ctx
+    }
+
+    #[test]
+    fn formats_message_in_each_output_format() {
+        let ctx = sample_message();
+        let plan = "file: order-1.json\n\
+            topic: orders\n\
+            key: 6f726465722d31 (hex)\n\
+            headers:\n  source: billing\n\
+            payload: 9 bytes\n";
+        let format = |output_format| format_message(&ctx, Some("orders"), output_format);
+
+        assert_eq!(
+            format(OutputFormat::Raw),
+            [plan.as_bytes(), b"{\"id\":1}\xff\n"].concat()
+        );
+        assert_eq!(
+            String::from_utf8(format(OutputFormat::Utf8)).unwrap(),
+            format!("{}{{\"id\":1}}\u{fffd}\n", plan)
+        );
+        assert_eq!(
+            String::from_utf8(format(OutputFormat::Hex)).unwrap(),
+            format!("{}7b226964223a317dff\n", plan)
+        );
+
+        let line = String::from_utf8(format(OutputFormat::JsonLines)).unwrap();
+        assert_eq!(line.lines().count(), 1);
+        let message: Value = serde_json::from_str(&line).expect("line is not JSON");
+        assert_eq!(message["file"], "order-1.json");
+        assert_eq!(message["topic"], "orders");
+        assert_eq!(message["key"], "order-1");
+        assert_eq!(message["headers"]["source"], "billing");
+        assert_eq!(message["payload"], "{\"id\":1}\u{fffd}");
+    }
+
+    #[test]
+    fn tombstone_has_no_payload() {
+        let mut ctx = sample_message();
+        ctx.tombstone = true;
+        ctx.content.clear();
+        let plan = String::from_utf8(format_message(&ctx, None, OutputFormat::Hex)).unwrap();
+        assert!(plan.ends_with("payload: tombstone (null value)\n"));
+        let line = format_message(&ctx, None, OutputFormat::JsonLines);
+        let message: Value = serde_json::from_slice(&line).expect("line is not JSON");
+        assert_eq!(message["payload"], Value::Null);
+        assert_eq!(message["topic"], Value::Null);
+    }
+}
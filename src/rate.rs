@@ -0,0 +1,79 @@
+/// Throttle how fast files are published
+///
+/// A token bucket that holds a single token
+/// so permits are handed out evenly, one every 1/rate seconds,
+/// rather than in bursts
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub struct RateLimiter {
+    interval: Duration,
+    // the earliest time the next permit is available
+    // a Mutex so the limiter can be shared between threads
+    next_permit: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    // a rate of 0 means unlimited
+    pub fn new(max_per_second: u32) -> Self {
+        let interval = if max_per_second == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs(1) / max_per_second
+        };
+
+        RateLimiter {
+            interval,
+            next_permit: Mutex::new(None),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.interval.is_zero()
+    }
+
+    // Take the next permit
+    // returns how long the caller must wait before using it
+    // the caller does the waiting so it can stop early on shutdown
+    pub fn reserve(&self, now: Instant) -> Duration {
+        if self.is_unlimited() {
+            return Duration::ZERO;
+        }
+
+        let mut next_permit = self.next_permit.lock().unwrap_or_else(|e| e.into_inner());
+        let permit = match *next_permit {
+            Some(next) if next > now => next,
+            _ => now,
+        };
+        *next_permit = Some(permit + self.interval);
+
+        permit - now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_never_waits() {
+        let limiter = RateLimiter::new(0);
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert_eq!(limiter.reserve(now), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn permits_spaced_by_interval() {
+        let limiter = RateLimiter::new(10);
+        let now = Instant::now();
+        assert_eq!(limiter.reserve(now), Duration::ZERO);
+        assert_eq!(limiter.reserve(now), Duration::from_millis(100));
+        assert_eq!(limiter.reserve(now), Duration::from_millis(200));
+
+        // time has passed so the next permit is available right away
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+    }
+}
@@ -0,0 +1,156 @@
+/// The Confluent wire format for Avro topics
+///
+/// Consumers using a schema registry expect every message to start with
+/// a zero magic byte and the big-endian id of the schema the payload was written with
+/// the id is given directly or looked up once at startup from the registry
+use serde_json::Value;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+pub const MAGIC_BYTE: u8 = 0x00;
+
+// How long to wait on the registry before giving up
+const REGISTRY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The payload in the wire format: the magic byte, the 4-byte schema id then the payload
+pub fn frame(schema_id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(5 + payload.len());
+    framed.push(MAGIC_BYTE);
+    framed.extend_from_slice(&schema_id.to_be_bytes());
+    framed.extend_from_slice(payload);
+    framed
+}
+
+// The subject of a topic's values under the default naming strategy e.g. orders-value
+pub fn value_subject(topic: &str) -> String {
+    format!("{}-value", topic)
+}
+
+// The URL as it may be shown in logs, with any user and password replaced by ***
+// e.g. http://***@registry:8081
+pub fn redacted_url(url: &str) -> String {
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest)) => (format!("{}://", scheme), rest),
+        None => (String::new(), url),
+    };
+    let authority_end = rest.find('/').unwrap_or(rest.len());
+    match rest[..authority_end].rfind('@') {
+        Some(at) => format!("{}***{}", scheme, &rest[at..]),
+        None => url.to_string(),
+    }
+}
+
+// The id of the latest schema registered for the subject
+// only plain http registries are supported
+pub fn latest_schema_id(registry_url: &str, subject: &str) -> Result<u32, Box<dyn Error>> {
+    let shown_url = redacted_url(registry_url);
+    let address = registry_url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("Schema registry URL {} is not an http:// URL", shown_url))?
+        .trim_end_matches('/');
+    let (host, base_path) = match address.split_once('/') {
+        Some((host, base_path)) => (host, format!("/{}", base_path)),
+        None => (address, String::new()),
+    };
+    let host_port = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    let registry_error =
+        |e: std::io::Error| format!("Failed to reach schema registry {}: {}", shown_url, e);
+
+    let mut stream = TcpStream::connect(&host_port).map_err(registry_error)?;
+    stream
+        .set_read_timeout(Some(REGISTRY_TIMEOUT))
+        .map_err(registry_error)?;
+    // HTTP/1.0 so the body is never chunked
+    let request = format!(
+        "GET {}/subjects/{}/versions/latest HTTP/1.0\r\nHost: {}\r\nAccept: application/vnd.schemaregistry.v1+json\r\n\r\n",
+        base_path, subject, host
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(registry_error)?;
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .map_err(registry_error)?;
+
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| format!("Schema registry {} sent a malformed response", shown_url))?;
+    let status_line = head.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(format!(
+            "Schema registry {} has no schema for subject {} ({}): {}",
+            shown_url, subject, status_line, body
+        )
+        .into());
+    }
+    let schema: Value = serde_json::from_str(body)?;
+    schema["id"]
+        .as_u64()
+        .and_then(|id| u32::try_from(id).ok())
+        .ok_or_else(|| format!("Schema registry response has no schema id: {}", body).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    #[test]
+    fn frame_prepends_magic_byte_and_schema_id() {
+        let framed = frame(0x0102_0304, b"avro");
+        assert_eq!(
+            framed,
+            [0x00, 0x01, 0x02, 0x03, 0x04, b'a', b'v', b'r', b'o']
+        );
+    }
+
+    #[test]
+    fn redacted_url_hides_user_and_password() {
+        assert_eq!(
+            redacted_url("http://u:pw@registry:8081/base"),
+            "http://***@registry:8081/base"
+        );
+        assert_eq!(redacted_url("http://registry:8081"), "http://registry:8081");
+        // an @ in the path is not a user
+        assert_eq!(redacted_url("http://registry/a@b"), "http://registry/a@b");
+    }
+
+    #[test]
+    fn latest_schema_id_asks_registry_for_subject() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind failed");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let registry = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept failed");
+            // the whole request is read so the client is not cut off while writing it
+            let mut request = BufReader::new(&stream).lines();
+            let request_line = request.next().unwrap().expect("read failed");
+            for header in request {
+                if header.expect("read failed").is_empty() {
+                    break;
+                }
+            }
+            let body = r#"{"subject":"orders-value","version":3,"id":42,"schema":"\"string\""}"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .expect("write failed");
+            request_line
+        });
+
+        let schema_id = latest_schema_id(&url, &value_subject("orders")).expect("lookup failed");
+        assert_eq!(schema_id, 42);
+        let request_line = registry.join().unwrap();
+        assert!(request_line.starts_with("GET /subjects/orders-value/versions/latest "));
+    }
+}
@@ -0,0 +1,382 @@
+/// Destinations for the files found by the DirectoryPoller
+///
+/// The poller reads each file into a FileContext
+/// and hands it to a FileSink which decides where the content goes
+/// (Kafka, S3, an HTTP endpoint, a closure in a test ...)
+use std::error::Error;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "test-util")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Everything a sink needs to know about a file
+pub struct FileContext {
+    pub path: PathBuf,
+    pub file_name: String,
+    pub content: Vec<u8>,
+    // where the file should be published
+    // None means the sink uses its configured destination
+    pub topic: Option<String>,
+    pub key: Option<Vec<u8>>,
+    pub headers: Vec<(String, Vec<u8>)>,
+    // publish a record with a null value (deleting the key from a compacted topic)
+    pub tombstone: bool,
+    // when the file was last modified, if the file system knows
+    pub modified: Option<SystemTime>,
+}
+
+impl FileContext {
+    pub fn new(path: &Path, content: Vec<u8>) -> Self {
+        let file_name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+
+        FileContext {
+            path: path.to_path_buf(),
+            file_name,
+            content,
+            topic: None,
+            key: None,
+            headers: Vec::new(),
+            tombstone: false,
+            modified: None,
+        }
+    }
+
+    // Apply a routing decision to the file
+    pub fn route(&mut self, outcome: PublishOutcome) {
+        if outcome.topic.is_some() {
+            self.topic = outcome.topic;
+        }
+        if outcome.key.is_some() {
+            self.key = outcome.key;
+        }
+    }
+
+    // The content as text
+    // fails if the file is not valid UTF-8
+    pub fn content_str(&self) -> Result<&str, Box<dyn Error>> {
+        std::str::from_utf8(&self.content)
+            .map_err(|e| format!("File content is not valid UTF-8: {}", e).into())
+    }
+}
+
+/// A routing decision for a file
+/// None keeps the destination the sink is configured with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublishOutcome {
+    pub topic: Option<String>,
+    pub key: Option<Vec<u8>>,
+}
+
+pub trait FileSink {
+    // Send the file to the destination
+    // returning Ok only once the destination has accepted it
+    // since the poller may delete the file afterwards
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>>;
+
+    // Push out anything the sink has buffered
+    // called periodically by the poller and when it stops
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    // A transactional sink publishes each poll cycle's files atomically
+    // the poller opens a transaction before each cycle, commits it at the end
+    // and only then deletes the files, or aborts it when a file fails
+    fn is_transactional(&self) -> bool {
+        false
+    }
+
+    fn begin_cycle(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn commit_cycle(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn abort_cycle(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// How a FanoutSink treats a file when some of its sinks fail
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FanoutMode {
+    // the file is only done once every sink accepted it
+    #[default]
+    All,
+    // the file is done once any sink accepted it, the failures are logged
+    Any,
+}
+
+/// FileSink that sends every file to each of its sinks in turn
+/// e.g. to publish to the old and the new topic during a migration
+pub struct FanoutSink {
+    sinks: Vec<Box<dyn FileSink>>,
+    mode: FanoutMode,
+}
+
+impl FanoutSink {
+    pub fn new(mode: FanoutMode) -> Self {
+        FanoutSink {
+            sinks: Vec::new(),
+            mode,
+        }
+    }
+
+    pub fn sink(mut self, sink: Box<dyn FileSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    // Run the step on each sink
+    // under all the first failure stops the fan-out
+    // (a file that is kept is sent to the sinks that accepted it again on the next cycle)
+    fn each<F>(&self, step: F) -> Result<(), Box<dyn Error>>
+    where
+        F: Fn(&dyn FileSink) -> Result<(), Box<dyn Error>>,
+    {
+        let mut failures = Vec::new();
+        for (index, sink) in self.sinks.iter().enumerate() {
+            if let Err(e) = step(sink.as_ref()) {
+                let failure = format!("sink {} of {} failed: {}", index + 1, self.sinks.len(), e);
+                if self.mode == FanoutMode::All {
+                    return Err(failure.into());
+                }
+                failures.push(failure);
+            }
+        }
+        if !failures.is_empty() && failures.len() == self.sinks.len() {
+            return Err(failures.join("; ").into());
+        }
+        for failure in failures {
+            log::warn!("Fan-out continues, {}", failure);
+        }
+        Ok(())
+    }
+}
+
+impl FileSink for FanoutSink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        self.each(|sink| sink.send(ctx))
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        self.each(|sink| sink.flush())
+    }
+
+    // each sink has a transaction of its own, they are not committed atomically together
+    fn is_transactional(&self) -> bool {
+        self.sinks.iter().any(|sink| sink.is_transactional())
+    }
+
+    fn begin_cycle(&self) -> Result<(), Box<dyn Error>> {
+        self.each(|sink| sink.begin_cycle())
+    }
+
+    fn commit_cycle(&self) -> Result<(), Box<dyn Error>> {
+        self.each(|sink| sink.commit_cycle())
+    }
+
+    fn abort_cycle(&self) -> Result<(), Box<dyn Error>> {
+        self.each(|sink| sink.abort_cycle())
+    }
+}
+
+/// A file as an InMemorySink received it
+#[cfg(feature = "test-util")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedRecord {
+    pub topic: Option<String>,
+    pub key: Option<Vec<u8>>,
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub payload: Vec<u8>,
+}
+
+/// FileSink that keeps every file it receives in memory (the test-util feature)
+/// for testing a pipeline end to end without a broker
+/// the clones share the records, so keep one to assert on after handing the other to the poller
+#[cfg(feature = "test-util")]
+#[derive(Clone, Default)]
+pub struct InMemorySink {
+    records: Arc<Mutex<Vec<ReceivedRecord>>>,
+    failing: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "test-util")]
+impl InMemorySink {
+    pub fn new() -> Self {
+        InMemorySink::default()
+    }
+
+    // While failing every file is rejected (and not recorded) like a broker that is down
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    pub fn records(&self) -> Vec<ReceivedRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    // The payloads as text, in the order they were received
+    pub fn payloads(&self) -> Vec<String> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|record| String::from_utf8_lossy(&record.payload).to_string())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().unwrap().clear();
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl FileSink for InMemorySink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(format!("In-memory sink rejected file {}", ctx.file_name).into());
+        }
+        self.records.lock().unwrap().push(ReceivedRecord {
+            topic: ctx.topic.clone(),
+            key: ctx.key.clone(),
+            headers: ctx.headers.clone(),
+            payload: ctx.content.clone(),
+        });
+        Ok(())
+    }
+}
+
+// Type alias for file content callback
+// Need to wrap closure in Box to allocate on the heap
+// and use dynamic dispatch since we don't know the closure at compile time
+// allowing us to use any closure that matches the signature
+type FileContentCallback = Box<dyn Fn(&str) -> Result<(), Box<dyn Error>>>;
+
+/// FileSink that passes the file content (as text) to a closure
+pub struct ClosureSink {
+    on_file_content: FileContentCallback,
+}
+
+impl ClosureSink {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&str) -> Result<(), Box<dyn Error>> + 'static,
+    {
+        ClosureSink {
+            on_file_content: Box::new(callback),
+        }
+    }
+}
+
+impl FileSink for ClosureSink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        (self.on_file_content)(ctx.content_str()?)
+    }
+}
+
+// Type alias for a file content callback that can run on another thread
+type SendFileContentCallback = Arc<Mutex<dyn Fn(&str) -> Result<(), Box<dyn Error>> + Send>>;
+
+/// How a failed send should be handled, boxed up as the error of FileSink::send
+/// the poller sends a file with a Retriable error again (with send_retries set)
+/// and gives up on a Fatal one straight away, any other error is not retried
+#[derive(Debug, thiserror::Error)]
+pub enum SinkError {
+    // sending again may work, e.g. a publish that timed out
+    #[error("{0}")]
+    Retriable(Box<dyn Error>),
+    // sending again cannot help, e.g. content the destination rejects
+    #[error("{0}")]
+    Fatal(Box<dyn Error>),
+}
+
+impl SinkError {
+    pub fn is_retriable(error: &(dyn Error + 'static)) -> bool {
+        matches!(error.downcast_ref(), Some(SinkError::Retriable(_)))
+    }
+
+    // The error the sink failed with
+    pub fn into_inner(self) -> Box<dyn Error> {
+        match self {
+            SinkError::Retriable(error) | SinkError::Fatal(error) => error,
+        }
+    }
+}
+
+/// FileSink that runs the closure on a worker thread
+/// and gives up waiting on it after the timeout (if there is one)
+/// a callback that times out keeps running in the background
+/// (and holds up the callbacks for later files until it returns)
+pub struct TimeoutSink {
+    on_file_content: SendFileContentCallback,
+    timeout: Option<Duration>,
+}
+
+impl TimeoutSink {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&str) -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        TimeoutSink {
+            on_file_content: Arc::new(Mutex::new(callback)),
+            timeout: None,
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl FileSink for TimeoutSink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        let content = ctx.content_str()?.to_string();
+        let on_file_content = Arc::clone(&self.on_file_content);
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            // the error is not Send so it crosses back as text
+            let result = match on_file_content.lock() {
+                Ok(callback) => callback(&content).map_err(|e| e.to_string()),
+                Err(_) => Err("Callback panicked on an earlier file".to_string()),
+            };
+            // the receiver is gone if the wait timed out
+            let _ = sender.send(result);
+        });
+
+        let Some(timeout) = self.timeout else {
+            let result = receiver
+                .recv()
+                .map_err(|_| SinkError::Fatal("Callback panicked".into()))?;
+            return result.map_err(|e| e.into());
+        };
+        // a slow callback may be done next time, one that panicked will not
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result.map_err(|e| e.into()),
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(Box::new(SinkError::Retriable(
+                format!("Callback did not finish within {} ms", timeout.as_millis()).into(),
+            ))),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(Box::new(SinkError::Fatal("Callback panicked".into())))
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+/// A human readable snapshot of what the poller is doing
+///
+/// The poll loop updates a shared Status
+/// and a small HTTP server thread serves it as JSON on GET /status
+use crate::file::PollStats;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Status shared between the poll loop and the status server
+pub type SharedStatus = Arc<Mutex<Status>>;
+
+pub struct Status {
+    pub running: bool,
+    // the cycle in progress (or the last one once the loop stops)
+    pub current_cycle: i32,
+    pub current_directory: Option<PathBuf>,
+    // the stats as they were at the end of the last completed cycle
+    pub last_cycle: Option<PollStats>,
+    started: Instant,
+}
+
+impl Status {
+    pub fn new() -> Self {
+        Status {
+            running: false,
+            current_cycle: 0,
+            current_directory: None,
+            last_cycle: None,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn shared() -> SharedStatus {
+        Arc::new(Mutex::new(Status::new()))
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "running": self.running,
+            "current_cycle": self.current_cycle,
+            "current_directory": self.current_directory.as_ref().map(|d| d.display().to_string()),
+            "uptime_seconds": self.uptime().as_secs(),
+            "last_cycle": self.last_cycle.as_ref().map(poll_stats_json),
+        })
+    }
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Status::new()
+    }
+}
+
+fn poll_stats_json(stats: &PollStats) -> Value {
+    json!({
+        "cycles": stats.cycles,
+        "files_processed": stats.files_processed,
+        "bytes_processed": stats.bytes_processed,
+        "files_failed": stats.files_failed,
+        "files_skipped": stats.files_skipped,
+        "files_vanished": stats.files_vanished,
+        "per_directory": stats.per_directory.iter().map(|directory| json!({
+            "directory": directory.directory.display().to_string(),
+            "files_processed": directory.files_processed,
+        })).collect::<Vec<Value>>(),
+    })
+}
+
+/// Serves the shared status until it is stopped (or dropped)
+pub struct StatusServer {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl StatusServer {
+    // Listen on all interfaces
+    // port 0 picks a free port (see local_addr)
+    pub fn start(port: u16, status: SharedStatus) -> Result<Self, Box<dyn Error>> {
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Failed to start status server on port {}: {}", port, e))?;
+        // non-blocking so the thread can notice the stop flag
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let handle = std::thread::spawn(move || {
+            while !thread_stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = handle_connection(stream, &status) {
+                            log::warn!("Status request failed: {}", e);
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(e) => log::warn!("Status server accept failed: {}", e),
+                }
+            }
+        });
+        log::info!("Status server listening on {}", local_addr);
+
+        Ok(StatusServer {
+            local_addr,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Stop accepting requests and wait for the server thread
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for StatusServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// Answer a single request and close the connection
+fn handle_connection(stream: TcpStream, status: &SharedStatus) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let (code, body) = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", "/status", ..] => {
+            let body = status.lock().map_or_else(
+                |_| json!({ "error": "status unavailable" }),
+                |status| status.to_json(),
+            );
+            ("200 OK", body.to_string())
+        }
+        _ => ("404 Not Found", json!({ "error": "not found" }).to_string()),
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,120 @@
+/// Publishing piped content without staging files on disk
+///
+/// The input is read whole or line by line
+/// and each message goes to the same sink a polled file would
+use crate::sink::{FileContext, FileSink};
+use std::error::Error;
+use std::io::BufRead;
+use std::path::Path;
+
+// The file name the sink sees for each message (e.g. in error messages)
+const STDIN_NAME: &str = "stdin";
+
+/// How the input is split into messages
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SplitMode {
+    // all of the input is one message
+    #[default]
+    Whole,
+    // one message per line, blank lines are left out
+    Lines,
+}
+
+// Send the input to the sink and flush it
+// returns the number of messages sent, empty input sends nothing
+pub fn publish_reader<R: BufRead>(
+    reader: R,
+    split_mode: SplitMode,
+    sink: &dyn FileSink,
+) -> Result<u64, Box<dyn Error>> {
+    publish_named_reader(reader, STDIN_NAME, split_mode, sink)
+}
+
+// publish_reader for input that the sink should see under another name (e.g. a tailed file)
+pub fn publish_named_reader<R: BufRead>(
+    mut reader: R,
+    name: &str,
+    split_mode: SplitMode,
+    sink: &dyn FileSink,
+) -> Result<u64, Box<dyn Error>> {
+    let mut sent = 0;
+    match split_mode {
+        SplitMode::Whole => {
+            let mut content = Vec::new();
+            reader
+                .read_to_end(&mut content)
+                .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+            if !content.is_empty() {
+                send(sink, name, content)?;
+                sent += 1;
+            }
+        }
+        SplitMode::Lines => {
+            for line in reader.split(b'\n') {
+                let mut line = line.map_err(|e| format!("Failed to read {}: {}", name, e))?;
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                if line.is_empty() {
+                    continue;
+                }
+                send(sink, name, line)
+                    .map_err(|e| format!("Failed to publish line {}: {}", sent + 1, e))?;
+                sent += 1;
+            }
+        }
+    }
+    sink.flush()?;
+    Ok(sent)
+}
+
+fn send(sink: &dyn FileSink, name: &str, content: Vec<u8>) -> Result<(), Box<dyn Error>> {
+    sink.send(&FileContext::new(Path::new(name), content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    // Fake sink that records each message
+    #[derive(Default)]
+    struct RecordingSink {
+        messages: RefCell<Vec<String>>,
+    }
+
+    impl FileSink for RecordingSink {
+        fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+            self.messages
+                .borrow_mut()
+                .push(ctx.content_str()?.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn publishes_whole_input_as_one_message() {
+        let sink = RecordingSink::default();
+        let sent = publish_reader(&b"first\nsecond\n"[..], SplitMode::Whole, &sink)
+            .expect("publishing failed");
+        assert_eq!(sent, 1);
+        assert_eq!(*sink.messages.borrow(), vec!["first\nsecond\n"]);
+    }
+
+    #[test]
+    fn publishes_each_line() {
+        let sink = RecordingSink::default();
+        let sent = publish_reader(&b"first\r\n\nsecond"[..], SplitMode::Lines, &sink)
+            .expect("publishing failed");
+        assert_eq!(sent, 2);
+        assert_eq!(*sink.messages.borrow(), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn empty_input_publishes_nothing() {
+        let sink = RecordingSink::default();
+        let sent = publish_reader(&b""[..], SplitMode::Whole, &sink).expect("publishing failed");
+        assert_eq!(sent, 0);
+        assert!(sink.messages.borrow().is_empty());
+    }
+}
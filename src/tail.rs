@@ -0,0 +1,256 @@
+/// Publishing the lines appended to a single growing file (the tail subcommand)
+///
+/// For an upstream that appends events to one log file rather than dropping files
+/// each new line is a message, split and sent as piped lines are
+/// the byte offset after the last published line is kept in an offset file
+/// so a tail that is restarted carries on where it stopped
+/// a file shorter than the offset was truncated (or rotated) and is read from the start
+use crate::sink::FileSink;
+use crate::stdin::{self, SplitMode};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Where the offset is kept unless set, next to the tailed file
+pub const OFFSET_FILE_EXTENSION: &str = ".offset";
+
+/// What a tail run published
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TailStats {
+    pub lines_published: u64,
+}
+
+/// Publishes the lines appended to the file since the stored offset
+pub struct Tail {
+    file: PathBuf,
+    offset_file: PathBuf,
+    sink: Box<dyn FileSink>,
+    // keep reading new lines until shutdown rather than stopping at the end of the file
+    follow: bool,
+    poll_interval: Duration,
+    shutdown_flag: Option<Arc<AtomicBool>>,
+}
+
+impl Tail {
+    pub fn new(file: &Path, sink: Box<dyn FileSink>) -> Self {
+        let mut offset_file = file.as_os_str().to_owned();
+        offset_file.push(OFFSET_FILE_EXTENSION);
+        Tail {
+            file: file.to_path_buf(),
+            offset_file: PathBuf::from(offset_file),
+            sink,
+            follow: false,
+            poll_interval: Duration::from_secs(1),
+            shutdown_flag: None,
+        }
+    }
+
+    pub fn offset_file(mut self, offset_file: &Path) -> Self {
+        self.offset_file = offset_file.to_path_buf();
+        self
+    }
+
+    // check for new lines this often until shutdown
+    pub fn follow(mut self, poll_interval: Duration) -> Self {
+        self.follow = true;
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    // stop between reads once the flag is set
+    pub fn shutdown_flag(mut self, shutdown_flag: Arc<AtomicBool>) -> Self {
+        self.shutdown_flag = Some(shutdown_flag);
+        self
+    }
+
+    // Publish the new lines, then (when following) keep publishing lines as they are appended
+    pub fn run(&self) -> Result<TailStats, Box<dyn Error>> {
+        log::info!("Tailing file {}", self.file.display());
+        let mut stats = TailStats::default();
+        loop {
+            stats.lines_published += self.publish_new_lines()?;
+            if !self.follow || self.is_shutdown() {
+                break;
+            }
+            std::thread::sleep(self.poll_interval);
+            if self.is_shutdown() {
+                break;
+            }
+        }
+        log::info!(
+            "Published {} lines from {}",
+            stats.lines_published,
+            self.file.display()
+        );
+        Ok(stats)
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shutdown_flag
+            .as_ref()
+            .is_some_and(|shutdown_flag| shutdown_flag.load(Ordering::SeqCst))
+    }
+
+    // Publish the complete lines after the stored offset and store the offset past them
+    // a last line without its newline is left for the writer to finish
+    // the offset is only stored once the sink confirms the lines
+    fn publish_new_lines(&self) -> Result<u64, Box<dyn Error>> {
+        let mut offset = self.read_offset()?;
+        let mut file = File::open(&self.file)
+            .map_err(|e| format!("Failed to open {}: {}", self.file.display(), e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| format!("Failed to read {}: {}", self.file.display(), e))?
+            .len();
+        if len < offset {
+            log::warn!(
+                "File {} is shorter than the offset {}, it was truncated or rotated, reading it from the start",
+                self.file.display(),
+                offset
+            );
+            offset = 0;
+        }
+
+        let mut appended = Vec::new();
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_to_end(&mut appended))
+            .map_err(|e| format!("Failed to read {}: {}", self.file.display(), e))?;
+        let Some(last_newline) = appended.iter().rposition(|&byte| byte == b'\n') else {
+            return Ok(0);
+        };
+        let lines = &appended[..=last_newline];
+
+        let name = self.file.file_name().unwrap_or_default().to_string_lossy();
+        let published =
+            stdin::publish_named_reader(lines, &name, SplitMode::Lines, self.sink.as_ref())?;
+        self.write_offset(offset + lines.len() as u64)?;
+        Ok(published)
+    }
+
+    // The offset stored by the last run, 0 before the first one
+    fn read_offset(&self) -> Result<u64, Box<dyn Error>> {
+        let text = match fs::read_to_string(&self.offset_file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(format!(
+                    "Failed to read offset file {}: {}",
+                    self.offset_file.display(),
+                    e
+                )
+                .into())
+            }
+        };
+        text.trim().parse().map_err(|e| {
+            format!(
+                "Offset file {} does not hold an offset: {}",
+                self.offset_file.display(),
+                e
+            )
+            .into()
+        })
+    }
+
+    // written to a temp file and renamed over the offset file
+    // so a crash never leaves a partial offset
+    fn write_offset(&self, offset: u64) -> Result<(), Box<dyn Error>> {
+        let mut temp_file = self.offset_file.as_os_str().to_owned();
+        temp_file.push(".tmp");
+        fs::write(&temp_file, format!("{}\n", offset))
+            .and_then(|()| fs::rename(&temp_file, &self.offset_file))
+            .map_err(|e| {
+                format!(
+                    "Failed to write offset file {}: {}",
+                    self.offset_file.display(),
+                    e
+                )
+                .into()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::FileContext;
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    // Fake sink that records each message
+    #[derive(Clone, Default)]
+    struct RecordingSink {
+        messages: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl FileSink for RecordingSink {
+        fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+            self.messages
+                .borrow_mut()
+                .push(ctx.content_str()?.to_string());
+            Ok(())
+        }
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .expect("opening file failed");
+        file.write_all(text.as_bytes()).expect("appending failed");
+    }
+
+    #[test]
+    fn second_run_publishes_only_new_lines() {
+        let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+        let path = temp_dir.path().join("events.log");
+        append(&path, "first\nsecond\nthi");
+
+        let sink = RecordingSink::default();
+        let stats = Tail::new(&path, Box::new(sink.clone()))
+            .run()
+            .expect("tailing failed");
+        assert_eq!(stats.lines_published, 2);
+        assert_eq!(*sink.messages.borrow(), ["first", "second"]);
+
+        // the partial line is published once it is finished
+        append(&path, "rd\nfourth\n");
+        let sink = RecordingSink::default();
+        let stats = Tail::new(&path, Box::new(sink.clone()))
+            .run()
+            .expect("tailing failed");
+        assert_eq!(stats.lines_published, 2);
+        assert_eq!(*sink.messages.borrow(), ["third", "fourth"]);
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("events.log.offset")).unwrap(),
+            "26\n"
+        );
+    }
+
+    #[test]
+    fn truncated_file_read_from_start() {
+        let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+        let path = temp_dir.path().join("events.log");
+        let offset_file = temp_dir.path().join("tail.offset");
+        append(&path, "first\nsecond\n");
+        let sink = RecordingSink::default();
+        Tail::new(&path, Box::new(sink.clone()))
+            .offset_file(&offset_file)
+            .run()
+            .expect("tailing failed");
+
+        // rotated: a new, shorter file in its place
+        fs::write(&path, "new\n").expect("writing failed");
+        let sink = RecordingSink::default();
+        Tail::new(&path, Box::new(sink.clone()))
+            .offset_file(&offset_file)
+            .run()
+            .expect("tailing failed");
+        assert_eq!(*sink.messages.borrow(), ["new"]);
+    }
+}
@@ -0,0 +1,116 @@
+/// Wrapping the file content in a fixed text
+///
+/// A template like "BEGIN {file_name}\n{content}\nEND" is parsed once
+/// and filled in for every file that is published
+/// {{ and }} stand for a literal brace
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Content,
+    FileName,
+    // seconds since the epoch when the file is published
+    Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadTemplate {
+    parts: Vec<Part>,
+}
+
+impl PayloadTemplate {
+    // Parse the template
+    // an unknown placeholder or an unmatched brace is an error
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("Unclosed {{ in template {:?}", template)),
+                        }
+                    }
+                    let part = match name.as_str() {
+                        "content" => Part::Content,
+                        "file_name" => Part::FileName,
+                        "timestamp" => Part::Timestamp,
+                        _ => {
+                            return Err(format!(
+                                "Unknown placeholder {{{}}} in template {:?} (use {{content}}, {{file_name}} or {{timestamp}})",
+                                name, template
+                            ))
+                        }
+                    };
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(part);
+                }
+                '}' => return Err(format!("Unmatched }} in template {:?}", template)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(PayloadTemplate { parts })
+    }
+
+    // The payload for a file published now
+    pub fn render(&self, content: &[u8], file_name: &str) -> Vec<u8> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.render_at(content, file_name, timestamp)
+    }
+
+    fn render_at(&self, content: &[u8], file_name: &str, timestamp: u64) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(content.len());
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => payload.extend_from_slice(text.as_bytes()),
+                Part::Content => payload.extend_from_slice(content),
+                Part::FileName => payload.extend_from_slice(file_name.as_bytes()),
+                Part::Timestamp => payload.extend_from_slice(timestamp.to_string().as_bytes()),
+            }
+        }
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_substituted() {
+        let template = PayloadTemplate::parse("{file_name} {timestamp}\n{content}\n{{END}}")
+            .expect("parsing template failed");
+        let payload = template.render_at(b"hello", "sample.txt", 1_700_000_000);
+        assert_eq!(payload, b"sample.txt 1700000000\nhello\n{END}");
+    }
+
+    #[test]
+    fn unknown_placeholder_is_an_error() {
+        let err = PayloadTemplate::parse("PREFIX {contents}").unwrap_err();
+        assert!(err.contains("{contents}"));
+        assert!(PayloadTemplate::parse("{content").is_err());
+        assert!(PayloadTemplate::parse("content}").is_err());
+    }
+}
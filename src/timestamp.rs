@@ -0,0 +1,162 @@
+/// The timestamp of the published records
+///
+/// By default the producer stamps each record with the publish time
+/// for event-time topics the record can carry the file's modified time instead
+/// or an RFC3339 time from one of its headers (e.g. from a sidecar)
+use crate::sink::FileContext;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TimestampSource {
+    // the producer (or broker) stamps the record when it is published
+    #[default]
+    Publish,
+    // the time the file was last modified
+    Mtime,
+    // the RFC3339 time in this header of the file
+    Header(String),
+}
+
+impl TimestampSource {
+    // Parse publish, mtime or header:NAME
+    pub fn parse(source: &str) -> Result<Self, String> {
+        match source {
+            "publish" => return Ok(TimestampSource::Publish),
+            "mtime" => return Ok(TimestampSource::Mtime),
+            _ => {}
+        }
+        if let Some(name) = source.strip_prefix("header:") {
+            if name.is_empty() {
+                return Err("The header name in header:NAME is empty".to_string());
+            }
+            return Ok(TimestampSource::Header(name.to_string()));
+        }
+        Err(format!(
+            "Unknown timestamp source {:?} (use publish, mtime or header:NAME)",
+            source
+        ))
+    }
+
+    // The record timestamp for the file in milliseconds since the epoch
+    // None leaves it to the producer, as for a file without a modified time
+    pub fn timestamp(&self, ctx: &FileContext) -> Result<Option<i64>, String> {
+        let time = match self {
+            TimestampSource::Publish => return Ok(None),
+            TimestampSource::Mtime => match ctx.modified {
+                Some(modified) => modified,
+                None => return Ok(None),
+            },
+            TimestampSource::Header(name) => {
+                let (_, value) = ctx
+                    .headers
+                    .iter()
+                    .find(|(header, _)| header == name)
+                    .ok_or_else(|| {
+                        format!(
+                            "File {} has no {} header for its timestamp",
+                            ctx.file_name, name
+                        )
+                    })?;
+                let value = String::from_utf8_lossy(value);
+                parse_rfc3339(&value).map_err(|e| {
+                    format!(
+                        "Header {} of file {} is not an RFC3339 time: {}",
+                        name, ctx.file_name, e
+                    )
+                })?
+            }
+        };
+        let millis = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|_| format!("The timestamp of file {} is before 1970", ctx.file_name))?
+            .as_millis();
+        Ok(Some(millis as i64))
+    }
+}
+
+// Parse an RFC3339 time such as 2024-05-01T12:30:00.250+02:00
+// humantime only takes UTC so the offset is taken off first
+fn parse_rfc3339(value: &str) -> Result<SystemTime, String> {
+    let value = value.trim();
+    if value.ends_with(['Z', 'z']) {
+        return humantime::parse_rfc3339(value).map_err(|e| e.to_string());
+    }
+    let (local, offset) = value
+        .len()
+        .checked_sub(6)
+        .and_then(|at| value.split_at_checked(at))
+        .ok_or("the time has no Z or +hh:mm offset")?;
+    let (sign, hours_minutes) = match offset.split_at_checked(1) {
+        Some(("+", hours_minutes)) => (1, hours_minutes),
+        Some(("-", hours_minutes)) => (-1, hours_minutes),
+        _ => return Err("the time has no Z or +hh:mm offset".to_string()),
+    };
+    let offset = match hours_minutes.split_once(':') {
+        Some((hours, minutes)) => {
+            let hours: u64 = hours.parse().map_err(|_| "invalid offset hours")?;
+            let minutes: u64 = minutes.parse().map_err(|_| "invalid offset minutes")?;
+            Duration::from_secs(hours * 3600 + minutes * 60)
+        }
+        None => return Err("the offset is not +hh:mm".to_string()),
+    };
+    let local = humantime::parse_rfc3339(&format!("{}Z", local)).map_err(|e| e.to_string())?;
+    // local time ahead of UTC means the UTC time is earlier
+    if sign > 0 {
+        local
+            .checked_sub(offset)
+            .ok_or_else(|| "the time is out of range".to_string())
+    } else {
+        local
+            .checked_add(offset)
+            .ok_or_else(|| "the time is out of range".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn ctx_with_header(name: &str, value: &str) -> FileContext {
+        let mut ctx = FileContext::new(Path::new("in/event.json"), Vec::new());
+        ctx.headers
+            .push((name.to_string(), value.as_bytes().to_vec()));
+        ctx
+    }
+
+    #[test]
+    fn parses_sources() {
+        assert_eq!(
+            TimestampSource::parse("publish"),
+            Ok(TimestampSource::Publish)
+        );
+        assert_eq!(TimestampSource::parse("mtime"), Ok(TimestampSource::Mtime));
+        assert_eq!(
+            TimestampSource::parse("header:event-time"),
+            Ok(TimestampSource::Header("event-time".to_string()))
+        );
+        assert!(TimestampSource::parse("header:").is_err());
+        assert!(TimestampSource::parse("ingest").is_err());
+    }
+
+    #[test]
+    fn header_time_honours_offset() {
+        let source = TimestampSource::Header("event-time".to_string());
+        let utc = ctx_with_header("event-time", "2024-05-01T10:30:00.250Z");
+        let offset = ctx_with_header("event-time", "2024-05-01T12:30:00.250+02:00");
+        assert_eq!(source.timestamp(&utc), Ok(Some(1_714_559_400_250)));
+        assert_eq!(source.timestamp(&offset), Ok(Some(1_714_559_400_250)));
+    }
+
+    #[test]
+    fn missing_or_invalid_header_fails() {
+        let source = TimestampSource::Header("event-time".to_string());
+        let missing = ctx_with_header("source", "billing");
+        let invalid = ctx_with_header("event-time", "yesterday");
+        assert!(source
+            .timestamp(&missing)
+            .unwrap_err()
+            .contains("no event-time header"));
+        assert!(source.timestamp(&invalid).is_err());
+    }
+}
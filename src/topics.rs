@@ -0,0 +1,207 @@
+/// Selecting topics by a pattern on their names
+///
+/// The topics a pattern covers come from the cluster metadata
+/// and are looked up again now and then so topics created later are picked up
+/// the mirror subcommand consumes them with --source-topic-regex
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+// How long a metadata request waits for the brokers
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Lists the names of every topic the cluster currently has
+/// a TopicSubscription matches its pattern against them on each refresh
+pub trait TopicMetadata {
+    fn topic_names(&self) -> Result<Vec<String>, Box<dyn Error>>;
+}
+
+/// TopicMetadata read from the brokers with an rdkafka client of its own
+pub struct RdKafkaMetadata {
+    consumer: BaseConsumer,
+}
+
+impl RdKafkaMetadata {
+    pub fn new(config: &ClientConfig) -> Result<Self, Box<dyn Error>> {
+        let consumer: BaseConsumer = config.create()?;
+        Ok(RdKafkaMetadata { consumer })
+    }
+}
+
+impl TopicMetadata for RdKafkaMetadata {
+    fn topic_names(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let metadata = self.consumer.fetch_metadata(None, METADATA_TIMEOUT)?;
+        Ok(metadata
+            .topics()
+            .iter()
+            .map(|topic| topic.name().to_string())
+            .collect())
+    }
+}
+
+/// A regex that has to match the whole topic name, e.g. orders-.*
+#[derive(Debug, Clone)]
+pub struct TopicPattern {
+    pattern: String,
+    regex: Regex,
+}
+
+impl TopicPattern {
+    // Parse the pattern, failing on an invalid regex
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let regex = Regex::new(&format!("^(?:{})$", pattern))
+            .map_err(|e| format!("Invalid topic regex {:?}: {}", pattern, e))?;
+        Ok(TopicPattern {
+            pattern: pattern.to_string(),
+            regex,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    // internal topics (e.g. __consumer_offsets) are never matched
+    pub fn matches(&self, topic: &str) -> bool {
+        !topic.starts_with("__") && self.regex.is_match(topic)
+    }
+
+    // The topics among these that the pattern covers
+    pub fn select<'a>(&self, topics: impl IntoIterator<Item = &'a str>) -> BTreeSet<String> {
+        topics
+            .into_iter()
+            .filter(|topic| self.matches(topic))
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// The topics a pattern covers, refreshed from the metadata every refresh_interval
+pub struct TopicSubscription {
+    pattern: TopicPattern,
+    metadata: Box<dyn TopicMetadata>,
+    refresh_interval: Duration,
+    topics: BTreeSet<String>,
+    // None until the metadata has been read once
+    refreshed_at: Option<Instant>,
+}
+
+impl TopicSubscription {
+    pub fn new(
+        pattern: TopicPattern,
+        metadata: Box<dyn TopicMetadata>,
+        refresh_interval: Duration,
+    ) -> Self {
+        TopicSubscription {
+            pattern,
+            metadata,
+            refresh_interval,
+            topics: BTreeSet::new(),
+            refreshed_at: None,
+        }
+    }
+
+    pub fn pattern(&self) -> &TopicPattern {
+        &self.pattern
+    }
+
+    pub fn topics(&self) -> &BTreeSet<String> {
+        &self.topics
+    }
+
+    // Read the metadata again if it is the first time or the refresh interval has passed
+    // true when the topics changed and the subscription has to be updated
+    pub fn refresh(&mut self, now: Instant) -> Result<bool, Box<dyn Error>> {
+        let due = self.refreshed_at.is_none_or(|refreshed_at| {
+            now.saturating_duration_since(refreshed_at) >= self.refresh_interval
+        });
+        if !due {
+            return Ok(false);
+        }
+        let names = self.metadata.topic_names()?;
+        let topics = self.pattern.select(names.iter().map(String::as_str));
+        self.refreshed_at = Some(now);
+        if topics == self.topics {
+            return Ok(false);
+        }
+        log::info!(
+            "Topic regex {:?} now matches {} topics: {}",
+            self.pattern.as_str(),
+            topics.len(),
+            topics.iter().cloned().collect::<Vec<_>>().join(", ")
+        );
+        self.topics = topics;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // metadata listing whatever topics the test puts in it
+    struct FakeMetadata {
+        topics: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl TopicMetadata for FakeMetadata {
+        fn topic_names(&self) -> Result<Vec<String>, Box<dyn Error>> {
+            Ok(self.topics.borrow().iter().map(|t| t.to_string()).collect())
+        }
+    }
+
+    #[test]
+    fn invalid_regex_fails_to_parse() {
+        let err = TopicPattern::parse("orders-(").unwrap_err();
+        assert!(err.contains("Invalid topic regex"));
+    }
+
+    #[test]
+    fn pattern_matches_whole_name() {
+        let pattern = TopicPattern::parse("orders-.*").unwrap();
+        assert!(pattern.matches("orders-eu"));
+        assert!(!pattern.matches("old-orders-eu"));
+        assert!(!TopicPattern::parse(".*")
+            .unwrap()
+            .matches("__consumer_offsets"));
+    }
+
+    #[test]
+    fn subscription_follows_matching_topics() {
+        let topics = Rc::new(RefCell::new(vec![
+            "orders-eu",
+            "orders-us",
+            "payments",
+            "__consumer_offsets",
+        ]));
+        let metadata = FakeMetadata {
+            topics: Rc::clone(&topics),
+        };
+        let mut subscription = TopicSubscription::new(
+            TopicPattern::parse("orders-.*").unwrap(),
+            Box::new(metadata),
+            Duration::from_secs(30),
+        );
+
+        let start = Instant::now();
+        assert!(subscription.refresh(start).unwrap());
+        let subscribed: Vec<&str> = subscription.topics().iter().map(String::as_str).collect();
+        assert_eq!(subscribed, ["orders-eu", "orders-us"]);
+
+        // a topic created later is only seen at the next refresh
+        topics.borrow_mut().push("orders-apac");
+        assert!(!subscription
+            .refresh(start + Duration::from_secs(10))
+            .unwrap());
+        assert_eq!(subscription.topics().len(), 2);
+        assert!(subscription
+            .refresh(start + Duration::from_secs(30))
+            .unwrap());
+        assert!(subscription.topics().contains("orders-apac"));
+    }
+}
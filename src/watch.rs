@@ -0,0 +1,81 @@
+/// Waking the poller up when files arrive
+///
+/// A file system watcher on the polled directories cuts the wait between polls short
+/// the poll cycle itself still decides which files are published
+/// so the regular polls catch anything the watcher missed
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+// Events closer together than this are treated as one
+// (a file being written fires several events)
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+// How often a wait checks for a shutdown request
+const SLICE: Duration = Duration::from_millis(50);
+
+pub struct DirectoryWatcher {
+    // dropping the watcher stops the events
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl DirectoryWatcher {
+    pub fn new(directories: &[&Path], recursive: bool) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        for directory in directories {
+            watcher.watch(directory, mode)?;
+        }
+        Ok(DirectoryWatcher {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    // Wait up to timeout for a file to be added (or written)
+    // and then for the events to settle
+    // returns early when stop returns true
+    pub fn wait(&self, timeout: Duration, stop: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        while !stop() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            match self.events.recv_timeout(remaining.min(SLICE)) {
+                Ok(Ok(event)) if is_new_file(&event) => {
+                    self.settle();
+                    return true;
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => log::warn!("File watcher error: {}", e),
+                Err(RecvTimeoutError::Timeout) => {}
+                // the watcher is gone, fall back to sleeping out the timeout
+                Err(RecvTimeoutError::Disconnected) => std::thread::sleep(remaining.min(SLICE)),
+            }
+        }
+        false
+    }
+
+    // Swallow events until none has arrived for the debounce time
+    fn settle(&self) {
+        while self.events.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+}
+
+// Files created, moved in or written to
+// a removal (e.g. the poller deleting a published file) is not a reason to poll
+fn is_new_file(event: &Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_) | ModifyKind::Data(_))
+    )
+}
@@ -0,0 +1,384 @@
+use kafka_rust_cli::args::{resolve_args, resolve_config, Command};
+use kafka_rust_cli::probe::OutputFormat;
+use kafka_rust_cli::stdin::SplitMode;
+use std::fs;
+
+/// integration tests for resolving the args from flags and the environment
+/// in their own binary because loading an env file changes the process environment
+#[test]
+fn env_file_sets_missing_args() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let env_file = temp_dir.path().join("dev.env");
+    fs::write(
+        &env_file,
+        "KAFKA_BOOTSTRAP_SERVER=broker.local:9092\nKAFKA_TOPIC=from-env\n",
+    )
+    .expect("writing env file failed");
+    let env_file = env_file.to_string_lossy().to_string();
+
+    // the bootstrap server only comes from the env file
+    // the explicit --topic wins over the env file
+    let args = resolve_args([
+        "kafka_pub_cli",
+        "--env-file",
+        env_file.as_str(),
+        "--topic",
+        "from-flag",
+        "--acks",
+        "1",
+        "--messageLocation",
+        "/tmp",
+    ])
+    .expect("resolving args failed");
+    assert_eq!(args.bootstrap.as_deref(), Some("broker.local:9092"));
+    assert_eq!(args.topic.as_deref(), Some("from-flag"));
+}
+
+#[test]
+fn dumped_config_redacts_secrets() {
+    let (args, config) = resolve_config([
+        "kafka_pub_cli",
+        "--topic",
+        "orders",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "all",
+        "--messageLocation",
+        "/data/in,/data/priority",
+        "--saslJaasConfig",
+        "username=\"app\" password=\"hunter2\"",
+        "--dump-config",
+    ])
+    .expect("resolving args failed");
+    assert!(args.dump_config);
+
+    let json: serde_json::Value =
+        serde_json::from_str(&config.to_json()).expect("config is not JSON");
+    let settings = &json["settings"];
+    assert_eq!(settings["topic"], "orders");
+    assert_eq!(settings["ack_mode"], "all");
+    assert_eq!(
+        settings["message_location"],
+        serde_json::json!(["/data/in", "/data/priority"])
+    );
+    assert_eq!(settings["no_delete_files"], false);
+    assert_eq!(settings["client_id"], "kafkautil.rust.producer");
+    assert_eq!(settings["dlq_topic"], serde_json::Value::Null);
+    assert_eq!(json["sources"]["topic"], "command line");
+    assert_eq!(json["sources"]["client_id"], "default");
+    // a secret shows it is set but not its value
+    assert_eq!(settings["sasl_jaas_config"], "***");
+    assert_eq!(settings["truststore_password"], serde_json::Value::Null);
+    assert!(!config.to_json().contains("hunter2"));
+}
+
+#[test]
+fn dumped_config_redacts_secret_kafka_props() {
+    let (_args, config) = resolve_config([
+        "kafka_pub_cli",
+        "--topic",
+        "orders",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "all",
+        "--messageLocation",
+        "/data/in",
+        "--kafka-prop",
+        "sasl.password=hunter2",
+        "--kafka-prop",
+        "ssl.key.password=hunter3",
+        "--kafka-prop",
+        "ssl.key.pem=-----BEGIN",
+        "--kafka-prop",
+        "linger.ms=5",
+        "--dump-config",
+    ])
+    .expect("resolving args failed");
+    let json: serde_json::Value =
+        serde_json::from_str(&config.to_json()).expect("config is not JSON");
+    assert_eq!(
+        json["settings"]["kafka_props"],
+        serde_json::json!([
+            "sasl.password=***",
+            "ssl.key.password=***",
+            "ssl.key.pem=***",
+            "linger.ms=5"
+        ])
+    );
+    assert!(!config.to_json().contains("hunter"));
+}
+
+#[test]
+fn dumped_config_redacts_registry_credentials() {
+    let (_args, config) = resolve_config([
+        "kafka_pub_cli",
+        "--topic",
+        "orders",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "all",
+        "--messageLocation",
+        "/data/in",
+        "--schema-registry-url",
+        "http://u:pw@registry:8081",
+        "--dump-config",
+    ])
+    .expect("resolving args failed");
+    let json: serde_json::Value =
+        serde_json::from_str(&config.to_json()).expect("config is not JSON");
+    assert_eq!(
+        json["settings"]["schema_registry_url"],
+        "http://***@registry:8081"
+    );
+}
+
+#[test]
+fn unknown_template_placeholder_fails_parsing() {
+    let result = resolve_args([
+        "kafka_pub_cli",
+        "--topic",
+        "foo",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "1",
+        "--messageLocation",
+        "/tmp",
+        "--payload-template",
+        "{body}",
+    ]);
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("Unknown placeholder {body}"));
+}
+
+#[test]
+fn replay_needs_no_message_location() {
+    let args = resolve_args([
+        "kafka_pub_cli",
+        "--topic",
+        "foo",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "1",
+        "replay",
+        "/archive",
+        "--since",
+        "2024-05-01T12:00:00Z",
+    ])
+    .expect("resolving args failed");
+    let Some(Command::Replay(replay)) = args.command else {
+        panic!("expected the replay command");
+    };
+    assert_eq!(replay.archive_directory, "/archive");
+    assert!(replay.since.is_some());
+}
+
+#[test]
+fn probe_needs_no_message_location() {
+    let args = resolve_args([
+        "kafka_pub_cli",
+        "--topic",
+        "foo",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "1",
+        "probe",
+        "/data/order-123.json",
+    ])
+    .expect("resolving args failed");
+    let Some(Command::Probe(probe)) = args.command else {
+        panic!("expected the probe command");
+    };
+    assert_eq!(probe.file, "/data/order-123.json");
+    assert_eq!(probe.output_format, None);
+}
+
+#[test]
+fn mirror_args_parsed() {
+    let args = resolve_args([
+        "kafka_pub_cli",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "all",
+        "mirror",
+        "--source-topic",
+        "orders-old",
+        "--dest-topic",
+        "orders-new",
+        "--group-id",
+        "orders-migration",
+        "--max-messages",
+        "1000",
+    ])
+    .expect("resolving args failed");
+    let Some(Command::Mirror(mirror)) = args.command else {
+        panic!("expected the mirror command");
+    };
+    assert_eq!(mirror.source_topic.as_deref(), Some("orders-old"));
+    assert!(mirror.source_topic_regex.is_none());
+    assert_eq!(mirror.dest_topic, "orders-new");
+    assert_eq!(mirror.group_id, "orders-migration");
+    assert_eq!(mirror.max_messages, Some(1000));
+}
+
+#[test]
+fn mirror_takes_a_source_topic_regex() {
+    let resolve_mirror = |source: &[&'static str]| {
+        let mut args = vec![
+            "kafka_pub_cli",
+            "--bootstrap-server",
+            "localhost:9092",
+            "--acks",
+            "all",
+            "mirror",
+            "--dest-topic",
+            "orders-new",
+            "--group-id",
+            "migration",
+        ];
+        args.extend_from_slice(source);
+        resolve_args(args)
+    };
+    let args =
+        resolve_mirror(&["--source-topic-regex", "orders-.*"]).expect("resolving args failed");
+    let Some(Command::Mirror(mirror)) = args.command else {
+        panic!("expected the mirror command");
+    };
+    assert!(mirror.source_topic.is_none());
+    let pattern = mirror.source_topic_regex.expect("no regex");
+    assert!(pattern.matches("orders-eu"));
+    assert_eq!(mirror.topic_refresh_secs, 60);
+
+    // one source or the other
+    assert!(resolve_mirror(&[]).is_err());
+    assert!(resolve_mirror(&[
+        "--source-topic",
+        "orders-old",
+        "--source-topic-regex",
+        "orders-.*"
+    ])
+    .is_err());
+}
+
+#[test]
+fn tail_args_parsed() {
+    let args = resolve_args([
+        "kafka_pub_cli",
+        "--topic",
+        "events",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "1",
+        "tail",
+        "/var/log/app/events.log",
+        "--offset-file",
+        "/var/lib/kafka_pub_cli/events.offset",
+        "--follow",
+    ])
+    .expect("resolving args failed");
+    let Some(Command::Tail(tail)) = args.command else {
+        panic!("expected the tail command");
+    };
+    assert_eq!(tail.file, "/var/log/app/events.log");
+    assert_eq!(
+        tail.offset_file.as_deref(),
+        Some("/var/lib/kafka_pub_cli/events.offset")
+    );
+    assert!(tail.follow);
+}
+
+#[test]
+fn probe_output_format_parsed() {
+    let args = resolve_args([
+        "kafka_pub_cli",
+        "--topic",
+        "foo",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "1",
+        "probe",
+        "/data/order-123.json",
+        "--output-format",
+        "json-lines",
+    ])
+    .expect("resolving args failed");
+    let Some(Command::Probe(probe)) = args.command else {
+        panic!("expected the probe command");
+    };
+    assert_eq!(probe.output_format, Some(OutputFormat::JsonLines));
+}
+
+#[test]
+fn polling_needs_message_location() {
+    let result = resolve_args([
+        "kafka_pub_cli",
+        "--topic",
+        "foo",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "1",
+    ]);
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("--messageLocation"));
+}
+
+#[test]
+fn missing_env_file_is_an_error() {
+    let result = resolve_args([
+        "kafka_pub_cli",
+        "--env-file",
+        "/nonexistent/dev.env",
+        "--topic",
+        "foo",
+    ]);
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("/nonexistent/dev.env"));
+}
+
+#[test]
+fn broker_srv_conflicts_with_bootstrap_server() {
+    let result = resolve_args([
+        "kafka_pub_cli",
+        "--topic",
+        "foo",
+        "--broker-srv",
+        "_kafka._tcp.kafka-headless",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "1",
+        "--messageLocation",
+        "/tmp",
+    ]);
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("--broker-srv"), "{}", err);
+}
+
+#[test]
+fn stdin_does_not_need_message_location() {
+    let args = resolve_args([
+        "kafka_pub_cli",
+        "--topic",
+        "foo",
+        "--bootstrap-server",
+        "localhost:9092",
+        "--acks",
+        "1",
+        "--stdin",
+        "--split-mode",
+        "lines",
+    ])
+    .expect("resolving args failed");
+    assert!(args.stdin);
+    assert_eq!(args.split_mode, SplitMode::Lines);
+    assert!(args.message_location.is_empty());
+}
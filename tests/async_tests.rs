@@ -0,0 +1,122 @@
+#![cfg(feature = "async")]
+
+use kafka_rust_cli::dedup::DedupMode;
+use kafka_rust_cli::file::{DirectoryPoller, FileOrder, EXIT_USAGE};
+use kafka_rust_cli::template::PayloadTemplate;
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+/// integration tests for the async poller
+/// run with: cargo test --features async
+#[tokio::test]
+async fn poll_directory_async_sends_every_file() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("a.txt"), "first").expect("writing failed");
+    fs::write(temp_dir.path().join("b.txt"), "second").expect("writing failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(2)
+        .file_order(FileOrder::Name)
+        .build();
+
+    let stats = poller
+        .poll_directory_async(temp_dir.path(), |ctx| {
+            let received = Rc::clone(&received);
+            async move {
+                // yield like a real async sink
+                tokio::task::yield_now().await;
+                received.borrow_mut().push(ctx.content);
+                Ok(())
+            }
+        })
+        .await
+        .expect("polling failed");
+
+    assert_eq!(stats.cycles, 2);
+    assert_eq!(stats.files_processed, 2);
+    assert_eq!(
+        *received.borrow(),
+        vec![b"first".to_vec(), b"second".to_vec()]
+    );
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn poll_directory_async_keeps_failed_file() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("a.txt");
+    fs::write(&file_path, "first").expect("writing failed");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .build();
+
+    let result = poller
+        .poll_directory_async(temp_dir.path(), |_ctx| async {
+            Err("Simulated error in callback".into())
+        })
+        .await;
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Simulated error in callback"));
+    assert!(file_path.exists());
+}
+
+#[tokio::test]
+async fn poll_directory_async_applies_payload_template() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("a.txt"), "first").expect("writing failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let template = PayloadTemplate::parse("{file_name}: {content}").unwrap();
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .payload_template(template)
+        .truncate_to_bytes(3)
+        .build();
+
+    poller
+        .poll_directory_async(temp_dir.path(), |ctx| {
+            let received = Rc::clone(&received);
+            async move {
+                received.borrow_mut().push(ctx.content);
+                Ok(())
+            }
+        })
+        .await
+        .expect("polling failed");
+    assert_eq!(*received.borrow(), vec![b"a.txt: fir".to_vec()]);
+}
+
+#[tokio::test]
+async fn poll_directory_async_rejects_blocking_only_options() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("a.txt");
+    fs::write(&file_path, "first").expect("writing failed");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .dedup_mode(DedupMode::ContentHash)
+        .sidecar_extension(".meta".to_string())
+        .build();
+
+    let error = poller
+        .poll_directory_async(temp_dir.path(), |_ctx| async { Ok(()) })
+        .await
+        .unwrap_err();
+    assert_eq!(error.exit_code(), EXIT_USAGE);
+    assert_eq!(
+        error.to_string(),
+        "poll_directory_async does not support dedup_mode, sidecar_extension"
+    );
+    assert!(file_path.exists());
+}
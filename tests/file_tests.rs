@@ -1,6 +1,29 @@
-use kafka_rust_cli::file::DirectoryPoller;
+use kafka_rust_cli::batch::BatchLimits;
+use kafka_rust_cli::budget::ByteBudget;
+use kafka_rust_cli::clock::Clock;
+use kafka_rust_cli::content::PayloadPath;
+use kafka_rust_cli::content_type::ContentTypeDetection;
+use kafka_rust_cli::dedup::{DedupMode, VisitedMarkers};
+use kafka_rust_cli::disk::DiskSpace;
+use kafka_rust_cli::envelope::EnvelopeFormat;
+use kafka_rust_cli::file::{
+    BuildError, DirectoryPoller, EmptyFilePolicy, FileOrder, KeyStrategy, NameMismatchPolicy,
+    PollError, Utf8Policy,
+};
+use kafka_rust_cli::lock::{DirectoryLock, DEFAULT_LOCK_FILE};
+use kafka_rust_cli::overrides::DirectoryOverrides;
+use kafka_rust_cli::path_template::PathTemplate;
+use kafka_rust_cli::probe;
+use kafka_rust_cli::sink::{FanoutMode, FileContext, FileSink, PublishOutcome, SinkError};
+use kafka_rust_cli::template::PayloadTemplate;
+use std::cell::{Cell, RefCell};
+use std::error::Error;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use tempfile::TempDir;
 
 /// integration tests for DirectoryPoller
@@ -16,8 +39,18 @@ fn poll_directory_returns_error_for_missing_dir() {
         .max_poll_cycles(1)
         .build();
 
-    let result = poller.poll_directory(missing_path.to_string_lossy().as_ref());
-    assert!(result.is_err());
+    let result = poller.poll_directory(&missing_path);
+    assert!(matches!(result, Err(PollError::DirectoryNotFound(path)) if path == missing_path));
+}
+
+#[test]
+fn poll_directory_returns_error_for_file_path() {
+    let (_temp_dir, file_path) = create_temp_dir_with_file();
+
+    let poller = DirectoryPoller::builder().max_poll_cycles(1).build();
+
+    let result = poller.poll_directory(&file_path);
+    assert!(matches!(result, Err(PollError::NotADirectory(_))));
 }
 
 #[test]
@@ -30,7 +63,7 @@ fn poll_directory_deletes_files_enabled() {
         .max_poll_cycles(1)
         .build();
 
-    let result = poller.poll_directory(temp_dir_to_string(&temp_dir).as_str());
+    let result = poller.poll_directory(temp_dir.path());
     assert!(result.is_ok());
     assert!(!file_path.exists());
 }
@@ -45,7 +78,7 @@ fn poll_directory_keeps_files_disabled() {
         .max_poll_cycles(1)
         .build();
 
-    let result = poller.poll_directory(temp_dir_to_string(&temp_dir).as_str());
+    let result = poller.poll_directory(temp_dir.path());
     assert!(result.is_ok());
     assert!(file_path.exists());
 }
@@ -74,7 +107,7 @@ fn poll_directory_runs_once() {
         })
         .build();
 
-    let result = poller.poll_directory(temp_dir_to_string(&temp_dir).as_str());
+    let result = poller.poll_directory(temp_dir.path());
     assert!(result.is_ok());
     assert!(file_path.exists());
 
@@ -106,7 +139,7 @@ fn poll_directory_runs_multiple_cycles() {
         })
         .build();
 
-    let result = poller.poll_directory(temp_dir_to_string(&temp_dir).as_str());
+    let result = poller.poll_directory(temp_dir.path());
     assert!(result.is_ok());
     assert!(file_path.exists());
 
@@ -115,10 +148,8 @@ fn poll_directory_runs_multiple_cycles() {
 }
 
 #[test]
-//#[should_panic(expected = "Simulated error in callback")]
-#[should_panic(expected = "Error processing content of file \"sample.txt\"")]
 fn poll_directory_with_error_in_callback() {
-    let (temp_dir, _file_path) = create_temp_dir_with_file();
+    let (temp_dir, file_path) = create_temp_dir_with_file();
 
     let poller = DirectoryPoller::builder()
         .delete_files(true)
@@ -127,17 +158,3117 @@ fn poll_directory_with_error_in_callback() {
         .on_file_content(move |_content| Err("Simulated error in callback".into()))
         .build();
 
-    let _ = poller.poll_directory(temp_dir_to_string(&temp_dir).as_str());
+    // the error is propagated and the file is kept
+    // even though delete_files is enabled
+    let result = poller.poll_directory(temp_dir.path());
+    match result {
+        Err(PollError::Callback(error)) => {
+            assert!(error.to_string().contains("Simulated error in callback"))
+        }
+        other => panic!("expected a callback error, got {:?}", other),
+    }
+    assert!(file_path.exists());
 }
 
-fn create_temp_dir_with_file() -> (TempDir, PathBuf) {
+#[test]
+fn poll_directory_until_empty_exits_on_empty_cycle() {
     let temp_dir = tempfile::tempdir().expect("create temp dir failed");
-    let file_path = temp_dir.path().join("sample.txt");
-    fs::write(&file_path, "hello").expect("writing temp file failed");
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(temp_dir.path().join(name), "hello").expect("writing temp file failed");
+    }
+
+    // the first cycle drains the directory
+    // the second cycle finds nothing and ends the loop
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .until_empty(true)
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.cycles, 2);
+    assert_eq!(stats.files_processed, 3);
+}
+
+// A clock that only moves when the poller sleeps (or the test advances it)
+struct FakeClock {
+    start: Instant,
+    wall_start: SystemTime,
+    elapsed: Mutex<Duration>,
+}
+
+impl FakeClock {
+    fn new() -> Self {
+        FakeClock {
+            start: Instant::now(),
+            wall_start: SystemTime::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        self.wall_start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+#[test]
+fn poll_directory_backoff_grows_with_fake_clock() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let clock = Arc::new(FakeClock::new());
+
+    let poller = DirectoryPoller::builder()
+        .keep_running(true)
+        .poll_interval_millis(100)
+        .backoff_on_empty(true)
+        .max_backoff_millis(1000)
+        .max_poll_cycles(5)
+        .clock(clock.clone())
+        .build();
+
+    let started = Instant::now();
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.cycles, 5);
+    // 200 + 400 + 800 and then capped at 1000
+    assert_eq!(clock.elapsed(), Duration::from_millis(2400));
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn poll_directory_fixed_rate_keeps_cadence_with_fake_clock() {
+    let (temp_dir, _file_path) = create_temp_dir_with_file();
+    let clock = Arc::new(FakeClock::new());
+    let cycle_starts = Rc::new(RefCell::new(Vec::new()));
+
+    // publishing the file takes 30 ms of the 100 ms interval
+    let sink_clock = clock.clone();
+    let route_clock = clock.clone();
+    let route_starts = Rc::clone(&cycle_starts);
+    let poller = DirectoryPoller::builder()
+        .keep_running(true)
+        .delete_files(false)
+        .poll_interval_millis(100)
+        .fixed_rate(true)
+        .max_poll_cycles(3)
+        .clock(clock.clone())
+        .on_file_route(move |_ctx| {
+            route_starts.borrow_mut().push(route_clock.elapsed());
+            Ok(PublishOutcome::default())
+        })
+        .on_file_content(move |_content| {
+            sink_clock.sleep(Duration::from_millis(30));
+            Ok(())
+        })
+        .build();
+
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    // each cycle starts on a 100 ms boundary rather than 130 ms after the last one
+    assert_eq!(
+        *cycle_starts.borrow(),
+        [0, 100, 200].map(Duration::from_millis)
+    );
+}
+
+// Disk space provider that reports the given MB free, one value per check
+struct FakeDiskSpace {
+    free_mb: Mutex<Vec<u64>>,
+}
+
+impl DiskSpace for FakeDiskSpace {
+    fn available_bytes(&self, _path: &Path) -> std::io::Result<u64> {
+        let mut free_mb = self.free_mb.lock().unwrap();
+        let mb = if free_mb.len() > 1 {
+            free_mb.remove(0)
+        } else {
+            free_mb[0]
+        };
+        Ok(mb * 1024 * 1024)
+    }
+}
+
+#[test]
+fn poll_directory_pauses_while_disk_space_is_low() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let clock = Arc::new(FakeClock::new());
+    let processed_at = Rc::new(RefCell::new(Vec::new()));
+
+    let sink_clock = clock.clone();
+    let sink_processed_at = Rc::clone(&processed_at);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .min_free_disk_mb(100)
+        .disk_space(Arc::new(FakeDiskSpace {
+            free_mb: Mutex::new(vec![10, 50, 500]),
+        }))
+        .clock(clock.clone())
+        .on_file_content(move |_content| {
+            sink_processed_at.borrow_mut().push(sink_clock.elapsed());
+            Ok(())
+        })
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    // two checks found too little space, the third let the cycle run
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(*processed_at.borrow(), [Duration::from_secs(10)]);
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn on_cycle_complete_fires_once_per_cycle() {
+    let (temp_dir, _file_path) = create_temp_dir_with_file();
+    fs::write(temp_dir.path().join("second.txt"), "second").expect("write failed");
+    let cycles = Rc::new(RefCell::new(Vec::new()));
+
+    let seen = Rc::clone(&cycles);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(2)
+        .on_file_content(|_content| Ok(()))
+        .on_cycle_complete(move |cycle| {
+            seen.borrow_mut().push((cycle.cycle, cycle.files_processed));
+            Ok(())
+        })
+        .build();
+
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    // the second cycle finds the directory empty
+    assert_eq!(*cycles.borrow(), [(1, 2), (2, 0)]);
+}
+
+#[test]
+fn on_stats_fires_per_cycle_with_cumulative_counts() {
+    let (temp_dir, _file_path) = create_temp_dir_with_file();
+    let reports = Rc::new(RefCell::new(Vec::new()));
+
+    let seen = Rc::clone(&reports);
+    let next_file = temp_dir.path().join("next.txt");
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(3)
+        .on_file_content(|_content| Ok(()))
+        .on_stats(move |stats, cycle| {
+            seen.borrow_mut().push((
+                stats.files_processed,
+                cycle.map(|cycle| (cycle.cycle, cycle.files_processed)),
+            ));
+            // a file for the next cycle
+            fs::write(&next_file, "next").expect("write failed");
+        })
+        .build();
+
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    // the last report is the final one at shutdown
+    assert_eq!(
+        *reports.borrow(),
+        [
+            (1, Some((1, 1))),
+            (2, Some((2, 1))),
+            (3, Some((3, 1))),
+            (3, None)
+        ]
+    );
+}
+
+#[test]
+fn panicking_on_stats_does_not_stop_poller() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
 
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(2)
+        .on_file_content(|_content| Ok(()))
+        .on_stats(|_stats, _cycle| panic!("metrics system unavailable"))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.cycles, 2);
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn on_cycle_complete_error_stops_poller_without_error_directory() {
+    let (temp_dir, _file_path) = create_temp_dir_with_file();
+    let calls = Rc::new(Cell::new(0));
+
+    let counted = Rc::clone(&calls);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(3)
+        .on_file_content(|_content| Ok(()))
+        .on_cycle_complete(move |_cycle| {
+            counted.set(counted.get() + 1);
+            Err("commit failed".into())
+        })
+        .build();
+
+    match poller.poll_directory(temp_dir.path()) {
+        Err(PollError::Callback(e)) => assert_eq!(e.to_string(), "commit failed"),
+        other => panic!(
+            "expected the cycle callback error, got {:?}",
+            other.map(|_| ())
+        ),
+    }
+    assert_eq!(calls.get(), 1);
+}
+
+// A temp dir holding a text file with a byte that is not valid UTF-8
+fn create_temp_dir_with_invalid_utf8_file() -> (TempDir, PathBuf) {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("messy.txt");
+    fs::write(&file_path, b"caf\xe9 au lait").expect("write failed");
     (temp_dir, file_path)
 }
 
-fn temp_dir_to_string(temp_dir: &TempDir) -> String {
-    temp_dir.path().to_string_lossy().to_string()
+// Poll the directory once sending text to a callback under the UTF-8 policy
+fn poll_with_utf8_policy(
+    temp_dir: &TempDir,
+    utf8_policy: Utf8Policy,
+) -> (Result<u64, PollError>, Vec<String>) {
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink_received = Rc::clone(&received);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .utf8_policy(utf8_policy)
+        .on_file_content(move |content| {
+            sink_received.borrow_mut().push(content.to_string());
+            Ok(())
+        })
+        .build();
+    let result = poller
+        .poll_directory(temp_dir.path())
+        .map(|stats| stats.files_skipped);
+    let received = received.borrow().clone();
+    (result, received)
+}
+
+#[test]
+fn strict_utf8_policy_fails_invalid_file() {
+    let (temp_dir, file_path) = create_temp_dir_with_invalid_utf8_file();
+    let (result, received) = poll_with_utf8_policy(&temp_dir, Utf8Policy::Strict);
+    match result {
+        Err(PollError::Callback(e)) => assert!(e.to_string().contains("not valid UTF-8")),
+        other => panic!("expected a UTF-8 error, got {:?}", other),
+    }
+    assert!(received.is_empty());
+    assert!(file_path.exists());
+}
+
+#[test]
+fn lossy_utf8_policy_repairs_invalid_file() {
+    let (temp_dir, file_path) = create_temp_dir_with_invalid_utf8_file();
+    let (result, received) = poll_with_utf8_policy(&temp_dir, Utf8Policy::Lossy);
+    assert_eq!(result.expect("polling failed"), 0);
+    assert_eq!(received, ["caf\u{FFFD} au lait"]);
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn skip_utf8_policy_leaves_out_invalid_file() {
+    let (temp_dir, file_path) = create_temp_dir_with_invalid_utf8_file();
+    // valid files are still sent
+    fs::write(temp_dir.path().join("clean.txt"), "clean").expect("write failed");
+    let (result, received) = poll_with_utf8_policy(&temp_dir, Utf8Policy::Skip);
+    assert_eq!(result.expect("polling failed"), 1);
+    assert_eq!(received, ["clean"]);
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn probe_prints_plan_without_publishing() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("order-123.json");
+    fs::write(&file_path, r#"{"id":123}"#).expect("writing failed");
+    fs::write(
+        temp_dir.path().join("order-123.json.meta"),
+        r#"{"headers":{"source":"billing"}}"#,
+    )
+    .expect("writing failed");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .key_strategy(KeyStrategy::FileStem)
+        .sidecar_extension(".meta".to_string())
+        .content_type_detection(ContentTypeDetection::Extension)
+        .transform(Box::new(|content| Ok([content, b"\n"].concat())))
+        .on_file_content(|_content| Err("the sink should not be called".into()))
+        .build();
+
+    let plan =
+        probe::plan(&poller, temp_dir.path(), &file_path, Some("orders")).expect("probe failed");
+    assert_eq!(
+        plan,
+        "file: order-123.json\n\
+         topic: orders\n\
+         key: 6f726465722d313233 (hex)\n\
+         headers:\n  \
+         source: billing\n  \
+         content-type: application/json\n\
+         payload: 11 bytes\n"
+    );
+    assert!(file_path.exists());
+}
+
+#[test]
+fn probe_routes_by_subdirectory_of_the_polled_directory() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::create_dir(temp_dir.path().join("payments")).expect("create dir failed");
+    let file_path = temp_dir.path().join("payments").join("p-1.json");
+    fs::write(&file_path, r#"{"id":1}"#).expect("writing failed");
+
+    let poller = DirectoryPoller::builder()
+        .topic_from_subdir(true)
+        .on_file_content(|_content| Err("the sink should not be called".into()))
+        .build();
+
+    let directories = [temp_dir.path().to_string_lossy().to_string()];
+    let root = probe::root_directory(&directories, &file_path);
+    assert_eq!(root, temp_dir.path());
+    let plan = probe::plan(&poller, root, &file_path, Some("orders")).expect("probe failed");
+    assert!(plan.contains("topic: payments\n"), "{}", plan);
+}
+
+#[test]
+fn poll_directory_publishes_only_the_payload_path() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(
+        temp_dir.path().join("order-1.json"),
+        r#"{"meta": {"source": "billing"}, "data": {"body": {"id": 1}}}"#,
+    )
+    .expect("writing failed");
+    let received = Rc::new(RefCell::new(Vec::new()));
+
+    let sink_received = Rc::clone(&received);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .payload_path(PayloadPath::parse("$.data.body").unwrap())
+        .on_file_content(move |content| {
+            sink_received.borrow_mut().push(content.to_string());
+            Ok(())
+        })
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(*received.borrow(), [r#"{"id":1}"#]);
+}
+
+#[test]
+fn visited_markers_skip_marked_files_and_mark_new_ones() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("old.json"), r#"{"id":1}"#).expect("write failed");
+    fs::write(temp_dir.path().join("old.json.done"), "").expect("write failed");
+    fs::write(temp_dir.path().join("new.json"), r#"{"id":2}"#).expect("write failed");
+    let published = Rc::new(RefCell::new(Vec::new()));
+
+    let sink_published = Rc::clone(&published);
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .poll_interval_millis(0)
+        .max_poll_cycles(2)
+        .visited_markers(VisitedMarkers::Persistent)
+        .on_file_content(move |content| {
+            sink_published.borrow_mut().push(content.to_string());
+            Ok(())
+        })
+        .build();
+
+    // the second cycle skips new.json by its marker too
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(*published.borrow(), [r#"{"id":2}"#]);
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(stats.files_skipped, 3);
+    assert!(temp_dir.path().join("new.json.done").exists());
+    assert!(temp_dir.path().join("old.json").exists());
+}
+
+#[test]
+fn content_hash_dedup_publishes_identical_content_once() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("a.json"), r#"{"id":1}"#).expect("write failed");
+    fs::write(temp_dir.path().join("b.json"), r#"{"id":1}"#).expect("write failed");
+    fs::write(temp_dir.path().join("c.json"), r#"{"id":2}"#).expect("write failed");
+    let published = Rc::new(RefCell::new(Vec::new()));
+
+    let sink_published = Rc::clone(&published);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .dedup_mode(DedupMode::ContentHash)
+        .on_file_content(move |content| {
+            sink_published.borrow_mut().push(content.to_string());
+            Ok(())
+        })
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(*published.borrow(), [r#"{"id":1}"#, r#"{"id":2}"#]);
+    assert_eq!(stats.files_skipped, 1);
+    // the duplicate is deleted like a published file
+    assert!(!temp_dir.path().join("b.json").exists());
+}
+
+#[test]
+fn path_dedup_skips_unchanged_files_without_deletion() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let published = Rc::new(Cell::new(0));
+
+    let counted = Rc::clone(&published);
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .poll_interval_millis(0)
+        .max_poll_cycles(3)
+        .dedup_mode(DedupMode::PathMtimeSize)
+        .on_file_content(move |_content| {
+            counted.set(counted.get() + 1);
+            Ok(())
+        })
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(published.get(), 1);
+    assert_eq!(stats.files_skipped, 2);
+    assert!(file_path.exists());
+}
+
+#[test]
+fn schema_id_frames_payload_in_wire_format() {
+    let (temp_dir, _file_path) = create_temp_dir_with_file();
+    let received = ReceivedFiles::default();
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .schema_id(7)
+        .sink(Box::new(RecordingSink {
+            received: Rc::clone(&received),
+        }))
+        .build();
+
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    // magic byte, big-endian schema id, then the file content
+    assert_eq!(
+        received.borrow()[0].1,
+        [0x00, 0x00, 0x00, 0x00, 0x07, b'h', b'e', b'l', b'l', b'o']
+    );
+}
+
+#[test]
+fn poll_directory_watchdog_fires_after_expected_interval() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let clock = Arc::new(FakeClock::new());
+
+    let poller = DirectoryPoller::builder()
+        .keep_running(true)
+        .poll_interval_millis(1000)
+        .expected_interval(Duration::from_millis(2500))
+        .exit_on_silence(true)
+        .max_poll_cycles(10)
+        .clock(clock.clone())
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(matches!(result, Err(PollError::Silent(_))), "{:?}", result);
+    // the cycles at 0, 1 and 2 s were within the window, the one at 3 s was not
+    assert_eq!(clock.elapsed(), Duration::from_secs(3));
+}
+
+#[test]
+fn poll_directory_skips_file_deleted_by_another_process() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("a.txt"), "first").expect("writing temp file failed");
+    let vanishing_path = temp_dir.path().join("b.txt");
+    fs::write(&vanishing_path, "second").expect("writing temp file failed");
+
+    // both files are enumerated, then b.txt is taken while a.txt is published
+    let taken_path = vanishing_path.clone();
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .error_directory(error_dir.path().to_path_buf())
+        .on_file_content(move |_content| {
+            if taken_path.exists() {
+                fs::remove_file(&taken_path)?;
+            }
+            Ok(())
+        })
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(stats.files_vanished, 1);
+    assert_eq!(stats.files_failed, 0);
+    assert_eq!(fs::read_dir(error_dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn poll_directory_until_empty_stops_at_max_cycles() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+
+    // the directory never drains since files are kept
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .poll_interval_millis(0)
+        .until_empty(true)
+        .max_poll_cycles(3)
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.cycles, 3);
+    assert!(file_path.exists());
+}
+
+#[test]
+fn poll_directory_honors_max_rate() {
+    use std::time::{Duration, Instant};
+
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for index in 0..5 {
+        let name = format!("file{}.txt", index);
+        fs::write(temp_dir.path().join(name), "hello").expect("writing temp file failed");
+    }
+
+    // 10 files per second spaces the 5 files 100ms apart
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .max_files_per_second(10)
+        .build();
+
+    let start = Instant::now();
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 5);
+    assert!(start.elapsed() >= Duration::from_millis(400));
+}
+
+#[test]
+fn poll_directory_rate_limit_interrupted_by_shutdown() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for index in 0..3 {
+        let name = format!("file{}.txt", index);
+        fs::write(temp_dir.path().join(name), "hello").expect("writing temp file failed");
+    }
+
+    // one file per second, but shutdown is requested after 100ms
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .max_files_per_second(1)
+        .shutdown_flag(Arc::clone(&shutdown_flag))
+        .build();
+
+    let flag = Arc::clone(&shutdown_flag);
+    let stopper = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        flag.store(true, Ordering::SeqCst);
+    });
+
+    let start = Instant::now();
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    stopper.join().expect("stopper thread failed");
+
+    assert_eq!(stats.files_processed, 1);
+    assert!(start.elapsed() < Duration::from_millis(900));
+}
+
+#[test]
+fn poll_directory_sends_every_file_to_sink() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for (name, content) in [("a.txt", "first"), ("b.txt", "second"), ("c.txt", "third")] {
+        fs::write(temp_dir.path().join(name), content).expect("writing temp file failed");
+    }
+
+    // share the recorded files between the sink and the test function
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = RecordingSink {
+        received: Rc::clone(&received),
+    };
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(sink))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+
+    // the directory order is not guaranteed
+    let mut received = received.borrow().clone();
+    received.sort();
+    assert_eq!(
+        received,
+        vec![
+            ("a.txt".to_string(), b"first".to_vec()),
+            ("b.txt".to_string(), b"second".to_vec()),
+            ("c.txt".to_string(), b"third".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn poll_directories_processes_in_priority_order() {
+    let high_dir = tempfile::tempdir().expect("create temp dir failed");
+    let low_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(high_dir.path().join("high.txt"), "high").expect("writing temp file failed");
+    fs::write(low_dir.path().join("low.txt"), "low").expect("writing temp file failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = RecordingSink {
+        received: Rc::clone(&received),
+    };
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .poll_interval_millis(0)
+        .max_poll_cycles(2)
+        .sink(Box::new(sink))
+        .build();
+
+    let stats = poller
+        .poll_directories(&[high_dir.path(), low_dir.path()])
+        .expect("polling failed");
+
+    // each cycle visits the high priority directory first
+    let names: Vec<String> = received.borrow().iter().map(|(n, _)| n.clone()).collect();
+    assert_eq!(names, vec!["high.txt", "low.txt", "high.txt", "low.txt"]);
+
+    assert_eq!(stats.cycles, 2);
+    assert_eq!(stats.files_processed, 4);
+    assert_eq!(stats.per_directory.len(), 2);
+    assert_eq!(stats.per_directory[0].directory, high_dir.path());
+    assert_eq!(stats.per_directory[0].files_processed, 2);
+    assert_eq!(stats.per_directory[1].directory, low_dir.path());
+    assert_eq!(stats.per_directory[1].files_processed, 2);
+}
+
+#[test]
+fn poll_directories_fails_fast_naming_missing_dir() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let missing_path = temp_dir.path().join("missing");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .build();
+
+    let result = poller.poll_directories(&[temp_dir.path(), missing_path.as_path()]);
+
+    // nothing is polled when any directory is invalid
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains(missing_path.to_string_lossy().as_ref()));
+    assert!(file_path.exists());
+}
+
+// the (file name, content) pairs a RecordingSink has received
+type ReceivedFiles = Rc<RefCell<Vec<(String, Vec<u8>)>>>;
+
+// Fake sink that records the name and content of every file it receives
+struct RecordingSink {
+    received: ReceivedFiles,
+}
+
+impl FileSink for RecordingSink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        self.received
+            .borrow_mut()
+            .push((ctx.file_name.clone(), ctx.content.clone()));
+        Ok(())
+    }
+}
+
+#[test]
+fn poll_directory_moves_failed_file_to_error_directory() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .error_directory(error_dir.path().to_path_buf())
+        .on_file_content(move |_content| Err("Simulated error in callback".into()))
+        .build();
+
+    // polling continues past the failed file
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_failed, 1);
+    assert_eq!(stats.files_processed, 0);
+    assert!(!file_path.exists());
+    assert!(error_dir.path().join("sample.txt").exists());
+}
+
+#[test]
+fn poll_directory_streams_file_to_reader() {
+    use std::cell::Cell;
+
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("large.bin");
+    let file_size = 1024 * 1024;
+    fs::write(&file_path, vec![b'x'; file_size]).expect("writing temp file failed");
+
+    let byte_count = Rc::new(Cell::new(0));
+    let byte_count_clone = Rc::clone(&byte_count);
+
+    // the callback reads the file in small chunks
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .on_file_reader(move |reader| {
+            let mut chunk = [0u8; 4096];
+            loop {
+                let read = reader.read(&mut chunk)?;
+                if read == 0 {
+                    break;
+                }
+                byte_count_clone.set(byte_count_clone.get() + read);
+            }
+            Ok(())
+        })
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    assert_eq!(byte_count.get(), file_size);
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn poll_directory_validates_json_schema() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let schema_dir = tempfile::tempdir().expect("create temp dir failed");
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+
+    let schema_path = schema_dir.path().join("order.schema.json");
+    let schema = r#"{
+        "type": "object",
+        "properties": { "id": { "type": "integer" } },
+        "required": ["id"]
+    }"#;
+    fs::write(&schema_path, schema).expect("writing schema failed");
+    fs::write(temp_dir.path().join("valid.json"), r#"{"id": 1}"#).expect("writing failed");
+    fs::write(temp_dir.path().join("invalid.json"), r#"{"id": "one"}"#).expect("writing failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = RecordingSink {
+        received: Rc::clone(&received),
+    };
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .json_schema(schema_path)
+        .error_directory(error_dir.path().to_path_buf())
+        .sink(Box::new(sink))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+
+    // only the valid document reaches the sink
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(stats.files_failed, 1);
+    let names: Vec<String> = received.borrow().iter().map(|(n, _)| n.clone()).collect();
+    assert_eq!(names, vec!["valid.json"]);
+    assert!(error_dir.path().join("invalid.json").exists());
+}
+
+#[test]
+fn compact_json_minifies_json_files() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(
+        temp_dir.path().join("pretty.json"),
+        "{\n  \"id\": 1,\n  \"note\": \"two  spaces\"\n}\n",
+    )
+    .expect("writing failed");
+    fs::write(temp_dir.path().join("broken.json"), "{\n  \"id\": \n").expect("writing failed");
+    fs::write(temp_dir.path().join("notes.txt"), "left  as  is\n").expect("writing failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = RecordingSink {
+        received: Rc::clone(&received),
+    };
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .compact_json(true)
+        .error_directory(error_dir.path().to_path_buf())
+        .sink(Box::new(sink))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 2);
+    assert_eq!(stats.files_failed, 1);
+    assert_eq!(
+        *received.borrow(),
+        [
+            ("notes.txt".to_string(), b"left  as  is\n".to_vec()),
+            (
+                "pretty.json".to_string(),
+                br#"{"id":1,"note":"two  spaces"}"#.to_vec()
+            ),
+        ]
+    );
+    // the invalid document goes down the error path untouched
+    assert_eq!(
+        fs::read_to_string(error_dir.path().join("broken.json")).unwrap(),
+        "{\n  \"id\": \n"
+    );
+}
+
+#[test]
+fn poll_directory_json_schema_error_names_path() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let schema_dir = tempfile::tempdir().expect("create temp dir failed");
+
+    let schema_path = schema_dir.path().join("order.schema.json");
+    let schema = r#"{ "properties": { "id": { "type": "integer" } } }"#;
+    fs::write(&schema_path, schema).expect("writing schema failed");
+    fs::write(temp_dir.path().join("invalid.json"), r#"{"id": "one"}"#).expect("writing failed");
+
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .json_schema(schema_path.clone())
+        .build();
+    let err = poller.poll_directory(temp_dir.path()).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("JSON schema validation failed at /id"));
+
+    // content that is not JSON at all is a clear error
+    fs::write(temp_dir.path().join("invalid.json"), "id=one").expect("writing failed");
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .json_schema(schema_path)
+        .build();
+    let err = poller.poll_directory(temp_dir.path()).unwrap_err();
+    assert!(err.to_string().contains("File content is not valid JSON"));
+}
+
+#[test]
+fn poll_directory_processes_empty_file_by_default() {
+    let (temp_dir, file_path) = create_temp_dir_with_empty_file();
+    let received: ReceivedFiles = Rc::new(RefCell::new(Vec::new()));
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(RecordingSink {
+            received: Rc::clone(&received),
+        }))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(
+        *received.borrow(),
+        vec![("empty.txt".to_string(), Vec::new())]
+    );
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn poll_directory_skips_empty_file() {
+    let (temp_dir, file_path) = create_temp_dir_with_empty_file();
+    let received: ReceivedFiles = Rc::new(RefCell::new(Vec::new()));
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .empty_file_policy(EmptyFilePolicy::Skip)
+        .sink(Box::new(RecordingSink {
+            received: Rc::clone(&received),
+        }))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_skipped, 1);
+    assert_eq!(stats.files_processed, 0);
+    assert!(received.borrow().is_empty());
+    // deleted since delete_files is enabled
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn poll_directory_skips_empty_file_without_deleting() {
+    let (temp_dir, file_path) = create_temp_dir_with_empty_file();
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .empty_file_policy(EmptyFilePolicy::Skip)
+        .on_file_content(|_content| Err("callback should not be called".into()))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_skipped, 1);
+    assert!(file_path.exists());
+}
+
+#[test]
+fn poll_directory_moves_empty_file_to_error_directory() {
+    let (temp_dir, file_path) = create_temp_dir_with_empty_file();
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .empty_file_policy(EmptyFilePolicy::Error)
+        .error_directory(error_dir.path().to_path_buf())
+        .on_file_content(|_content| Err("callback should not be called".into()))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_failed, 1);
+    assert!(!file_path.exists());
+    assert!(error_dir.path().join("empty.txt").exists());
+}
+
+#[test]
+fn poll_directory_fails_on_empty_file() {
+    let (temp_dir, file_path) = create_temp_dir_with_empty_file();
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .empty_file_policy(EmptyFilePolicy::Error)
+        .build();
+
+    let err = poller.poll_directory(temp_dir.path()).unwrap_err();
+    assert!(err.to_string().contains("File \"empty.txt\" is empty"));
+    assert!(file_path.exists());
+}
+
+#[test]
+fn poll_directory_sends_transformed_content() {
+    let (temp_dir, _file_path) = create_temp_dir_with_file();
+    let received: ReceivedFiles = Rc::new(RefCell::new(Vec::new()));
+
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .transform(Box::new(|content| Ok(content.to_ascii_uppercase())))
+        .sink(Box::new(RecordingSink {
+            received: Rc::clone(&received),
+        }))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    assert_eq!(
+        *received.borrow(),
+        vec![("sample.txt".to_string(), b"HELLO".to_vec())]
+    );
+}
+
+#[test]
+fn poll_directory_chains_transforms_in_order() {
+    let (temp_dir, _file_path) = create_temp_dir_with_file();
+    let received: ReceivedFiles = Rc::new(RefCell::new(Vec::new()));
+
+    // wrap first then uppercase so the envelope is uppercased too
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .transform(Box::new(|content| {
+            Ok([b"<".as_slice(), content, b">".as_slice()].concat())
+        }))
+        .transform(Box::new(|content| Ok(content.to_ascii_uppercase())))
+        .sink(Box::new(RecordingSink {
+            received: Rc::clone(&received),
+        }))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    assert_eq!(received.borrow()[0].1, b"<HELLO>".to_vec());
+}
+
+#[test]
+fn poll_directory_transform_error_fails_file() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .transform(Box::new(|_content| Err("Simulated transform error".into())))
+        .build();
+
+    let err = poller.poll_directory(temp_dir.path()).unwrap_err();
+    assert!(err.to_string().contains("Simulated transform error"));
+    assert!(file_path.exists());
+}
+
+#[test]
+fn enumerate_files_sorts_by_name_and_excludes() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for name in ["c.json", "a.json", "b.tmp", "b.json"] {
+        fs::write(temp_dir.path().join(name), "{}").expect("writing temp file failed");
+    }
+    fs::create_dir(temp_dir.path().join("d.json")).expect("create sub dir failed");
+
+    let poller = DirectoryPoller::builder()
+        .file_order(FileOrder::Name)
+        .exclude("*.tmp")
+        .exclude("c.*")
+        .build();
+
+    let files = poller
+        .enumerate_files(temp_dir.path())
+        .expect("enumerate failed");
+    assert_eq!(
+        files,
+        vec![
+            temp_dir.path().join("a.json"),
+            temp_dir.path().join("b.json")
+        ]
+    );
+}
+
+#[test]
+fn hidden_files_skipped_unless_included() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for name in ["a.json", ".a.json.swp", ".partial", "b.json"] {
+        fs::write(temp_dir.path().join(name), "{}").expect("writing temp file failed");
+    }
+
+    let poller = DirectoryPoller::builder()
+        .file_order(FileOrder::Name)
+        .build();
+    let files = poller
+        .enumerate_files(temp_dir.path())
+        .expect("enumerate failed");
+    assert_eq!(
+        files,
+        vec![
+            temp_dir.path().join("a.json"),
+            temp_dir.path().join("b.json")
+        ]
+    );
+
+    let poller = DirectoryPoller::builder()
+        .file_order(FileOrder::Name)
+        .skip_hidden(false)
+        .build();
+    let files = poller
+        .enumerate_files(temp_dir.path())
+        .expect("enumerate failed");
+    assert_eq!(
+        files,
+        vec![
+            temp_dir.path().join(".a.json.swp"),
+            temp_dir.path().join(".partial"),
+            temp_dir.path().join("a.json"),
+            temp_dir.path().join("b.json")
+        ]
+    );
+}
+
+#[test]
+fn enumerate_files_oldest_first_with_include() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let now = SystemTime::now();
+    // written newest first so the listing order does not give the answer away
+    for (name, age_secs) in [
+        ("new.json", 10),
+        ("old.json", 300),
+        ("mid.json", 60),
+        ("x.txt", 500),
+    ] {
+        let file_path = temp_dir.path().join(name);
+        fs::write(&file_path, "{}").expect("writing temp file failed");
+        let file = fs::File::options()
+            .write(true)
+            .open(&file_path)
+            .expect("open failed");
+        file.set_modified(now - Duration::from_secs(age_secs))
+            .expect("set modified failed");
+    }
+
+    let poller = DirectoryPoller::builder()
+        .file_order(FileOrder::Oldest)
+        .include("*.json")
+        .build();
+
+    let files = poller
+        .enumerate_files(temp_dir.path())
+        .expect("enumerate failed");
+    let names: Vec<String> = files
+        .iter()
+        .map(|f| f.file_name().unwrap().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(names, vec!["old.json", "mid.json", "new.json"]);
+}
+
+#[test]
+fn newest_order_with_lookback_publishes_fresh_files_newest_first() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let now = SystemTime::now();
+    for (name, age_secs) in [
+        ("mid.json", 60),
+        ("stale.json", 7200),
+        ("new.json", 10),
+        ("old.json", 300),
+        ("ancient.json", 86400),
+    ] {
+        let file_path = temp_dir.path().join(name);
+        fs::write(&file_path, name).expect("writing temp file failed");
+        let file = fs::File::options()
+            .write(true)
+            .open(&file_path)
+            .expect("open failed");
+        file.set_modified(now - Duration::from_secs(age_secs))
+            .expect("set modified failed");
+    }
+
+    let published = Rc::new(RefCell::new(Vec::new()));
+    let sink_published = Rc::clone(&published);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Newest)
+        .file_ttl(Duration::from_secs(3600))
+        .on_file_content(move |content| {
+            sink_published.borrow_mut().push(content.to_string());
+            Ok(())
+        })
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(*published.borrow(), ["new.json", "mid.json", "old.json"]);
+    // older than the window, dropped without being published
+    assert_eq!(stats.files_skipped, 2);
+    assert!(!temp_dir.path().join("stale.json").exists());
+    assert!(!temp_dir.path().join("ancient.json").exists());
+}
+
+#[test]
+fn poll_directory_skips_excluded_files() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let excluded_path = temp_dir.path().join("sample.tmp");
+    fs::write(&excluded_path, "partial").expect("writing temp file failed");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .exclude("*.tmp")
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert!(!file_path.exists());
+    assert!(excluded_path.exists());
+}
+
+// the SHA-256 of "hello"
+const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+#[test]
+fn poll_directory_publishes_file_matching_checksum() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let checksum_path = temp_dir.path().join("sample.txt.sha256");
+    fs::write(&checksum_path, format!("{}  sample.txt\n", HELLO_SHA256))
+        .expect("writing checksum failed");
+    let received: ReceivedFiles = Rc::new(RefCell::new(Vec::new()));
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .verify_checksum(true)
+        .sink(Box::new(RecordingSink {
+            received: Rc::clone(&received),
+        }))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    // the checksum file is not published on its own
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(received.borrow().len(), 1);
+    assert!(!file_path.exists());
+    assert!(!checksum_path.exists());
+}
+
+#[test]
+fn poll_directory_rejects_checksum_mismatch() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("sample.txt.sha256"), "0".repeat(64))
+        .expect("writing checksum failed");
+    let received: ReceivedFiles = Rc::new(RefCell::new(Vec::new()));
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .verify_checksum(true)
+        .error_directory(error_dir.path().to_path_buf())
+        .sink(Box::new(RecordingSink {
+            received: Rc::clone(&received),
+        }))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_failed, 1);
+    assert!(received.borrow().is_empty());
+    assert!(!file_path.exists());
+    assert!(error_dir.path().join("sample.txt").exists());
+    assert!(error_dir.path().join("sample.txt.sha256").exists());
+}
+
+#[test]
+fn poll_directory_without_checksum_file() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+
+    // processed normally when a checksum is not required
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .verify_checksum(true)
+        .build();
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .verify_checksum(true)
+        .require_checksum(true)
+        .build();
+    let err = poller.poll_directory(temp_dir.path()).unwrap_err();
+    assert!(err.to_string().contains("has no checksum file"));
+    assert!(file_path.exists());
+}
+
+#[test]
+fn poll_directory_stops_at_max_runtime() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for i in 0..10 {
+        fs::write(temp_dir.path().join(format!("{}.txt", i)), "hello").expect("writing failed");
+    }
+
+    // each file takes 50ms so all 10 would take 500ms
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .keep_running(true)
+        .max_runtime(Duration::from_millis(120))
+        .on_file_content(|_content| {
+            std::thread::sleep(Duration::from_millis(50));
+            Ok(())
+        })
+        .build();
+
+    let started = std::time::Instant::now();
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    let elapsed = started.elapsed();
+
+    // the file in flight at the deadline is finished
+    assert!(elapsed >= Duration::from_millis(120));
+    assert!(elapsed < Duration::from_millis(400));
+    assert!((2..=4).contains(&stats.files_processed));
+    assert_eq!(stats.cycles, 0);
+}
+
+#[test]
+fn poll_directory_caps_files_per_cycle() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for i in 0..10 {
+        fs::write(temp_dir.path().join(format!("{}.txt", i)), "hello").expect("writing failed");
+    }
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .file_order(FileOrder::Name)
+        .max_poll_cycles(1)
+        .max_files_per_cycle(3)
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 3);
+
+    // the first files in order were taken and the rest were left for the next cycle
+    let mut remaining: Vec<String> = fs::read_dir(temp_dir.path())
+        .expect("reading temp dir failed")
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    remaining.sort();
+    assert_eq!(
+        remaining,
+        ["3.txt", "4.txt", "5.txt", "6.txt", "7.txt", "8.txt", "9.txt"]
+    );
+}
+
+#[test]
+fn poll_directory_drops_expired_file() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let old_path = temp_dir.path().join("old.txt");
+    fs::write(&old_path, "stale").expect("writing temp file failed");
+    fs::write(temp_dir.path().join("new.txt"), "fresh").expect("writing temp file failed");
+    let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    fs::File::options()
+        .write(true)
+        .open(&old_path)
+        .and_then(|file| file.set_modified(an_hour_ago))
+        .expect("setting mtime failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let received_clone = Rc::clone(&received);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .file_ttl(Duration::from_secs(60))
+        .on_file_content(move |content| {
+            received_clone.borrow_mut().push(content.to_string());
+            Ok(())
+        })
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(*received.borrow(), vec!["fresh"]);
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(stats.files_skipped, 1);
+    assert!(!old_path.exists());
+}
+
+#[test]
+fn file_ttl_measured_by_the_clock() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let clock = Arc::new(FakeClock::new());
+    clock.advance(Duration::from_secs(3600));
+
+    let published = Rc::new(Cell::new(0));
+    let counted = Rc::clone(&published);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .file_ttl(Duration::from_secs(60))
+        .clock(clock)
+        .on_file_content(move |_content| {
+            counted.set(counted.get() + 1);
+            Ok(())
+        })
+        .build();
+
+    // the file was written just now but is an hour old by the clock
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(published.get(), 0);
+    assert_eq!(stats.files_skipped, 1);
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn poll_directory_max_runtime_cuts_wait_between_cycles() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(10_000)
+        .keep_running(true)
+        .max_runtime(Duration::from_millis(100))
+        .build();
+
+    let started = std::time::Instant::now();
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(stats.cycles, 1);
+}
+
+#[test]
+fn poll_directory_claimed_file_skipped_by_other_poller() {
+    use std::cell::Cell;
+
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let directory = temp_dir.path().to_path_buf();
+    let other_calls = Rc::new(Cell::new(0));
+    let other_calls_clone = Rc::clone(&other_calls);
+
+    // while the first poller is processing the file
+    // a second poller runs over the same directory
+    let other_directory = directory.clone();
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .claim_suffix(".inprogress".to_string())
+        .on_file_content(move |_content| {
+            let calls = Rc::clone(&other_calls_clone);
+            let other_poller = DirectoryPoller::builder()
+                .delete_files(true)
+                .poll_interval_millis(0)
+                .max_poll_cycles(1)
+                .claim_suffix(".inprogress".to_string())
+                .on_file_content(move |_content| {
+                    calls.set(calls.get() + 1);
+                    Ok(())
+                })
+                .build();
+            other_poller.poll_directory(&other_directory)?;
+            Ok(())
+        })
+        .build();
+
+    let stats = poller.poll_directory(&directory).expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(other_calls.get(), 0);
+    assert!(!file_path.exists());
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn poll_directory_releases_claim_on_failure() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .claim_suffix(".inprogress".to_string())
+        .on_file_content(|_content| Err("Simulated error in callback".into()))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_err());
+    // renamed back for the next attempt
+    assert!(file_path.exists());
+    assert!(!temp_dir.path().join("sample.txt.inprogress").exists());
+}
+
+#[test]
+fn poll_directory_abandons_stuck_callback() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .callback_timeout(Duration::from_millis(50))
+        .on_file_content_send(|_content| {
+            std::thread::sleep(Duration::from_secs(2));
+            Ok(())
+        })
+        .build();
+
+    let started = std::time::Instant::now();
+    let err = poller.poll_directory(temp_dir.path()).unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(err
+        .to_string()
+        .contains("Callback did not finish within 50 ms"));
+    assert!(file_path.exists());
+}
+
+#[test]
+fn poll_directory_send_callback_within_timeout() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .on_file_content_send(move |content| {
+            sender.send(content.to_string())?;
+            Ok(())
+        })
+        .callback_timeout(Duration::from_secs(5))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    assert_eq!(receiver.try_recv().unwrap(), "hello");
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn poll_directory_writes_checkpoint() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let checkpoint_dir = tempfile::tempdir().expect("create temp dir failed");
+    let checkpoint_file = checkpoint_dir.path().join("checkpoint");
+    fs::write(temp_dir.path().join("a.txt"), "first").expect("writing failed");
+    fs::write(temp_dir.path().join("b.txt"), "second").expect("writing failed");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .checkpoint_file(checkpoint_file.clone())
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+
+    let checkpoint = fs::read_to_string(&checkpoint_file).expect("reading checkpoint failed");
+    let (timestamp, path) = checkpoint.trim_end().split_once(' ').unwrap();
+    assert!(timestamp.parse::<u64>().is_ok());
+    assert_eq!(path, temp_dir.path().join("b.txt").display().to_string());
+    // only the checkpoint is left, not the temp file
+    assert_eq!(fs::read_dir(checkpoint_dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn poll_directory_publishes_json_envelope() {
+    let (temp_dir, _) = create_temp_dir_with_file();
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = RecordingSink {
+        received: Rc::clone(&received),
+    };
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .max_poll_cycles(1)
+        .envelope(EnvelopeFormat::JsonEnvelope)
+        .sink(Box::new(sink))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+
+    let received = received.borrow();
+    let envelope: serde_json::Value =
+        serde_json::from_slice(&received[0].1).expect("envelope is not JSON");
+    assert_eq!(envelope["file_name"], "sample.txt");
+    assert_eq!(envelope["size"], 5);
+    assert!(envelope["modified"].as_u64().is_some());
+    // "hello" base64 encoded
+    assert_eq!(envelope["payload_base64"], "aGVsbG8=");
+}
+
+// the (file name, topic) pairs a TopicSink has received
+type ReceivedTopics = Rc<RefCell<Vec<(String, Option<String>)>>>;
+
+// Fake sink that records the topic each file was routed to
+struct TopicSink {
+    received: ReceivedTopics,
+}
+
+impl FileSink for TopicSink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        self.received
+            .borrow_mut()
+            .push((ctx.file_name.clone(), ctx.topic.clone()));
+        Ok(())
+    }
+}
+
+#[test]
+fn poll_directory_topic_from_subdir() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for (directory, name) in [("orders", "o1.json"), ("payments", "p1.json")] {
+        fs::create_dir(temp_dir.path().join(directory)).expect("create subdir failed");
+        fs::write(temp_dir.path().join(directory).join(name), "{}")
+            .expect("writing temp file failed");
+    }
+    fs::write(temp_dir.path().join("root.json"), "{}").expect("writing temp file failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .topic_from_subdir(true)
+        .sink(Box::new(TopicSink {
+            received: Rc::clone(&received),
+        }))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 3);
+    assert_eq!(
+        *received.borrow(),
+        vec![
+            ("o1.json".to_string(), Some("orders".to_string())),
+            ("p1.json".to_string(), Some("payments".to_string())),
+            // files directly in the polled directory keep the default topic
+            ("root.json".to_string(), None),
+        ]
+    );
+    // the subdirectories stay
+    assert!(temp_dir.path().join("orders").is_dir());
+    assert!(!temp_dir.path().join("orders").join("o1.json").exists());
+}
+
+#[test]
+fn poll_directory_invalid_subdir_topic_fails_file() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+    let subdir = temp_dir.path().join("not a topic");
+    fs::create_dir(&subdir).expect("create subdir failed");
+    fs::write(subdir.join("a.json"), "{}").expect("writing temp file failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .topic_from_subdir(true)
+        .error_directory(error_dir.path().to_path_buf())
+        .sink(Box::new(TopicSink {
+            received: Rc::clone(&received),
+        }))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_failed, 1);
+    assert!(received.borrow().is_empty());
+    assert!(error_dir.path().join("a.json").exists());
+}
+
+// the (file name, topic, key, headers) a RoutingSink has received
+type ReceivedRoutes = Rc<RefCell<Vec<(String, Option<String>, Option<String>, Vec<String>)>>>;
+
+// Fake sink that records the topic, key and header names of each file
+struct RoutingSink {
+    received: ReceivedRoutes,
+}
+
+impl FileSink for RoutingSink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        self.received.borrow_mut().push((
+            ctx.file_name.clone(),
+            ctx.topic.clone(),
+            ctx.key
+                .as_deref()
+                .map(|key| String::from_utf8_lossy(key).to_string()),
+            ctx.headers
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, String::from_utf8_lossy(value)))
+                .collect(),
+        ));
+        Ok(())
+    }
+}
+
+#[test]
+fn path_template_routes_by_path_segments() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+    let matching = temp_dir.path().join("orders").join("eu").join("cust-42");
+    fs::create_dir_all(&matching).expect("create subdir failed");
+    fs::write(matching.join("a.json"), "{}").expect("writing temp file failed");
+    // one directory short of the template
+    let short = temp_dir.path().join("orders").join("cust-7");
+    fs::write(short.with_file_name("b.json"), "{}").expect("writing temp file failed");
+    fs::create_dir_all(&short).expect("create subdir failed");
+    fs::write(short.join("c.json"), "{}").expect("writing temp file failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .path_template(PathTemplate::parse("{topic}/{region}/{key}/*").unwrap())
+        .error_directory(error_dir.path().to_path_buf())
+        .sink(Box::new(RoutingSink {
+            received: Rc::clone(&received),
+        }))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(stats.files_failed, 2);
+    assert_eq!(
+        *received.borrow(),
+        [(
+            "a.json".to_string(),
+            Some("orders".to_string()),
+            Some("cust-42".to_string()),
+            vec!["region=eu".to_string()],
+        )]
+    );
+    assert!(error_dir.path().join("b.json").exists());
+    assert!(error_dir.path().join("c.json").exists());
+}
+
+#[test]
+fn try_build_rejects_path_template_with_topic_from_subdir() {
+    let result = DirectoryPoller::builder()
+        .topic_from_subdir(true)
+        .path_template(PathTemplate::parse("{topic}/*").unwrap())
+        .try_build();
+    assert!(matches!(
+        result,
+        Err(BuildError::PathTemplateWithTopicFromSubdir)
+    ));
+}
+
+#[test]
+fn try_build_rejects_require_checksum_without_verify() {
+    let result = DirectoryPoller::builder()
+        .require_checksum(true)
+        .try_build();
+    assert!(matches!(
+        result,
+        Err(BuildError::RequireChecksumWithoutVerify)
+    ));
+}
+
+#[test]
+fn try_build_rejects_callback_timeout_without_callback() {
+    let result = DirectoryPoller::builder()
+        .callback_timeout(Duration::from_secs(1))
+        .try_build();
+    assert!(matches!(
+        result,
+        Err(BuildError::CallbackTimeoutWithoutCallback)
+    ));
+}
+
+#[test]
+fn try_build_rejects_claim_suffix_matching_sidecar() {
+    let result = DirectoryPoller::builder()
+        .claim_suffix(".meta".to_string())
+        .sidecar_extension(".meta".to_string())
+        .try_build();
+    assert!(matches!(
+        result,
+        Err(BuildError::ClaimSuffixIsSidecarExtension(suffix)) if suffix == ".meta"
+    ));
+}
+
+#[test]
+fn try_build_reports_invalid_glob() {
+    let result = DirectoryPoller::builder().include("[").try_build();
+    assert!(matches!(result, Err(BuildError::InvalidGlob { pattern, .. }) if pattern == "["));
+}
+
+#[test]
+fn poll_directory_counts_bytes_processed() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("a.txt"), "hello").expect("writing temp file failed");
+    fs::write(temp_dir.path().join("b.txt"), "hello world").expect("writing temp file failed");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 2);
+    assert_eq!(stats.bytes_processed, 16);
+}
+
+#[test]
+fn poll_directory_counts_bytes_after_transform() {
+    let (temp_dir, _) = create_temp_dir_with_file();
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .transform(Box::new(|content| Ok(content.repeat(2))))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.bytes_processed, 10);
+}
+
+#[test]
+fn poll_directory_applies_payload_template() {
+    let (temp_dir, _) = create_temp_dir_with_file();
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = RecordingSink {
+        received: Rc::clone(&received),
+    };
+
+    let template = PayloadTemplate::parse("BEGIN {file_name}\n{content}\nEND").unwrap();
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .max_poll_cycles(1)
+        .payload_template(template)
+        .sink(Box::new(sink))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    assert_eq!(received.borrow()[0].1, b"BEGIN sample.txt\nhello\nEND");
+}
+
+#[test]
+fn replay_publishes_files_and_leaves_them() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for name in ["a.json", "b.json", "c.txt"] {
+        fs::write(temp_dir.path().join(name), name).expect("writing temp file failed");
+    }
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = RecordingSink {
+        received: Rc::clone(&received),
+    };
+
+    // replay wins over the deletion and polling options
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .keep_running(true)
+        .claim_suffix(".inprogress".to_string())
+        .include("*.json")
+        .file_order(FileOrder::Name)
+        .replay(true)
+        .sink(Box::new(sink))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.cycles, 1);
+    let names: Vec<String> = received
+        .borrow()
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect();
+    assert_eq!(names, vec!["a.json", "b.json"]);
+    for name in ["a.json", "b.json", "c.txt"] {
+        assert!(temp_dir.path().join(name).exists());
+    }
+}
+
+#[test]
+fn replay_only_publishes_files_modified_since() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let old_file = temp_dir.path().join("old.txt");
+    fs::write(&old_file, "old").expect("writing temp file failed");
+    fs::write(temp_dir.path().join("new.txt"), "new").expect("writing temp file failed");
+    let since = SystemTime::now() - Duration::from_secs(60);
+    fs::File::options()
+        .write(true)
+        .open(&old_file)
+        .and_then(|file| file.set_modified(since - Duration::from_secs(60)))
+        .expect("setting modified time failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = RecordingSink {
+        received: Rc::clone(&received),
+    };
+    let poller = DirectoryPoller::builder()
+        .replay(true)
+        .modified_since(since)
+        .sink(Box::new(sink))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    let received = received.borrow();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].0, "new.txt");
+}
+
+#[cfg(unix)]
+#[test]
+fn poll_directory_accepts_non_utf8_path() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let directory = temp_dir.path().join(OsStr::from_bytes(b"in\xff"));
+    fs::create_dir(&directory).expect("create dir failed");
+    fs::write(directory.join("sample.txt"), "hello").expect("writing temp file failed");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .build();
+
+    let stats = poller.poll_directory(&directory).expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+}
+
+// Fake sink that buffers sends and only delivers them on flush
+// like a producer waiting on linger.ms
+#[derive(Default)]
+struct BufferingSink {
+    pending: RefCell<Vec<String>>,
+    events: Rc<RefCell<Vec<String>>>,
+}
+
+impl FileSink for BufferingSink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        self.events
+            .borrow_mut()
+            .push(format!("send {}", ctx.file_name));
+        self.pending.borrow_mut().push(ctx.file_name.clone());
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        for file_name in self.pending.borrow_mut().drain(..) {
+            self.events
+                .borrow_mut()
+                .push(format!("delivered {}", file_name));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn poll_directory_ordered_waits_for_each_delivery() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(temp_dir.path().join(name), "hello").expect("writing temp file failed");
+    }
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let sink = BufferingSink {
+        events: Rc::clone(&events),
+        ..Default::default()
+    };
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .ordered(true)
+        .sink(Box::new(sink))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            "send a.txt",
+            "delivered a.txt",
+            "send b.txt",
+            "delivered b.txt",
+            "send c.txt",
+            "delivered c.txt",
+        ]
+    );
+}
+
+type SentMessages = Rc<RefCell<Vec<(Option<String>, String)>>>;
+
+// Fake sink that records what was sent to which topic and when it was flushed
+struct ControlSink {
+    events: SentMessages,
+}
+
+impl FileSink for ControlSink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        let content = String::from_utf8(ctx.content.clone())?;
+        self.events.borrow_mut().push((ctx.topic.clone(), content));
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        self.events.borrow_mut().push((None, "flush".to_string()));
+        Ok(())
+    }
+}
+
+#[test]
+fn poll_directory_sends_cycle_marker_to_control_topic() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("a.txt"), "hello").expect("writing temp file failed");
+    fs::write(temp_dir.path().join("b.txt"), "hi!").expect("writing temp file failed");
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let sink = ControlSink {
+        events: Rc::clone(&events),
+    };
+
+    // the second cycle finds nothing and sends no marker
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(2)
+        .control_topic("batches".to_string())
+        .sink(Box::new(sink))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.cycles, 2);
+
+    let events = events.borrow();
+    let markers: Vec<usize> = (0..events.len())
+        .filter(|&i| events[i].0.as_deref() == Some("batches"))
+        .collect();
+    assert_eq!(markers.len(), 1);
+    // both files were sent and the sink flushed before the marker
+    let marker_index = markers[0];
+    assert_eq!(marker_index, 3);
+    assert_eq!(events[2], (None, "flush".to_string()));
+
+    let marker: serde_json::Value =
+        serde_json::from_str(&events[marker_index].1).expect("marker is not JSON");
+    assert_eq!(marker["cycle"], 1);
+    assert_eq!(marker["files"], 2);
+    assert_eq!(marker["bytes"], 8);
+    assert!(marker["timestamp"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn poll_directory_sends_one_silence_alert_to_control_topic() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let clock = Arc::new(FakeClock::new());
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let sink = ControlSink {
+        events: Rc::clone(&events),
+    };
+
+    let poller = DirectoryPoller::builder()
+        .keep_running(true)
+        .poll_interval_millis(1000)
+        .expected_interval(Duration::from_secs(2))
+        .max_poll_cycles(6)
+        .control_topic("alerts".to_string())
+        .clock(clock.clone())
+        .sink(Box::new(sink))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.cycles, 6);
+
+    // still silent after the alert, but it is only raised once
+    let events = events.borrow();
+    let alerts: Vec<&String> = events
+        .iter()
+        .filter(|(topic, _)| topic.as_deref() == Some("alerts"))
+        .map(|(_, content)| content)
+        .collect();
+    assert_eq!(alerts.len(), 1);
+    let alert: serde_json::Value = serde_json::from_str(alerts[0]).expect("alert is not JSON");
+    assert_eq!(alert["alert"], "no_files");
+    assert_eq!(alert["silent_secs"], 3);
+    assert_eq!(alert["expected_interval_secs"], 2);
+}
+
+#[test]
+fn poll_directories_apply_directory_overrides() {
+    let orders_dir = tempfile::tempdir().expect("create temp dir failed");
+    let audit_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(orders_dir.path().join("order-1.json"), "order").expect("writing failed");
+    fs::write(audit_dir.path().join("audit-1.csv"), "audit").expect("writing failed");
+    // only csv files are published from the audit directory
+    fs::write(audit_dir.path().join("audit-1.json"), "audit").expect("writing failed");
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let sink = ControlSink {
+        events: Rc::clone(&events),
+    };
+
+    // the orders directory keeps the global delete policy
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .directory_overrides(
+            orders_dir.path().to_path_buf(),
+            DirectoryOverrides {
+                topic: Some("orders".to_string()),
+                ..Default::default()
+            },
+        )
+        .directory_overrides(
+            audit_dir.path().to_path_buf(),
+            DirectoryOverrides {
+                topic: Some("audit".to_string()),
+                delete_files: Some(false),
+                include: Some(vec!["*.csv".to_string()]),
+                ..Default::default()
+            },
+        )
+        .sink(Box::new(sink))
+        .build();
+
+    let stats = poller
+        .poll_directories(&[orders_dir.path(), audit_dir.path()])
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 2);
+
+    let published: Vec<(Option<String>, String)> = events
+        .borrow()
+        .iter()
+        .filter(|(topic, _)| topic.is_some())
+        .cloned()
+        .collect();
+    assert_eq!(
+        published,
+        vec![
+            (Some("orders".to_string()), "order".to_string()),
+            (Some("audit".to_string()), "audit".to_string()),
+        ]
+    );
+    assert!(!orders_dir.path().join("order-1.json").exists());
+    assert!(audit_dir.path().join("audit-1.csv").exists());
+    assert!(audit_dir.path().join("audit-1.json").exists());
+}
+
+// Poll a directory holding order-1.json and Order_2.JSON under the name mismatch policy
+// returns what was published, the error directory and the poll directory
+fn poll_with_required_name(
+    name_mismatch_policy: NameMismatchPolicy,
+) -> (Vec<String>, TempDir, TempDir) {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("order-1.json"), "good").expect("writing failed");
+    fs::write(temp_dir.path().join("Order_2.JSON"), "bad").expect("writing failed");
+    let received = Rc::new(RefCell::new(Vec::new()));
+
+    let sink_received = Rc::clone(&received);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .error_directory(error_dir.path().to_path_buf())
+        .require_name_regex(regex::Regex::new(r"^[a-z]+-\d+\.json$").unwrap())
+        .name_mismatch_policy(name_mismatch_policy)
+        .on_file_content(move |content| {
+            sink_received.borrow_mut().push(content.to_string());
+            Ok(())
+        })
+        .build();
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    let received = received.borrow().clone();
+    (received, error_dir, temp_dir)
+}
+
+#[test]
+fn rejected_file_name_moves_to_error_directory() {
+    let (received, error_dir, temp_dir) = poll_with_required_name(NameMismatchPolicy::Reject);
+    assert_eq!(received, ["good"]);
+    assert!(!temp_dir.path().join("order-1.json").exists());
+    assert!(error_dir.path().join("Order_2.JSON").exists());
+}
+
+#[test]
+fn skipped_file_name_is_left_in_place() {
+    let (received, error_dir, temp_dir) = poll_with_required_name(NameMismatchPolicy::Skip);
+    assert_eq!(received, ["good"]);
+    assert!(temp_dir.path().join("Order_2.JSON").exists());
+    assert!(!error_dir.path().join("Order_2.JSON").exists());
+}
+
+#[test]
+fn batched_files_published_as_one_message() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for (name, content) in [
+        ("a.json", "{\"id\":1}"),
+        ("b.json", "{\"id\":2}"),
+        ("c.json", "{\"id\":3}"),
+    ] {
+        fs::write(temp_dir.path().join(name), content).expect("writing failed");
+    }
+    let received = Rc::new(RefCell::new(Vec::new()));
+
+    let sink_received = Rc::clone(&received);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .batch(BatchLimits::new(Some(3), None))
+        .on_file_content(move |content| {
+            sink_received.borrow_mut().push(content.to_string());
+            Ok(())
+        })
+        .build();
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+
+    assert_eq!(stats.files_processed, 3);
+    assert_eq!(*received.borrow(), ["{\"id\":1}\n{\"id\":2}\n{\"id\":3}"]);
+    for name in ["a.json", "b.json", "c.json"] {
+        assert!(!temp_dir.path().join(name).exists(), "{} was kept", name);
+    }
+}
+
+#[test]
+fn batched_files_kept_when_batch_fails() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("a.json"), "a").expect("writing failed");
+    fs::write(temp_dir.path().join("b.json"), "b").expect("writing failed");
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .batch(BatchLimits::new(Some(10), None))
+        .on_file_content(|_| Err("broker unavailable".into()))
+        .build();
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+
+    // the batch is published at the end of the cycle
+    assert!(temp_dir.path().join("a.json").exists());
+    assert!(temp_dir.path().join("b.json").exists());
+}
+
+#[test]
+fn visited_marker_written_once_batch_is_delivered() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let sends = Rc::new(Cell::new(0));
+
+    let counted = Rc::clone(&sends);
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .poll_interval_millis(0)
+        .max_poll_cycles(3)
+        .visited_markers(VisitedMarkers::Persistent)
+        .batch(BatchLimits::new(Some(10), None))
+        .on_file_content(move |_| {
+            counted.set(counted.get() + 1);
+            if counted.get() == 1 {
+                return Err("broker unavailable".into());
+            }
+            Ok(())
+        })
+        .build();
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+
+    // the failed batch leaves no marker so the second cycle publishes the file
+    // and the third skips it by the marker written then
+    assert_eq!(sends.get(), 2);
+    assert!(VisitedMarkers::marker_path(&file_path).exists());
+}
+
+#[test]
+fn dedup_remembers_file_once_batch_is_delivered() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let sends = Rc::new(Cell::new(0));
+
+    let counted = Rc::clone(&sends);
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .poll_interval_millis(0)
+        .max_poll_cycles(3)
+        .dedup_mode(DedupMode::PathMtimeSize)
+        .batch(BatchLimits::new(Some(10), None))
+        .on_file_content(move |_| {
+            counted.set(counted.get() + 1);
+            if counted.get() == 1 {
+                return Err("broker unavailable".into());
+            }
+            Ok(())
+        })
+        .build();
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+
+    // the file of the failed batch is published again by the second cycle
+    assert_eq!(sends.get(), 2);
+    assert_eq!(stats.files_skipped, 1);
+    assert!(file_path.exists());
+}
+
+// Fake sink whose first sends (up to failures) fail with the error from make_error
+struct FlakySink {
+    sends: Rc<Cell<u32>>,
+    failures: u32,
+    make_error: fn() -> SinkError,
+}
+
+impl FileSink for FlakySink {
+    fn send(&self, _ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        self.sends.set(self.sends.get() + 1);
+        if self.sends.get() <= self.failures {
+            return Err(Box::new((self.make_error)()));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn send_retry_backoff_interrupted_by_shutdown() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let sends = Rc::new(Cell::new(0));
+
+    let flag = Arc::clone(&shutdown_flag);
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        flag.store(true, Ordering::SeqCst);
+    });
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .shutdown_flag(shutdown_flag)
+        .send_retries(10, Duration::from_secs(60))
+        .sink(Box::new(FlakySink {
+            sends: Rc::clone(&sends),
+            failures: u32::MAX,
+            make_error: || SinkError::Retriable("timed out".into()),
+        }))
+        .build();
+
+    let started = Instant::now();
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_err());
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert_eq!(sends.get(), 1);
+    assert!(file_path.exists());
+}
+
+// Poll a directory holding one file into a FlakySink allowing 3 send retries
+// returns the number of sends and whether the file is still there
+fn poll_into_flaky_sink(failures: u32, make_error: fn() -> SinkError) -> (u32, bool) {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let sends = Rc::new(Cell::new(0));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .error_directory(temp_dir.path().join("errors"))
+        .send_retries(3, Duration::ZERO)
+        .sink(Box::new(FlakySink {
+            sends: Rc::clone(&sends),
+            failures,
+            make_error,
+        }))
+        .build();
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    (sends.get(), file_path.exists())
+}
+
+#[test]
+fn fatal_sink_error_is_not_retried() {
+    let (sends, kept) = poll_into_flaky_sink(1, || SinkError::Fatal("payload rejected".into()));
+    assert_eq!(sends, 1);
+    // moved to the error directory
+    assert!(!kept);
+}
+
+#[test]
+fn retriable_sink_error_is_retried() {
+    let (sends, kept) =
+        poll_into_flaky_sink(2, || SinkError::Retriable("publish timed out".into()));
+    assert_eq!(sends, 3);
+    assert!(!kept);
+}
+
+#[test]
+fn retriable_sink_error_retries_run_out() {
+    let (sends, _kept) =
+        poll_into_flaky_sink(10, || SinkError::Retriable("publish timed out".into()));
+    assert_eq!(sends, 4);
+}
+
+#[test]
+fn error_files_retried_on_startup() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(error_dir.path().join("fixed.txt"), "fixed").expect("writing failed");
+    fs::write(error_dir.path().join("broken.txt"), "broken").expect("writing failed");
+    let received = Rc::new(RefCell::new(Vec::new()));
+
+    let sink_received = Rc::clone(&received);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .error_directory(error_dir.path().to_path_buf())
+        .retry_errors_on_startup(true)
+        .on_file_content(move |content| {
+            if content == "broken" {
+                return Err("still broken".into());
+            }
+            sink_received.borrow_mut().push(content.to_string());
+            Ok(())
+        })
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(stats.files_failed, 1);
+    assert_eq!(*received.borrow(), ["fixed"]);
+    assert!(!error_dir.path().join("fixed.txt").exists());
+    // the file that failed again is back in the error directory
+    assert!(error_dir.path().join("broken.txt").exists());
+    assert!(!temp_dir.path().join("broken.txt").exists());
+}
+
+#[test]
+fn error_files_retried_in_the_directory_they_failed_in() {
+    let first_dir = tempfile::tempdir().expect("create temp dir failed");
+    let second_dir = tempfile::tempdir().expect("create temp dir failed");
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(second_dir.path().join("order.json"), "{}").expect("writing failed");
+    let directories = [first_dir.path(), second_dir.path()];
+
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .error_directory(error_dir.path().to_path_buf())
+        .on_file_content(|_content| Err("broker rejected it".into()))
+        .build();
+    let stats = poller
+        .poll_directories(&directories)
+        .expect("polling failed");
+    assert_eq!(stats.files_failed, 1);
+    assert!(error_dir.path().join("order.json").exists());
+
+    // published again but kept, so it shows where it was put back
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .error_directory(error_dir.path().to_path_buf())
+        .retry_errors_on_startup(true)
+        .build();
+    let stats = poller
+        .poll_directories(&directories)
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert!(second_dir.path().join("order.json").exists());
+    assert!(!first_dir.path().join("order.json").exists());
+    assert_eq!(fs::read_dir(error_dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn build_rejects_retrying_errors_without_error_directory() {
+    let result = DirectoryPoller::builder()
+        .retry_errors_on_startup(true)
+        .try_build();
+    assert!(matches!(
+        result,
+        Err(BuildError::RetryErrorsWithoutErrorDirectory)
+    ));
+}
+
+#[test]
+fn build_rejects_invalid_control_topic() {
+    let result = DirectoryPoller::builder()
+        .control_topic("not a topic".to_string())
+        .try_build();
+    assert!(matches!(result, Err(BuildError::InvalidControlTopic(_))));
+}
+
+// Fake sink that holds on to deliveries until it is flushed
+// only the first confirm_flushes flushes succeed
+// and sending stop_after requests a shutdown
+struct HoldingSink {
+    confirm_flushes: Cell<u32>,
+    stop_after: String,
+    shutdown_flag: Arc<AtomicBool>,
+}
+
+impl FileSink for HoldingSink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        // long enough for the flush interval to pass
+        std::thread::sleep(Duration::from_millis(5));
+        if ctx.file_name == self.stop_after {
+            self.shutdown_flag.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        if self.confirm_flushes.get() == 0 {
+            return Err("deliveries still outstanding".into());
+        }
+        self.confirm_flushes.set(self.confirm_flushes.get() - 1);
+        Ok(())
+    }
+}
+
+#[test]
+fn poll_directory_keeps_unconfirmed_files_on_shutdown() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(temp_dir.path().join(name), "hello").expect("writing temp file failed");
+    }
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let sink = HoldingSink {
+        confirm_flushes: Cell::new(1),
+        stop_after: "b.txt".to_string(),
+        shutdown_flag: Arc::clone(&shutdown_flag),
+    };
+
+    // a.txt is confirmed by the flush after the first cycle
+    // b.txt is sent in the second cycle, then shutdown is requested
+    // and neither the flush after that cycle nor the one on shutdown confirms it
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .keep_running(true)
+        .poll_interval_millis(0)
+        .flush_interval_millis(1)
+        .file_order(FileOrder::Name)
+        .max_files_per_cycle(1)
+        .shutdown_flag(shutdown_flag)
+        .sink(Box::new(sink))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.cycles, 2);
+    assert_eq!(stats.files_processed, 2);
+    assert!(!temp_dir.path().join("a.txt").exists());
+    assert!(temp_dir.path().join("b.txt").exists());
+    assert!(temp_dir.path().join("c.txt").exists());
+}
+
+#[test]
+fn poll_directory_waits_while_paused() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let directory = temp_dir.path().to_path_buf();
+    let pause_flag = Arc::new(AtomicBool::new(true));
+
+    let poller_pause_flag = Arc::clone(&pause_flag);
+    let poller_thread = std::thread::spawn(move || {
+        let poller = DirectoryPoller::builder()
+            .delete_files(true)
+            .max_poll_cycles(1)
+            .pause_flag(poller_pause_flag)
+            .build();
+        // PollError can hold a callback error, which is not Send
+        poller.poll_directory(&directory).map_err(|e| e.to_string())
+    });
+
+    // nothing is touched while paused
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(file_path.exists());
+    assert!(!poller_thread.is_finished());
+
+    pause_flag.store(false, Ordering::SeqCst);
+    let stats = poller_thread
+        .join()
+        .expect("poller thread panicked")
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn poll_directory_watch_picks_up_new_file() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let directory = temp_dir.path().to_path_buf();
+
+    // the first cycle finds nothing
+    // the second starts when the file shows up, long before the poll interval
+    let started = Instant::now();
+    let poller_thread = std::thread::spawn(move || {
+        let poller = DirectoryPoller::builder()
+            .delete_files(true)
+            .keep_running(true)
+            .poll_interval_millis(30_000)
+            .max_poll_cycles(2)
+            .watch(true)
+            .build();
+        // PollError can hold a callback error, which is not Send
+        poller.poll_directory(&directory).map_err(|e| e.to_string())
+    });
+    std::thread::sleep(Duration::from_millis(500));
+    fs::write(temp_dir.path().join("sample.txt"), "hello").expect("writing temp file failed");
+
+    let stats = poller_thread
+        .join()
+        .expect("poller thread panicked")
+        .expect("polling failed");
+    assert_eq!(stats.cycles, 2);
+    assert_eq!(stats.files_processed, 1);
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn config_summary_reflects_builder_settings() {
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(2500)
+        .max_poll_cycles(3)
+        .delete_files(false)
+        .error_directory(PathBuf::from("/tmp/failed"))
+        .include("*.json")
+        .file_order(FileOrder::Oldest)
+        .build();
+
+    let config = poller.config();
+    assert_eq!(config.poll_interval_millis, 2500);
+    assert_eq!(config.max_poll_cycles, 3);
+    assert!(!config.delete_files);
+    assert_eq!(config.error_directory, Some(PathBuf::from("/tmp/failed")));
+    assert_eq!(config.include, vec!["*.json"]);
+    assert_eq!(config.file_order, FileOrder::Oldest);
+
+    let summary = poller.config_summary();
+    assert!(summary.contains("poll_interval_millis: 2500"));
+    assert!(summary.contains("max_poll_cycles: 3"));
+    assert!(summary.contains("delete_files: false"));
+    assert!(summary.contains("\"*.json\""));
+    assert!(summary.contains("Oldest"));
+}
+
+#[test]
+fn poll_directory_follows_manifest() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for name in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+        fs::write(temp_dir.path().join(name), name).expect("writing temp file failed");
+    }
+    let other_dir = tempfile::tempdir().expect("create temp dir failed");
+    let outside = other_dir.path().join("outside.txt");
+    fs::write(&outside, "outside").expect("writing temp file failed");
+    let manifest = other_dir.path().join("manifest.txt");
+    fs::write(
+        &manifest,
+        format!(
+            "# replay\nc.txt\n\nmissing.txt\na.txt\n{}\n",
+            outside.display()
+        ),
+    )
+    .expect("writing manifest failed");
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let received_clone = Rc::clone(&received);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .manifest(manifest.clone())
+        .on_file_content(move |content| {
+            received_clone.borrow_mut().push(content.to_string());
+            Ok(())
+        })
+        .build();
+
+    // the missing and outside entries are skipped
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(*received.borrow(), vec!["c.txt", "a.txt"]);
+    assert_eq!(stats.files_processed, 2);
+    assert!(temp_dir.path().join("b.txt").exists());
+    assert!(temp_dir.path().join("d.txt").exists());
+    assert!(outside.exists());
+
+    // a strict manifest stops on the first bad entry
+    let poller = DirectoryPoller::builder()
+        .max_poll_cycles(1)
+        .manifest(manifest)
+        .strict_manifest(true)
+        .build();
+    let err = poller.poll_directory(temp_dir.path()).unwrap_err();
+    assert!(matches!(err, PollError::ManifestEntry { .. }));
+}
+
+fn create_temp_dir_with_empty_file() -> (TempDir, PathBuf) {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("empty.txt");
+    fs::write(&file_path, "").expect("writing temp file failed");
+
+    (temp_dir, file_path)
+}
+
+// Fake sink that accepts or rejects every file, counting what it is sent
+struct CountingSink {
+    fail: bool,
+    sent: Rc<Cell<u32>>,
+}
+
+impl FileSink for CountingSink {
+    fn send(&self, _ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        self.sent.set(self.sent.get() + 1);
+        if self.fail {
+            return Err("topic is not reachable".into());
+        }
+        Ok(())
+    }
+}
+
+fn fanout_poller(fanout_mode: FanoutMode, fail_second: bool) -> (DirectoryPoller, Rc<Cell<u32>>) {
+    let sent = Rc::new(Cell::new(0));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(CountingSink {
+            fail: false,
+            sent: Rc::clone(&sent),
+        }))
+        .add_sink(Box::new(CountingSink {
+            fail: fail_second,
+            sent: Rc::clone(&sent),
+        }))
+        .fanout_mode(fanout_mode)
+        .build();
+    (poller, sent)
+}
+
+#[test]
+fn poll_directory_fanout_deletes_file_once_all_sinks_succeed() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let (poller, sent) = fanout_poller(FanoutMode::All, false);
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(sent.get(), 2);
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn poll_directory_fanout_keeps_file_when_a_sink_fails() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let (poller, sent) = fanout_poller(FanoutMode::All, true);
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(matches!(result, Err(PollError::Callback(e)) if e.to_string().contains("sink 2 of 2")));
+    assert_eq!(sent.get(), 2);
+    assert!(file_path.exists());
+
+    // under any one accepting sink is enough
+    let (poller, _sent) = fanout_poller(FanoutMode::Any, true);
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert!(!file_path.exists());
+}
+
+// Fake sink that tracks how many bytes are being published at once
+struct InflightSink {
+    inflight: Arc<AtomicU64>,
+    max_inflight: Arc<AtomicU64>,
+}
+
+impl FileSink for InflightSink {
+    fn send(&self, ctx: &FileContext) -> Result<(), Box<dyn Error>> {
+        let bytes = ctx.content.len() as u64;
+        let inflight = self.inflight.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        self.max_inflight.fetch_max(inflight, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(20));
+        self.inflight.fetch_sub(bytes, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[test]
+fn poll_directory_shares_inflight_budget_between_threads() {
+    let budget = ByteBudget::shared(10);
+    let inflight = Arc::new(AtomicU64::new(0));
+    let max_inflight = Arc::new(AtomicU64::new(0));
+
+    // two pollers with 6 byte files cannot both fit in the 10 byte budget
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+            for name in ["a.txt", "b.txt"] {
+                fs::write(temp_dir.path().join(name), "hello!").expect("writing temp file failed");
+            }
+            let budget = Arc::clone(&budget);
+            let sink = InflightSink {
+                inflight: Arc::clone(&inflight),
+                max_inflight: Arc::clone(&max_inflight),
+            };
+            std::thread::spawn(move || {
+                let poller = DirectoryPoller::builder()
+                    .poll_interval_millis(0)
+                    .max_poll_cycles(1)
+                    .inflight_budget(budget)
+                    .sink(Box::new(sink))
+                    .build();
+                let stats = poller
+                    .poll_directory(temp_dir.path())
+                    .map_err(|e| e.to_string());
+                (temp_dir, stats)
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        let (_temp_dir, stats) = worker.join().expect("poller thread panicked");
+        assert_eq!(stats.expect("polling failed").files_processed, 2);
+    }
+    assert_eq!(max_inflight.load(Ordering::SeqCst), 6);
+    assert_eq!(budget.in_use(), 0);
+}
+
+#[test]
+fn poll_directory_fails_file_larger_than_inflight_budget() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .inflight_budget(ByteBudget::shared(4))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(
+        matches!(result, Err(PollError::Callback(e)) if e.to_string().contains("larger than the in-flight budget"))
+    );
+    assert!(file_path.exists());
+}
+
+#[test]
+fn poll_directory_keeps_files_until_delete_after_cycles() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let sent = Rc::new(Cell::new(0));
+    let poller_sent = Rc::clone(&sent);
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .delete_after_cycles(2)
+        .on_file_content(move |_content| {
+            poller_sent.set(poller_sent.get() + 1);
+            Ok(())
+        })
+        .build();
+
+    // cycle 1 publishes the file and keeps it
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert!(file_path.exists());
+    assert_eq!(sent.get(), 1);
+
+    // cycle 2 deletes it without publishing it again
+    // and files arriving from then on are deleted once published
+    let new_file_path = temp_dir.path().join("new.txt");
+    fs::write(&new_file_path, "world").expect("writing temp file failed");
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert!(!file_path.exists());
+    assert!(!new_file_path.exists());
+    assert_eq!(sent.get(), 2);
+}
+
+#[test]
+fn poll_directory_fails_while_another_poller_holds_lock() {
+    let (temp_dir, file_path) = create_temp_dir_with_file();
+    let directory = temp_dir.path().to_path_buf();
+    let lock_file = directory.join(".poller.lock");
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+
+    let first_lock_file = lock_file.clone();
+    let first_shutdown_flag = Arc::clone(&shutdown_flag);
+    let first = std::thread::spawn(move || {
+        DirectoryPoller::builder()
+            .delete_files(false)
+            .keep_running(true)
+            .poll_interval_millis(10)
+            .lock_file(first_lock_file)
+            .shutdown_flag(first_shutdown_flag)
+            .build()
+            .poll_directory(&directory)
+            .map_err(|e| e.to_string())
+    });
+    let started = Instant::now();
+    while !lock_file.exists() && started.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let second = DirectoryPoller::builder()
+        .max_poll_cycles(1)
+        .lock_file(lock_file.clone())
+        .on_file_content(|_content| panic!("the second poller published a file"))
+        .build();
+    let result = second.poll_directory(temp_dir.path());
+    assert!(matches!(result, Err(PollError::Locked { path, .. }) if path == lock_file));
+
+    // the first poller releases the lock when it stops
+    shutdown_flag.store(true, Ordering::SeqCst);
+    let stats = first
+        .join()
+        .expect("poller thread panicked")
+        .expect("polling failed");
+    assert!(stats.files_processed >= 1);
+    assert!(!lock_file.exists());
+    assert!(file_path.exists());
+}
+
+#[test]
+fn each_polled_directory_is_locked() {
+    let first_dir = tempfile::tempdir().expect("create temp dir failed");
+    let second_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(second_dir.path().join("a.json"), "{}").expect("writing temp file failed");
+    // another instance polling only the second directory
+    let second_lock_file = second_dir.path().join(DEFAULT_LOCK_FILE);
+    let other_instance = DirectoryLock::acquire(&second_lock_file).expect("locking failed");
+
+    let poller = DirectoryPoller::builder()
+        .max_poll_cycles(1)
+        .lock_directories(true)
+        .on_file_content(|_content| panic!("a file was published while locked"))
+        .build();
+    let result = poller.poll_directories(&[first_dir.path(), second_dir.path()]);
+    assert!(
+        matches!(&result, Err(PollError::Locked { path, .. }) if path.ends_with(DEFAULT_LOCK_FILE)),
+        "{:?}",
+        result.map(|_| ())
+    );
+    // the lock already taken on the first directory is released again
+    assert!(!first_dir.path().join(DEFAULT_LOCK_FILE).exists());
+
+    drop(other_instance);
+    let poller = DirectoryPoller::builder()
+        .delete_files(false)
+        .max_poll_cycles(1)
+        .lock_directories(true)
+        .build();
+    let stats = poller
+        .poll_directories(&[first_dir.path(), second_dir.path()])
+        .expect("polling failed");
+    // the lock files are not published
+    assert_eq!(stats.files_processed, 1);
+}
+
+#[test]
+fn poll_directory_times_each_stage() {
+    let (temp_dir, _file_path) = create_temp_dir_with_file();
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .on_file_content(|_content| {
+            std::thread::sleep(Duration::from_millis(20));
+            Ok(())
+        })
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert!(stats.stage_times.publish >= Duration::from_millis(20));
+    assert!(stats.stage_times.read < stats.stage_times.publish);
+}
+
+fn create_temp_dir_with_file() -> (TempDir, PathBuf) {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("sample.txt");
+    fs::write(&file_path, "hello").expect("writing temp file failed");
+
+    (temp_dir, file_path)
 }
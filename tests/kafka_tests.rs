@@ -0,0 +1,1216 @@
+use kafka_rust_cli::budget::RetryBudget;
+use kafka_rust_cli::content_type::ContentTypeDetection;
+use kafka_rust_cli::envelope::EnvelopeFormat;
+use kafka_rust_cli::file::{DirectoryPoller, FileOrder, KeyStrategy, PollError, EXIT_CONNECTION};
+use kafka_rust_cli::kafka::{
+    DeliveryFuture, KafkaProducer, MessageProducer, ProducerRecord, DLQ_ERROR_HEADER,
+    DLQ_FILE_NAME_HEADER,
+};
+use kafka_rust_cli::mirror::{ConsumedMessage, MessageConsumer, Mirror};
+use kafka_rust_cli::partition::PartitionStrategy;
+use kafka_rust_cli::sink::PublishOutcome;
+use kafka_rust_cli::timestamp::TimestampSource;
+use kafka_rust_cli::topics::{TopicMetadata, TopicPattern, TopicSubscription};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// integration tests for KafkaProducer
+/// a mock producer stands in for the broker
+/// so we can control when (or if) delivery is confirmed
+struct MockProducer {
+    confirm_delivery: bool,
+}
+
+impl MessageProducer for MockProducer {
+    fn send(&self, _record: &ProducerRecord) -> Result<DeliveryFuture, Box<dyn Error>> {
+        if self.confirm_delivery {
+            Ok(Box::pin(std::future::ready(Ok(()))))
+        } else {
+            // the delivery report never arrives
+            Ok(Box::pin(std::future::pending()))
+        }
+    }
+}
+
+// a record the RoutingProducer accepted
+struct SentRecord {
+    topic: String,
+    key: Option<Vec<u8>>,
+    payload: Option<Vec<u8>>,
+    headers: Vec<(String, Vec<u8>)>,
+    partition: Option<i32>,
+    timestamp: Option<i64>,
+}
+
+/// mock producer that rejects every message for the failing topics
+/// and records the messages it accepts
+struct RoutingProducer {
+    failing_topics: Vec<String>,
+    sent: Rc<RefCell<Vec<SentRecord>>>,
+}
+
+impl MessageProducer for RoutingProducer {
+    fn send(&self, record: &ProducerRecord) -> Result<DeliveryFuture, Box<dyn Error>> {
+        if self.failing_topics.iter().any(|t| t == record.topic) {
+            return Ok(Box::pin(std::future::ready(Err(
+                "Message rejected by broker".into(),
+            ))));
+        }
+        self.sent.borrow_mut().push(SentRecord {
+            topic: record.topic.to_string(),
+            key: record.key.map(|k| k.to_vec()),
+            payload: record.payload.map(|p| p.to_vec()),
+            headers: record.headers.clone(),
+            partition: record.partition,
+            timestamp: record.timestamp,
+        });
+        Ok(Box::pin(std::future::ready(Ok(()))))
+    }
+
+    // every topic has 3 partitions
+    fn partition_count(&self, _topic: &str, _timeout: Duration) -> Result<i32, Box<dyn Error>> {
+        Ok(3)
+    }
+}
+
+/// mock producer that counts how often it is flushed
+struct FlushCountingProducer {
+    flush_count: Rc<Cell<u32>>,
+}
+
+impl MessageProducer for FlushCountingProducer {
+    fn send(&self, _record: &ProducerRecord) -> Result<DeliveryFuture, Box<dyn Error>> {
+        Ok(Box::pin(std::future::ready(Ok(()))))
+    }
+
+    fn flush(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.flush_count.set(self.flush_count.get() + 1);
+        Ok(())
+    }
+}
+
+/// mock producer that fails the first sends with the given error code
+/// and then accepts everything
+struct FlakyProducer {
+    failures: u32,
+    error_code: RDKafkaErrorCode,
+    attempts: Rc<Cell<u32>>,
+}
+
+impl MessageProducer for FlakyProducer {
+    fn send(&self, _record: &ProducerRecord) -> Result<DeliveryFuture, Box<dyn Error>> {
+        self.attempts.set(self.attempts.get() + 1);
+        if self.attempts.get() <= self.failures {
+            let error = KafkaError::MessageProduction(self.error_code);
+            return Ok(Box::pin(std::future::ready(Err(error.into()))));
+        }
+        Ok(Box::pin(std::future::ready(Ok(()))))
+    }
+}
+
+/// mock producer whose queue is full for the first sends
+/// and that counts how often it is flushed to drain it
+struct QueueFullProducer {
+    full_sends: u32,
+    attempts: Rc<Cell<u32>>,
+    flush_count: Rc<Cell<u32>>,
+}
+
+impl MessageProducer for QueueFullProducer {
+    fn send(&self, _record: &ProducerRecord) -> Result<DeliveryFuture, Box<dyn Error>> {
+        self.attempts.set(self.attempts.get() + 1);
+        if self.attempts.get() <= self.full_sends {
+            // rdkafka refuses to enqueue the message
+            return Err(KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull).into());
+        }
+        Ok(Box::pin(std::future::ready(Ok(()))))
+    }
+
+    fn flush(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.flush_count.set(self.flush_count.get() + 1);
+        Ok(())
+    }
+}
+
+/// mock transactional producer that rejects payloads of "fail"
+/// and records the transaction calls
+struct TransactionalProducer {
+    calls: Rc<RefCell<Vec<&'static str>>>,
+}
+
+impl MessageProducer for TransactionalProducer {
+    fn send(&self, record: &ProducerRecord) -> Result<DeliveryFuture, Box<dyn Error>> {
+        if record.payload == Some(b"fail") {
+            return Ok(Box::pin(std::future::ready(Err(
+                "Message rejected by broker".into(),
+            ))));
+        }
+        self.calls.borrow_mut().push("send");
+        Ok(Box::pin(std::future::ready(Ok(()))))
+    }
+
+    fn init_transactions(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.calls.borrow_mut().push("init");
+        Ok(())
+    }
+
+    fn begin_transaction(&self) -> Result<(), Box<dyn Error>> {
+        self.calls.borrow_mut().push("begin");
+        Ok(())
+    }
+
+    fn commit_transaction(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.calls.borrow_mut().push("commit");
+        Ok(())
+    }
+
+    fn abort_transaction(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.calls.borrow_mut().push("abort");
+        Ok(())
+    }
+}
+
+#[test]
+fn file_deleted_on_confirmed_delivery() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("sample.txt");
+    fs::write(&file_path, "hello").expect("writing temp file failed");
+
+    let producer = mock_kafka_producer(true);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn file_retained_on_delivery_timeout() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("sample.txt");
+    fs::write(&file_path, "hello").expect("writing temp file failed");
+
+    let producer = mock_kafka_producer(false);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_err());
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("was not confirmed within 50 ms"));
+    assert!(file_path.exists());
+}
+
+#[test]
+fn failing_file_routed_to_dlq() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("poison.json");
+    fs::write(&file_path, "{not json").expect("writing temp file failed");
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&["test-topic"], &sent);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    assert!(!file_path.exists());
+
+    let sent = sent.borrow();
+    assert_eq!(sent.len(), 1);
+    let record = &sent[0];
+    assert_eq!(record.topic, "test-dlq");
+    assert_eq!(record.payload, Some(b"{not json".to_vec()));
+    assert!(record
+        .headers
+        .iter()
+        .any(|(name, value)| name == DLQ_FILE_NAME_HEADER && value == b"poison.json"));
+    assert!(record
+        .headers
+        .iter()
+        .any(|(name, value)| name == DLQ_ERROR_HEADER
+            && String::from_utf8_lossy(value).contains("Message rejected by broker")));
+}
+
+#[test]
+fn failing_dlq_falls_back_to_error_directory() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("poison.json");
+    fs::write(&file_path, "{not json").expect("writing temp file failed");
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&["test-topic", "test-dlq"], &sent);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .error_directory(error_dir.path().to_path_buf())
+        .sink(Box::new(producer))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_failed, 1);
+    assert!(!file_path.exists());
+    assert!(error_dir.path().join("poison.json").exists());
+    assert!(sent.borrow().is_empty());
+}
+
+#[test]
+fn timed_out_file_not_routed_to_dlq() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("sample.txt");
+    fs::write(&file_path, "hello").expect("writing temp file failed");
+
+    let producer = mock_kafka_producer(false).dlq_topic(Some("test-dlq".to_string()));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+
+    // the timeout is the error rather than a failed dead-letter publish
+    let error = poller
+        .poll_directory(temp_dir.path())
+        .expect_err("polling succeeded")
+        .to_string();
+    assert!(
+        error.contains("was not confirmed within 50 ms"),
+        "{}",
+        error
+    );
+    assert!(!error.contains("test-dlq"), "{}", error);
+    assert!(file_path.exists());
+}
+
+#[test]
+fn routing_callback_picks_topic_per_file() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("a.json"), r#"{"type":"order"}"#).expect("writing failed");
+    fs::write(temp_dir.path().join("b.json"), r#"{"type":"payment"}"#).expect("writing failed");
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+
+    // route on the event type inside the JSON
+    // and key each message by its file name
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .on_file_route(|ctx| {
+            let content = ctx.content_str()?;
+            let topic = if content.contains("order") {
+                "orders"
+            } else {
+                "payments"
+            };
+            Ok(PublishOutcome {
+                topic: Some(topic.to_string()),
+                key: Some(ctx.file_name.as_bytes().to_vec()),
+            })
+        })
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+
+    let mut routed: Vec<(String, Vec<u8>)> = sent
+        .borrow()
+        .iter()
+        .map(|record| (record.topic.clone(), record.key.clone().unwrap_or_default()))
+        .collect();
+    routed.sort();
+    assert_eq!(
+        routed,
+        vec![
+            ("orders".to_string(), b"a.json".to_vec()),
+            ("payments".to_string(), b"b.json".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn files_without_route_use_configured_topic() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("a.json"), "{}").expect("writing failed");
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .on_file_route(|_ctx| Ok(PublishOutcome::default()))
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    assert_eq!(sent.borrow()[0].topic, "test-topic");
+    assert_eq!(sent.borrow()[0].key, None);
+}
+
+#[test]
+fn producer_flushed_after_interval() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let flush_count = Rc::new(Cell::new(0));
+    let producer = flush_counting_producer(&flush_count);
+
+    // each cycle takes at least 20ms so every cycle passes the 10ms interval
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(20)
+        .max_poll_cycles(3)
+        .flush_interval_millis(10)
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    // flushed on 2 cycle boundaries after waiting (plus the final cycle)
+    // and once more when polling stops
+    assert!(flush_count.get() >= 3);
+}
+
+#[test]
+fn producer_flushed_only_on_stop_when_disabled() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let flush_count = Rc::new(Cell::new(0));
+    let producer = flush_counting_producer(&flush_count);
+
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(20)
+        .max_poll_cycles(3)
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    assert_eq!(flush_count.get(), 1);
+}
+
+#[test]
+fn file_published_after_broker_reconnects() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("sample.txt");
+    fs::write(&file_path, "hello").expect("writing temp file failed");
+
+    let attempts = Rc::new(Cell::new(0));
+    let producer = flaky_kafka_producer(3, RDKafkaErrorCode::BrokerTransportFailure, &attempts);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(attempts.get(), 4);
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn file_retained_when_reconnect_retries_exhausted() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("sample.txt");
+    fs::write(&file_path, "hello").expect("writing temp file failed");
+
+    let attempts = Rc::new(Cell::new(0));
+    let producer = flaky_kafka_producer(10, RDKafkaErrorCode::AllBrokersDown, &attempts);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_err());
+    // the first attempt plus 5 retries
+    assert_eq!(attempts.get(), 6);
+    assert!(file_path.exists());
+}
+
+#[test]
+fn unreachable_broker_is_a_connection_error() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("sample.txt"), "hello").expect("writing temp file failed");
+
+    let attempts = Rc::new(Cell::new(0));
+    let producer = flaky_kafka_producer(10, RDKafkaErrorCode::AllBrokersDown, &attempts);
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+
+    let err = poller.poll_directory(temp_dir.path()).unwrap_err();
+    assert!(matches!(err, PollError::Connection(_)));
+    assert_eq!(err.exit_code(), EXIT_CONNECTION);
+}
+
+#[test]
+fn reconnect_backoff_interrupted_by_shutdown() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("sample.txt");
+    fs::write(&file_path, "hello").expect("writing temp file failed");
+
+    let attempts = Rc::new(Cell::new(0));
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let producer = flaky_kafka_producer(10, RDKafkaErrorCode::AllBrokersDown, &attempts)
+        .reconnect(5, Duration::from_secs(30))
+        .shutdown_flag(Arc::clone(&shutdown_flag));
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+
+    let handler_flag = Arc::clone(&shutdown_flag);
+    let handler = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        handler_flag.store(true, Ordering::SeqCst);
+    });
+    let started = Instant::now();
+    let result = poller.poll_directory(temp_dir.path());
+    handler.join().expect("shutdown thread panicked");
+
+    // well short of the first 30 s backoff
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(result.is_err());
+    assert_eq!(attempts.get(), 1);
+    assert!(file_path.exists());
+}
+
+#[test]
+fn permanent_error_not_retried() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("sample.txt");
+    fs::write(&file_path, "hello").expect("writing temp file failed");
+
+    let attempts = Rc::new(Cell::new(0));
+    let producer = flaky_kafka_producer(1, RDKafkaErrorCode::UnknownTopicOrPartition, &attempts);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_err());
+    assert_eq!(attempts.get(), 1);
+    assert!(file_path.exists());
+}
+
+// four files behind a broker that is down for good
+fn create_temp_dir_with_failing_files() -> tempfile::TempDir {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for name in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+        fs::write(temp_dir.path().join(name), name).expect("writing temp file failed");
+    }
+    temp_dir
+}
+
+#[test]
+fn retry_budget_shared_across_files() {
+    let temp_dir = create_temp_dir_with_failing_files();
+    let error_dir = tempfile::tempdir().expect("create error dir failed");
+    let retry_budget = RetryBudget::shared(3);
+
+    let attempts = Rc::new(Cell::new(0));
+    let producer = flaky_kafka_producer(u32::MAX, RDKafkaErrorCode::AllBrokersDown, &attempts)
+        .retry_budget(Some(retry_budget.clone()));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .error_directory(error_dir.path().to_path_buf())
+        .sink(Box::new(producer))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_failed, 4);
+    // the first file takes the whole budget, the rest are tried once
+    assert_eq!(attempts.get(), 4 + 3);
+    assert_eq!(retry_budget.used(), 3);
+    assert!(retry_budget.is_exhausted());
+    // each file with the record of the directory it failed in
+    assert_eq!(fs::read_dir(error_dir.path()).unwrap().count(), 8);
+}
+
+#[test]
+fn exhausted_retry_budget_stops_poller() {
+    let temp_dir = create_temp_dir_with_failing_files();
+    let error_dir = tempfile::tempdir().expect("create error dir failed");
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let retry_budget = Arc::new(RetryBudget::new(2).stop_when_exhausted(shutdown_flag.clone()));
+
+    let attempts = Rc::new(Cell::new(0));
+    let producer = flaky_kafka_producer(u32::MAX, RDKafkaErrorCode::AllBrokersDown, &attempts)
+        .retry_budget(Some(retry_budget));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .error_directory(error_dir.path().to_path_buf())
+        .shutdown_flag(shutdown_flag)
+        .sink(Box::new(producer))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    // stopped after the file that used up the budget
+    assert_eq!(stats.files_failed, 1);
+    assert_eq!(attempts.get(), 3);
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 3);
+}
+
+// runs the executable, so the budget goes through main's wiring
+// nothing listens on the broker's port and every publish times out
+#[test]
+fn send_retries_take_from_retry_budget() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("sample.txt"), "hello").expect("writing temp file failed");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_kafka_pub_cli"))
+        .args(["--acks", "all", "--topic", "test-topic"])
+        .args(["--bootstrap-server", "127.0.0.1:1", "--runOnce"])
+        .arg("--messageLocation")
+        .arg(temp_dir.path())
+        .args([
+            "--delivery-timeout-ms",
+            "100",
+            "--send-retry-backoff-ms",
+            "1",
+        ])
+        .args(["--send-retries", "5", "--max-total-retries", "2"])
+        .output()
+        .expect("running kafka_pub_cli failed");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("retrying in 2 ms (2/5)"), "{}", stderr);
+    assert!(!stderr.contains("(3/5)"), "{}", stderr);
+    assert!(
+        stderr.contains("the 2 retries of the run are used up"),
+        "{}",
+        stderr
+    );
+    assert!(temp_dir.path().join("sample.txt").exists());
+}
+
+#[test]
+fn file_published_once_full_queue_drains() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("sample.txt");
+    fs::write(&file_path, "hello").expect("writing temp file failed");
+
+    let attempts = Rc::new(Cell::new(0));
+    let flush_count = Rc::new(Cell::new(0));
+    let producer = QueueFullProducer {
+        full_sends: 3,
+        attempts: Rc::clone(&attempts),
+        flush_count: Rc::clone(&flush_count),
+    };
+    // no reconnect retries, the full queue is waited out on its own
+    let producer = KafkaProducer::new(Box::new(producer), "test-topic", Duration::from_secs(5))
+        .expect("create producer failed")
+        .queue_full_backoff(Duration::from_millis(1));
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+    assert_eq!(attempts.get(), 4);
+    // once per full queue, and once more when polling stops
+    assert_eq!(flush_count.get(), 4);
+    assert!(!file_path.exists());
+}
+
+// retries transient errors up to 5 times
+fn flaky_kafka_producer(
+    failures: u32,
+    error_code: RDKafkaErrorCode,
+    attempts: &Rc<Cell<u32>>,
+) -> KafkaProducer {
+    let producer = FlakyProducer {
+        failures,
+        error_code,
+        attempts: Rc::clone(attempts),
+    };
+    KafkaProducer::new(Box::new(producer), "test-topic", Duration::from_millis(50))
+        .expect("create producer failed")
+        .reconnect(5, Duration::from_millis(1))
+}
+
+#[test]
+fn sidecar_key_and_headers_published() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("order-123.json");
+    let sidecar_path = temp_dir.path().join("order-123.json.meta");
+    fs::write(&file_path, r#"{"id":123}"#).expect("writing failed");
+    fs::write(
+        &sidecar_path,
+        r#"{"key":"order-123","headers":{"source":"billing","version":"2"}}"#,
+    )
+    .expect("writing failed");
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sidecar_extension(".meta".to_string())
+        .sink(Box::new(producer))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    // the sidecar is not published on its own
+    assert_eq!(stats.files_processed, 1);
+
+    let sent = sent.borrow();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].payload, Some(br#"{"id":123}"#.to_vec()));
+    assert_eq!(sent[0].key, Some(b"order-123".to_vec()));
+    assert_eq!(
+        sent[0].headers,
+        vec![
+            ("source".to_string(), b"billing".to_vec()),
+            ("version".to_string(), b"2".to_vec()),
+        ]
+    );
+    assert!(!file_path.exists());
+    assert!(!sidecar_path.exists());
+}
+
+#[test]
+fn malformed_sidecar_moves_pair_to_error_directory() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("order-123.json"), "{}").expect("writing failed");
+    fs::write(
+        temp_dir.path().join("order-123.json.meta"),
+        r#"{"key":123}"#,
+    )
+    .expect("writing failed");
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sidecar_extension(".meta".to_string())
+        .error_directory(error_dir.path().to_path_buf())
+        .sink(Box::new(producer))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_failed, 1);
+    assert!(sent.borrow().is_empty());
+    assert!(error_dir.path().join("order-123.json").exists());
+    assert!(error_dir.path().join("order-123.json.meta").exists());
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn empty_file_publishes_tombstone() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("order-123.json");
+    fs::write(&file_path, "").expect("writing failed");
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .key_strategy(KeyStrategy::FileStem)
+        .tombstone_on_empty(true)
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    let sent = sent.borrow();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].key, Some(b"order-123".to_vec()));
+    assert_eq!(sent[0].payload, None);
+    assert!(!file_path.exists());
+}
+
+#[test]
+fn delete_extension_publishes_tombstone() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("order-123.json.delete"), "ignored").expect("writing failed");
+    fs::write(temp_dir.path().join("order-456.json"), "{}").expect("writing failed");
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .key_strategy(KeyStrategy::FileName)
+        .tombstone_on_empty(true)
+        .sink(Box::new(producer))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+    let sent = sent.borrow();
+    assert_eq!(sent[0].key, Some(b"order-123.json".to_vec()));
+    assert_eq!(sent[0].payload, None);
+    assert_eq!(sent[1].key, Some(b"order-456.json".to_vec()));
+    assert_eq!(sent[1].payload, Some(b"{}".to_vec()));
+}
+
+#[test]
+fn tombstone_without_key_fails() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("order-123.json");
+    fs::write(&file_path, "").expect("writing failed");
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .tombstone_on_empty(true)
+        .sink(Box::new(producer))
+        .build();
+
+    let err = poller.poll_directory(temp_dir.path()).unwrap_err();
+    assert!(err.to_string().contains("A tombstone needs a key"));
+    assert!(sent.borrow().is_empty());
+    assert!(file_path.exists());
+}
+
+fn flush_counting_producer(flush_count: &Rc<Cell<u32>>) -> KafkaProducer {
+    let producer = FlushCountingProducer {
+        flush_count: Rc::clone(flush_count),
+    };
+    KafkaProducer::new(Box::new(producer), "test-topic", Duration::from_millis(50))
+        .expect("create producer failed")
+}
+
+#[test]
+fn poll_directory_publishes_to_strategy_partition() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for name in ["acme-1.json", "acme-2.json", "README"] {
+        fs::write(temp_dir.path().join(name), "{}").expect("writing temp file failed");
+    }
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let strategy = PartitionStrategy::parse("regex-hash:^([a-z]+)-").unwrap();
+    let producer = routing_kafka_producer(&[], &sent).partition_strategy(strategy);
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .sink(Box::new(producer))
+        .build();
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+
+    // README does not match and is left to the producer
+    let partitions: Vec<Option<i32>> = sent.borrow().iter().map(|r| r.partition).collect();
+    assert_eq!(partitions[0], None);
+    assert!(partitions[1].is_some_and(|partition| (0..3).contains(&partition)));
+    assert_eq!(partitions[1], partitions[2]);
+}
+
+#[test]
+fn fixed_partition_checked_against_partition_count() {
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent)
+        .partition_strategy(PartitionStrategy::parse("fixed:2").unwrap());
+    assert!(producer.check_partition_strategy("test-topic").is_ok());
+
+    let producer = routing_kafka_producer(&[], &sent)
+        .partition_strategy(PartitionStrategy::parse("fixed:3").unwrap());
+    let err = producer
+        .check_partition_strategy("test-topic")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("has 3 partitions"));
+}
+
+#[test]
+fn content_type_header_published() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("a.json"), r#"{"id": 1}"#).expect("writing temp file failed");
+    fs::write(temp_dir.path().join("b.dat"), [0x00, 0xff, 0x10]).expect("writing temp file failed");
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .content_type_detection(ContentTypeDetection::Magic)
+        .sink(Box::new(producer))
+        .build();
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+
+    let content_types: Vec<Vec<(String, Vec<u8>)>> =
+        sent.borrow().iter().map(|r| r.headers.clone()).collect();
+    assert_eq!(
+        content_types,
+        vec![
+            vec![("content-type".to_string(), b"application/json".to_vec())],
+            vec![(
+                "content-type".to_string(),
+                b"application/octet-stream".to_vec()
+            )],
+        ]
+    );
+}
+
+#[test]
+fn oversize_file_published_truncated() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("app.log"), "line one\nline two\n")
+        .expect("writing temp file failed");
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .truncate_to_bytes(9)
+        .sink(Box::new(producer))
+        .build();
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 1);
+
+    let sent = sent.borrow();
+    assert_eq!(sent[0].payload.as_deref(), Some(&b"line one\n"[..]));
+    assert_eq!(
+        sent[0].headers,
+        [
+            ("truncated".to_string(), b"true".to_vec()),
+            ("truncated.original.size".to_string(), b"18".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn truncated_payload_keeps_envelope_whole() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("app.log"), "line one\nline two\n")
+        .expect("writing temp file failed");
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .truncate_to_bytes(9)
+        .envelope(EnvelopeFormat::JsonEnvelope)
+        .sink(Box::new(producer))
+        .build();
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+
+    let sent = sent.borrow();
+    let envelope: serde_json::Value =
+        serde_json::from_slice(sent[0].payload.as_deref().unwrap()).expect("envelope is not JSON");
+    assert_eq!(envelope["file_name"], "app.log");
+    assert_eq!(envelope["size"], 9);
+    assert!(sent[0]
+        .headers
+        .contains(&("truncated.original.size".to_string(), b"18".to_vec())));
+}
+
+#[test]
+fn record_timestamp_from_file_mtime() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("event.json");
+    fs::write(&file_path, r#"{"id": 1}"#).expect("writing temp file failed");
+    // the event happened well before the file is published
+    let event_time = SystemTime::UNIX_EPOCH + Duration::from_millis(1_714_559_400_250);
+    fs::File::options()
+        .write(true)
+        .open(&file_path)
+        .and_then(|file| file.set_modified(event_time))
+        .expect("setting mtime failed");
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent).timestamp_source(TimestampSource::Mtime);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .max_poll_cycles(1)
+        .sink(Box::new(producer))
+        .build();
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+
+    let timestamps: Vec<Option<i64>> = sent.borrow().iter().map(|r| r.timestamp).collect();
+    assert_eq!(timestamps, vec![Some(1_714_559_400_250)]);
+}
+
+#[test]
+fn validate_setup_needs_topic_metadata() {
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let producer = routing_kafka_producer(&[], &sent);
+    assert!(producer.validate_setup("test-topic").is_ok());
+
+    // the mock producer cannot report the partitions of the topic
+    let err = mock_kafka_producer(true)
+        .validate_setup("test-topic")
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("Cannot publish to topic test-topic"));
+}
+
+fn transactional_poll(contents: &[(&str, &str)]) -> (tempfile::TempDir, Vec<&'static str>) {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    for (name, content) in contents {
+        fs::write(temp_dir.path().join(name), content).expect("writing temp file failed");
+    }
+    let error_dir = tempfile::tempdir().expect("create temp dir failed");
+
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let producer = TransactionalProducer {
+        calls: Rc::clone(&calls),
+    };
+    let producer = KafkaProducer::new(Box::new(producer), "test-topic", Duration::from_secs(1))
+        .expect("creating producer failed")
+        .transactional()
+        .expect("initializing transactions failed");
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .error_directory(error_dir.path().to_path_buf())
+        .sink(Box::new(producer))
+        .build();
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+
+    let calls = calls.borrow().clone();
+    (temp_dir, calls)
+}
+
+#[test]
+fn transaction_commit_deletes_cycle_files() {
+    let (temp_dir, calls) = transactional_poll(&[("a.txt", "first"), ("b.txt", "second")]);
+    assert_eq!(calls, ["init", "begin", "send", "send", "commit"]);
+    assert!(!temp_dir.path().join("a.txt").exists());
+    assert!(!temp_dir.path().join("b.txt").exists());
+}
+
+#[test]
+fn transaction_aborted_when_a_file_fails() {
+    let (temp_dir, calls) = transactional_poll(&[("a.txt", "first"), ("b.txt", "fail")]);
+    assert_eq!(calls, ["init", "begin", "send", "abort"]);
+    // a.txt was published but its transaction was aborted
+    assert!(temp_dir.path().join("a.txt").exists());
+}
+
+/// mock consumer handing out the queued messages
+/// that records each commit with how many records had been sent by then
+struct MockConsumer {
+    messages: RefCell<VecDeque<ConsumedMessage>>,
+    sent: Rc<RefCell<Vec<SentRecord>>>,
+    commits: Rc<RefCell<Vec<(i64, usize)>>>,
+    subscriptions: Rc<RefCell<Vec<Vec<String>>>>,
+}
+
+impl MessageConsumer for MockConsumer {
+    fn subscribe(&self, topics: &[String]) -> Result<(), Box<dyn Error>> {
+        self.subscriptions.borrow_mut().push(topics.to_vec());
+        Ok(())
+    }
+
+    fn poll(&self, _timeout: Duration) -> Result<Option<ConsumedMessage>, Box<dyn Error>> {
+        Ok(self.messages.borrow_mut().pop_front())
+    }
+
+    fn commit(&self, message: &ConsumedMessage) -> Result<(), Box<dyn Error>> {
+        let sent = self.sent.borrow().len();
+        self.commits.borrow_mut().push((message.offset, sent));
+        Ok(())
+    }
+}
+
+fn mock_consumer(
+    sent: &Rc<RefCell<Vec<SentRecord>>>,
+    commits: &Rc<RefCell<Vec<(i64, usize)>>>,
+) -> MockConsumer {
+    let messages = (0..3)
+        .map(|offset| ConsumedMessage {
+            topic: "orders-old".to_string(),
+            partition: 0,
+            offset,
+            key: Some(format!("order-{}", offset).into_bytes()),
+            payload: Some(format!(r#"{{"id":{}}}"#, offset).into_bytes()),
+            headers: vec![("source".to_string(), b"billing".to_vec())],
+            timestamp: None,
+        })
+        .collect();
+    MockConsumer {
+        messages: RefCell::new(messages),
+        sent: Rc::clone(sent),
+        commits: Rc::clone(commits),
+        subscriptions: Rc::default(),
+    }
+}
+
+#[test]
+fn mirror_copies_messages_and_commits_after_delivery() {
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let commits = Rc::new(RefCell::new(Vec::new()));
+    let consumer = mock_consumer(&sent, &commits);
+    let subscriptions = Rc::clone(&consumer.subscriptions);
+    let mirror = Mirror::new(
+        Box::new(consumer),
+        Box::new(routing_kafka_producer(&[], &sent)),
+        "orders-old",
+        "orders-new",
+    )
+    .max_messages(2);
+
+    let stats = mirror.run().expect("mirror failed");
+    assert_eq!(*subscriptions.borrow(), [["orders-old"]]);
+    assert_eq!(stats.messages_mirrored, 2);
+    let sent = sent.borrow();
+    assert_eq!(sent.len(), 2);
+    assert!(sent.iter().all(|record| record.topic == "orders-new"));
+    assert_eq!(sent[1].key.as_deref(), Some(&b"order-1"[..]));
+    assert_eq!(sent[1].payload.as_deref(), Some(&br#"{"id":1}"#[..]));
+    assert_eq!(
+        sent[1].headers,
+        [("source".to_string(), b"billing".to_vec())]
+    );
+    // each offset is committed once its message was delivered
+    assert_eq!(*commits.borrow(), [(0, 1), (1, 2)]);
+}
+
+#[test]
+fn mirror_does_not_commit_undelivered_message() {
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let commits = Rc::new(RefCell::new(Vec::new()));
+    let mirror = Mirror::new(
+        Box::new(mock_consumer(&sent, &commits)),
+        Box::new(mock_kafka_producer(false)),
+        "orders-old",
+        "orders-new",
+    );
+
+    let err = mirror.run().unwrap_err().to_string();
+    assert!(err.contains("orders-old/0@0"), "{}", err);
+    assert!(commits.borrow().is_empty());
+}
+
+// metadata listing a fixed set of topics
+struct FixedMetadata(Vec<&'static str>);
+
+impl TopicMetadata for FixedMetadata {
+    fn topic_names(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self.0.iter().map(|topic| topic.to_string()).collect())
+    }
+}
+
+#[test]
+fn mirror_subscribes_to_topics_matching_regex() {
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let commits = Rc::new(RefCell::new(Vec::new()));
+    let consumer = mock_consumer(&sent, &commits);
+    let subscriptions = Rc::clone(&consumer.subscriptions);
+    let metadata = FixedMetadata(vec!["orders-old", "orders-eu", "orders-new", "payments"]);
+    let subscription = TopicSubscription::new(
+        TopicPattern::parse("orders-.*").unwrap(),
+        Box::new(metadata),
+        Duration::from_secs(60),
+    );
+    let mirror = Mirror::new(
+        Box::new(consumer),
+        Box::new(routing_kafka_producer(&[], &sent)),
+        "orders-.*",
+        "orders-new",
+    )
+    .topic_subscription(subscription)
+    .max_messages(1);
+
+    mirror.run().expect("mirror failed");
+    // the destination topic matches too but is never consumed
+    assert_eq!(*subscriptions.borrow(), [["orders-eu", "orders-old"]]);
+    assert_eq!(sent.borrow().len(), 1);
+}
+
+fn mock_kafka_producer(confirm_delivery: bool) -> KafkaProducer {
+    KafkaProducer::new(
+        Box::new(MockProducer { confirm_delivery }),
+        "test-topic",
+        Duration::from_millis(50),
+    )
+    .expect("create producer failed")
+}
+
+fn routing_kafka_producer(
+    failing_topics: &[&str],
+    sent: &Rc<RefCell<Vec<SentRecord>>>,
+) -> KafkaProducer {
+    let producer = RoutingProducer {
+        failing_topics: failing_topics.iter().map(|t| t.to_string()).collect(),
+        sent: Rc::clone(sent),
+    };
+    KafkaProducer::new(Box::new(producer), "test-topic", Duration::from_millis(50))
+        .expect("create producer failed")
+        .dlq_topic(Some("test-dlq".to_string()))
+}
@@ -0,0 +1,107 @@
+use kafka_rust_cli::file::DirectoryPoller;
+use log::{Level, Log, Metadata, Record};
+use std::fs;
+use std::sync::{Mutex, MutexGuard, Once};
+
+/// integration tests for what the poller logs
+/// the logger is global so these live in their own test binary
+static MESSAGES: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+
+struct CapturingLogger;
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        MESSAGES
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger;
+
+// Install the logger (once) and clear what earlier tests captured
+// the guard keeps tests from logging over each other
+fn capture_logs() -> MutexGuard<'static, ()> {
+    static INSTALL: Once = Once::new();
+    static SERIAL: Mutex<()> = Mutex::new(());
+    let guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    INSTALL.call_once(|| {
+        log::set_logger(&LOGGER).expect("set logger failed");
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+    MESSAGES.lock().unwrap().clear();
+    guard
+}
+
+// The captured messages starting with the prefix, with their levels
+fn logged(prefix: &str) -> Vec<(Level, String)> {
+    MESSAGES
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, message)| message.starts_with(prefix))
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn empty_cycles_logged_at_heartbeat_frequency() {
+    let _logs = capture_logs();
+
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(7)
+        .heartbeat_every(3)
+        .build();
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+
+    let empty_lines: Vec<String> = logged("No files found")
+        .into_iter()
+        .map(|(_, message)| message)
+        .collect();
+    assert_eq!(
+        empty_lines,
+        vec![
+            "No files found in the last 3 poll cycles (3 empty cycles in a row)",
+            "No files found in the last 3 poll cycles (6 empty cycles in a row)",
+        ]
+    );
+}
+
+#[test]
+fn poll_messages_use_configured_levels() {
+    let _logs = capture_logs();
+
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("sample.txt"), "hello").expect("write failed");
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(2)
+        .on_file_content(|_content| Ok(()))
+        .file_log_level(Some(Level::Debug))
+        .cycle_log_level(Some(Level::Warn))
+        .empty_cycle_log_level(None)
+        .build();
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+
+    assert_eq!(
+        logged("Processing file"),
+        [(Level::Debug, "Processing file: \"sample.txt\"".to_string())]
+    );
+    let cycle_lines = logged("cycle 1:");
+    assert_eq!(cycle_lines.len(), 1);
+    assert_eq!(cycle_lines[0].0, Level::Warn);
+    // the second cycle found nothing, which is not logged
+    assert!(logged("No files found").is_empty());
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "test-util")]
+
+use kafka_rust_cli::file::{DirectoryPoller, FileOrder, KeyStrategy, PollError};
+use kafka_rust_cli::sink::{InMemorySink, ReceivedRecord};
+use std::fs;
+
+/// integration tests running the whole pipeline into an InMemorySink
+/// run with: cargo test --features test-util
+#[test]
+fn in_memory_sink_captures_published_records() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("order-1.json"), r#"{"id":1}"#).expect("writing failed");
+    fs::write(
+        temp_dir.path().join("order-1.json.meta"),
+        r#"{"headers":{"source":"billing"}}"#,
+    )
+    .expect("writing failed");
+    fs::write(temp_dir.path().join("order-2.json"), r#"{"id":2}"#).expect("writing failed");
+
+    let sink = InMemorySink::new();
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .file_order(FileOrder::Name)
+        .key_strategy(KeyStrategy::FileStem)
+        .sidecar_extension(".meta".to_string())
+        .sink(Box::new(sink.clone()))
+        .build();
+
+    let stats = poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(stats.files_processed, 2);
+    assert_eq!(
+        sink.records(),
+        [
+            ReceivedRecord {
+                topic: None,
+                key: Some(b"order-1".to_vec()),
+                headers: vec![("source".to_string(), b"billing".to_vec())],
+                payload: br#"{"id":1}"#.to_vec(),
+            },
+            ReceivedRecord {
+                topic: None,
+                key: Some(b"order-2".to_vec()),
+                headers: Vec::new(),
+                payload: br#"{"id":2}"#.to_vec(),
+            },
+        ]
+    );
+    assert!(!temp_dir.path().join("order-1.json").exists());
+}
+
+#[test]
+fn failing_in_memory_sink_retains_file() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    let file_path = temp_dir.path().join("order-1.json");
+    fs::write(&file_path, r#"{"id":1}"#).expect("writing failed");
+
+    let sink = InMemorySink::new();
+    sink.set_failing(true);
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .sink(Box::new(sink.clone()))
+        .build();
+
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(
+        matches!(result, Err(PollError::Callback(_))),
+        "{:?}",
+        result
+    );
+    assert!(sink.is_empty());
+    assert!(file_path.exists());
+
+    // the file is published once the sink recovers
+    sink.set_failing(false);
+    poller
+        .poll_directory(temp_dir.path())
+        .expect("polling failed");
+    assert_eq!(sink.payloads(), [r#"{"id":1}"#]);
+    assert!(!file_path.exists());
+}
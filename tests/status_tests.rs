@@ -0,0 +1,58 @@
+use kafka_rust_cli::file::DirectoryPoller;
+use kafka_rust_cli::status::{Status, StatusServer};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+/// integration tests for the status endpoint
+/// the server is started on a free port and queried over HTTP
+#[test]
+fn status_endpoint_reports_last_cycle() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("sample.txt"), "hello").expect("writing temp file failed");
+
+    let status = Status::shared();
+    let server = StatusServer::start(0, status.clone()).expect("start status server failed");
+
+    let poller = DirectoryPoller::builder()
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .status(status)
+        .build();
+    let result = poller.poll_directory(temp_dir.path());
+    assert!(result.is_ok());
+
+    let (head, body) = http_get(server.local_addr(), "/status");
+    assert!(head.starts_with("HTTP/1.1 200 OK"));
+    let json: serde_json::Value = serde_json::from_str(&body).expect("status is not JSON");
+    assert_eq!(json["running"], false);
+    assert_eq!(json["current_cycle"], 1);
+    assert!(json["uptime_seconds"].is_u64());
+    assert_eq!(json["last_cycle"]["cycles"], 1);
+    assert_eq!(json["last_cycle"]["files_processed"], 1);
+    assert_eq!(
+        json["last_cycle"]["per_directory"][0]["directory"],
+        temp_dir.path().display().to_string()
+    );
+
+    server.stop();
+}
+
+#[test]
+fn status_endpoint_unknown_path_not_found() {
+    let server = StatusServer::start(0, Status::shared()).expect("start status server failed");
+    let (head, _body) = http_get(server.local_addr(), "/nope");
+    assert!(head.starts_with("HTTP/1.1 404"));
+}
+
+// a minimal HTTP GET returning the response head and body
+fn http_get(addr: SocketAddr, path: &str) -> (String, String) {
+    let mut stream =
+        TcpStream::connect(("127.0.0.1", addr.port())).expect("connect to status server failed");
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).expect("write failed");
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("read failed");
+    let (head, body) = response.split_once("\r\n\r\n").expect("malformed response");
+    (head.to_string(), body.to_string())
+}
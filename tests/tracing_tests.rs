@@ -0,0 +1,78 @@
+#![cfg(feature = "tracing")]
+
+use kafka_rust_cli::file::DirectoryPoller;
+use std::fmt::Debug;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+// the (name, file field) of every span opened
+type OpenedSpans = Arc<Mutex<Vec<(String, Option<String>)>>>;
+
+// Test layer that records the spans the poller opens
+struct SpanRecorder {
+    spans: OpenedSpans,
+}
+
+struct FileField(Option<String>);
+
+impl Visit for FileField {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "file" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for SpanRecorder {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+        let mut file = FileField(None);
+        attrs.record(&mut file);
+        self.spans
+            .lock()
+            .unwrap()
+            .push((attrs.metadata().name().to_string(), file.0));
+    }
+}
+
+/// integration tests for the per-file spans
+/// run with: cargo test --features tracing
+#[test]
+fn processed_file_emits_stage_spans() {
+    let temp_dir = tempfile::tempdir().expect("create temp dir failed");
+    fs::write(temp_dir.path().join("order-1.json"), "{}").expect("writing failed");
+
+    let spans = OpenedSpans::default();
+    let subscriber = tracing_subscriber::registry().with(SpanRecorder {
+        spans: Arc::clone(&spans),
+    });
+    let poller = DirectoryPoller::builder()
+        .delete_files(true)
+        .poll_interval_millis(0)
+        .max_poll_cycles(1)
+        .on_file_content(|_content| Ok(()))
+        .build();
+    tracing::subscriber::with_default(subscriber, || {
+        poller
+            .poll_directory(temp_dir.path())
+            .expect("polling failed")
+    });
+
+    let spans = spans.lock().unwrap();
+    let names: Vec<&str> = spans.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "process_file",
+            "read",
+            "transform",
+            "publish",
+            "delete",
+            "delivery"
+        ]
+    );
+    assert_eq!(spans[0].1.as_deref(), Some("order-1.json"));
+}